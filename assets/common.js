@@ -0,0 +1,352 @@
+// Helpers shared by every page
+
+const API_BASE = '';
+let preferences = {};
+// Deployment settings from /api/ui
+let uiSettings = { confirm_stop: 'always', protect: [], stop_undo_seconds: 5, refresh_interval_seconds: 2, branding: { footer_links: [] } };
+// Translated strings from /api/i18n, keyed like `header.refresh`
+let messages = {};
+// Namespace the UI is limited to, or '' for every service
+let currentNamespace = localStorage.getItem('fgp-namespace') || '';
+// The caller's namespaces from /api/namespaces
+let namespaceList = { namespaces: [], restricted: false };
+
+// The translation of `key`, with `{name}` placeholders filled from `params`
+//
+// Falls back to the key itself so a missing string is easy to spot.
+function t(key, params = {}) {
+    const text = messages[key] ?? key;
+    return text.replace(/\{(\w+)\}/g, (match, name) => name in params ? params[name] : match);
+}
+
+// A `?lang=` parameter wins and is remembered; otherwise the server goes by
+// Accept-Language
+function requestedLang() {
+    const lang = new URLSearchParams(location.search).get('lang');
+    if (lang) localStorage.setItem('fgp-lang', lang);
+    return lang || localStorage.getItem('fgp-lang');
+}
+
+async function loadMessages() {
+    const lang = requestedLang();
+    try {
+        const response = await fetch(`${API_BASE}/api/i18n${lang ? `?lang=${encodeURIComponent(lang)}` : ''}`);
+        const result = await response.json();
+        if (result.ok) {
+            messages = result.data.messages;
+            document.documentElement.lang = result.data.locale;
+        }
+    } catch (error) {
+        console.error('Failed to load translations:', error);
+    }
+    translatePage();
+}
+
+// Fill in elements marked with data-i18n (text), data-i18n-title or
+// data-i18n-placeholder
+function translatePage() {
+    document.querySelectorAll('[data-i18n]').forEach(el => {
+        el.textContent = t(el.dataset.i18n);
+    });
+    document.querySelectorAll('[data-i18n-title]').forEach(el => {
+        el.title = t(el.dataset.i18nTitle);
+    });
+    document.querySelectorAll('[data-i18n-placeholder]').forEach(el => {
+        el.placeholder = t(el.dataset.i18nPlaceholder);
+    });
+    updateThemeToggle();
+    updateNotificationBadge();
+}
+
+function escapeHtml(text) {
+    return String(text).replace(/[&<>"']/g, c => ({
+        '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;'
+    })[c]);
+}
+
+function formatUptime(seconds) {
+    if (!seconds) return '-';
+    if (seconds < 60) return `${seconds}s`;
+    if (seconds < 3600) return `${Math.floor(seconds / 60)}m ${seconds % 60}s`;
+    if (seconds < 86400) return `${Math.floor(seconds / 3600)}h ${Math.floor((seconds % 3600) / 60)}m`;
+    return `${Math.floor(seconds / 86400)}d ${Math.floor((seconds % 86400) / 3600)}h`;
+}
+
+// A supervisor's view of a unit, such as `active (running)`
+function formatUnitState(supervisor) {
+    return supervisor.sub_state && supervisor.sub_state !== supervisor.state
+        ? `${supervisor.state} (${supervisor.sub_state})`
+        : supervisor.state;
+}
+
+function formatBytes(bytes) {
+    if (bytes == null) return '-';
+    const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+    let i = 0;
+    while (bytes >= 1024 && i < units.length - 1) {
+        bytes /= 1024;
+        i++;
+    }
+    return `${bytes.toFixed(i ? 1 : 0)} ${units[i]}`;
+}
+
+// A daemon call's timing from a `Server-Timing` header, or null
+function parseServerTiming(header) {
+    if (!header) return null;
+    const timing = {};
+    header.split(',').forEach(metric => {
+        const [name, ...parts] = metric.trim().split(';');
+        const dur = parts.find(part => part.startsWith('dur='));
+        const desc = parts.find(part => part.startsWith('desc='));
+        if (dur) timing[`${name}_ms`] = Number(dur.slice(4));
+        if (name === 'connect' && desc) timing.reused = desc.slice(5).replace(/"/g, '') === 'pooled';
+    });
+    return 'connect_ms' in timing ? timing : null;
+}
+
+// "connect 0.4 ms (pooled), daemon 12.3 ms" for a call timing
+function formatCallTiming(timing) {
+    if (!timing) return '-';
+    return t('timing.breakdown', {
+        connect: timing.connect_ms.toFixed(2),
+        call: timing.call_ms.toFixed(2),
+        connection: t(timing.reused ? 'timing.pooled' : 'timing.new'),
+    });
+}
+
+// Area chart of `values` (nulls leave gaps) as an SVG string
+function areaChart(values, width, height, className) {
+    const known = values.filter(value => value != null);
+    if (known.length === 0) return '';
+    const max = Math.max(...known) || 1;
+    const step = values.length > 1 ? width / (values.length - 1) : width;
+    const segments = [];
+    let current = [];
+    values.forEach((value, i) => {
+        if (value == null) {
+            if (current.length) segments.push(current);
+            current = [];
+        } else {
+            current.push([i * step, height - (value / max) * (height - 2) - 1]);
+        }
+    });
+    if (current.length) segments.push(current);
+    const paths = segments.map(points => {
+        const line = points.map(([x, y]) => `${x.toFixed(1)},${y.toFixed(1)}`).join(' L');
+        const [firstX] = points[0];
+        const [lastX] = points[points.length - 1];
+        return `<path class="area" d="M${firstX.toFixed(1)},${height} L${line} L${lastX.toFixed(1)},${height} Z"/>` +
+            `<path class="line" d="M${line}"/>`;
+    }).join('');
+    return `<svg class="${className}" viewBox="0 0 ${width} ${height}" preserveAspectRatio="none">${paths}</svg>`;
+}
+
+// The charted series of a history: [title, values, format]
+function metricSeries(points) {
+    return [
+        [t('metrics.latency'), points.map(point => point.latency_ms), value => `${value.toFixed(1)} ms`],
+        [t('metrics.cpu'), points.map(point => point.cpu_percent), value => `${value.toFixed(1)}%`],
+        [t('metrics.memory'), points.map(point => point.memory_bytes), formatBytes],
+    ];
+}
+
+function getStatusClass(status) {
+    if (status === 'running' || status === 'healthy') return 'running';
+    if (status === 'stopped' || status === 'stale') return 'stopped';
+    if (status === 'unhealthy' || status === 'degraded') return 'unhealthy';
+    if (status === 'incompatible') return 'incompatible';
+    return 'error';
+}
+
+// Path of an API route such as `/services`, within the chosen namespace
+function apiPath(route) {
+    return currentNamespace ? `/api/ns/${encodeURIComponent(currentNamespace)}${route}` : `/api${route}`;
+}
+
+function setNamespace(name) {
+    currentNamespace = name;
+    if (name) localStorage.setItem('fgp-namespace', name);
+    else localStorage.removeItem('fgp-namespace');
+}
+
+// Switch namespaces; pages reload so streams and caches start over
+function chooseNamespace(name) {
+    setNamespace(name);
+    location.reload();
+}
+
+// Namespace tokens are kept to their own namespace
+async function loadNamespaces() {
+    try {
+        const response = await fetch(`${API_BASE}/api/namespaces`);
+        const result = await response.json();
+        if (!result.ok) return;
+        namespaceList = result.data;
+    } catch (error) {
+        console.error('Failed to load namespaces:', error);
+        return;
+    }
+    const names = namespaceList.namespaces.map(namespace => namespace.name);
+    if (currentNamespace && !names.includes(currentNamespace)) setNamespace('');
+    if (namespaceList.restricted && !currentNamespace && names.length) setNamespace(names[0]);
+}
+
+function renderNamespacePicker() {
+    const select = document.getElementById('namespace-select');
+    const names = namespaceList.namespaces.map(namespace => namespace.name);
+    if (!select || names.length === 0) return;
+    const all = namespaceList.restricted ? '' : `<option value="">${t('namespace.all')}</option>`;
+    select.innerHTML = all + names.map(name =>
+        `<option value="${escapeHtml(name)}">${escapeHtml(name)}</option>`).join('');
+    select.value = currentNamespace;
+    select.hidden = false;
+}
+
+async function loadPreferences() {
+    try {
+        const response = await fetch(`${API_BASE}/api/preferences`);
+        const result = await response.json();
+        if (result.ok) {
+            preferences = result.data;
+            applyTheme(preferences.theme);
+        }
+    } catch (error) {
+        console.error('Failed to load preferences:', error);
+    }
+}
+
+async function loadUiSettings() {
+    try {
+        const response = await fetch(`${API_BASE}/api/ui`);
+        const result = await response.json();
+        if (result.ok) uiSettings = result.data;
+    } catch (error) {
+        console.error('Failed to load UI settings:', error);
+    }
+    applyBranding();
+}
+
+const DEFAULT_TITLE = 'FGP Dashboard';
+
+function brandTitle() {
+    return uiSettings.branding.title || DEFAULT_TITLE;
+}
+
+// Apply the deployment's title, logo, accent and footer links
+function applyBranding() {
+    const branding = uiSettings.branding;
+    document.title = document.title.replace(DEFAULT_TITLE, brandTitle());
+    const title = document.getElementById('brand-title');
+    if (title) title.textContent = brandTitle();
+
+    const logo = document.getElementById('brand-logo');
+    if (logo && branding.logo_url) {
+        logo.src = branding.logo_url;
+        logo.alt = brandTitle();
+        logo.hidden = false;
+        let icon = document.querySelector('link[rel="icon"]');
+        if (!icon) {
+            icon = document.createElement('link');
+            icon.rel = 'icon';
+            document.head.appendChild(icon);
+        }
+        icon.href = branding.logo_url;
+    }
+
+    applyAccent();
+
+    if (branding.footer_links.length && !document.getElementById('brand-footer')) {
+        const footer = document.createElement('footer');
+        footer.id = 'brand-footer';
+        footer.className = 'brand-footer';
+        footer.innerHTML = branding.footer_links.map(link =>
+            `<a href="${escapeHtml(link.url)}" target="_blank" rel="noopener">${escapeHtml(link.label)}</a>`
+        ).join('');
+        document.querySelector('.container').appendChild(footer);
+    }
+}
+
+function applyAccent() {
+    const { accent_color: dark, accent_color_light: light } = uiSettings.branding;
+    const accent = currentTheme() === 'light' ? light || dark : dark;
+    if (accent) document.documentElement.style.setProperty('--brand-accent', accent);
+}
+
+// A modal asking for confirmation, resolving to true if confirmed
+//
+// With `requireText` the confirm button stays disabled until it is typed.
+function confirmDialog({ title, message, confirmLabel = t('common.confirm'), requireText = null }) {
+    return new Promise(resolve => {
+        const dialog = document.createElement('dialog');
+        dialog.className = 'confirm-dialog';
+        dialog.innerHTML = `
+            <form method="dialog">
+                <h2>${escapeHtml(title)}</h2>
+                <p>${escapeHtml(message)}</p>
+                ${requireText ? `
+                    <label>${t('dialog.type_to_confirm', { text: `<strong>${escapeHtml(requireText)}</strong>` })}
+                        <input class="search" autocomplete="off">
+                    </label>` : ''}
+                <div class="dialog-actions">
+                    <button class="btn btn-small" type="button" onclick="this.closest('dialog').close()">${t('common.cancel')}</button>
+                    <button class="btn btn-stop" value="confirm" ${requireText ? 'disabled' : ''}>${escapeHtml(confirmLabel)}</button>
+                </div>
+            </form>`;
+        const input = dialog.querySelector('input');
+        const confirmButton = dialog.querySelector('button[value="confirm"]');
+        input?.addEventListener('input', () => {
+            confirmButton.disabled = input.value.trim() !== requireText;
+        });
+        dialog.addEventListener('close', () => {
+            resolve(dialog.returnValue === 'confirm');
+            dialog.remove();
+        });
+        document.body.appendChild(dialog);
+        dialog.showModal();
+        (input || confirmButton).focus();
+    });
+}
+
+async function savePreference(key, value) {
+    preferences[key] = value;
+    try {
+        await fetch(`${API_BASE}/api/preferences`, {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ [key]: value }),
+        });
+    } catch (error) {
+        console.error(`Failed to save preference ${key}:`, error);
+    }
+}
+
+function currentTheme() {
+    return document.documentElement.dataset.theme ||
+        (window.matchMedia('(prefers-color-scheme: light)').matches ? 'light' : 'dark');
+}
+
+// Without a saved choice the OS preference applies
+function applyTheme(theme) {
+    if (theme === 'light' || theme === 'dark') {
+        document.documentElement.dataset.theme = theme;
+        localStorage.setItem('fgp-theme', theme);
+    } else {
+        delete document.documentElement.dataset.theme;
+        localStorage.removeItem('fgp-theme');
+    }
+    updateThemeToggle();
+    applyAccent();
+}
+
+function updateThemeToggle() {
+    document.getElementById('theme-toggle').textContent =
+        t(currentTheme() === 'light' ? 'theme.dark' : 'theme.light');
+}
+
+function toggleTheme() {
+    const theme = currentTheme() === 'light' ? 'dark' : 'light';
+    applyTheme(theme);
+    savePreference('theme', theme);
+}
+
+const namespacesReady = loadNamespaces();
@@ -0,0 +1,836 @@
+let services = null;
+let annotations = {};
+// Last hour of metrics by service, refreshed less often than the list
+let sparklines = {};
+// Cards expanded on small screens, where they start collapsed
+const expanded = new Set();
+// Service picked with j/k
+let selectedName = null;
+// Services ticked for bulk actions
+const checked = new Set();
+// Confirmed stops waiting out the undo window, by ID
+const pendingStops = new Map();
+let nextPendingId = 1;
+// Status chip in effect: null, 'running', 'stopped' or 'error'
+let statusFilter = null;
+let searchTimer = null;
+// Custom panels, and the last data or error of each by ID
+let widgets = [];
+const widgetData = {};
+
+// Server-side filter expressions behind the status chips
+const STATUS_QUERIES = {
+    running: 'status IN (running, healthy)',
+    stopped: 'status = stopped',
+    error: 'NOT status IN (running, healthy, stopped)',
+};
+
+function renderAnnotations(name) {
+    const list = annotations[name] || [];
+    const note = list.find(a => a.key === 'note');
+    const tags = list.filter(a => a.key !== 'note');
+    return (note ? `<div class="service-note">${escapeHtml(note.value)}</div>` : '') +
+        (tags.length ? `<div class="annotations">${tags.map(a =>
+            `<span class="annotation">${escapeHtml(a.key)}: ${escapeHtml(a.value)}</span>`
+        ).join('')}</div>` : '');
+}
+
+function renderSparklines(name) {
+    const charts = metricSeries(sparklines[name] || []).map(([title, values, format]) => {
+        const chart = areaChart(values, 100, 24, 'sparkline');
+        if (!chart) return '';
+        const latest = values.filter(value => value != null).pop();
+        return `<div class="sparkline-cell" title="${t('metrics.last_hour', { metric: title })}">
+                    <span>${title} ${format(latest)}</span>${chart}
+                </div>`;
+    }).join('');
+    return charts ? `<div class="sparklines">${charts}</div>` : '';
+}
+
+async function fetchSparklines() {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath('/sparklines?range=1h')}`);
+        const result = await response.json();
+        if (result.ok) {
+            sparklines = result.data.services;
+            renderServices();
+        }
+    } catch (error) {
+        console.error('Failed to fetch sparklines:', error);
+    }
+}
+
+async function fetchWidgets() {
+    try {
+        const response = await fetch(`${API_BASE}/api/widgets`);
+        const result = await response.json();
+        if (!result.ok) return;
+        widgets = result.data;
+        renderWidgets();
+        for (const widget of widgets) {
+            refreshWidget(widget);
+            setInterval(() => refreshWidget(widget), widget.refresh_seconds * 1000);
+        }
+    } catch (error) {
+        console.error('Failed to fetch widgets:', error);
+    }
+}
+
+async function refreshWidget(widget) {
+    try {
+        const response = await fetch(`${API_BASE}/api/widgets/${encodeURIComponent(widget.id)}/data`);
+        const result = await response.json();
+        widgetData[widget.id] = result.ok ? { data: result.data } : { error: result.error };
+    } catch (error) {
+        widgetData[widget.id] = { error: error.message };
+    }
+    renderWidgets();
+}
+
+function formatWidgetValue(value) {
+    if (value == null) return '-';
+    if (typeof value === 'number') return value.toLocaleString();
+    if (typeof value === 'object') return JSON.stringify(value);
+    return String(value);
+}
+
+function renderWidgetBody(widget, data) {
+    if (widget.kind === 'list') {
+        const items = Array.isArray(data)
+            ? data
+            : Object.entries(data || {}).map(([label, value]) => ({ label, value }));
+        return `<ul class="widget-list">${items.map(item =>
+            `<li><span>${escapeHtml(formatWidgetValue(item.label))}</span><span>${escapeHtml(formatWidgetValue(item.value))}</span></li>`
+        ).join('')}</ul>`;
+    }
+    if (widget.kind === 'table') {
+        const rows = Array.isArray(data) ? data.filter(row => row && typeof row === 'object') : [];
+        if (!rows.length) return `<div class="widget-empty">${t('widgets.empty')}</div>`;
+        const columns = Object.keys(rows[0]);
+        return `<table class="widget-table">
+                    <thead><tr>${columns.map(column => `<th>${escapeHtml(column)}</th>`).join('')}</tr></thead>
+                    <tbody>${rows.map(row =>
+                        `<tr>${columns.map(column => `<td>${escapeHtml(formatWidgetValue(row[column]))}</td>`).join('')}</tr>`
+                    ).join('')}</tbody>
+                </table>`;
+    }
+    const unit = widget.unit ? `<span class="widget-unit">${escapeHtml(widget.unit)}</span>` : '';
+    return `<div class="widget-stat">${escapeHtml(formatWidgetValue(data))}${unit}</div>`;
+}
+
+function renderWidgets() {
+    const container = document.getElementById('widgets');
+    container.hidden = widgets.length === 0;
+    container.innerHTML = widgets.map(widget => {
+        const state = widgetData[widget.id];
+        const body = !state
+            ? `<div class="widget-empty">${t('widgets.loading')}</div>`
+            : state.error
+                ? `<div class="widget-error" title="${escapeHtml(state.error)}">${t('widgets.unavailable')}</div>`
+                : renderWidgetBody(widget, state.data);
+        return `<div class="widget"><div class="widget-title">${escapeHtml(widget.title)}</div>${body}</div>`;
+    }).join('');
+}
+
+function renderServices() {
+    const app = document.getElementById('app');
+
+    if (services === null) return;
+    if (services.length === 0) {
+        app.innerHTML = `<div class="empty-state">${t('services.none')}</div>`;
+        return;
+    }
+
+    const listView = preferences.view === 'list';
+    const render = listView ? renderRow : renderCard;
+    app.classList.toggle('list', listView);
+
+    const shown = visibleServices();
+    if (shown.length === 0) {
+        app.innerHTML = `<div class="empty-state">${t('services.no_match')}</div>`;
+        return;
+    }
+    if (preferences.group_by) {
+        app.innerHTML = groups(shown).map(([title, members]) =>
+            `<div class="section-title">${escapeHtml(title)}</div>` + members.map(render).join('')
+        ).join('');
+        return;
+    }
+    const pinned = shown.filter(service => service.pinned);
+    const others = shown.filter(service => !service.pinned);
+    app.innerHTML = pinned.length
+        ? `<div class="section-title">${t('services.pinned')}</div>` + pinned.map(render).join('') +
+            (others.length ? `<div class="section-title">${t('services.all')}</div>` : '') +
+            others.map(render).join('')
+        : others.map(render).join('');
+}
+
+// The value a service is grouped by, or null if it has none
+function groupValue(service, groupBy) {
+    if (groupBy === 'host') return service.host;
+    if (groupBy.startsWith('label:')) return service.labels[groupBy.slice(6)] ?? null;
+    if (groupBy.startsWith('tag:')) {
+        const key = groupBy.slice(4);
+        return (annotations[service.name] || []).find(a => a.key === key)?.value ?? null;
+    }
+    return null;
+}
+
+// [title, services] pairs in title order, services without a value last
+function groups(shown) {
+    const byValue = new Map();
+    shown.forEach(service => {
+        const value = groupValue(service, preferences.group_by);
+        if (!byValue.has(value)) byValue.set(value, []);
+        byValue.get(value).push(service);
+    });
+    const titled = [...byValue.keys()].filter(value => value !== null).sort()
+        .map(value => [value, byValue.get(value)]);
+    if (byValue.has(null)) titled.push([t('group.ungrouped'), byValue.get(null)]);
+    return titled;
+}
+
+// Sort key and direction from the `default_sort` preference, e.g. `-uptime`
+function currentSort() {
+    const sort = preferences.default_sort || 'name';
+    return { key: sort.replace(/^-/, ''), descending: sort.startsWith('-') };
+}
+
+function setSort(key) {
+    savePreference('default_sort', (currentSort().descending ? '-' : '') + key);
+    fetchServices();
+}
+
+function toggleSortOrder() {
+    const sort = currentSort();
+    savePreference('default_sort', (sort.descending ? '' : '-') + sort.key);
+    updateSortControls();
+    fetchServices();
+}
+
+function setGroupBy(groupBy) {
+    savePreference('group_by', groupBy || null);
+    renderServices();
+}
+
+function updateSortControls() {
+    const sort = currentSort();
+    document.getElementById('sort').value = sort.key;
+    const order = document.getElementById('sort-order');
+    order.textContent = sort.descending ? '\u2193' : '\u2191';
+    order.title = t(sort.descending ? 'sort.descending' : 'sort.ascending');
+}
+
+// Offer host plus every label and tag key currently in use
+function updateGroupOptions() {
+    const labels = new Set((services || []).flatMap(service => Object.keys(service.labels)));
+    const tags = new Set(Object.values(annotations).flat()
+        .map(a => a.key).filter(key => key !== 'note'));
+    const options = [['', t('group.none')], ['host', t('group.host')],
+        ...[...tags].sort().map(key => [`tag:${key}`, t('group.tag', { key })]),
+        ...[...labels].sort().map(key => [`label:${key}`, t('group.label', { key })])];
+    const groupBy = preferences.group_by || '';
+    if (!options.some(([value]) => value === groupBy)) {
+        options.push([groupBy, t('group.other', { key: groupBy })]);
+    }
+
+    const select = document.getElementById('group-by');
+    select.innerHTML = options.map(([value, label]) =>
+        `<option value="${escapeHtml(value)}">${escapeHtml(label)}</option>`).join('');
+    select.value = groupBy;
+}
+
+function searchText() {
+    return document.getElementById('search').value.trim();
+}
+
+function matchesStatus(service) {
+    const statusClass = getStatusClass(service.status);
+    switch (statusFilter) {
+        case 'running': return statusClass === 'running';
+        case 'stopped': return statusClass === 'stopped';
+        case 'error': return ['error', 'unhealthy', 'incompatible'].includes(statusClass);
+        default: return true;
+    }
+}
+
+// Services left after the search box and status chip, in display order
+//
+// The server filters too; filtering here as well keeps typing responsive
+// until the next fetch lands.
+function visibleServices() {
+    const search = searchText().toLowerCase();
+    return (services || []).filter(service =>
+        service.name.toLowerCase().includes(search) && matchesStatus(service));
+}
+
+function onSearchInput() {
+    renderServices();
+    clearTimeout(searchTimer);
+    searchTimer = setTimeout(fetchServices, 250);
+}
+
+function setStatusFilter(status) {
+    statusFilter = statusFilter === status ? null : status;
+    document.querySelectorAll('.chip').forEach(chip =>
+        chip.classList.toggle('active', (chip.dataset.status || null) === statusFilter));
+    renderServices();
+    fetchServices();
+}
+
+function servicesUrl() {
+    const params = new URLSearchParams();
+    const search = searchText();
+    if (search) params.set('name_contains', search);
+    if (statusFilter) params.set('q', STATUS_QUERIES[statusFilter]);
+    const sort = currentSort();
+    if (sort.key !== 'name') params.set('sort', sort.key);
+    if (sort.descending) params.set('order', 'desc');
+    const query = params.toString();
+    return `${API_BASE}${apiPath('/services')}${query ? `?${query}` : ''}`;
+}
+
+function renderCheckbox(service) {
+    return `<input type="checkbox" class="select" title="${t('bulk.select')}"
+                   ${checked.has(service.name) ? 'checked' : ''}
+                   onchange="toggleChecked('${service.name}', this.checked)">`;
+}
+
+function toggleChecked(name, on) {
+    if (on) checked.add(name);
+    else checked.delete(name);
+    updateBulkBar();
+}
+
+function selectAll(on) {
+    visibleServices().forEach(service => on ? checked.add(service.name) : checked.delete(service.name));
+    renderServices();
+    updateBulkBar();
+}
+
+function clearSelection() {
+    checked.clear();
+    renderServices();
+    updateBulkBar();
+}
+
+function updateBulkBar() {
+    // Forget services that have gone away
+    const known = new Set((services || []).map(service => service.name));
+    [...checked].filter(name => !known.has(name)).forEach(name => checked.delete(name));
+
+    document.getElementById('bulk-bar').hidden = checked.size === 0;
+    document.getElementById('bulk-count').textContent = t('bulk.selected', { count: checked.size });
+    const shown = visibleServices();
+    const selectAllBox = document.getElementById('select-all');
+    selectAllBox.checked = shown.length > 0 && shown.every(service => checked.has(service.name));
+    selectAllBox.indeterminate = !selectAllBox.checked && shown.some(service => checked.has(service.name));
+}
+
+async function runBatch(action, annotations) {
+    const names = [...checked];
+    if (action === 'stop' || action === 'restart') {
+        const targets = (services || []).filter(service => checked.has(service.name));
+        if (!await confirmStop(targets, action)) return;
+        scheduleStop(t(`pending.${action}`, { what: t('stop.n_services', { count: names.length }) }),
+            () => sendBatch(action, names, annotations));
+        return;
+    }
+    await sendBatch(action, names, annotations);
+}
+
+async function sendBatch(action, names, annotations) {
+    try {
+        const response = await fetch(`${API_BASE}/api/batch`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ action, services: names, annotations }),
+        });
+        const result = await response.json();
+        if (!result.ok) {
+            toast(t('bulk.failed', { action: t(`action.${action}`), error: result.error }), 'error');
+        } else if (result.data.failed > 0) {
+            const failures = result.data.results
+                .filter(r => !r.ok)
+                .map(r => `${r.service}: ${r.error}`)
+                .join('\n');
+            toast(t('bulk.partial', { action: t(`action.${action}`), count: result.data.failed, failures }), 'error');
+        } else {
+            toast(t('bulk.done', { action: t(`action.${action}`), count: names.length }), 'success');
+        }
+        await fetchServices();
+    } catch (error) {
+        toast(t('bulk.failed', { action: t(`action.${action}`), error: error.message }), 'error');
+    }
+}
+
+function bulkTag() {
+    const input = prompt(t('bulk.tag_prompt'));
+    if (!input) return;
+    const [key, ...rest] = input.split('=');
+    if (!key.trim() || rest.length === 0) {
+        toast(t('bulk.expected_key_value'), 'error', { record: false });
+        return;
+    }
+    runBatch('tag', { [key.trim()]: rest.join('=').trim() });
+}
+
+// Whether a service matches a `--protect` rule by label or annotation
+function isProtected(service) {
+    const tags = annotations[service.name] || [];
+    return uiSettings.protect.some(({ key, value }) =>
+        service.labels[key] === value || tags.some(a => a.key === key && a.value === value));
+}
+
+// Ask before stopping or restarting `targets` as configured, resolving to
+// whether to go ahead; `action` is `stop` or `restart`
+//
+// Protected services always need their name (or the count) typed in.
+async function confirmStop(targets, action) {
+    const protectedTargets = targets.filter(isProtected);
+    const what = targets.length === 1 ? targets[0].name : t('stop.n_services', { count: targets.length });
+    const title = t(`confirm.${action}`, { what });
+    const verb = t(`action.${action}`);
+    if (protectedTargets.length > 0) {
+        const names = protectedTargets.map(service => service.name).join(', ');
+        return confirmDialog({
+            title,
+            message: t(protectedTargets.length === 1 ? 'stop.protected_one' : 'stop.protected_many', { names }),
+            confirmLabel: verb,
+            requireText: what,
+        });
+    }
+    if (uiSettings.confirm_stop !== 'always') return true;
+    return confirmDialog({
+        title,
+        message: t('stop.message', { names: targets.map(service => service.name).join(', ') }),
+        confirmLabel: verb,
+    });
+}
+
+// Run `send` once the undo window has passed, unless undone first
+function scheduleStop(label, send) {
+    const seconds = uiSettings.stop_undo_seconds;
+    if (!seconds) {
+        send();
+        return;
+    }
+    const id = nextPendingId++;
+    const timer = setTimeout(() => {
+        pendingStops.delete(id);
+        renderPendingStops();
+        send();
+    }, seconds * 1000);
+    pendingStops.set(id, { label, timer, due: Date.now() + seconds * 1000 });
+    renderPendingStops();
+}
+
+function undoStop(id) {
+    const pending = pendingStops.get(id);
+    if (!pending) return;
+    clearTimeout(pending.timer);
+    pendingStops.delete(id);
+    renderPendingStops();
+}
+
+function renderPendingStops() {
+    document.getElementById('pending-stops').innerHTML = [...pendingStops].map(([id, pending]) => {
+        const left = Math.max(Math.ceil((pending.due - Date.now()) / 1000), 0);
+        return `<div class="pending-stop">
+                    <span>${escapeHtml(t('pending.countdown', { label: pending.label, seconds: left }))}</span>
+                    <button class="btn btn-small" onclick="undoStop(${id})">${t('pending.undo')}</button>
+                </div>`;
+    }).join('');
+}
+
+async function requestStop(name) {
+    const service = (services || []).find(service => service.name === name);
+    if (!service || !await confirmStop([service], 'stop')) return;
+    scheduleStop(t('pending.stop', { what: name }), () => stopService(name));
+}
+
+function renderPin(service) {
+    return `<button class="pin ${service.pinned ? 'pinned' : ''}"
+                    title="${t(service.pinned ? 'service.unpin' : 'service.pin')}"
+                    onclick="togglePin('${service.name}', ${!service.pinned})">&#9733;</button>`;
+}
+
+// One line per service for the compact list view
+function renderRow(service) {
+    const statusClass = getStatusClass(service.status);
+    const isRunning = statusClass === 'running';
+    return `
+        <div class="service-row ${service.name === selectedName ? 'selected' : ''}" data-name="${service.name}">
+            ${renderCheckbox(service)}
+            ${renderPin(service)}
+            <span class="status-dot ${statusClass}" title="${service.status}"></span>
+            <a class="service-name" href="/service/${encodeURIComponent(service.name)}">${service.name}</a>
+            <span class="row-uptime">${formatUptime(service.uptime_seconds)}</span>
+            ${isRunning
+                ? `<button class="btn btn-stop" onclick="requestStop('${service.name}')">${t('action.stop')}</button>`
+                : `<button class="btn btn-start" onclick="startService('${service.name}')">${t('action.start')}</button>`}
+        </div>
+    `;
+}
+
+function toggleCard(event, name) {
+    if (event.target.closest('a, button, input')) return;
+    if (expanded.has(name)) expanded.delete(name);
+    else expanded.add(name);
+    renderServices();
+}
+
+function toggleView() {
+    savePreference('view', preferences.view === 'list' ? 'cards' : 'list');
+    updateViewToggle();
+    renderServices();
+}
+
+function updateViewToggle() {
+    document.getElementById('view-toggle').textContent =
+        t(preferences.view === 'list' ? 'header.card_view' : 'header.list_view');
+}
+
+// Warn once about every daemon the dashboard can't safely talk to
+function renderProtocolBanner() {
+    const count = (services || []).filter(service => service.status === 'incompatible').length;
+    const banner = document.getElementById('protocol-banner');
+    banner.hidden = count === 0;
+    banner.textContent = count ? t('protocol.banner', { count }) : '';
+}
+
+// Badges for the features a daemon advertises; none when it can't be asked
+function renderCapabilities(service) {
+    const capabilities = service.capabilities;
+    if (!capabilities) return '';
+    const badges = ['reload', 'metrics', 'log_streaming']
+        .filter(name => capabilities[name])
+        .map(name => `<span class="capability-badge" title="${t('capability.supports')}">${t(`capability.${name}`)}</span>`);
+    return badges.length ? `<div class="capabilities">${badges.join('')}</div>` : '';
+}
+
+// What the service manager makes of the unit, next to what the daemon reports
+function renderSupervisor(service) {
+    const supervisor = service.supervisor;
+    if (!supervisor) return '';
+    const state = ['active', 'failed'].includes(supervisor.state) ? supervisor.state : 'inactive';
+    const label = t('service.unit', { manager: supervisor.manager, state: formatUnitState(supervisor) });
+    const help = t('service.unit_help', { unit: supervisor.unit, enabled: supervisor.enabled || '-' });
+    return `<span class="unit-badge unit-${state}" title="${escapeHtml(help)}">${escapeHtml(label)}</span>`;
+}
+
+function renderCard(service) {
+    const statusClass = getStatusClass(service.status);
+    const isRunning = statusClass === 'running';
+
+    return `
+        <div class="service-card ${expanded.has(service.name) ? 'expanded' : ''} ${service.name === selectedName ? 'selected' : ''}"
+             data-name="${service.name}">
+            <div class="service-header" onclick="toggleCard(event, '${service.name}')">
+                <span>
+                    ${renderCheckbox(service)}
+                    ${renderPin(service)}
+                    <a class="service-name" href="/service/${encodeURIComponent(service.name)}">${service.name}</a>
+                </span>
+                <span class="status-badge ${statusClass}" title="${service.probe_error || ''}">
+                    ${service.status === 'permission_denied'
+                        ? `<span class="warning-icon" title="${t('service.permission_denied')}">&#9888;</span>`
+                        : `<span class="status-dot ${statusClass}"></span>`}
+                    ${service.status}
+                </span>
+            </div>
+            <div class="card-body">
+                <div class="service-details">
+                    <span>${t('service.version', { version: service.version || '-' })}</span>
+                    <span>${t('service.uptime', { uptime: formatUptime(service.uptime_seconds) })}</span>
+                    <span>${t('service.restarts', { count: service.restarts })}</span>
+                </div>
+                ${renderSparklines(service.name)}
+                ${statusClass === 'incompatible'
+                    ? `<div class="incompatible-note">${escapeHtml(service.probe_error || '')}</div>` : ''}
+                ${service.status === 'permission_denied'
+                    ? `<div class="permission-note">${escapeHtml(service.probe_error || '')}</div>` : ''}
+                ${renderCapabilities(service)}
+                ${service.config_drift ? `<span class="drift-badge" title="${t('service.drift_help')}">${t('service.drift')}</span>` : ''}
+                ${renderSupervisor(service)}
+                ${service.outdated ? `<span class="update-badge" title="${escapeHtml(t('service.update_help', { version: service.version }))}">${escapeHtml(t('service.update_available', { version: service.latest_version }))}</span>` : ''}
+                ${renderAnnotations(service.name)}
+                <div class="service-actions">
+                    <button class="btn btn-start"
+                            onclick="startService('${service.name}')"
+                            ${isRunning ? 'disabled' : ''}>
+                        ${t('action.start')}
+                    </button>
+                    <button class="btn btn-stop"
+                            onclick="requestStop('${service.name}')"
+                            ${!isRunning ? 'disabled' : ''}>
+                        ${t('action.stop')}
+                    </button>
+                    ${service.status === 'stale' ? `
+                        <button class="btn" onclick="cleanupService('${service.name}')">${t('action.cleanup')}</button>` : ''}
+                    ${isRunning && service.capabilities?.reload ? `
+                        <button class="btn" onclick="reloadService('${service.name}')">${t('action.reload')}</button>` : ''}
+                </div>
+            </div>
+        </div>
+    `;
+}
+
+// Ask a daemon to reload its configuration through the passthrough
+async function reloadService(name) {
+    const service = (services || []).find(s => s.name === name);
+    const methods = service?.capabilities?.methods || [];
+    const method = methods.includes('reload') || !methods.includes('config.reload') ? 'reload' : 'config.reload';
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/call/${encodeURIComponent(name)}/${method}`)}`, { method: 'POST' });
+        const result = await response.json();
+        if (!result.ok) {
+            toast(t('service.reload_failed', { name, error: result.error }), 'error');
+        } else {
+            toast(t('service.reloaded', { name }), 'success');
+        }
+    } catch (error) {
+        toast(t('service.reload_failed', { name, error: error.message }), 'error');
+    }
+}
+
+async function cleanupService(name) {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/cleanup/${encodeURIComponent(name)}`)}`, { method: 'POST' });
+        const result = await response.json();
+        if (!result.ok) {
+            toast(t('service.cleanup_failed', { name, error: result.error }), 'error');
+        } else {
+            toast(t('service.cleaned_up', { name }), 'success');
+        }
+        await fetchServices();
+    } catch (error) {
+        toast(t('service.cleanup_failed', { name, error: error.message }), 'error');
+    }
+}
+
+async function togglePin(name, pin) {
+    try {
+        await fetch(`${API_BASE}/api/services/${name}/pin`, { method: pin ? 'PUT' : 'DELETE' });
+        await fetchServices();
+    } catch (error) {
+        toast(t('service.update_failed', { name, error: error.message }), 'error');
+    }
+}
+
+async function fetchServices() {
+    try {
+        const [response, annotationsResponse] = await Promise.all([
+            fetch(servicesUrl()),
+            fetch(`${API_BASE}${apiPath('/annotations')}`),
+        ]);
+        const result = await response.json();
+        const annotationsResult = await annotationsResponse.json();
+        if (annotationsResult.ok) {
+            annotations = annotationsResult.data;
+        }
+        if (result.ok) {
+            services = result.data;
+            updateGroupOptions();
+            renderProtocolBanner();
+            renderServices();
+            updateBulkBar();
+        }
+    } catch (error) {
+        console.error('Failed to fetch services:', error);
+    }
+    lastUpdated = new Date();
+    updateRefreshInfo();
+}
+
+async function startService(name) {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/start/${name}`)}`, { method: 'POST' });
+        const result = await response.json();
+        if (!result.ok) {
+            toast(t('service.start_failed', { name, error: result.error }), 'error');
+        } else {
+            toast(t('service.started', { name }), 'success');
+        }
+        await fetchServices();
+    } catch (error) {
+        toast(t('service.start_failed', { name, error: error.message }), 'error');
+    }
+}
+
+async function stopService(name) {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/stop/${name}`)}`, { method: 'POST' });
+        const result = await response.json();
+        if (!result.ok) {
+            toast(t('service.stop_failed', { name, error: result.error }), 'error');
+        } else {
+            toast(t('service.stopped', { name }), 'success');
+        }
+        await fetchServices();
+    } catch (error) {
+        toast(t('service.stop_failed', { name, error: error.message }), 'error');
+    }
+}
+
+// A QR code opening the dashboard on a phone, with a one-time token if needed
+async function showQrCode() {
+    let image, expiresIn;
+    try {
+        const response = await fetch(`${API_BASE}/api/qr`);
+        if (!response.ok) {
+            const result = await response.json();
+            throw new Error(result.error);
+        }
+        expiresIn = Number(response.headers.get('x-token-expires-in'));
+        image = URL.createObjectURL(await response.blob());
+    } catch (error) {
+        toast(t('qr.failed', { error: error.message }), 'error');
+        return;
+    }
+
+    const dialog = document.createElement('dialog');
+    dialog.className = 'confirm-dialog';
+    dialog.innerHTML = `
+        <form method="dialog">
+            <h2>${t('qr.title')}</h2>
+            <img class="qr-code" src="${image}" alt="${t('qr.title')}">
+            <p>${expiresIn ? t('qr.expires', { minutes: Math.round(expiresIn / 60) }) : t('qr.hint')}</p>
+            <div class="dialog-actions">
+                <button class="btn btn-small">${t('common.dismiss')}</button>
+            </div>
+        </form>`;
+    dialog.addEventListener('close', () => {
+        URL.revokeObjectURL(image);
+        dialog.remove();
+    });
+    document.body.appendChild(dialog);
+    dialog.showModal();
+}
+
+function moveSelection(step) {
+    const names = visibleServices().map(service => service.name);
+    if (names.length === 0) return;
+    const index = names.indexOf(selectedName);
+    const next = index === -1
+        ? (step > 0 ? 0 : names.length - 1)
+        : Math.min(Math.max(index + step, 0), names.length - 1);
+    selectedName = names[next];
+    renderServices();
+    document.querySelector(`[data-name="${CSS.escape(selectedName)}"]`)
+        ?.scrollIntoView({ block: 'nearest' });
+}
+
+document.addEventListener('keydown', event => {
+    if (event.ctrlKey || event.metaKey || event.altKey) return;
+    if (document.querySelector('dialog[open]')) return;
+    const typing = event.target.closest('input, textarea, select');
+    if (typing) {
+        if (event.key === 'Escape') event.target.blur();
+        return;
+    }
+
+    const selected = (services || []).find(service => service.name === selectedName);
+    switch (event.key) {
+        case '/':
+            event.preventDefault();
+            document.getElementById('search').focus();
+            break;
+        case 'j':
+            moveSelection(1);
+            break;
+        case 'k':
+            moveSelection(-1);
+            break;
+        case 'Enter':
+            if (selected) location.href = `/service/${encodeURIComponent(selected.name)}`;
+            break;
+        case 's':
+            if (selected && confirm(t('confirm.start', { what: selected.name }))) startService(selected.name);
+            break;
+        case 'x':
+            if (selected) requestStop(selected.name);
+            break;
+        case 'r':
+            fetchServices();
+            break;
+        case '?':
+            toast(t('shortcuts.help'));
+            break;
+    }
+});
+
+// Seconds between refreshes without a `refresh_interval` preference
+const DEFAULT_REFRESH_SECONDS = 5;
+const REFRESH_CHOICES = [2, 5, 10, 30, 60, 300];
+let refreshTimer = null;
+
+// The preferred interval, but never faster than the server updates its data
+function refreshSeconds() {
+    return Math.max(preferences.refresh_interval || DEFAULT_REFRESH_SECONDS,
+        uiSettings.refresh_interval_seconds);
+}
+
+function formatInterval(seconds) {
+    return seconds % 60 ? `${seconds}s` : `${seconds / 60}m`;
+}
+
+function scheduleRefresh() {
+    clearInterval(refreshTimer);
+    refreshTimer = preferences.refresh_paused ? null : setInterval(fetchServices, refreshSeconds() * 1000);
+    updateRefreshControls();
+}
+
+function updateRefreshControls() {
+    const minimum = uiSettings.refresh_interval_seconds;
+    const current = refreshSeconds();
+    const choices = [...new Set([minimum, current, ...REFRESH_CHOICES.filter(seconds => seconds > minimum)])]
+        .sort((a, b) => a - b);
+    document.getElementById('refresh-interval').innerHTML = choices.map(seconds =>
+        `<option value="${seconds}" ${seconds === current ? 'selected' : ''}>
+            ${t('refresh.every', { interval: formatInterval(seconds) })}
+        </option>`
+    ).join('');
+    document.getElementById('refresh-pause').textContent =
+        t(preferences.refresh_paused ? 'refresh.resume' : 'refresh.pause');
+}
+
+function setRefreshInterval(seconds) {
+    savePreference('refresh_interval', Number(seconds));
+    scheduleRefresh();
+}
+
+function toggleRefreshPause() {
+    const paused = !preferences.refresh_paused;
+    savePreference('refresh_paused', paused);
+    scheduleRefresh();
+    if (paused) updateRefreshInfo();
+    else fetchServices();
+}
+
+let lastUpdated = null;
+
+function updateRefreshInfo() {
+    if (!lastUpdated) return;
+    document.getElementById('refresh-info').textContent =
+        t(preferences.refresh_paused ? 'header.paused' : 'header.last_updated',
+            { time: lastUpdated.toLocaleTimeString() });
+}
+
+// Initial fetch
+applyTheme(localStorage.getItem('fgp-theme'));
+Promise.all([loadPreferences(), loadUiSettings(), loadMessages(), namespacesReady]).then(() => {
+    renderNamespacePicker();
+    updateViewToggle();
+    updateSortControls();
+    updateGroupOptions();
+    fetchServices();
+    scheduleRefresh();
+    fetchWidgets();
+});
+namespacesReady.then(fetchSparklines);
+
+// The charts change slowly
+setInterval(fetchSparklines, 30000);
+// Count down pending stops
+setInterval(() => pendingStops.size && renderPendingStops(), 1000);
@@ -0,0 +1,354 @@
+// Call daemon methods through the passthrough and inspect the results
+
+const HISTORY_KEY = 'fgp-explorer-history';
+const MAX_HISTORY = 20;
+
+const query = new URLSearchParams(location.search);
+let serviceName = query.get('service') || '';
+let methodName = query.get('method') || '';
+// Methods of the selected service, or null if it can't list them
+let methods = null;
+// 'form' when the method has a schema to build one from, otherwise 'json'
+let mode = 'json';
+
+async function getJson(url, options) {
+    const response = await fetch(`${API_BASE}${url}`, options);
+    const result = await response.json();
+    if (!result.ok) throw new Error(result.error);
+    return result.data;
+}
+
+function updateUrl() {
+    const params = new URLSearchParams();
+    if (serviceName) params.set('service', serviceName);
+    if (methodName) params.set('method', methodName);
+    history.replaceState(null, '', params.toString() ? `?${params}` : location.pathname);
+}
+
+async function loadServices() {
+    const select = document.getElementById('service');
+    try {
+        const services = await getJson(apiPath('/services'));
+        const names = services.filter(service => service.status !== 'stopped').map(service => service.name);
+        if (!serviceName || !names.includes(serviceName)) serviceName = names[0] || '';
+        select.innerHTML = names.length
+            ? names.map(name =>
+                `<option value="${escapeHtml(name)}" ${name === serviceName ? 'selected' : ''}>${escapeHtml(name)}</option>`
+            ).join('')
+            : `<option value="">${t('explorer.no_services')}</option>`;
+    } catch (error) {
+        toast(error.message, 'error');
+    }
+    await selectService(serviceName);
+}
+
+async function selectService(name) {
+    serviceName = name;
+    methods = null;
+    document.getElementById('methods').innerHTML = '';
+    if (name) {
+        try {
+            methods = await getJson(apiPath(`/methods/${encodeURIComponent(name)}`));
+            document.getElementById('methods').innerHTML = methods.map(method =>
+                `<option value="${escapeHtml(method.name)}">${escapeHtml(method.description || '')}</option>`
+            ).join('');
+        } catch (error) {
+            // Daemons without introspection can still be called by method name
+        }
+    }
+    if (methods && methodName && !methods.some(method => method.name === methodName)) methodName = '';
+    document.getElementById('method').value = methodName;
+    selectMethod(methodName);
+}
+
+function currentMethod() {
+    return (methods || []).find(method => method.name === methodName);
+}
+
+function selectMethod(name) {
+    methodName = name.trim();
+    updateUrl();
+    const method = currentMethod();
+    const info = document.getElementById('method-info');
+    if (!methods) {
+        info.textContent = serviceName ? t('explorer.no_discovery') : '';
+    } else if (method) {
+        info.textContent = [method.description, method.allowed ? '' : t('explorer.not_allowed')]
+            .filter(Boolean).join(' ');
+    } else {
+        info.textContent = methodName ? t('explorer.unknown_method') : t('explorer.pick_method');
+    }
+    document.getElementById('send').disabled = !serviceName || !methodName || (method && !method.allowed);
+    renderForm(schemaProperties(method));
+    mode = schemaProperties(method) ? 'form' : 'json';
+    showMode();
+}
+
+// The properties of an object schema, or null if there's no usable schema
+function schemaProperties(method) {
+    const schema = method?.params;
+    if (!schema || typeof schema !== 'object' || !schema.properties) return null;
+    return Object.entries(schema.properties).map(([name, property]) => ({
+        name,
+        ...property,
+        required: (schema.required || []).includes(name),
+    }));
+}
+
+function renderField(field) {
+    const label = `${escapeHtml(field.name)}${field.required ? ' *' : ''}`;
+    const help = field.description ? `<span class="muted">${escapeHtml(field.description)}</span>` : '';
+    const value = field.default ?? '';
+    let input;
+    if (Array.isArray(field.enum)) {
+        input = `<select name="${escapeHtml(field.name)}" class="toolbar-select">
+            ${field.required ? '' : '<option value=""></option>'}
+            ${field.enum.map(option =>
+                `<option value="${escapeHtml(JSON.stringify(option))}" ${option === value ? 'selected' : ''}>${escapeHtml(String(option))}</option>`
+            ).join('')}
+        </select>`;
+    } else if (isBinary(field)) {
+        input = `<input type="file" name="${escapeHtml(field.name)}" ${field.required ? 'required' : ''}>`;
+    } else if (field.type === 'boolean') {
+        input = `<input type="checkbox" name="${escapeHtml(field.name)}" ${value ? 'checked' : ''}>`;
+    } else if (field.type === 'number' || field.type === 'integer') {
+        input = `<input type="number" name="${escapeHtml(field.name)}" class="search" value="${escapeHtml(value)}"
+                        ${field.type === 'integer' ? 'step="1"' : 'step="any"'} ${field.required ? 'required' : ''}>`;
+    } else if (field.type === 'object' || field.type === 'array') {
+        input = `<textarea name="${escapeHtml(field.name)}" class="params-json" rows="3" spellcheck="false"
+                           ${field.required ? 'required' : ''}>${value === '' ? '' : escapeHtml(JSON.stringify(value, null, 2))}</textarea>`;
+    } else {
+        input = `<input name="${escapeHtml(field.name)}" class="search" value="${escapeHtml(value)}" ${field.required ? 'required' : ''}>`;
+    }
+    const type = field.enum ? 'enum' : isBinary(field) ? 'file' : field.type || 'string';
+    return `<label class="param" data-type="${escapeHtml(type)}">
+                <span>${label}</span>${input}${help}
+            </label>`;
+}
+
+// Base64 fields, which the passthrough fills from uploaded files
+function isBinary(field) {
+    return field.contentEncoding === 'base64' || field.format === 'binary';
+}
+
+function renderForm(fields) {
+    document.getElementById('params-form').innerHTML = fields
+        ? fields.map(renderField).join('') || `<div class="muted">${t('explorer.no_params')}</div>`
+        : '';
+}
+
+// Params from the generated form, without files; throws on invalid JSON in
+// nested fields
+function formParams() {
+    const params = {};
+    document.querySelectorAll('#params-form .param').forEach(label => {
+        const input = label.querySelector('input, select, textarea');
+        const type = label.dataset.type;
+        if (type === 'file') {
+            // Sent as upload parts by invoke()
+        } else if (type === 'boolean') {
+            params[input.name] = input.checked;
+        } else if (input.value === '') {
+            // Left out, so the daemon's default applies
+        } else if (type === 'enum' || type === 'object' || type === 'array') {
+            params[input.name] = JSON.parse(input.value);
+        } else if (type === 'number' || type === 'integer') {
+            params[input.name] = Number(input.value);
+        } else {
+            params[input.name] = input.value;
+        }
+    });
+    return params;
+}
+
+// Fill the form from params, for switching from the JSON editor or history
+function fillForm(params) {
+    document.querySelectorAll('#params-form .param').forEach(label => {
+        const input = label.querySelector('input, select, textarea');
+        const value = params[input.name];
+        const type = label.dataset.type;
+        if (type === 'file') return;
+        if (type === 'boolean') input.checked = Boolean(value);
+        else if (value === undefined) input.value = '';
+        else if (type === 'enum') input.value = JSON.stringify(value);
+        else if (type === 'object' || type === 'array') input.value = JSON.stringify(value, null, 2);
+        else input.value = value;
+    });
+}
+
+function setMode(next) {
+    const hasForm = Boolean(schemaProperties(currentMethod()));
+    if (next === 'form' && !hasForm) next = 'json';
+    try {
+        if (mode === 'form' && next === 'json' && hasForm) {
+            document.getElementById('params-json').value = JSON.stringify(formParams(), null, 2);
+        } else if (mode === 'json' && next === 'form') {
+            fillForm(JSON.parse(document.getElementById('params-json').value || '{}'));
+        }
+    } catch (error) {
+        toast(t('explorer.invalid_json', { error: error.message }), 'error', { record: false });
+        return;
+    }
+    mode = next;
+    showMode();
+}
+
+function showMode() {
+    const hasForm = Boolean(schemaProperties(currentMethod()));
+    document.getElementById('params-form').hidden = mode !== 'form';
+    document.getElementById('params-json').hidden = mode !== 'json';
+    document.getElementById('mode-form').disabled = mode === 'form' || !hasForm;
+    document.getElementById('mode-json').disabled = mode === 'json';
+}
+
+function currentParams() {
+    return mode === 'form' ? formParams() : JSON.parse(document.getElementById('params-json').value || '{}');
+}
+
+// Files picked in the form, as [param, file] pairs
+function currentFiles() {
+    if (mode !== 'form') return [];
+    return Array.from(document.querySelectorAll('#params-form .param[data-type="file"] input'))
+        .filter(input => input.files.length)
+        .map(input => [input.name, input.files[0]]);
+}
+
+// The request body: JSON, or multipart when files are attached
+function requestOptions(params, files) {
+    if (!files.length) {
+        return { method: 'POST', headers: { 'Content-Type': 'application/json' }, body: JSON.stringify(params) };
+    }
+    const body = new FormData();
+    body.append('params', JSON.stringify(params));
+    files.forEach(([name, file]) => body.append(name, file, file.name));
+    return { method: 'POST', body };
+}
+
+function callUrl() {
+    const url = `${API_BASE}${apiPath(`/call/${encodeURIComponent(serviceName)}/${encodeURIComponent(methodName)}`)}`;
+    if (!document.getElementById('download').checked) return url;
+    const query = new URLSearchParams({ download: document.getElementById('download-pointer').value.trim() });
+    return `${url}?${query}`;
+}
+
+function showDownload(blob, response) {
+    const disposition = response.headers.get('Content-Disposition') || '';
+    const filename = disposition.match(/filename="([^"]*)"/)?.[1] || `${methodName}.bin`;
+    const link = document.createElement('a');
+    link.href = URL.createObjectURL(blob);
+    link.download = filename;
+    link.textContent = t('explorer.download_file', { name: filename, size: formatBytes(blob.size) });
+    const output = document.getElementById('response');
+    output.textContent = '';
+    output.appendChild(link);
+}
+
+// The daemon side of a call's time, from the passthrough's Server-Timing
+function daemonTiming(response) {
+    const timing = parseServerTiming(response.headers.get('Server-Timing'));
+    return timing ? `<div class="muted">${escapeHtml(formatCallTiming(timing))}</div>` : '';
+}
+
+async function invoke(event) {
+    event?.preventDefault();
+    if (!serviceName || !methodName) return;
+    let params;
+    try {
+        params = currentParams();
+    } catch (error) {
+        toast(t('explorer.invalid_json', { error: error.message }), 'error', { record: false });
+        return;
+    }
+
+    const send = document.getElementById('send');
+    send.disabled = true;
+    const meta = document.getElementById('response-meta');
+    const output = document.getElementById('response');
+    meta.textContent = t('explorer.sending');
+    const started = performance.now();
+    try {
+        const response = await fetch(callUrl(), requestOptions(params, currentFiles()));
+        if (response.ok && response.headers.get('Content-Type') === 'application/octet-stream') {
+            const blob = await response.blob();
+            const elapsed = performance.now() - started;
+            meta.innerHTML = `<span class="status-badge running">${response.status}</span>
+                ${t('explorer.timing', { ms: elapsed.toFixed(1), size: formatBytes(blob.size) })}
+                ${daemonTiming(response)}`;
+            showDownload(blob, response);
+            remember({ service: serviceName, method: methodName, params, status: response.status, ms: elapsed });
+            send.disabled = false;
+            return;
+        }
+        const text = await response.text();
+        const elapsed = performance.now() - started;
+        let body = text;
+        let ok = response.ok;
+        try {
+            const result = JSON.parse(text);
+            ok = result.ok;
+            body = JSON.stringify(result.ok ? result.data : result.error, null, 2);
+        } catch {
+            // Not JSON; show it as is
+        }
+        meta.innerHTML = `<span class="status-badge ${ok ? 'running' : 'error'}">${response.status}</span>
+            ${t('explorer.timing', { ms: elapsed.toFixed(1), size: formatBytes(new Blob([text]).size) })}
+            ${daemonTiming(response)}`;
+        output.textContent = body;
+        remember({ service: serviceName, method: methodName, params, status: response.status, ms: elapsed });
+    } catch (error) {
+        meta.textContent = t('explorer.failed', { error: error.message });
+        output.textContent = '';
+    }
+    send.disabled = false;
+}
+
+function savedCalls() {
+    try {
+        return JSON.parse(localStorage.getItem(HISTORY_KEY)) || [];
+    } catch {
+        return [];
+    }
+}
+
+function remember(call) {
+    const calls = [{ ...call, at: Date.now() }, ...savedCalls()].slice(0, MAX_HISTORY);
+    localStorage.setItem(HISTORY_KEY, JSON.stringify(calls));
+    renderHistory();
+}
+
+function renderHistory() {
+    const calls = savedCalls();
+    document.getElementById('history').innerHTML = calls.length
+        ? `<table class="kv">${calls.map((call, i) => `
+            <tr class="history-row" onclick="replay(${i})">
+                <td>${new Date(call.at).toLocaleString()}</td>
+                <td><code>${escapeHtml(call.service)}.${escapeHtml(call.method)}</code></td>
+                <td>${call.status}</td>
+                <td>${call.ms.toFixed(1)} ms</td>
+            </tr>`).join('')}</table>`
+        : `<div class="muted">${t('explorer.no_history')}</div>`;
+}
+
+// Load a past call into the editor without sending it
+async function replay(index) {
+    const call = savedCalls()[index];
+    if (!call) return;
+    methodName = call.method;
+    document.getElementById('service').value = call.service;
+    await selectService(call.service);
+    if (methodName !== call.method) {
+        methodName = call.method;
+        document.getElementById('method').value = call.method;
+        selectMethod(call.method);
+    }
+    document.getElementById('params-json').value = JSON.stringify(call.params, null, 2);
+    if (mode === 'form') fillForm(call.params);
+}
+
+applyTheme(localStorage.getItem('fgp-theme'));
+loadPreferences();
+loadUiSettings();
+Promise.all([loadMessages(), namespacesReady]).then(() => {
+    renderHistory();
+    loadServices();
+});
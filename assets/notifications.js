@@ -0,0 +1,122 @@
+// Toasts, pushed service events and the notification history, on every page
+
+const NOTIFICATIONS_KEY = 'fgp-notifications';
+const MAX_NOTIFICATIONS = 50;
+
+// Event kinds that raise a notification
+const NOTIFY_KINDS = ['status_changed', 'restarted', 'config_drift', 'update_available'];
+
+function notifications() {
+    try {
+        return JSON.parse(localStorage.getItem(NOTIFICATIONS_KEY)) || [];
+    } catch {
+        return [];
+    }
+}
+
+function saveNotifications(list) {
+    localStorage.setItem(NOTIFICATIONS_KEY, JSON.stringify(list.slice(-MAX_NOTIFICATIONS)));
+    updateNotificationBadge();
+}
+
+function recordNotification(message, kind) {
+    saveNotifications([...notifications(), { message, kind, at: Date.now(), read: false }]);
+    if (document.getElementById('notification-panel')) renderNotificationPanel();
+}
+
+function singleton(id, className) {
+    let element = document.getElementById(id);
+    if (!element) {
+        element = document.createElement('div');
+        element.id = id;
+        element.className = className;
+        document.body.appendChild(element);
+    }
+    return element;
+}
+
+// Show a message without blocking; `kind` is info, success, warning or error
+//
+// Warnings and errors are also kept in the notification history.
+function toast(message, kind = 'info', { record = kind === 'warning' || kind === 'error' } = {}) {
+    const element = document.createElement('div');
+    element.className = `toast ${kind}`;
+    element.setAttribute('role', kind === 'error' ? 'alert' : 'status');
+    element.innerHTML = `
+        <span class="toast-message">${escapeHtml(message)}</span>
+        <button class="toast-close" aria-label="${t('common.dismiss')}">&times;</button>`;
+    element.querySelector('button').onclick = () => element.remove();
+    singleton('toasts', 'toasts').appendChild(element);
+    setTimeout(() => element.remove(), kind === 'error' ? 10000 : 5000);
+    if (record) recordNotification(message, kind);
+}
+
+function updateNotificationBadge() {
+    const button = document.getElementById('notifications-toggle');
+    if (!button) return;
+    const unread = notifications().filter(n => !n.read).length;
+    button.textContent = unread ? t('notifications.unread', { count: unread }) : t('notifications.title');
+}
+
+function renderNotificationPanel() {
+    const panel = document.getElementById('notification-panel');
+    const list = notifications().reverse();
+    const permission = 'Notification' in window && Notification.permission === 'default'
+        ? `<button class="btn btn-small" onclick="enableDesktopNotifications()">${t('notifications.desktop')}</button>`
+        : '';
+    panel.innerHTML = `
+        <div class="notification-header">
+            <strong>${t('notifications.title')}</strong>
+            <span>
+                ${permission}
+                <button class="btn btn-small" onclick="clearNotifications()">${t('common.clear')}</button>
+            </span>
+        </div>
+        ${list.length === 0 ? `<div class="empty-state">${t('notifications.empty')}</div>` : list.map(n => `
+            <div class="notification ${n.kind} ${n.read ? '' : 'unread'}">
+                <span class="notification-time">${new Date(n.at).toLocaleString()}</span>
+                <span>${escapeHtml(n.message)}</span>
+            </div>`).join('')}`;
+}
+
+function toggleNotifications() {
+    const open = document.getElementById('notification-panel');
+    if (open) {
+        open.remove();
+        return;
+    }
+    singleton('notification-panel', 'notification-panel');
+    renderNotificationPanel();
+    saveNotifications(notifications().map(n => ({ ...n, read: true })));
+}
+
+function clearNotifications() {
+    saveNotifications([]);
+    renderNotificationPanel();
+}
+
+async function enableDesktopNotifications() {
+    await Notification.requestPermission();
+    renderNotificationPanel();
+}
+
+function notifyEvent(event) {
+    if (!NOTIFY_KINDS.includes(event.kind)) return;
+    const recovered = event.kind === 'status_changed' && /to (running|healthy)$/.test(event.message);
+    const message = `${event.service}: ${event.message}`;
+    const kind = recovered ? 'success' : event.kind === 'update_available' ? 'info' : 'warning';
+    toast(message, kind, { record: true });
+    // Reach the user in another tab or window too
+    if (document.hidden && 'Notification' in window && Notification.permission === 'granted') {
+        new Notification(brandTitle(), { body: message, tag: `fgp-${event.id}` });
+    }
+}
+
+function watchEvents() {
+    if (!('EventSource' in window)) return;
+    const source = new EventSource(`${API_BASE}${apiPath('/events/stream')}`);
+    source.addEventListener('service', message => notifyEvent(JSON.parse(message.data)));
+}
+
+updateNotificationBadge();
+namespacesReady.then(watchEvents);
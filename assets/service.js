@@ -0,0 +1,337 @@
+const serviceName = decodeURIComponent(location.pathname.split('/').pop());
+const encodedName = encodeURIComponent(serviceName);
+document.getElementById('explorer-link').href = `/explorer?service=${encodedName}`;
+let activeTab = null;
+let tabTimer = null;
+let historyRange = '24h';
+let logSource = 'file';
+// The systemd unit or launch agent supervising the service, if any
+let serviceUnit = null;
+// The config file as last loaded or saved, for its hash
+let loadedConfig = null;
+
+const TABS = {
+    health: renderHealth,
+    logs: renderLogs,
+    metrics: renderMetrics,
+    events: renderEvents,
+    process: renderProcess,
+    annotations: renderAnnotationsTab,
+    config: renderConfig,
+};
+
+// Tabs that keep refreshing while shown
+const LIVE_TABS = { logs: 2000, health: 5000 };
+
+async function getJson(url, options) {
+    const response = await fetch(`${API_BASE}${url}`, options);
+    const result = await response.json();
+    if (!result.ok) throw new Error(result.error);
+    return result.data;
+}
+
+function setContent(html) {
+    document.getElementById('tab-content').innerHTML = html;
+}
+
+function showError(error) {
+    setContent(`<div class="empty-state">${escapeHtml(error.message)}</div>`);
+}
+
+function showTab(tab) {
+    activeTab = tab;
+    history.replaceState(null, '', `#${tab}`);
+    document.querySelectorAll('.tab').forEach(button =>
+        button.classList.toggle('active', button.dataset.tab === tab));
+    clearInterval(tabTimer);
+    setContent(`<div class="loading">${t('common.loading')}</div>`);
+    TABS[tab]();
+    if (LIVE_TABS[tab]) {
+        tabTimer = setInterval(() => activeTab === tab && TABS[tab](), LIVE_TABS[tab]);
+    }
+}
+
+async function renderHealth() {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/health/${encodedName}`)}`);
+        const result = await response.json();
+        if (!result.ok) throw new Error(result.error);
+        const timing = parseServerTiming(response.headers.get('Server-Timing'));
+        setContent(`
+            ${timing ? `<div class="muted">${t('timing.last_probe', { timing: escapeHtml(formatCallTiming(timing)) })}</div>` : ''}
+            <pre>${escapeHtml(JSON.stringify(result.data, null, 2))}</pre>
+        `);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function renderLogs() {
+    const previous = document.querySelector('.log');
+    const atBottom = !previous ||
+        previous.scrollTop + previous.clientHeight >= previous.scrollHeight - 10;
+    try {
+        const journal = serviceUnit && serviceUnit.manager === 'systemd';
+        const source = journal && logSource === 'journal' ? '&source=journal' : '';
+        const tail = await getJson(apiPath(`/services/${encodedName}/logs?lines=500${source}`));
+        const sources = journal ? ['file', 'journal'].map(name =>
+            `<button class="btn btn-small" onclick="logSource='${name}'; renderLogs()"
+                     ${name === logSource ? 'disabled' : ''}>${t(`logs.${name}`)}</button>`).join('') : '';
+        setContent(`
+            ${sources ? `<div class="range-picker">${sources}</div>` : ''}
+            <div class="muted">${escapeHtml(tail.path)}</div>
+            <pre class="log">${escapeHtml(tail.lines.join('\n'))}</pre>
+        `);
+        const log = document.querySelector('.log');
+        if (atBottom) log.scrollTop = log.scrollHeight;
+        else log.scrollTop = previous.scrollTop;
+    } catch (error) {
+        showError(error);
+    }
+}
+
+function renderChart(points) {
+    if (points.length === 0) return `<div class="empty-state">${t('metrics.no_history')}</div>`;
+    const width = 1000;
+    const height = 120;
+    const barWidth = width / points.length;
+    const bars = points.map((point, i) => {
+        const ratio = point.samples ? point.healthy / point.samples : 0;
+        const kind = ratio === 1 ? 'ok' : ratio > 0 ? 'partial' : 'down';
+        const barHeight = Math.max(ratio * height, 4);
+        const time = new Date(point.timestamp * 1000).toLocaleString();
+        return `<rect class="${kind}" x="${i * barWidth}" y="${height - barHeight}"
+                      width="${Math.max(barWidth - 1, 1)}" height="${barHeight}">
+                    <title>${time}: ${t('metrics.healthy_of', { healthy: point.healthy, samples: point.samples })}</title>
+                </rect>`;
+    }).join('');
+    return `<svg class="chart" viewBox="0 0 ${width} ${height}" preserveAspectRatio="none">${bars}</svg>`;
+}
+
+function renderMetricCharts(points) {
+    return metricSeries(points).map(([title, values, format]) => {
+        const chart = areaChart(values, 1000, 120, 'chart metric-chart');
+        if (!chart) return '';
+        const peak = Math.max(...values.filter(value => value != null));
+        return `<h3 class="chart-title">${title} <span class="muted">${t('metrics.peak', { value: format(peak) })}</span></h3>
+                ${chart}`;
+    }).join('');
+}
+
+async function renderMetrics() {
+    try {
+        const history = await getJson(apiPath(`/services/${encodedName}/history?range=${historyRange}`));
+        const samples = history.points.reduce((sum, point) => sum + point.samples, 0);
+        const healthy = history.points.reduce((sum, point) => sum + point.healthy, 0);
+        const availability = samples
+            ? t('metrics.availability', { percent: (100 * healthy / samples).toFixed(2), range: historyRange })
+            : t('metrics.no_samples');
+        const ranges = ['1h', '24h', '7d', '30d', '1y'].map(range =>
+            `<button class="btn btn-small" onclick="historyRange='${range}'; renderMetrics()"
+                     ${range === historyRange ? 'disabled' : ''}>${range}</button>`).join('');
+        setContent(`
+            <div class="range-picker">${ranges}</div>
+            <div class="muted">${availability}</div>
+            ${renderChart(history.points)}
+            ${renderMetricCharts(history.points)}
+        `);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function renderEvents() {
+    try {
+        const query = encodeURIComponent(`service = "${serviceName}"`);
+        const events = await getJson(apiPath(`/events?q=${query}`));
+        if (events.length === 0) {
+            setContent(`<div class="empty-state">${t('events.none')}</div>`);
+            return;
+        }
+        setContent(`<table class="kv">${events.reverse().map(event => `
+            <tr>
+                <td>${new Date(event.timestamp * 1000).toLocaleString()}</td>
+                <td>${escapeHtml(event.kind)}: ${escapeHtml(event.message)}</td>
+            </tr>`).join('')}</table>`);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+function row(key, value) {
+    return `<tr><td>${escapeHtml(key)}</td><td>${escapeHtml(value ?? '-')}</td></tr>`;
+}
+
+async function renderProcess() {
+    try {
+        const service = await fetchService();
+        if (!service) throw new Error(t('service.not_found', { name: serviceName }));
+        let health = {};
+        try {
+            health = await getJson(apiPath(`/health/${encodedName}`));
+        } catch (error) {
+            // Stopped or unreachable daemons still have their discovery info
+        }
+        // Plugins that can't be asked just offer nothing
+        const actions = await getJson(`/api/services/${encodedName}/actions`).catch(() => []);
+        const buttons = actions.map(action => `
+            <button class="btn btn-small" data-plugin="${escapeHtml(action.plugin)}" data-action="${escapeHtml(action.id)}"
+                    title="${escapeHtml(t('plugins.action_help', { plugin: action.plugin }))}"
+                    onclick="runPluginAction(this.dataset.plugin, this.dataset.action)">${escapeHtml(action.label)}</button>`).join('');
+        const scalars = Object.entries(health)
+            .filter(([, value]) => value === null || typeof value !== 'object')
+            .map(([key, value]) => row(key, String(value)));
+        setContent(`<table class="kv">
+            ${row(t('process.status'), service.status)}
+            ${row(t('process.version'), service.version)}
+            ${row(t('process.latest_version'), service.latest_version
+                && (service.outdated ? t('process.outdated', { version: service.latest_version }) : service.latest_version))}
+            ${row(t('process.uptime'), formatUptime(service.uptime_seconds))}
+            ${service.supervisor ? row(t('process.unit'), `${service.supervisor.unit}${service.supervisor.user && service.supervisor.manager === 'systemd' ? ' (--user)' : ''}`) : ''}
+            ${service.supervisor ? row(t('process.unit_state'), formatUnitState(service.supervisor)) : ''}
+            ${service.supervisor ? row(t('process.unit_enabled'), service.supervisor.enabled) : ''}
+            ${service.supervisor ? row(t('process.unit_pid'), service.supervisor.pid != null ? String(service.supervisor.pid) : null) : ''}
+            ${row(t('process.socket'), service.socket_path)}
+            ${row(t('process.protocol'), service.protocol_version)}
+            ${row(t('process.services_dir'), service.source)}
+            ${row(t('process.host'), service.host)}
+            ${row(t('process.probe_error'), service.probe_error)}
+            ${row(t('process.probe_timing'), formatCallTiming(service.probe_timing))}
+            ${service.capabilities ? row(t('process.capabilities'),
+                ['reload', 'metrics', 'log_streaming'].filter(name => service.capabilities[name])
+                    .map(name => t(`capability.${name}`)).join(', ') || '-') : ''}
+            ${service.capabilities ? row(t('process.methods'), service.capabilities.methods.join(', ') || '-') : ''}
+            ${scalars.join('')}
+        </table>
+        ${buttons ? `<h3>${t('plugins.actions')}</h3><div class="range-picker">${buttons}</div>` : ''}`);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function runPluginAction(plugin, action) {
+    try {
+        const result = await getJson(
+            `/api/services/${encodedName}/actions/${encodeURIComponent(plugin)}/${encodeURIComponent(action)}`,
+            { method: 'POST' });
+        toast(result.message, 'success');
+        renderProcess();
+    } catch (error) {
+        toast(t('plugins.action_failed', { error: error.message }), 'error');
+    }
+}
+
+async function renderConfig() {
+    try {
+        loadedConfig = await getJson(`/api/config/${encodedName}`);
+        setContent(`
+            <div class="muted">${escapeHtml(loadedConfig.path)}</div>
+            <form class="config-form" onsubmit="saveConfig(event)">
+                <textarea name="contents" spellcheck="false" rows="24">${escapeHtml(loadedConfig.contents)}</textarea>
+                <div class="range-picker">
+                    <button class="btn btn-small" type="submit">${t('common.save')}</button>
+                    <button class="btn btn-small" type="button" onclick="renderConfig()">${t('config.revert')}</button>
+                </div>
+            </form>
+            <div class="muted">${t('config.restart_hint')}</div>
+        `);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function saveConfig(event) {
+    event.preventDefault();
+    try {
+        loadedConfig = await getJson(`/api/config/${encodedName}`, {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ contents: event.target.contents.value, hash: loadedConfig.hash }),
+        });
+        toast(t('config.saved', { path: loadedConfig.path }), 'success');
+    } catch (error) {
+        toast(t('config.save_failed', { error: error.message }), 'error');
+    }
+}
+
+async function renderAnnotationsTab() {
+    try {
+        const annotations = await getJson(apiPath(`/services/${encodedName}/annotations`));
+        const rows = annotations.map(annotation => `
+            <tr>
+                <td>${escapeHtml(annotation.key)}</td>
+                <td>${escapeHtml(annotation.value)}</td>
+                <td><button class="btn btn-small"
+                            onclick="removeAnnotation('${escapeHtml(annotation.key)}')">${t('common.remove')}</button></td>
+            </tr>`).join('');
+        setContent(`
+            ${annotations.length ? `<table class="kv">${rows}</table>` : `<div class="muted">${t('annotations.none')}</div>`}
+            <form class="annotation-form" onsubmit="addAnnotation(event)">
+                <input name="key" placeholder="${t('annotations.key')}" required>
+                <textarea name="value" rows="1" placeholder="${t('annotations.value')}" required></textarea>
+                <button class="btn btn-small" type="submit">${t('common.save')}</button>
+            </form>
+        `);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function addAnnotation(event) {
+    event.preventDefault();
+    const form = event.target;
+    try {
+        await getJson(apiPath(`/services/${encodedName}/annotations`), {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ [form.key.value]: form.value.value }),
+        });
+        renderAnnotationsTab();
+    } catch (error) {
+        toast(t('annotations.save_failed', { error: error.message }), 'error');
+    }
+}
+
+async function removeAnnotation(key) {
+    try {
+        await getJson(apiPath(`/services/${encodedName}/annotations/${encodeURIComponent(key)}`),
+            { method: 'DELETE' });
+        renderAnnotationsTab();
+    } catch (error) {
+        toast(t('annotations.remove_failed', { error: error.message }), 'error');
+    }
+}
+
+async function fetchService() {
+    const query = encodeURIComponent(`name = "${serviceName}"`);
+    const services = await getJson(apiPath(`/services?q=${query}`));
+    return services[0];
+}
+
+async function updateHeader() {
+    try {
+        const service = await fetchService();
+        serviceUnit = service ? service.supervisor : null;
+        const badge = document.getElementById('service-status');
+        const status = service ? service.status : t('service.missing');
+        const statusClass = getStatusClass(status);
+        badge.className = `status-badge ${statusClass}`;
+        badge.innerHTML = `<span class="status-dot ${statusClass}"></span>${escapeHtml(status)}`;
+    } catch (error) {
+        console.error('Failed to fetch service:', error);
+    }
+}
+
+document.title = `${serviceName} - FGP Dashboard`;
+document.getElementById('service-name').textContent = serviceName;
+document.querySelectorAll('.tab').forEach(button =>
+    button.addEventListener('click', () => showTab(button.dataset.tab)));
+
+applyTheme(localStorage.getItem('fgp-theme'));
+loadPreferences();
+loadUiSettings();
+Promise.all([loadMessages(), namespacesReady]).then(() => {
+    updateHeader();
+    setInterval(updateHeader, 5000);
+    showTab(TABS[location.hash.slice(1)] ? location.hash.slice(1) : 'health');
+});
@@ -0,0 +1,148 @@
+// Events of every service on one time axis, newest first
+
+const RANGES = ['1h', '6h', '24h', '7d', '30d'];
+
+// Event kinds by category, so operator starts and stops filter together
+const CATEGORIES = {
+    status: ['status_changed'],
+    restarts: ['restarted'],
+    drift: ['config_drift'],
+    operator: ['started', 'stopped', 'cleaned_up', 'plugin_action', 'script', 'config_edited', 'chaos'],
+    upgrades: ['upgrade_progress', 'upgraded', 'upgrade_rolled_back', 'update_available'],
+};
+
+let range = new URLSearchParams(location.search).get('range') || '24h';
+let events = [];
+let rangeStart = 0;
+let rangeEnd = 0;
+const hiddenCategories = new Set();
+
+function category(kind) {
+    return Object.keys(CATEGORIES).find(key => CATEGORIES[key].includes(kind)) || 'other';
+}
+
+function renderControls() {
+    document.getElementById('ranges').innerHTML = RANGES.map(r =>
+        `<button class="btn btn-small" onclick="setRange('${r}')" ${r === range ? 'disabled' : ''}>${r}</button>`
+    ).join('');
+    document.getElementById('categories').innerHTML = Object.keys(CATEGORIES).map(key =>
+        `<button class="chip ${hiddenCategories.has(key) ? '' : 'active'}" onclick="toggleCategory('${key}')">
+            <span class="timeline-dot ${key}"></span>${t(`timeline.category.${key}`)}
+        </button>`
+    ).join('');
+}
+
+function setRange(r) {
+    range = r;
+    history.replaceState(null, '', `?range=${r}`);
+    renderControls();
+    fetchEvents();
+}
+
+function toggleCategory(key) {
+    if (hiddenCategories.has(key)) hiddenCategories.delete(key);
+    else hiddenCategories.add(key);
+    renderControls();
+    renderTimeline();
+}
+
+function shownEvents() {
+    const search = document.getElementById('search').value.trim().toLowerCase();
+    return events.filter(event =>
+        !hiddenCategories.has(category(event.kind)) &&
+        (event.service.toLowerCase().includes(search) || event.message.toLowerCase().includes(search)));
+}
+
+// A strip across the whole range with a tick per event
+function renderOverview(shown) {
+    const width = 1000;
+    const height = 28;
+    const span = Math.max(rangeEnd - rangeStart, 1);
+    const ticks = shown.map(event => {
+        const x = ((event.timestamp - rangeStart) / span) * width;
+        return `<rect class="${category(event.kind)}" x="${x.toFixed(1)}" y="4" width="2" height="${height - 8}"
+                      onclick="scrollToEvent(${event.id})">
+                    <title>${new Date(event.timestamp * 1000).toLocaleString()} ${escapeHtml(event.service)}: ${escapeHtml(event.message)}</title>
+                </rect>`;
+    }).join('');
+    document.getElementById('overview').innerHTML = `
+        <svg viewBox="0 0 ${width} ${height}" preserveAspectRatio="none">${ticks}</svg>
+        <div class="timeline-axis">
+            <span>${new Date(rangeStart * 1000).toLocaleString()}</span>
+            <span>${new Date(rangeEnd * 1000).toLocaleString()}</span>
+        </div>`;
+}
+
+function renderTimeline() {
+    const shown = shownEvents();
+    renderOverview(shown);
+
+    const timeline = document.getElementById('timeline');
+    if (shown.length === 0) {
+        timeline.innerHTML = `<div class="empty-state">${t('timeline.empty', { range })}</div>`;
+        return;
+    }
+
+    // Newest first, with a heading whenever the hour changes
+    let lastHour = null;
+    timeline.innerHTML = shown.slice().reverse().map(event => {
+        const time = new Date(event.timestamp * 1000);
+        const hour = new Date(time).setMinutes(0, 0, 0);
+        const heading = hour === lastHour ? '' :
+            `<div class="timeline-hour">${new Date(hour).toLocaleString([], {
+                weekday: 'short', month: 'short', day: 'numeric', hour: '2-digit', minute: '2-digit',
+            })}</div>`;
+        lastHour = hour;
+        return `${heading}
+            <div class="timeline-event" id="event-${event.id}" data-timestamp="${event.timestamp}">
+                <span class="timeline-time">${time.toLocaleTimeString()}</span>
+                <span class="timeline-dot ${category(event.kind)}" title="${escapeHtml(event.kind)}"></span>
+                <a class="service-name" href="/service/${encodeURIComponent(event.service)}">${escapeHtml(event.service)}</a>
+                <span class="timeline-message">${escapeHtml(event.message)}</span>
+            </div>`;
+    }).join('');
+}
+
+function scrollToEvent(id) {
+    const element = document.getElementById(`event-${id}`);
+    if (!element) return;
+    document.querySelectorAll('.timeline-event.selected').forEach(e => e.classList.remove('selected'));
+    element.classList.add('selected');
+    element.scrollIntoView({ block: 'center' });
+}
+
+// Scroll to the last event at or before a local time
+function jumpTo(value) {
+    if (!value) return;
+    const target = new Date(value).getTime() / 1000;
+    const event = shownEvents().filter(event => event.timestamp <= target).pop() || shownEvents()[0];
+    if (event) scrollToEvent(event.id);
+}
+
+async function fetchEvents() {
+    try {
+        const response = await fetch(`${API_BASE}${apiPath(`/events?since=${range}`)}`);
+        const result = await response.json();
+        if (!result.ok) throw new Error(result.error);
+        events = result.data;
+        rangeEnd = Math.floor(Date.now() / 1000);
+        rangeStart = rangeEnd - rangeSeconds(range);
+        renderTimeline();
+    } catch (error) {
+        document.getElementById('timeline').innerHTML =
+            `<div class="empty-state">${escapeHtml(error.message)}</div>`;
+    }
+}
+
+function rangeSeconds(r) {
+    const units = { h: 3600, d: 86400 };
+    return parseInt(r, 10) * units[r.slice(-1)];
+}
+
+applyTheme(localStorage.getItem('fgp-theme'));
+loadPreferences();
+loadUiSettings();
+Promise.all([loadMessages(), namespacesReady]).then(() => {
+    renderControls();
+    fetchEvents();
+});
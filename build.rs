@@ -0,0 +1,84 @@
+//! Embed build identity for `GET /api/version`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Version of the `fgp-daemon` package in Cargo.lock, with the git revision
+/// when it comes from git
+fn fgp_daemon_version() -> Option<String> {
+    let lock = fs::read_to_string("Cargo.lock").ok()?;
+    let package = lock
+        .split("[[package]]")
+        .find(|package| package.contains("name = \"fgp-daemon\""))?;
+
+    let field = |name: &str| {
+        package
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} = \"", name)))
+            .and_then(|rest| rest.strip_suffix('"'))
+            .map(|value| value.to_string())
+    };
+
+    let version = field("version")?;
+    match field("source").and_then(|source| source.rsplit_once('#').map(|(_, rev)| rev.to_string()))
+    {
+        Some(rev) => Some(format!("{} ({})", version, &rev[..rev.len().min(12)])),
+        None => Some(version),
+    }
+}
+
+fn main() {
+    // Honor SOURCE_DATE_EPOCH for reproducible builds
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=FGP_DASHBOARD_GIT_COMMIT={}",
+        git_commit().unwrap_or_else(|| "unknown".to_string())
+    );
+    println!(
+        "cargo:rustc-env=FGP_DASHBOARD_BUILD_TIMESTAMP={}",
+        timestamp
+    );
+    println!(
+        "cargo:rustc-env=FGP_DASHBOARD_FEATURES={}",
+        features.join(",")
+    );
+    println!(
+        "cargo:rustc-env=FGP_DASHBOARD_FGP_DAEMON_VERSION={}",
+        fgp_daemon_version().unwrap_or_else(|| "unknown".to_string())
+    );
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
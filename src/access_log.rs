@@ -0,0 +1,76 @@
+//! HTTP access log.
+//!
+//! With `--access-log`, every request is logged under the
+//! `fgp_dashboard::access` target, so it can be filtered separately, e.g.
+//! `RUST_LOG=fgp_dashboard=info,fgp_dashboard::access=off`. Secrets in
+//! query strings are masked (see [`redact`](crate::redact)). Each line names
+//! who made the request (`admin` or `ns:<namespace>`) and the start of the
+//! token's SHA-256, when there was one. The setting is picked up on config
+//! reload.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use crate::auth::{Principal, TokenId};
+use crate::logging::RequestId;
+use crate::state::AppState;
+
+/// Log one line per request
+pub async fn log(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.settings().access_log {
+        return next.run(req).await;
+    }
+
+    let method = req.method().clone();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| state.settings().redact.text(p.as_str()))
+        .unwrap_or_default();
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone())
+        .unwrap_or_default();
+
+    let started = Instant::now();
+    let response = next.run(req).await;
+    let principal = match response.extensions().get::<Principal>() {
+        Some(Principal::Admin) => "admin".to_string(),
+        Some(Principal::Namespace(name)) => format!("ns:{}", name),
+        None => "-".to_string(),
+    };
+    let token = response
+        .extensions()
+        .get::<TokenId>()
+        .map(|TokenId(id)| id.as_str())
+        .unwrap_or("-");
+
+    tracing::info!(
+        target: "fgp_dashboard::access",
+        %method,
+        %path,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+        %client,
+        %request_id,
+        %principal,
+        token,
+        "{} {} {}",
+        method,
+        path,
+        response.status().as_u16(),
+    );
+
+    response
+}
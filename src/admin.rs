@@ -0,0 +1,67 @@
+//! Administrative endpoints for the dashboard itself.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::state::AppState;
+
+/// Reload the config and log the outcome
+///
+/// Secret lookups may go over the network, so this runs off the async
+/// runtime.
+pub async fn reload(state: &AppState) -> anyhow::Result<Vec<&'static str>> {
+    let reloading = state.clone();
+    let restart_required = calls::blocking(move || reloading.reload()).await?;
+    tracing::info!("Configuration reloaded");
+    if !restart_required.is_empty() {
+        tracing::warn!(
+            "Changes to {} require a restart to take effect",
+            restart_required.join(", ")
+        );
+    }
+    Ok(restart_required)
+}
+
+/// Re-read the config file without restarting
+pub async fn reload_config(State(state): State<AppState>) -> impl IntoResponse {
+    match reload(&state).await {
+        Ok(restart_required) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({
+                "message": "Configuration reloaded",
+                "restart_required": restart_required,
+            })),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<serde_json::Value>::error(&format!("Failed to reload config: {:#}", e)),
+        ),
+    }
+}
+
+/// Reload the config whenever the process receives SIGHUP
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(state: AppState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading configuration");
+            if let Err(e) = reload(&state).await {
+                tracing::error!("Failed to reload config: {:#}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_state: AppState) {}
@@ -0,0 +1,160 @@
+//! Operator notes and annotations on services.
+//!
+//! Annotations are key/value strings attached to a service ("owner" =
+//! "team-infra"); the `note` key holds a free-form note and is shown as
+//! text rather than a tag. `GET /api/services/{service}/annotations` lists
+//! them, `PUT` merges an object of keys into them (a `null` value removes a
+//! key), and `DELETE .../annotations/{key}` removes one. `GET
+//! /api/annotations` returns every service's at once for the dashboard.
+//! Annotations are kept for services that are no longer installed, so they
+//! come back with the service.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::events;
+use crate::namespaces::{Scope, View};
+use crate::state::AppState;
+
+/// Key holding a service's free-form note
+pub const NOTE_KEY: &str = "note";
+
+/// Longest accepted key
+const MAX_KEY_LEN: usize = 64;
+
+/// Longest accepted value, enough for a short note
+const MAX_VALUE_LEN: usize = 4096;
+
+/// One annotation on a service
+#[derive(Clone, Serialize, TS)]
+pub struct Annotation {
+    pub key: String,
+    pub value: String,
+    #[ts(type = "number")]
+    pub updated_at: u64,
+}
+
+/// Check an annotation key and value
+pub fn validate(key: &str, value: Option<&str>) -> Result<(), String> {
+    if key.is_empty()
+        || key.len() > MAX_KEY_LEN
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+    {
+        return Err(format!(
+            "Invalid annotation key '{}': use up to {} letters, digits, '_', '-', '.' or '/'",
+            key, MAX_KEY_LEN
+        ));
+    }
+    if value.is_some_and(|value| value.len() > MAX_VALUE_LEN) {
+        return Err(format!(
+            "Annotation '{}' is longer than {} bytes",
+            key, MAX_VALUE_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Annotations on every service, by service name
+pub async fn all_annotations(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+) -> impl IntoResponse {
+    let view = View::of(&state.settings(), scope);
+    let db = state.db.clone();
+    match calls::blocking(move || db.all_annotations()).await {
+        Ok(mut annotations) => {
+            annotations.retain(|service, _| view.shows(service));
+            (StatusCode::OK, ApiResponse::success(annotations))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<BTreeMap<String, Vec<Annotation>>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Annotations on one service
+pub async fn list_annotations(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    let db = state.db.clone();
+    match calls::blocking(move || db.annotations(&service)).await {
+        Ok(annotations) => (StatusCode::OK, ApiResponse::success(annotations)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Vec<Annotation>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Set or remove annotations on a service, returning the result
+pub async fn update_annotations(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Json(changes): Json<BTreeMap<String, Option<String>>>,
+) -> impl IntoResponse {
+    if let Err(e) = changes
+        .iter()
+        .try_for_each(|(key, value)| validate(key, value.as_deref()))
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Vec<Annotation>>::error(&e),
+        );
+    }
+
+    let db = state.db.clone();
+    let result = calls::blocking(move || {
+        db.update_annotations(&service, &changes, events::now())?;
+        db.annotations(&service)
+    })
+    .await;
+    match result {
+        Ok(annotations) => (StatusCode::OK, ApiResponse::success(annotations)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Vec<Annotation>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Remove one annotation from a service
+pub async fn delete_annotation(
+    State(state): State<AppState>,
+    Path((service, key)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let db = state.db.clone();
+    let changes = BTreeMap::from([(key.clone(), None)]);
+    let target = service.clone();
+    match calls::blocking(move || db.update_annotations(&target, &changes, events::now())).await {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "Service '{}' has no annotation '{}'",
+                service, key
+            )),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({
+                "message": format!("Annotation '{}' removed from '{}'", key, service)
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<serde_json::Value>::error(&format!("{:#}", e)),
+        ),
+    }
+}
@@ -1,25 +1,107 @@
 //! REST API endpoints for the FGP Dashboard.
 
 use axum::{
-    extract::Path,
-    http::StatusCode,
-    response::{Html, IntoResponse, Json},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+use crate::breaker;
+use crate::calls::{self, BackendCall};
+use crate::capabilities::Capabilities;
+use crate::docker::{self, Lifecycle};
+use crate::etag;
+use crate::events;
+use crate::export::{self, Format};
+use crate::health_cache::{HealthCache, ProbeOptions, CACHE_AGE_HEADER};
+use crate::kubernetes;
+use crate::launchd;
+use crate::listing::{self, FieldsQuery, ServicesQuery};
+use crate::metrics;
+use crate::namespaces::{Scope, View};
+use crate::pins;
+use crate::plugins;
+use crate::pool;
+use crate::protocol;
+use crate::retry;
+use crate::rpc;
+use crate::sockets;
+use crate::state::{self, AppState, Settings};
+use crate::systemd::{self, Supervision};
+use crate::timing::CallTiming;
+use crate::transport;
 
 /// Service status information
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, TS)]
 pub struct ServiceInfo {
     pub name: String,
     pub status: String,
     pub version: Option<String>,
+    #[ts(type = "number | null")]
     pub uptime_seconds: Option<u64>,
+    /// Why the last health probe failed, if it did
+    pub probe_error: Option<String>,
+    /// Age of the cached health data when the scan ran
+    #[serde(skip)]
+    #[ts(skip)]
+    pub cache_age: Option<Duration>,
     pub socket_path: String,
+    /// Services directory the service was found in
+    pub source: String,
+    pub host: String,
+    pub labels: BTreeMap<String, String>,
+    /// Pinned to the top of the list
+    #[serde(default)]
+    pub pinned: bool,
+    /// The manifest changed on disk since the daemon started
+    #[serde(default)]
+    pub config_drift: bool,
+    /// Restarts seen since the dashboard started
+    #[serde(default)]
+    pub restarts: u64,
+    /// Round trip of the last successful health probe
+    #[serde(default)]
+    pub latency_ms: Option<f64>,
+    /// Where the time went in the last health probe that got an answer
+    #[serde(default)]
+    pub probe_timing: Option<CallTiming>,
+    /// CPU usage, if the daemon reports it in its health payload
+    #[serde(default)]
+    pub cpu_percent: Option<f64>,
+    /// Resident memory, if the daemon reports it in its health payload
+    #[serde(default)]
+    #[ts(type = "number | null")]
+    pub memory_bytes: Option<u64>,
+    /// What the daemon advertises, if it supports introspection
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
+    /// FGP protocol version the daemon reports speaking
+    #[serde(default)]
+    pub protocol_version: Option<String>,
+    /// Latest released version, if an update index or the manifest names one
+    #[serde(default)]
+    pub latest_version: Option<String>,
+    /// The daemon reports an older version than `latest_version`
+    #[serde(default)]
+    pub outdated: bool,
+    /// The unit supervising the daemon, if a service manager has one
+    #[serde(default)]
+    pub supervisor: Option<Supervision>,
+    /// The daemon's last health payload, for WASM checks
+    #[serde(skip)]
+    #[ts(skip)]
+    pub health: Option<serde_json::Value>,
 }
 
 /// API response wrapper
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, TS)]
 pub struct ApiResponse<T> {
     pub ok: bool,
     pub data: Option<T>,
@@ -44,17 +126,113 @@ impl<T: Serialize> ApiResponse<T> {
     }
 }
 
-/// List all installed services and their status
-pub async fn list_services() -> impl IntoResponse {
-    let services_dir = fgp_daemon::fgp_services_dir();
+/// Header carrying the number of services matching the filters
+const TOTAL_COUNT: &str = "x-total-count";
 
-    if !services_dir.exists() {
-        return ApiResponse::<Vec<ServiceInfo>>::success(vec![]);
+/// List all installed services and their status
+///
+/// The JSON response carries an ETag, so pollers can send `If-None-Match` and
+/// get a 304 when nothing changed. CSV and NDJSON are available for exports.
+pub async fn list_services(
+    State(state): State<AppState>,
+    Query(query): Query<ServicesQuery>,
+    scope: Option<Extension<Scope>>,
+    headers: HeaderMap,
+) -> Response {
+    let format = match Format::negotiate(&headers, query.format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<Vec<ServiceInfo>>::error(&e),
+            )
+                .into_response()
+        }
+    };
+
+    let snapshot = if query.fresh {
+        state
+            .scan(ProbeOptions {
+                fresh: true,
+                timeout: query.timeout_ms.map(Duration::from_millis),
+            })
+            .await
+    } else {
+        state.services().await
+    };
+
+    let view = View::of(&state.settings(), scope);
+    let mut services = snapshot.services.clone();
+    services.retain(|service| view.shows(&service.name));
+    pins::mark_pinned(&state, &mut services).await;
+
+    match query.apply(services) {
+        Ok(page) => {
+            let data = listing::select_fields(page.services, query.fields.as_deref());
+            let mut response = match format {
+                Format::Json => etag::json_response(
+                    &headers,
+                    &ApiResponse::success(&data).0,
+                    &listing::stable_fields(&data),
+                ),
+                other => export::render(other, &data),
+            };
+            response
+                .headers_mut()
+                .insert(TOTAL_COUNT, HeaderValue::from(page.total));
+            with_cache_age(response, Some(snapshot.age()))
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Vec<ServiceInfo>>::error(&e),
+        )
+            .into_response(),
     }
+}
 
+/// How long a health probe may take when no timeout is configured
+pub const DEFAULT_HEALTH_TIMEOUT_MS: u64 = 2000;
+
+/// A service found on disk, not yet probed
+struct Discovered {
+    name: String,
+    socket_path: PathBuf,
+    has_socket: bool,
+    source: String,
+    /// What runs it, when discovery already knows
+    supervisor: Option<Supervision>,
+}
+
+/// Sources of services found some other way than in a services dir
+pub const ADAPTER_SOURCES: &[&str] = &[docker::SOURCE, kubernetes::SOURCE];
+
+/// Whether a service was found some other way than in a services dir
+pub fn is_adapter_source(source: &str) -> bool {
+    ADAPTER_SOURCES.contains(&source) || source.starts_with(plugins::SOURCE_PREFIX)
+}
+
+/// Result of asking a daemon for its health
+#[derive(Clone)]
+pub enum HealthOutcome {
+    /// The daemon's health payload
+    Healthy(serde_json::Value),
+    /// The probe failed; `status` is what the service is reported as
+    Failed { status: &'static str, error: String },
+}
+
+/// List the services in the services directories
+///
+/// Directories are scanned in the configured order. If a service name
+/// appears in several of them, the first directory wins.
+fn discover_services(services_dirs: &[PathBuf]) -> Vec<Discovered> {
     let mut services = Vec::new();
+    let mut seen = HashSet::new();
+
+    for services_dir in services_dirs {
+        let Ok(entries) = fs::read_dir(services_dir) else {
+            continue;
+        };
 
-    if let Ok(entries) = fs::read_dir(&services_dir) {
         for entry in entries.flatten() {
             let path = entry.path();
             if !path.is_dir() {
@@ -66,401 +244,676 @@ pub async fn list_services() -> impl IntoResponse {
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown")
                 .to_string();
+            // Hidden directories hold the dashboard's upgrade scratch space
+            if name.starts_with('.') {
+                continue;
+            }
+
+            if !seen.insert(name.clone()) {
+                tracing::debug!(
+                    "Ignoring '{}' in {}: shadowed by an earlier services dir",
+                    name,
+                    services_dir.display()
+                );
+                continue;
+            }
+
+            let Some(socket_path) = state::socket_path(services_dir, &name) else {
+                continue;
+            };
+            services.push(Discovered {
+                has_socket: transport::endpoint_exists(&socket_path),
+                name,
+                socket_path,
+                source: services_dir.to_string_lossy().to_string(),
+                supervisor: None,
+            });
+        }
+    }
+
+    services
+}
+
+/// Add the services running in labelled containers, unless a services dir
+/// already has them
+fn discover_containers(services: &mut Vec<Discovered>) {
+    let containers = match docker::containers() {
+        Ok(containers) => containers,
+        Err(e) => {
+            tracing::debug!("Cannot list Docker containers: {}", e);
+            return;
+        }
+    };
+    for container in containers {
+        if services
+            .iter()
+            .any(|service| service.name == container.service)
+        {
+            tracing::debug!(
+                "Ignoring container {}: '{}' is installed in a services dir",
+                container.name,
+                container.service
+            );
+            continue;
+        }
+        services.push(Discovered {
+            has_socket: container.running && transport::endpoint_exists(&container.socket_path),
+            name: container.service,
+            socket_path: container.socket_path,
+            source: docker::SOURCE.to_string(),
+            supervisor: None,
+        });
+    }
+}
+
+/// Add the services running in pods matching the selector, unless a
+/// services dir or container already has them
+fn discover_pods(services: &mut Vec<Discovered>, scope: &kubernetes::Scope) {
+    let pods = match kubernetes::pods(scope) {
+        Ok(pods) => pods,
+        Err(e) => {
+            tracing::debug!("Cannot list Kubernetes pods: {}", e);
+            return;
+        }
+    };
+    for pod in pods {
+        if services.iter().any(|service| service.name == pod.service) {
+            tracing::debug!(
+                "Ignoring pod {}: '{}' is already installed",
+                pod.name,
+                pod.service
+            );
+            continue;
+        }
+        services.push(Discovered {
+            has_socket: pod.reachable && transport::endpoint_exists(&pod.socket_path),
+            name: pod.service,
+            socket_path: pod.socket_path,
+            source: kubernetes::SOURCE.to_string(),
+            supervisor: Some(pod.supervision),
+        });
+    }
+}
 
-            let socket_path = fgp_daemon::service_socket_path(&name);
-            let socket_str = socket_path.to_string_lossy().to_string();
-
-            let (status, version, uptime) = if socket_path.exists() {
-                match fgp_daemon::FgpClient::new(&socket_path) {
-                    Ok(client) => match client.health() {
-                        Ok(response) if response.ok => {
-                            let result = response.result.unwrap_or_default();
-                            let version = result["version"].as_str().map(|s| s.to_string());
-                            let uptime = result["uptime_seconds"].as_u64();
-                            let status = result["status"].as_str().unwrap_or("running").to_string();
-                            (status, version, uptime)
-                        }
-                        _ => ("not_responding".to_string(), None, None),
-                    },
-                    Err(_) => ("socket_error".to_string(), None, None),
+/// Add the services plugins find, unless a services dir or other source
+/// already has them
+fn discover_plugin_services(services: &mut Vec<Discovered>) {
+    for plugin in plugins::registry().iter() {
+        let found = match plugin.discover() {
+            Ok(found) => found,
+            Err(e) => {
+                tracing::debug!(
+                    "Plugin {} failed to discover services: {}",
+                    plugin.name(),
+                    e
+                );
+                continue;
+            }
+        };
+        for service in found {
+            if services.iter().any(|known| known.name == service.name) {
+                tracing::debug!(
+                    "Ignoring '{}' from plugin {}: already installed",
+                    service.name,
+                    plugin.name()
+                );
+                continue;
+            }
+            services.push(Discovered {
+                has_socket: transport::endpoint_exists(&service.socket_path),
+                name: service.name,
+                socket_path: service.socket_path,
+                source: format!("{}{}", plugins::SOURCE_PREFIX, plugin.name()),
+                supervisor: None,
+            });
+        }
+    }
+}
+
+/// Call a daemon's `health` method, retrying transient failures
+fn health_call(socket_path: &FsPath, retries: u32) -> (HealthOutcome, CallTiming) {
+    let _call = BackendCall::start(format!("health on {}", socket_path.display()));
+    let mut timing = CallTiming::default();
+    let result = retry::retry(
+        retries,
+        || {
+            timing.attempts += 1;
+            let started = Instant::now();
+            let client = pool::checkout(socket_path);
+            timing.connected(started);
+            let client = client.map_err(|e| ("socket_error", e))?;
+            timing.reused = client.reused;
+            let started = Instant::now();
+            let response = client.health();
+            timing.answered(started);
+            match response {
+                Ok(response) => Ok(response),
+                Err(e) => {
+                    client.discard();
+                    Err(("not_responding", anyhow::Error::from(e)))
                 }
-            } else {
-                ("stopped".to_string(), None, None)
+            }
+        },
+        |(_, e)| retry::is_transient(e),
+    );
+    let outcome = match result {
+        Ok(response) if response.ok => HealthOutcome::Healthy(response.result.unwrap_or_default()),
+        Ok(response) => HealthOutcome::Failed {
+            status: "not_responding",
+            error: response.error.unwrap_or_default(),
+        },
+        Err((_, e)) if transport::is_refused(socket_path, &e) => HealthOutcome::Failed {
+            status: "stopped",
+            error: format!("{}: nothing is listening", e),
+        },
+        Err((_, e)) if sockets::is_stale(socket_path) => HealthOutcome::Failed {
+            status: sockets::STALE,
+            error: format!(
+                "{}: nothing is listening and the daemon is gone; clean up the socket to start it again",
+                e
+            ),
+        },
+        Err((status, e)) => match sockets::diagnose(socket_path, &e) {
+            Some(diagnosis) => HealthOutcome::Failed {
+                status: sockets::PERMISSION_DENIED,
+                error: format!("{}: {}", e, diagnosis),
+            },
+            None => HealthOutcome::Failed {
+                status,
+                error: e.to_string(),
+            },
+        },
+    };
+    metrics::record_socket_call(matches!(outcome, HealthOutcome::Healthy(_)));
+    (outcome, timing)
+}
+
+/// Probe a daemon's health off the async runtime, giving up after `timeout`
+///
+/// Daemons that keep timing out are skipped for a while; see [`breaker`].
+/// Timeouts only count towards that when `count_timeouts` is set, so a
+/// caller asking for a very short timeout can't trip the breaker for
+/// everyone else. The call's timing is returned unless it was skipped or
+/// timed out.
+pub async fn check_health(
+    socket_path: PathBuf,
+    retries: u32,
+    timeout: Duration,
+    count_timeouts: bool,
+) -> (HealthOutcome, Option<CallTiming>) {
+    if let Err(retry_in) = breaker::allow(&socket_path) {
+        let outcome = HealthOutcome::Failed {
+            status: "not_responding",
+            error: format!(
+                "probes skipped after repeated timeouts, retrying in {}s",
+                retry_in.as_secs().max(1)
+            ),
+        };
+        return (outcome, None);
+    }
+
+    let path = socket_path.clone();
+    match tokio::time::timeout(
+        timeout,
+        calls::blocking(move || health_call(&path, retries)),
+    )
+    .await
+    {
+        Ok((outcome, timing)) => {
+            breaker::record_answer(&socket_path);
+            (outcome, Some(timing))
+        }
+        Err(_) => {
+            if count_timeouts {
+                breaker::record_timeout(&socket_path);
+            }
+            metrics::record_socket_call(false);
+            let outcome = HealthOutcome::Failed {
+                status: "not_responding",
+                error: format!("health probe timed out after {}ms", timeout.as_millis()),
             };
+            (outcome, None)
+        }
+    }
+}
 
-            services.push(ServiceInfo {
-                name,
+/// Scan the services directories and probe every service's health
+///
+/// Probes run concurrently, each with its own timeout, so one hung daemon
+/// only affects its own entry. Recent results are reused from `cache`
+/// unless `options` asks for fresh ones.
+pub async fn scan_services(
+    settings: &Settings,
+    cache: &HealthCache,
+    options: ProbeOptions,
+) -> Vec<ServiceInfo> {
+    let services_dirs = settings.services_dirs.clone();
+    let docker = settings.docker;
+    let kubernetes = settings.kubernetes.clone();
+    let namespace = settings.kubernetes_namespace.clone();
+    let discovered = calls::blocking(move || {
+        let mut discovered = discover_services(&services_dirs);
+        if docker {
+            discover_containers(&mut discovered);
+        }
+        if let Some(selector) = &kubernetes {
+            let scope = kubernetes::Scope {
+                selector,
+                namespace: namespace.as_deref(),
+            };
+            discover_pods(&mut discovered, &scope);
+        }
+        discover_plugin_services(&mut discovered);
+        discovered
+    })
+    .await;
+
+    let probes = discovered.iter().map(|service| async move {
+        if service.has_socket {
+            Some(
+                cache
+                    .probe(&service.name, &service.socket_path, settings, options)
+                    .await,
+            )
+        } else {
+            None
+        }
+    });
+    let probes = futures::future::join_all(probes).await;
+
+    let host = &settings.host;
+    let mut services: Vec<ServiceInfo> = discovered
+        .into_iter()
+        .zip(probes)
+        .map(|(service, probe)| {
+            let protocol_version = match &probe {
+                Some((HealthOutcome::Healthy(result), _)) => protocol::reported_version(result),
+                _ => None,
+            };
+            let (status, version, uptime, probe_error) = match &probe {
+                None => ("stopped".to_string(), None, None, None),
+                Some((HealthOutcome::Healthy(result), _)) => {
+                    let mismatch = protocol_version.as_deref().and_then(protocol::mismatch);
+                    (
+                        match mismatch {
+                            Some(_) => protocol::INCOMPATIBLE.to_string(),
+                            None => result["status"].as_str().unwrap_or("running").to_string(),
+                        },
+                        result["version"].as_str().map(|s| s.to_string()),
+                        result["uptime_seconds"].as_u64(),
+                        mismatch,
+                    )
+                }
+                Some((HealthOutcome::Failed { status, error }, _)) => {
+                    (status.to_string(), None, None, Some(error.clone()))
+                }
+            };
+            let (latency_ms, cpu_percent, memory_bytes) = match &probe {
+                Some((HealthOutcome::Healthy(result), _)) => (
+                    cache
+                        .latency(&service.socket_path)
+                        .map(|latency| latency.as_secs_f64() * 1000.0),
+                    result["cpu_percent"].as_f64(),
+                    result["memory_bytes"].as_u64(),
+                ),
+                _ => (None, None, None),
+            };
+            let health = match &probe {
+                Some((HealthOutcome::Healthy(result), _)) => Some(result.clone()),
+                _ => None,
+            };
+            ServiceInfo {
+                name: service.name,
                 status,
                 version,
                 uptime_seconds: uptime,
-                socket_path: socket_str,
-            });
-        }
-    }
+                probe_error,
+                cache_age: probe.as_ref().map(|(_, age)| *age),
+                socket_path: service.socket_path.to_string_lossy().to_string(),
+                source: service.source,
+                host: host.name.clone(),
+                labels: host.labels.clone(),
+                pinned: false,
+                config_drift: false,
+                restarts: 0,
+                latency_ms,
+                probe_timing: probe.as_ref().and(cache.timing(&service.socket_path)),
+                cpu_percent,
+                memory_bytes,
+                capabilities: None,
+                protocol_version,
+                latest_version: None,
+                outdated: false,
+                supervisor: service.supervisor,
+                health,
+            }
+        })
+        .collect();
 
     // Sort by name
     services.sort_by(|a, b| a.name.cmp(&b.name));
 
-    ApiResponse::success(services)
+    if settings.systemd || settings.launchd {
+        let (systemd, launchd) = (settings.systemd, settings.launchd);
+        services = calls::blocking(move || {
+            if systemd {
+                systemd::annotate(&mut services);
+            }
+            if launchd {
+                launchd::annotate(&mut services);
+            }
+            services
+        })
+        .await;
+    }
+
+    services
+}
+
+/// Insert the cache age header
+fn with_cache_age(mut response: Response, age: Option<Duration>) -> Response {
+    if let Some(age) = age {
+        response
+            .headers_mut()
+            .insert(CACHE_AGE_HEADER, HeaderValue::from(age.as_millis() as u64));
+    }
+    response
 }
 
 /// Get detailed health info for a specific service
-pub async fn service_health(Path(service): Path<String>) -> impl IntoResponse {
-    let socket_path = fgp_daemon::service_socket_path(&service);
+pub async fn service_health(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Query(query): Query<FieldsQuery>,
+) -> Response {
+    if let Some(fleet) = &state.demo {
+        return match fleet.health(&service) {
+            Ok(result) => (
+                StatusCode::OK,
+                ApiResponse::success(listing::select_fields(result, query.fields.as_deref())),
+            )
+                .into_response(),
+            Err(e) => (
+                StatusCode::NOT_FOUND,
+                ApiResponse::<serde_json::Value>::error(&e),
+            )
+                .into_response(),
+        };
+    }
+    let Some(socket_path) = state.settings().socket_path(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Service '{}' not found", service)),
+        )
+            .into_response();
+    };
 
-    if !socket_path.exists() {
+    if !transport::endpoint_exists(&socket_path) {
         return (
             StatusCode::NOT_FOUND,
             ApiResponse::<serde_json::Value>::error(&format!(
                 "Service '{}' is not running",
                 service
             )),
-        );
+        )
+            .into_response();
     }
 
-    match fgp_daemon::FgpClient::new(&socket_path) {
-        Ok(client) => match client.health() {
-            Ok(response) if response.ok => {
-                let result = response.result.unwrap_or_default();
-                (StatusCode::OK, ApiResponse::success(result))
-            }
-            Ok(response) => {
-                let error = response.error.map(|e| e.message).unwrap_or_default();
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    ApiResponse::<serde_json::Value>::error(&error),
-                )
-            }
-            Err(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                ApiResponse::<serde_json::Value>::error(&e.to_string()),
-            ),
-        },
-        Err(e) => (
+    let (outcome, age) = state
+        .health_cache
+        .probe(
+            &service,
+            &socket_path,
+            &state.settings(),
+            ProbeOptions {
+                fresh: query.fresh,
+                timeout: query.timeout_ms.map(Duration::from_millis),
+            },
+        )
+        .await;
+
+    let response = match outcome {
+        HealthOutcome::Healthy(mut result) => {
+            state.settings().redact.json(&mut result);
+            (
+                StatusCode::OK,
+                ApiResponse::success(listing::select_fields(result, query.fields.as_deref())),
+            )
+        }
+        HealthOutcome::Failed { error, .. } => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            ApiResponse::<serde_json::Value>::error(&e.to_string()),
+            ApiResponse::<serde_json::Value>::error(&error),
         ),
+    };
+    let response = with_cache_age(response.into_response(), Some(age));
+    match state.health_cache.timing(&socket_path) {
+        Some(timing) => rpc::with_timing(response, &timing),
+        None => response,
     }
 }
 
-/// Start a service
-pub async fn start_service(Path(service): Path<String>) -> impl IntoResponse {
-    match fgp_daemon::start_service(&service) {
-        Ok(()) => (
-            StatusCode::OK,
-            ApiResponse::success(serde_json::json!({
-                "message": format!("Service '{}' started", service)
-            })),
-        ),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            ApiResponse::<serde_json::Value>::error(&e.to_string()),
-        ),
+/// Query a service's health over its socket
+pub fn probe_health(socket_path: &FsPath, retries: u32) -> Result<serde_json::Value, String> {
+    match health_call(socket_path, retries).0 {
+        HealthOutcome::Healthy(result) => Ok(result),
+        HealthOutcome::Failed { error, .. } => Err(error),
     }
 }
 
-/// Stop a service
-pub async fn stop_service(Path(service): Path<String>) -> impl IntoResponse {
-    match fgp_daemon::stop_service(&service) {
-        Ok(()) => (
-            StatusCode::OK,
-            ApiResponse::success(serde_json::json!({
-                "message": format!("Service '{}' stopped", service)
-            })),
-        ),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            ApiResponse::<serde_json::Value>::error(&e.to_string()),
-        ),
-    }
+/// What starts and stops a service
+enum Supervisor {
+    Container,
+    Pod,
+    Unit(Supervision),
+    Daemon,
 }
 
-/// Serve the static HTML dashboard
-pub async fn serve_dashboard() -> Html<&'static str> {
-    Html(DASHBOARD_HTML)
+/// What starts and stops a service, going by the last scan
+async fn supervisor_of(state: &AppState, service: &str) -> Supervisor {
+    let settings = state.settings();
+    let snapshot = state.services().await;
+    let Some(info) = snapshot.services.iter().find(|info| info.name == service) else {
+        return Supervisor::Daemon;
+    };
+    if settings.docker && info.source == docker::SOURCE {
+        return Supervisor::Container;
+    }
+    if settings.kubernetes.is_some() && info.source == kubernetes::SOURCE {
+        return Supervisor::Pod;
+    }
+    match &info.supervisor {
+        Some(unit)
+            if (settings.systemd && unit.manager == systemd::MANAGER)
+                || (settings.launchd && unit.manager == launchd::MANAGER) =>
+        {
+            Supervisor::Unit(unit.clone())
+        }
+        _ => Supervisor::Daemon,
+    }
 }
 
-/// Embedded HTML dashboard
-const DASHBOARD_HTML: &str = r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>FGP Dashboard</title>
-    <style>
-        * {
-            box-sizing: border-box;
-            margin: 0;
-            padding: 0;
-        }
-        body {
-            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, sans-serif;
-            background: #0f0f0f;
-            color: #e0e0e0;
-            min-height: 100vh;
-            padding: 2rem;
-        }
-        .container {
-            max-width: 1200px;
-            margin: 0 auto;
-        }
-        header {
-            display: flex;
-            justify-content: space-between;
-            align-items: center;
-            margin-bottom: 2rem;
-            padding-bottom: 1rem;
-            border-bottom: 1px solid #333;
-        }
-        h1 {
-            font-size: 1.5rem;
-            font-weight: 600;
-            color: #fff;
-        }
-        .refresh-info {
-            font-size: 0.85rem;
-            color: #666;
-        }
-        .services-grid {
-            display: grid;
-            grid-template-columns: repeat(auto-fill, minmax(300px, 1fr));
-            gap: 1rem;
-        }
-        .service-card {
-            background: #1a1a1a;
-            border: 1px solid #333;
-            border-radius: 8px;
-            padding: 1.25rem;
-            transition: border-color 0.2s;
-        }
-        .service-card:hover {
-            border-color: #555;
-        }
-        .service-header {
-            display: flex;
-            justify-content: space-between;
-            align-items: center;
-            margin-bottom: 1rem;
-        }
-        .service-name {
-            font-weight: 600;
-            font-size: 1.1rem;
-            color: #fff;
-        }
-        .status-badge {
-            display: inline-flex;
-            align-items: center;
-            gap: 0.5rem;
-            padding: 0.25rem 0.75rem;
-            border-radius: 9999px;
-            font-size: 0.8rem;
-            font-weight: 500;
-        }
-        .status-badge.running {
-            background: rgba(34, 197, 94, 0.15);
-            color: #22c55e;
-        }
-        .status-badge.stopped {
-            background: rgba(100, 100, 100, 0.15);
-            color: #888;
-        }
-        .status-badge.error {
-            background: rgba(239, 68, 68, 0.15);
-            color: #ef4444;
-        }
-        .status-badge.unhealthy {
-            background: rgba(245, 158, 11, 0.15);
-            color: #f59e0b;
-        }
-        .status-dot {
-            width: 8px;
-            height: 8px;
-            border-radius: 50%;
-            animation: pulse 2s infinite;
-        }
-        .status-dot.running { background: #22c55e; }
-        .status-dot.stopped { background: #888; animation: none; }
-        .status-dot.error { background: #ef4444; }
-        .status-dot.unhealthy { background: #f59e0b; }
-        @keyframes pulse {
-            0%, 100% { opacity: 1; }
-            50% { opacity: 0.5; }
-        }
-        .service-details {
-            font-size: 0.85rem;
-            color: #888;
-            margin-bottom: 1rem;
-        }
-        .service-details span {
-            display: block;
-            margin-bottom: 0.25rem;
-        }
-        .service-actions {
-            display: flex;
-            gap: 0.5rem;
-        }
-        .btn {
-            flex: 1;
-            padding: 0.5rem 1rem;
-            border: none;
-            border-radius: 6px;
-            font-size: 0.85rem;
-            font-weight: 500;
-            cursor: pointer;
-            transition: all 0.2s;
-        }
-        .btn:disabled {
-            opacity: 0.5;
-            cursor: not-allowed;
-        }
-        .btn-start {
-            background: #22c55e;
-            color: #000;
-        }
-        .btn-start:hover:not(:disabled) {
-            background: #16a34a;
-        }
-        .btn-stop {
-            background: #ef4444;
-            color: #fff;
-        }
-        .btn-stop:hover:not(:disabled) {
-            background: #dc2626;
-        }
-        .loading {
-            text-align: center;
-            padding: 3rem;
-            color: #666;
-        }
-        .empty-state {
-            text-align: center;
-            padding: 3rem;
-            color: #666;
-        }
-    </style>
-</head>
-<body>
-    <div class="container">
-        <header>
-            <h1>FGP Dashboard</h1>
-            <span class="refresh-info" id="refresh-info">Refreshing...</span>
-        </header>
-        <div id="app" class="services-grid">
-            <div class="loading">Loading services...</div>
-        </div>
-    </div>
-    <script>
-        const API_BASE = '';
-        let services = [];
-
-        function formatUptime(seconds) {
-            if (!seconds) return '-';
-            if (seconds < 60) return `${seconds}s`;
-            if (seconds < 3600) return `${Math.floor(seconds / 60)}m ${seconds % 60}s`;
-            if (seconds < 86400) return `${Math.floor(seconds / 3600)}h ${Math.floor((seconds % 3600) / 60)}m`;
-            return `${Math.floor(seconds / 86400)}d ${Math.floor((seconds % 86400) / 3600)}h`;
-        }
+/// The systemd unit or launch agent a service was last seen supervised by
+pub async fn unit_of(state: &AppState, service: &str) -> Option<Supervision> {
+    match supervisor_of(state, service).await {
+        Supervisor::Unit(unit) => Some(unit),
+        _ => None,
+    }
+}
 
-        function getStatusClass(status) {
-            if (status === 'running' || status === 'healthy') return 'running';
-            if (status === 'stopped') return 'stopped';
-            if (status === 'unhealthy' || status === 'degraded') return 'unhealthy';
-            return 'error';
+/// Start, stop or restart a service through whatever runs it: its
+/// container or pod, its systemd unit or launch agent, or `fgp_daemon`
+async fn supervise(state: &AppState, service: &str, lifecycle: Lifecycle) -> Result<(), String> {
+    if let Some(fleet) = &state.demo {
+        return fleet.control(service, lifecycle);
+    }
+    let name = service.to_string();
+    match supervisor_of(state, service).await {
+        Supervisor::Container => {
+            calls::blocking(move || {
+                docker::control(&name, lifecycle)
+                    .unwrap_or_else(|| Err(format!("No container runs '{}' any more", name)))
+            })
+            .await
+        }
+        Supervisor::Pod => {
+            let settings = state.settings();
+            calls::blocking(move || {
+                let scope = kubernetes::Scope {
+                    selector: settings.kubernetes.as_deref().unwrap_or_default(),
+                    namespace: settings.kubernetes_namespace.as_deref(),
+                };
+                kubernetes::control(&scope, &name, lifecycle)
+                    .unwrap_or_else(|| Err(format!("No pod runs '{}' any more", name)))
+            })
+            .await
+        }
+        Supervisor::Unit(unit) => {
+            calls::blocking(move || match unit.manager.as_str() {
+                launchd::MANAGER => launchd::control(&unit, lifecycle),
+                _ => systemd::control(&unit, lifecycle),
+            })
+            .await
+        }
+        Supervisor::Daemon => {
+            calls::blocking(move || {
+                match lifecycle {
+                    Lifecycle::Start => fgp_daemon::start_service(&name),
+                    Lifecycle::Stop => fgp_daemon::stop_service(&name),
+                    Lifecycle::Restart => fgp_daemon::stop_service(&name)
+                        .and_then(|_| fgp_daemon::start_service(&name)),
+                }
+                .map_err(|e| e.to_string())
+            })
+            .await
         }
+    }
+}
 
-        function renderServices() {
-            const app = document.getElementById('app');
+/// Start a service and publish the event
+///
+/// Start and stop go through `fgp_daemon`, which always uses its default
+/// services layout, even when `--services-dir` points elsewhere. Services
+/// found in containers start and stop their container instead, those in
+/// pods are deleted, and those with a systemd unit or launch agent start and
+/// stop their unit or agent.
+pub async fn start(state: &AppState, service: &str) -> Result<String, String> {
+    supervise(state, service, Lifecycle::Start).await?;
+
+    let message = format!("Service '{}' started", service);
+    state
+        .events
+        .publish(service, events::STARTED, message.clone());
+    state.store.request_refresh();
+    Ok(message)
+}
 
-            if (services.length === 0) {
-                app.innerHTML = '<div class="empty-state">No services installed</div>';
-                return;
-            }
+/// Stop a service and publish the event
+///
+/// Daemons speaking an incompatible protocol are left alone.
+pub async fn stop(state: &AppState, service: &str) -> Result<String, String> {
+    protocol::check(state, service).await?;
+    supervise(state, service, Lifecycle::Stop).await?;
+
+    let message = format!("Service '{}' stopped", service);
+    state
+        .events
+        .publish(service, events::STOPPED, message.clone());
+    state.store.request_refresh();
+    Ok(message)
+}
 
-            app.innerHTML = services.map(service => {
-                const statusClass = getStatusClass(service.status);
-                const isRunning = statusClass === 'running';
-
-                return `
-                    <div class="service-card">
-                        <div class="service-header">
-                            <span class="service-name">${service.name}</span>
-                            <span class="status-badge ${statusClass}">
-                                <span class="status-dot ${statusClass}"></span>
-                                ${service.status}
-                            </span>
-                        </div>
-                        <div class="service-details">
-                            <span>Version: ${service.version || '-'}</span>
-                            <span>Uptime: ${formatUptime(service.uptime_seconds)}</span>
-                        </div>
-                        <div class="service-actions">
-                            <button class="btn btn-start"
-                                    onclick="startService('${service.name}')"
-                                    ${isRunning ? 'disabled' : ''}>
-                                Start
-                            </button>
-                            <button class="btn btn-stop"
-                                    onclick="stopService('${service.name}')"
-                                    ${!isRunning ? 'disabled' : ''}>
-                                Stop
-                            </button>
-                        </div>
-                    </div>
-                `;
-            }).join('');
-        }
+/// Restart a service, publishing the stop and start
+///
+/// Containers, pods, systemd units and launch agents are restarted in one
+/// step.
+pub async fn restart(state: &AppState, service: &str) -> Result<String, String> {
+    if matches!(supervisor_of(state, service).await, Supervisor::Daemon) {
+        stop(state, service).await?;
+        start(state, service).await?;
+        return Ok(format!("Service '{}' restarted", service));
+    }
 
-        async function fetchServices() {
-            try {
-                const response = await fetch(`${API_BASE}/api/services`);
-                const result = await response.json();
-                if (result.ok) {
-                    services = result.data;
-                    renderServices();
-                }
-            } catch (error) {
-                console.error('Failed to fetch services:', error);
-            }
-            updateRefreshInfo();
-        }
+    protocol::check(state, service).await?;
+    supervise(state, service, Lifecycle::Restart).await?;
+    let message = format!("Service '{}' restarted", service);
+    state
+        .events
+        .publish(service, events::STARTED, message.clone());
+    state.store.request_refresh();
+    Ok(message)
+}
 
-        async function startService(name) {
-            try {
-                const response = await fetch(`${API_BASE}/api/start/${name}`, { method: 'POST' });
-                const result = await response.json();
-                if (!result.ok) {
-                    alert(`Failed to start ${name}: ${result.error}`);
-                }
-                await fetchServices();
-            } catch (error) {
-                alert(`Failed to start ${name}: ${error.message}`);
-            }
-        }
+/// Remove a stale service's socket and publish the event
+pub async fn cleanup(state: &AppState, service: &str) -> Result<String, String> {
+    let socket_path = state
+        .settings()
+        .socket_path(service)
+        .ok_or_else(|| format!("Service '{}' not found", service))?;
+    calls::blocking(move || sockets::remove_stale(&socket_path)).await?;
+
+    let message = format!("Removed the stale socket of '{}'", service);
+    state
+        .events
+        .publish(service, events::CLEANED_UP, message.clone());
+    state.store.request_refresh();
+    Ok(message)
+}
 
-        async function stopService(name) {
-            try {
-                const response = await fetch(`${API_BASE}/api/stop/${name}`, { method: 'POST' });
-                const result = await response.json();
-                if (!result.ok) {
-                    alert(`Failed to stop ${name}: ${result.error}`);
-                }
-                await fetchServices();
-            } catch (error) {
-                alert(`Failed to stop ${name}: ${error.message}`);
-            }
-        }
+/// Refusals to act on an incompatible daemon get a 409
+fn control_response(result: Result<String, String>) -> impl IntoResponse {
+    match result {
+        Ok(message) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({ "message": message })),
+        ),
+        Err(e) if protocol::is_refusal(&e) => (
+            StatusCode::CONFLICT,
+            ApiResponse::<serde_json::Value>::error(&e),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<serde_json::Value>::error(&e),
+        ),
+    }
+}
 
-        function updateRefreshInfo() {
-            const now = new Date().toLocaleTimeString();
-            document.getElementById('refresh-info').textContent = `Last updated: ${now}`;
-        }
+/// Start a service
+pub async fn start_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    control_response(start(&state, &service).await)
+}
 
-        // Initial fetch
-        fetchServices();
+/// Remove a stale service's socket
+///
+/// Services whose socket is live get a 409.
+pub async fn cleanup_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    if !state::is_service_name(&service) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Service '{}' not found", service)),
+        );
+    }
+    match cleanup(&state, &service).await {
+        Ok(message) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({ "message": message })),
+        ),
+        Err(e) => (
+            StatusCode::CONFLICT,
+            ApiResponse::<serde_json::Value>::error(&e),
+        ),
+    }
+}
 
-        // Auto-refresh every 5 seconds
-        setInterval(fetchServices, 5000);
-    </script>
-</body>
-</html>
-"#;
+/// Stop a service
+pub async fn stop_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    control_response(stop(&state, &service).await)
+}
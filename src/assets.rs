@@ -0,0 +1,174 @@
+//! The web UI's static assets.
+//!
+//! `assets/` is embedded into the binary at build time (and read from disk
+//! in debug builds, so UI changes show up on reload). `/` serves
+//! `index.html`, `/service/<name>` the detail page `service.html`,
+//! `/timeline` the event timeline `timeline.html`, `/explorer` the RPC
+//! explorer `explorer.html`, and `/assets/<path>` everything else. With
+//! `--assets-dir`, files in that directory are served in preference to the
+//! embedded ones, so a deployment can replace or add assets without
+//! rebuilding.
+//!
+//! Every asset carries an ETag. Pages must be revalidated on each load;
+//! other assets may be cached for a few minutes.
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::{Component, Path};
+
+use crate::api::ApiResponse;
+use crate::etag;
+use crate::state::AppState;
+
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+struct Embedded;
+
+const INDEX: &str = "index.html";
+
+/// Page for a single service, which reads the name from the URL
+const SERVICE_PAGE: &str = "service.html";
+
+/// Events of all services on one time axis
+const TIMELINE_PAGE: &str = "timeline.html";
+
+/// Form for calling daemon methods through the passthrough
+const EXPLORER_PAGE: &str = "explorer.html";
+
+/// Pages, which are revalidated on every load
+const PAGES: &[&str] = &[INDEX, SERVICE_PAGE, TIMELINE_PAGE, EXPLORER_PAGE];
+
+/// Cache policy for assets other than `index.html`
+const ASSET_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Whether `path` stays inside the assets directory
+fn is_safe(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Find an asset in the override directory, then among the embedded ones
+pub async fn load(dir: Option<&Path>, path: &str) -> Option<(Cow<'static, [u8]>, String)> {
+    if let Some(dir) = dir {
+        if let Ok(body) = tokio::fs::read(dir.join(path)).await {
+            let etag = etag::etag_for(&body);
+            return Some((Cow::Owned(body), etag));
+        }
+    }
+    Embedded::get(path).map(|file| {
+        let etag = format!("\"{}\"", hex::encode(file.metadata.sha256_hash()));
+        (file.data, etag)
+    })
+}
+
+/// Names of the files directly under `dir` (e.g. `i18n`), built-in or in
+/// the override directory
+pub async fn list(override_dir: Option<&Path>, dir: &str) -> BTreeSet<String> {
+    let prefix = format!("{}/", dir);
+    let mut names: BTreeSet<String> = Embedded::iter()
+        .filter_map(|path| {
+            let name = path.strip_prefix(&prefix)?;
+            (!name.contains('/')).then(|| name.to_string())
+        })
+        .collect();
+    if let Some(override_dir) = override_dir {
+        if let Ok(mut entries) = tokio::fs::read_dir(override_dir.join(dir)).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+async fn serve(state: &AppState, headers: &HeaderMap, path: &str) -> Response {
+    let settings = state.settings();
+    let found = if is_safe(path) {
+        load(settings.assets_dir.as_deref(), path).await
+    } else {
+        None
+    };
+    let Some((body, etag)) = found else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<()>::error(&format!("No asset '{}'", path)),
+        )
+            .into_response();
+    };
+
+    let cache_control = if PAGES.contains(&path) {
+        "no-cache"
+    } else {
+        ASSET_CACHE_CONTROL
+    };
+    let etag_value = HeaderValue::from_str(&etag).expect("ETag is valid ASCII");
+    if etag::if_none_match(headers, &etag) {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, etag_value),
+                (
+                    header::CACHE_CONTROL,
+                    HeaderValue::from_static(cache_control),
+                ),
+            ],
+        )
+            .into_response();
+    }
+
+    let content_type = mime_guess::from_path(path).first_or_octet_stream();
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(content_type.as_ref())
+                    .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+            ),
+            (header::ETAG, etag_value),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(cache_control),
+            ),
+        ],
+        body.into_owned(),
+    )
+        .into_response()
+}
+
+/// Serve the dashboard page
+pub async fn index(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    serve(&state, &headers, INDEX).await
+}
+
+/// Serve the detail page for a service
+pub async fn service_page(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    serve(&state, &headers, SERVICE_PAGE).await
+}
+
+/// Serve the event timeline
+pub async fn timeline_page(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    serve(&state, &headers, TIMELINE_PAGE).await
+}
+
+/// Serve the RPC explorer
+pub async fn explorer_page(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    serve(&state, &headers, EXPLORER_PAGE).await
+}
+
+/// Serve an asset by path
+pub async fn asset(
+    State(state): State<AppState>,
+    UrlPath(path): UrlPath<String>,
+    headers: HeaderMap,
+) -> Response {
+    serve(&state, &headers, &path).await
+}
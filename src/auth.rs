@@ -0,0 +1,266 @@
+//! Token authentication.
+//!
+//! With `--auth-token` or namespace tokens set, every request except `/healthz` and `/readyz`
+//! needs one of the tokens. Send it in `Authorization: Bearer <token>`, or in
+//! the [`COOKIE`] cookie as the web UI does. Opening any page with
+//! `?token=<token>` sets that cookie and redirects to the page without the
+//! token. Tokens are picked up on config reload, so they can be rotated
+//! without a restart.
+//!
+//! Each namespace has tokens of its own, which only open that namespace
+//! (see [`namespaces`](crate::namespaces)).
+//!
+//! The dashboard also hands out one-time links (see [`qr`](crate::qr)),
+//! which start a session of their own when opened. Links and sessions are
+//! kept in memory, so they end when the dashboard restarts.
+//!
+//! Each token and session counts against the [`quotas`](crate::quotas).
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::api::ApiResponse;
+use crate::namespaces;
+use crate::state::{AppState, Settings};
+
+/// Cookie holding the token of a browser session
+pub const COOKIE: &str = "fgp_dashboard_token";
+
+/// How long the cookie lasts, in seconds
+const COOKIE_MAX_AGE: u64 = 30 * 86400;
+
+/// How long a one-time link can be opened
+pub const LINK_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a session started from a link lasts
+const SESSION_TTL: Duration = Duration::from_secs(12 * 3600);
+
+/// Who a request was authenticated as
+#[derive(Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// An `--auth-token`, or anyone when no tokens are configured
+    Admin,
+    /// A token of this namespace (see [`namespaces`](crate::namespaces))
+    Namespace(String),
+}
+
+/// Which token a request was made with, as the start of its SHA-256
+#[derive(Clone)]
+pub struct TokenId(pub String);
+
+impl TokenId {
+    fn of(token: &str) -> Self {
+        Self(token_hash(token)[..12].to_string())
+    }
+}
+
+struct Grant {
+    expires: Instant,
+    principal: Principal,
+}
+
+/// Tokens issued by the dashboard rather than configured
+#[derive(Default)]
+pub struct Grants {
+    /// Unused one-time links, by token hash
+    links: Mutex<HashMap<String, Grant>>,
+    /// Sessions started from links, by token hash
+    sessions: Mutex<HashMap<String, Grant>>,
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn issue(grants: &Mutex<HashMap<String, Grant>>, ttl: Duration, principal: Principal) -> String {
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let now = Instant::now();
+    let mut grants = grants.lock().unwrap();
+    grants.retain(|_, grant| grant.expires > now);
+    grants.insert(
+        token_hash(&token),
+        Grant {
+            expires: now + ttl,
+            principal,
+        },
+    );
+    token
+}
+
+impl Grants {
+    /// A token that can be exchanged once, within [`LINK_TTL`], for a
+    /// session acting as `principal`
+    pub fn link(&self, principal: Principal) -> String {
+        issue(&self.links, LINK_TTL, principal)
+    }
+
+    /// Use up a link token, returning the token of the session it starts
+    fn redeem(&self, token: &str) -> Option<String> {
+        let grant = self.links.lock().unwrap().remove(&token_hash(token))?;
+        (grant.expires > Instant::now())
+            .then(|| issue(&self.sessions, SESSION_TTL, grant.principal))
+    }
+
+    fn session(&self, token: &str) -> Option<Principal> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&token_hash(token))
+            .filter(|grant| grant.expires > Instant::now())
+            .map(|grant| grant.principal.clone())
+    }
+}
+
+/// Whether any tokens are configured, so requests need one
+pub fn required(settings: &Settings) -> bool {
+    !settings.auth_tokens.is_empty()
+        || settings
+            .namespaces
+            .iter()
+            .any(|namespace| !namespace.tokens.is_empty())
+}
+
+/// Who `token` belongs to, if it is a configured token
+///
+/// Hashes are compared, so how long the comparison takes says nothing about
+/// the tokens.
+fn principal(settings: &Settings, token: &str) -> Option<Principal> {
+    let token = Sha256::digest(token.as_bytes());
+    let matches = |allowed: &String| Sha256::digest(allowed.as_bytes()) == token;
+    if settings.auth_tokens.iter().any(matches) {
+        return Some(Principal::Admin);
+    }
+    settings
+        .namespaces
+        .iter()
+        .find(|namespace| namespace.tokens.iter().any(matches))
+        .map(|namespace| Principal::Namespace(namespace.name.clone()))
+}
+
+fn bearer(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+fn cookie(req: &Request) -> Option<&str> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            (name == COOKIE).then_some(value)
+        })
+}
+
+/// The `token` query parameter, and the query without it
+fn query_token(req: &Request) -> Option<(String, String)> {
+    let query = req.uri().query()?;
+    let mut token = None;
+    let rest: Vec<&str> = query
+        .split('&')
+        .filter(|pair| match pair.strip_prefix("token=") {
+            Some(value) => {
+                token = Some(value.to_string());
+                false
+            }
+            None => true,
+        })
+        .collect();
+    Some((token?, rest.join("&")))
+}
+
+fn unauthorized(message: &str) -> Response {
+    let mut response =
+        (StatusCode::UNAUTHORIZED, ApiResponse::<()>::error(message)).into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+/// Reject requests without a valid token, when tokens are configured
+///
+/// Accepted requests carry their [`Principal`] as an extension, and their
+/// [`TokenId`] when a token was needed; so do their responses, for the
+/// layers outside this one.
+pub async fn require(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let settings = state.settings();
+    if !required(&settings) || matches!(req.uri().path(), "/healthz" | "/readyz") {
+        req.extensions_mut().insert(Principal::Admin);
+        let mut response = next.run(req).await;
+        response.extensions_mut().insert(Principal::Admin);
+        return response;
+    }
+
+    if let Some((token, rest)) = query_token(&req) {
+        let (token, max_age) = if principal(&settings, &token).is_some() {
+            (token, COOKIE_MAX_AGE)
+        } else if let Some(session) = state.grants.redeem(&token) {
+            (session, SESSION_TTL.as_secs())
+        } else {
+            return unauthorized("Invalid or expired token");
+        };
+        let path = req.uri().path();
+        let target = if rest.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, rest)
+        };
+        let secure = if state.tls { "; Secure" } else { "" };
+        let cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+            COOKIE, token, max_age, secure
+        );
+        let mut response = Redirect::to(&target).into_response();
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().insert(header::SET_COOKIE, cookie);
+        }
+        return response;
+    }
+
+    let (principal, id) = match bearer(&req).or_else(|| cookie(&req)) {
+        Some(token) => match principal(&settings, token).or_else(|| state.grants.session(token)) {
+            Some(principal) => (principal, TokenId::of(token)),
+            None => return unauthorized("Invalid or expired token"),
+        },
+        None => return unauthorized(
+            "Authentication required: send Authorization: Bearer <token>, or open the dashboard with ?token=<token>",
+        ),
+    };
+    if let Principal::Namespace(name) = &principal {
+        if !namespaces::permits(name, &req) {
+            return (
+                StatusCode::FORBIDDEN,
+                ApiResponse::<()>::error(&format!(
+                    "This token only opens namespace '{}', under /api/ns/{}/",
+                    name, name
+                )),
+            )
+                .into_response();
+        }
+    }
+    req.extensions_mut().insert(principal.clone());
+    req.extensions_mut().insert(id.clone());
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(principal);
+    response.extensions_mut().insert(id);
+    response
+}
@@ -0,0 +1,240 @@
+//! Backup and restore of the dashboard's own state.
+//!
+//! A backup is a gzipped tarball with a consistent copy of the database
+//! (history, events, annotations and the like), the webhook subscriptions and
+//! the config file. `GET /api/admin/backup` and `fgp-dashboard backup`
+//! create one; `POST /api/admin/restore` and `fgp-dashboard restore` put it
+//! back. Restoring through the API swaps the database in place and reloads
+//! the subscriptions and config, so only restart-only settings need a
+//! restart. Uploads may be up to 1GB regardless of `--max-request-body`,
+//! and neither endpoint is cut off by `--request-timeout`.
+
+use anyhow::{bail, Context, Result};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::admin;
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::config::FileConfig;
+use crate::db::{Database, DB_FILE};
+use crate::events;
+use crate::state::AppState;
+use crate::webhooks::SUBSCRIPTIONS_FILE;
+
+/// Name of the config file inside an archive
+const CONFIG_ENTRY: &str = "config.toml";
+
+/// Largest archive `POST /api/admin/restore` accepts
+pub const MAX_ARCHIVE_SIZE: usize = 1 << 30;
+
+/// Where the backed-up files live on this host
+#[derive(Clone)]
+pub struct Paths {
+    pub data_dir: PathBuf,
+    pub config_file: PathBuf,
+}
+
+impl Paths {
+    /// Scratch path inside the data dir, so renames stay on one filesystem
+    fn staging(&self, kind: &str) -> PathBuf {
+        self.data_dir
+            .join(format!(".{}-{}", kind, uuid::Uuid::new_v4()))
+    }
+}
+
+/// What a restore put back
+#[derive(Serialize, Default)]
+pub struct Restored {
+    pub database: bool,
+    pub subscriptions: bool,
+    pub config: bool,
+}
+
+/// Build a backup archive
+///
+/// Blocks, so call it off the async runtime.
+pub fn create(db: &Database, paths: &Paths) -> Result<Vec<u8>> {
+    fs::create_dir_all(&paths.data_dir)
+        .with_context(|| format!("Failed to create {}", paths.data_dir.display()))?;
+    let db_copy = paths.staging("backup");
+    let result = db
+        .backup_to(&db_copy)
+        .and_then(|()| archive(&db_copy, paths));
+    let _ = fs::remove_file(&db_copy);
+    result
+}
+
+fn archive(db_copy: &Path, paths: &Paths) -> Result<Vec<u8>> {
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    tar.append_path_with_name(db_copy, DB_FILE)?;
+
+    let subscriptions = paths.data_dir.join(SUBSCRIPTIONS_FILE);
+    if subscriptions.exists() {
+        tar.append_path_with_name(&subscriptions, SUBSCRIPTIONS_FILE)?;
+    }
+    if paths.config_file.exists() {
+        tar.append_path_with_name(&paths.config_file, CONFIG_ENTRY)
+            .with_context(|| format!("Failed to read {}", paths.config_file.display()))?;
+    }
+
+    Ok(tar.into_inner()?.finish()?)
+}
+
+/// Restore a backup archive over the current state
+///
+/// The archive is unpacked and checked before anything is replaced. Blocks,
+/// so call it off the async runtime.
+pub fn restore(db: &Database, paths: &Paths, archive: &[u8]) -> Result<Restored> {
+    let staging = paths.staging("restore");
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+    let result = unpack(archive, &staging).and_then(|()| apply(db, paths, &staging));
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Extract the entries a backup may contain into `dir`
+fn unpack(archive: &[u8], dir: &Path) -> Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(archive));
+    for entry in tar.entries().context("Not a backup archive")? {
+        let mut entry = entry.context("Not a backup archive")?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            tracing::warn!("Skipping {} in backup: not a regular file", name);
+            continue;
+        }
+        match name.as_str() {
+            DB_FILE | SUBSCRIPTIONS_FILE | CONFIG_ENTRY => {
+                entry.unpack(dir.join(&name))?;
+            }
+            _ => tracing::warn!("Skipping unexpected {} in backup", name),
+        }
+    }
+
+    if !dir.join(DB_FILE).exists() {
+        bail!("Not a dashboard backup: {} is missing", DB_FILE);
+    }
+    Ok(())
+}
+
+fn apply(db: &Database, paths: &Paths, staging: &Path) -> Result<Restored> {
+    // A config that won't load would leave the restore half done
+    let config = staging.join(CONFIG_ENTRY);
+    if config.exists() {
+        FileConfig::load(&config).context("The backup's config file is invalid")?;
+    }
+
+    db.restore_from(&staging.join(DB_FILE))?;
+    let mut restored = Restored {
+        database: true,
+        ..Restored::default()
+    };
+
+    let subscriptions = staging.join(SUBSCRIPTIONS_FILE);
+    if subscriptions.exists() {
+        replace(&subscriptions, &paths.data_dir.join(SUBSCRIPTIONS_FILE))?;
+        restored.subscriptions = true;
+    }
+    if config.exists() {
+        replace(&config, &paths.config_file)?;
+        restored.config = true;
+    }
+
+    Ok(restored)
+}
+
+fn replace(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::copy(from, to).with_context(|| format!("Failed to write {}", to.display()))?;
+    Ok(())
+}
+
+/// File name offered for a backup taken now
+pub fn file_name() -> String {
+    format!("fgp-dashboard-backup-{}.tar.gz", events::now())
+}
+
+/// Download a backup of the dashboard's state
+pub async fn backup(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    let paths = state.backup_paths();
+    match calls::blocking(move || create(&db, &paths)).await {
+        Ok(archive) => (
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", file_name()),
+                ),
+            ],
+            archive,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<()>::error(&format!("Backup failed: {:#}", e)),
+        )
+            .into_response(),
+    }
+}
+
+/// Restore a backup uploaded as the request body
+pub async fn restore_backup(State(state): State<AppState>, archive: Bytes) -> Response {
+    let db = state.db.clone();
+    let paths = state.backup_paths();
+    let restored = match calls::blocking(move || restore(&db, &paths, &archive)).await {
+        Ok(restored) => restored,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<()>::error(&format!("Restore failed: {:#}", e)),
+            )
+                .into_response()
+        }
+    };
+    tracing::info!("Restored state from backup");
+
+    if restored.subscriptions {
+        if let Err(e) = state.webhooks.reload() {
+            tracing::error!("Failed to reload restored subscriptions: {:#}", e);
+        }
+    }
+    let restart_required = if restored.config {
+        match admin::reload(&state).await {
+            Ok(restart_required) => restart_required,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ApiResponse::<()>::error(&format!(
+                        "Backup restored, but its config failed to load: {:#}",
+                        e
+                    )),
+                )
+                    .into_response()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    (
+        StatusCode::OK,
+        ApiResponse::success(serde_json::json!({
+            "restored": restored,
+            "restart_required": restart_required,
+        })),
+    )
+        .into_response()
+}
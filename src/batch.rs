@@ -0,0 +1,144 @@
+//! Acting on several services in one request.
+//!
+//! `POST /api/batch` takes an action and a list of services:
+//!
+//! ```text
+//! {"action": "restart", "services": ["gateway", "search"]}
+//! {"action": "tag", "services": ["gateway"], "annotations": {"owner": "team-infra"}}
+//! ```
+//!
+//! Actions are `start`, `stop`, `restart` (stop, then start), `cleanup`
+//! (remove a stale socket) and `tag` (set or, with `null`, remove
+//! annotations). Services are handled
+//! concurrently and each gets its own result, so one failure doesn't hide
+//! the others.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::annotations;
+use crate::api::{self, ApiResponse};
+use crate::calls;
+use crate::events;
+use crate::state::AppState;
+
+/// Most services accepted in one batch
+const MAX_SERVICES: usize = 100;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Start,
+    Stop,
+    Restart,
+    Cleanup,
+    Tag,
+}
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    pub action: Action,
+    pub services: Vec<String>,
+    /// Annotations to set for `tag`
+    #[serde(default)]
+    pub annotations: BTreeMap<String, Option<String>>,
+}
+
+/// Outcome for one service
+#[derive(Serialize)]
+pub struct BatchResult {
+    pub service: String,
+    pub ok: bool,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+    pub failed: usize,
+}
+
+async fn run(
+    state: &AppState,
+    action: Action,
+    service: &str,
+    tags: &BTreeMap<String, Option<String>>,
+) -> Result<String, String> {
+    match action {
+        Action::Start => api::start(state, service).await,
+        Action::Stop => api::stop(state, service).await,
+        Action::Restart => api::restart(state, service).await,
+        Action::Cleanup => api::cleanup(state, service).await,
+        Action::Tag => {
+            let db = state.db.clone();
+            let name = service.to_string();
+            let tags = tags.clone();
+            calls::blocking(move || db.update_annotations(&name, &tags, events::now()))
+                .await
+                .map_err(|e| format!("{:#}", e))?;
+            Ok(format!("Service '{}' tagged", service))
+        }
+    }
+}
+
+/// Apply one action to several services
+pub async fn batch(
+    State(state): State<AppState>,
+    Json(request): Json<BatchRequest>,
+) -> impl IntoResponse {
+    let invalid = if request.services.is_empty() {
+        Some("No services given".to_string())
+    } else if request.services.len() > MAX_SERVICES {
+        Some(format!("At most {} services per batch", MAX_SERVICES))
+    } else if matches!(request.action, Action::Tag) && request.annotations.is_empty() {
+        Some("'tag' needs annotations".to_string())
+    } else {
+        request
+            .annotations
+            .iter()
+            .try_for_each(|(key, value)| annotations::validate(key, value.as_deref()))
+            .err()
+    };
+    if let Some(e) = invalid {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<BatchResponse>::error(&e),
+        );
+    }
+
+    let outcomes = futures::future::join_all(
+        request
+            .services
+            .iter()
+            .map(|service| run(&state, request.action, service, &request.annotations)),
+    )
+    .await;
+
+    let results: Vec<BatchResult> = request
+        .services
+        .into_iter()
+        .zip(outcomes)
+        .map(|(service, outcome)| match outcome {
+            Ok(message) => BatchResult {
+                service,
+                ok: true,
+                message: Some(message),
+                error: None,
+            },
+            Err(error) => BatchResult {
+                service,
+                ok: false,
+                message: None,
+                error: Some(error),
+            },
+        })
+        .collect();
+    let failed = results.iter().filter(|result| !result.ok).count();
+
+    (
+        StatusCode::OK,
+        ApiResponse::success(BatchResponse { results, failed }),
+    )
+}
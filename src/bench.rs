@@ -0,0 +1,299 @@
+//! Load testing a daemon's socket.
+//!
+//! `POST /api/bench/{service}` makes `requests` calls (100 by default) of
+//! `method` (`health` by default) with `params`, over `concurrency`
+//! connections at once (4 by default), and reports the throughput and the
+//! latency percentiles:
+//!
+//! ```json
+//! { "method": "health", "requests": 5000, "concurrency": 16 }
+//! ```
+//!
+//! Each connection is opened for the run rather than taken from the
+//! [`pool`](crate::pool), so the numbers are the socket's alone; connecting
+//! is timed apart from the calls. Methods other than `health` must be
+//! allowed by `--allow-method` / `--deny-method`, and should be safe to call
+//! many times. A run stops early, reporting what it measured, before
+//! `--request-timeout` would cut the request off.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls::{self, BackendCall};
+use crate::protocol;
+use crate::state::AppState;
+use crate::transport::{self, Client};
+
+/// Calls made when the request doesn't say
+const DEFAULT_REQUESTS: usize = 100;
+
+/// Most calls one run can make
+const MAX_REQUESTS: usize = 100_000;
+
+/// Connections used when the request doesn't say
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Most connections one run can open
+const MAX_CONCURRENCY: usize = 64;
+
+/// Longest a run lasts when `--request-timeout` is disabled
+const MAX_RUN: Duration = Duration::from_secs(60);
+
+/// Body of `POST /api/bench/{service}`; everything is optional
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BenchRequest {
+    pub method: Option<String>,
+    pub params: Option<serde_json::Value>,
+    pub requests: Option<usize>,
+    pub concurrency: Option<usize>,
+}
+
+/// Latencies of the successful calls, in milliseconds
+#[derive(Serialize, TS, Default)]
+pub struct Latency {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Outcome of a run
+#[derive(Serialize, TS)]
+pub struct BenchReport {
+    pub service: String,
+    pub method: String,
+    #[ts(type = "number")]
+    pub concurrency: usize,
+    /// Calls asked for
+    #[ts(type = "number")]
+    pub requested: usize,
+    /// Calls made, successful or not
+    #[ts(type = "number")]
+    pub completed: usize,
+    #[ts(type = "number")]
+    pub errors: usize,
+    /// The first error, if any call failed
+    pub first_error: Option<String>,
+    /// The run stopped before making every call
+    pub stopped_early: bool,
+    pub duration_ms: f64,
+    /// Calls made per second
+    pub throughput: f64,
+    /// Mean time to open a connection
+    pub connect_ms: f64,
+    pub latency_ms: Latency,
+}
+
+/// What one connection measured
+#[derive(Default)]
+struct Worker {
+    connect_ms: Option<f64>,
+    latencies: Vec<f64>,
+    errors: usize,
+    first_error: Option<String>,
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The value below which `fraction` of the sorted `values` fall
+fn percentile(values: &[f64], fraction: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let rank = (fraction * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+/// Make calls over one connection until none are left or time runs out
+fn work(
+    endpoint: &std::path::Path,
+    method: &str,
+    params: &serde_json::Value,
+    remaining: &AtomicUsize,
+    deadline: Instant,
+) -> Worker {
+    let mut worker = Worker::default();
+    let started = Instant::now();
+    let client = match Client::connect(endpoint) {
+        Ok(client) => client,
+        Err(e) => {
+            worker.errors += 1;
+            worker.first_error = Some(format!("Failed to connect: {:#}", e));
+            return worker;
+        }
+    };
+    worker.connect_ms = Some(ms(started.elapsed()));
+
+    while Instant::now() < deadline
+        && remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    {
+        let started = Instant::now();
+        let reply = if method == "health" {
+            client.health()
+        } else {
+            client.call(method, params.clone())
+        };
+        let elapsed = ms(started.elapsed());
+        let error = match reply {
+            Ok(reply) if reply.ok => {
+                worker.latencies.push(elapsed);
+                continue;
+            }
+            Ok(reply) => reply.error.unwrap_or_default(),
+            Err(e) => {
+                // The connection can't be trusted after a failed exchange
+                worker.errors += 1;
+                worker.first_error.get_or_insert(e.to_string());
+                return worker;
+            }
+        };
+        worker.errors += 1;
+        worker.first_error.get_or_insert(error);
+    }
+    worker
+}
+
+fn bad_request(message: &str) -> Response {
+    (StatusCode::BAD_REQUEST, ApiResponse::<()>::error(message)).into_response()
+}
+
+/// Load test a daemon with concurrent calls
+pub async fn bench_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    body: Bytes,
+) -> Response {
+    let request: BenchRequest = if body.is_empty() {
+        BenchRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => return bad_request(&format!("Invalid benchmark: {}", e)),
+        }
+    };
+    let settings = state.settings();
+    let method = request.method.unwrap_or_else(|| "health".to_string());
+    let requested = request.requests.unwrap_or(DEFAULT_REQUESTS);
+    let concurrency = request.concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+    if requested == 0 || requested > MAX_REQUESTS {
+        return bad_request(&format!("requests must be between 1 and {}", MAX_REQUESTS));
+    }
+    if concurrency == 0 || concurrency > MAX_CONCURRENCY {
+        return bad_request(&format!(
+            "concurrency must be between 1 and {}",
+            MAX_CONCURRENCY
+        ));
+    }
+    if method != "health" && !settings.methods.permits(&service, &method) {
+        return (
+            StatusCode::FORBIDDEN,
+            ApiResponse::<()>::error(&format!(
+                "Method '{}' is not allowed on '{}'",
+                method, service
+            )),
+        )
+            .into_response();
+    }
+    if let Err(e) = protocol::check(&state, &service).await {
+        return (StatusCode::CONFLICT, ApiResponse::<()>::error(&e)).into_response();
+    }
+    let Some(endpoint) = settings.socket_path(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<()>::error(&format!("Service '{}' not found", service)),
+        )
+            .into_response();
+    };
+    if !transport::endpoint_exists(&endpoint) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<()>::error(&format!("Service '{}' is not running", service)),
+        )
+            .into_response();
+    }
+
+    // Leave time to answer before the request times out
+    let budget = if settings.request_timeout.is_zero() {
+        MAX_RUN
+    } else {
+        settings.request_timeout.mul_f64(0.8)
+    };
+    let params = Arc::new(request.params.unwrap_or_else(|| serde_json::json!({})));
+    let remaining = Arc::new(AtomicUsize::new(requested));
+    let concurrency = concurrency.min(requested);
+    let started = Instant::now();
+    let deadline = started + budget;
+
+    let _call = BackendCall::start(format!("bench of {} on {}", method, service));
+    let workers = futures::future::join_all((0..concurrency).map(|_| {
+        let endpoint = endpoint.clone();
+        let method = method.clone();
+        let params = params.clone();
+        let remaining = remaining.clone();
+        calls::blocking(move || work(&endpoint, &method, &params, &remaining, deadline))
+    }))
+    .await;
+    let elapsed = started.elapsed();
+
+    let mut latencies = Vec::new();
+    let mut connects = Vec::new();
+    let mut errors = 0;
+    let mut first_error = None;
+    for worker in workers {
+        latencies.extend(worker.latencies);
+        connects.extend(worker.connect_ms);
+        errors += worker.errors;
+        if first_error.is_none() {
+            first_error = worker.first_error;
+        }
+    }
+    latencies.sort_by(f64::total_cmp);
+    let completed = latencies.len() + errors;
+    let mean = |values: &[f64]| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    };
+
+    ApiResponse::success(BenchReport {
+        service,
+        method,
+        concurrency,
+        requested,
+        completed,
+        errors,
+        first_error,
+        stopped_early: completed < requested,
+        duration_ms: ms(elapsed),
+        throughput: completed as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        connect_ms: mean(&connects),
+        latency_ms: Latency {
+            min: latencies.first().copied().unwrap_or_default(),
+            mean: mean(&latencies),
+            p50: percentile(&latencies, 0.5),
+            p90: percentile(&latencies, 0.9),
+            p99: percentile(&latencies, 0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+        },
+    })
+    .into_response()
+}
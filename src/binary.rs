@@ -0,0 +1,260 @@
+//! Binary payloads for the RPC passthrough.
+//!
+//! FGP calls carry JSON, so daemons that import or export files take and
+//! return their contents as base64 strings. The passthrough converts at the
+//! edge so clients can send and receive the files themselves:
+//!
+//! - A `multipart/form-data` call takes its params from an optional `params`
+//!   part holding a JSON object, and sets each other part's name to the
+//!   base64 of its contents. Parts are streamed to temp files as they
+//!   arrive rather than buffered.
+//! - `?download=<pointer>` decodes the base64 string at that JSON pointer in
+//!   the result (the whole result for `?download=`) and sends it as a file,
+//!   named by `?filename=`. It is decoded to a temp file before anything is
+//!   sent, so malformed or oversized data gets a clean error.
+//!
+//! Passthrough bodies, JSON or multipart, may be up to
+//! `--max-rpc-upload-size` (64MB by default); downloads are held to
+//! `--max-rpc-response-size` after decoding.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Multipart,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::read::DecoderReader;
+use base64::write::EncoderStringWriter;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::rpc::CHUNK_SIZE;
+
+/// Passthrough body limit used when none is configured
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 64 << 20;
+
+/// Name of the multipart part holding the JSON params
+const PARAMS_PART: &str = "params";
+
+/// Query options for a passthrough call
+#[derive(Deserialize, Default)]
+pub struct CallQuery {
+    /// JSON pointer to a base64 string in the result to send as a file
+    pub download: Option<String>,
+    /// File name for the download
+    pub filename: Option<String>,
+}
+
+/// A scratch file, removed when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(kind: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("fgp-dashboard-{}-{}", kind, uuid::Uuid::new_v4())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, ApiResponse::<serde_json::Value>::error(message)).into_response()
+}
+
+/// Base64 of a file's contents, without reading it into memory first
+fn encode(path: &std::path::Path) -> io::Result<String> {
+    let mut encoder = EncoderStringWriter::new(&STANDARD);
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    Ok(encoder.into_inner())
+}
+
+/// Build call params from a multipart body
+///
+/// The body limit on the passthrough routes bounds the parts' total size.
+pub async fn params_from_multipart(
+    mut multipart: Multipart,
+) -> Result<serde_json::Value, Response> {
+    let mut params = serde_json::Map::new();
+    let mut files = Vec::new();
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Err(error(e.status(), &format!("Invalid upload: {}", e))),
+        };
+        let name = match field.name() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    "Upload parts must be named after the param they fill",
+                ))
+            }
+        };
+
+        if name == PARAMS_PART {
+            let text = field
+                .text()
+                .await
+                .map_err(|e| error(e.status(), &format!("Invalid upload: {}", e)))?;
+            match serde_json::from_str(&text) {
+                Ok(serde_json::Value::Object(fields)) => params.extend(fields),
+                Ok(_) => {
+                    return Err(error(
+                        StatusCode::BAD_REQUEST,
+                        "The params part must be a JSON object",
+                    ))
+                }
+                Err(e) => {
+                    return Err(error(
+                        StatusCode::BAD_REQUEST,
+                        &format!("Invalid params: {}", e),
+                    ))
+                }
+            }
+            continue;
+        }
+
+        let temp = TempFile::new("upload");
+        let mut file = tokio::fs::File::create(&temp.0).await.map_err(|e| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to stage upload: {}", e),
+            )
+        })?;
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => file.write_all(&chunk).await.map_err(|e| {
+                    error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &format!("Failed to stage upload: {}", e),
+                    )
+                })?,
+                Ok(None) => break,
+                Err(e) => return Err(error(e.status(), &format!("Invalid upload: {}", e))),
+            }
+        }
+        file.flush().await.map_err(|e| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to stage upload: {}", e),
+            )
+        })?;
+        files.push((name, temp));
+    }
+
+    for (name, temp) in files {
+        let encoded = calls::blocking(move || encode(&temp.0))
+            .await
+            .map_err(|e| {
+                error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Failed to read upload: {}", e),
+                )
+            })?;
+        params.insert(name, serde_json::Value::String(encoded));
+    }
+    Ok(serde_json::Value::Object(params))
+}
+
+/// Decode base64 to a temp file, returning it and its size
+fn decode(encoded: &str, limit: u64) -> Result<(TempFile, u64), String> {
+    let temp = TempFile::new("download");
+    let mut file = File::create(&temp.0).map_err(|e| format!("Failed to stage download: {}", e))?;
+    let mut decoder = DecoderReader::new(encoded.as_bytes(), &STANDARD).take(limit + 1);
+    let size = io::copy(&mut decoder, &mut file)
+        .map_err(|e| format!("Result is not valid base64: {}", e))?;
+    if size > limit {
+        return Err(format!(
+            "Download exceeds the {} byte limit (--max-rpc-response-size)",
+            limit
+        ));
+    }
+    Ok((temp, size))
+}
+
+/// A `Content-Disposition` file name with quoting characters left out
+fn attachment(filename: &str) -> String {
+    let safe: String = filename
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '"' | '\\' | '/'))
+        .collect();
+    format!("attachment; filename=\"{}\"", safe)
+}
+
+/// Send the base64 string at `pointer` in a call's result as a file
+pub async fn send_download(
+    mut result: serde_json::Value,
+    pointer: &str,
+    filename: String,
+    limit: u64,
+) -> Response {
+    let encoded = match result.pointer_mut(pointer).map(serde_json::Value::take) {
+        Some(serde_json::Value::String(encoded)) => encoded,
+        _ => {
+            return error(
+                StatusCode::BAD_GATEWAY,
+                &format!("Result has no base64 string at '{}'", pointer),
+            )
+        }
+    };
+
+    let (temp, size) = match calls::blocking(move || decode(encoded.trim(), limit)).await {
+        Ok(decoded) => decoded,
+        Err(e) => return error(StatusCode::BAD_GATEWAY, &e),
+    };
+
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let mut file = match File::open(&temp.0) {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx
+                        .blocking_send(Ok(Bytes::copy_from_slice(&buf[..n])))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    break;
+                }
+            }
+        }
+    });
+    let chunks = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+            (header::CONTENT_DISPOSITION, attachment(&filename)),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
@@ -0,0 +1,67 @@
+//! Request body size limits for endpoints that accept uploads.
+//!
+//! Bodies larger than `--max-request-body` (1MB by default), or
+//! `--max-rpc-upload-size` on the passthrough, are refused with a JSON 413
+//! before they are read into memory: up front when the client declares a
+//! `Content-Length`, and by the body extractors otherwise.
+
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+use crate::api::ApiResponse;
+
+/// Body size limit used when none is configured
+pub const DEFAULT_MAX_BODY: u64 = 1 << 20;
+
+fn too_large(max: usize, flag: &str) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        ApiResponse::<()>::error(&format!(
+            "Request body exceeds the {} byte limit (--{})",
+            max, flag
+        )),
+    )
+        .into_response()
+}
+
+async fn enforce(max: usize, flag: &'static str, req: Request, next: Next) -> Response {
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > max as u64) {
+        return too_large(max, flag);
+    }
+
+    let response = next.run(req).await;
+
+    // Extractors cut off undeclared bodies with a plain-text 413
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return too_large(max, flag);
+    }
+    response
+}
+
+/// Cap request bodies on all of `router`'s routes at `max` bytes, naming
+/// `flag` as the setting to raise
+pub fn limit<S: Clone + Send + Sync + 'static>(
+    router: Router<S>,
+    max: usize,
+    flag: &'static str,
+) -> Router<S> {
+    router
+        .route_layer(DefaultBodyLimit::max(max))
+        .route_layer(middleware::from_fn(move |req: Request, next: Next| {
+            enforce(max, flag, req, next)
+        }))
+}
@@ -0,0 +1,69 @@
+//! Circuit breaker for daemons that stop answering health probes.
+//!
+//! After [`TRIP_AFTER`] consecutive probe timeouts a socket's breaker
+//! opens: probes are skipped for [`COOL_DOWN`] and the service is reported
+//! `not_responding` straight away, so a hung daemon doesn't hold up every
+//! scan. Once the cool-down has passed a single trial probe is let through;
+//! an answer closes the breaker, another timeout reopens it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive timeouts that open the breaker
+const TRIP_AFTER: u32 = 3;
+
+/// How long probes are skipped once the breaker opens
+const COOL_DOWN: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Breaker {
+    timeouts: u32,
+    /// Probes are skipped until then
+    open_until: Option<Instant>,
+}
+
+static BREAKERS: Mutex<BTreeMap<PathBuf, Breaker>> = Mutex::new(BTreeMap::new());
+
+/// Whether a probe may be sent to `socket_path`
+///
+/// Returns how long until the next trial probe if the breaker is open.
+pub fn allow(socket_path: &Path) -> Result<(), Duration> {
+    let mut breakers = BREAKERS.lock().unwrap();
+    let Some(breaker) = breakers.get_mut(socket_path) else {
+        return Ok(());
+    };
+    match breaker.open_until {
+        Some(until) if until > Instant::now() => Err(until - Instant::now()),
+        Some(_) => {
+            // Hold other probes back while this one is the trial
+            breaker.open_until = Some(Instant::now() + COOL_DOWN);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// The daemon answered, whatever it said
+pub fn record_answer(socket_path: &Path) {
+    BREAKERS.lock().unwrap().remove(socket_path);
+}
+
+/// The probe timed out
+pub fn record_timeout(socket_path: &Path) {
+    let mut breakers = BREAKERS.lock().unwrap();
+    let breaker = breakers.entry(socket_path.to_path_buf()).or_default();
+    breaker.timeouts += 1;
+    if breaker.timeouts >= TRIP_AFTER {
+        if breaker.open_until.is_none() {
+            tracing::warn!(
+                "{} timed out {} times in a row, skipping probes for {}s",
+                socket_path.display(),
+                breaker.timeouts,
+                COOL_DOWN.as_secs()
+            );
+        }
+        breaker.open_until = Some(Instant::now() + COOL_DOWN);
+    }
+}
@@ -0,0 +1,98 @@
+//! Backend calls made while serving a request.
+//!
+//! Daemon socket calls and peer requests are wrapped in a [`BackendCall`],
+//! which records what is currently in flight (for timeout errors) and the
+//! slowest call so far (for slow-request warnings). Use [`blocking`] to run
+//! them, since the daemon client is synchronous.
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct RequestCalls {
+    in_flight: Option<String>,
+    slowest: Option<(String, Duration)>,
+}
+
+tokio::task_local! {
+    static CALLS: Arc<Mutex<RequestCalls>>;
+}
+
+/// Marks a backend call as in flight for the current request until dropped
+pub struct BackendCall {
+    label: String,
+    started: Instant,
+}
+
+impl BackendCall {
+    /// Record that `label` (e.g. `health on gmail`) has started
+    ///
+    /// Does nothing outside a request, e.g. in CLI subcommands.
+    pub fn start(label: String) -> Self {
+        let _ = CALLS.try_with(|calls| {
+            calls.lock().unwrap().in_flight = Some(label.clone());
+        });
+        Self {
+            label,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for BackendCall {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let _ = CALLS.try_with(|calls| {
+            let mut calls = calls.lock().unwrap();
+            if calls.in_flight.as_deref() == Some(self.label.as_str()) {
+                calls.in_flight = None;
+            }
+            if calls.slowest.as_ref().is_none_or(|(_, d)| elapsed > *d) {
+                calls.slowest = Some((self.label.clone(), elapsed));
+            }
+        });
+    }
+}
+
+/// The backend call currently in flight for this request
+pub fn in_flight() -> Option<String> {
+    CALLS
+        .try_with(|calls| calls.lock().unwrap().in_flight.clone())
+        .ok()
+        .flatten()
+}
+
+/// The slowest completed backend call for this request
+pub fn slowest() -> Option<(String, Duration)> {
+    CALLS
+        .try_with(|calls| calls.lock().unwrap().slowest.clone())
+        .ok()
+        .flatten()
+}
+
+/// Run blocking work (socket I/O, directory scans) off the async runtime
+///
+/// Calls made inside `f` are still attributed to the current request. A
+/// panic in `f` is resumed in the caller.
+pub async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let calls = CALLS.try_with(Arc::clone).ok();
+    let task = tokio::task::spawn_blocking(move || match calls {
+        Some(calls) => CALLS.sync_scope(calls, f),
+        None => f(),
+    });
+
+    match task.await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Track backend calls for the duration of each request
+pub async fn scope(req: Request, next: Next) -> Response {
+    CALLS.scope(Arc::default(), next.run(req)).await
+}
@@ -0,0 +1,174 @@
+//! What each daemon says it can do.
+//!
+//! Scans ask running daemons for their `methods` (see
+//! [`rpc::parse_methods`](crate::rpc::parse_methods)) and report what they
+//! find in `ServiceInfo.capabilities`, so the UI only offers actions a
+//! daemon supports. Answers are kept until the daemon restarts (its uptime
+//! goes backwards) or stops, and re-checked every [`REFRESH_AFTER`] in case
+//! of an upgrade that kept the process. Daemons without introspection have
+//! no capabilities listed, and the UI falls back to offering everything.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+use crate::api::ServiceInfo;
+use crate::calls;
+use crate::rpc;
+use crate::state::Settings;
+
+/// How long a daemon's answer is trusted
+pub const REFRESH_AFTER: Duration = Duration::from_secs(300);
+
+/// Methods that reload a daemon's configuration in place
+const RELOAD_METHODS: &[&str] = &["reload", "config.reload"];
+
+/// Methods that report metrics
+const METRICS_METHODS: &[&str] = &["metrics", "stats"];
+
+/// Methods that stream or tail logs
+const LOG_METHODS: &[&str] = &["logs", "logs.stream", "logs.tail"];
+
+/// Features a daemon advertises
+#[derive(Clone, Default, Serialize, Deserialize, TS)]
+pub struct Capabilities {
+    /// Methods from the daemon's `methods` call
+    pub methods: Vec<String>,
+    pub reload: bool,
+    pub metrics: bool,
+    pub log_streaming: bool,
+}
+
+impl Capabilities {
+    /// Work out capabilities from a `methods` result
+    ///
+    /// A `capabilities` list next to the methods names features the daemon
+    /// supports without a method of the usual name.
+    pub fn from_methods(result: &serde_json::Value) -> Self {
+        let methods: Vec<String> = rpc::parse_methods(result)
+            .into_iter()
+            .map(|method| method.name)
+            .collect();
+        let advertised: Vec<&str> = result
+            .get("capabilities")
+            .and_then(|c| c.as_array())
+            .map(|c| c.iter().filter_map(|c| c.as_str()).collect())
+            .unwrap_or_default();
+        let supports = |names: &[&str], capability: &str| {
+            advertised.contains(&capability)
+                || methods
+                    .iter()
+                    .any(|method| names.contains(&method.as_str()))
+        };
+
+        Self {
+            reload: supports(RELOAD_METHODS, "reload"),
+            metrics: supports(METRICS_METHODS, "metrics"),
+            log_streaming: supports(LOG_METHODS, "log_streaming"),
+            methods,
+        }
+    }
+
+    /// Whether the named capability is supported, for filters
+    pub fn supports(&self, name: &str) -> Option<bool> {
+        match name {
+            "reload" => Some(self.reload),
+            "metrics" => Some(self.metrics),
+            "log_streaming" => Some(self.log_streaming),
+            _ => None,
+        }
+    }
+}
+
+struct Entry {
+    /// `None` when the daemon doesn't support introspection
+    capabilities: Option<Capabilities>,
+    uptime_seconds: Option<u64>,
+    checked_at: Instant,
+}
+
+/// Capabilities of running services
+#[derive(Default)]
+pub struct CapabilityTracker {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl CapabilityTracker {
+    /// Fill in `capabilities` on `services`, asking daemons that are new,
+    /// restarted or due for a re-check
+    pub async fn check(&self, services: &mut [ServiceInfo], settings: &Settings) {
+        let due: Vec<(String, PathBuf)> = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|name, _| {
+                services
+                    .iter()
+                    .any(|service| service.name == *name && service.status != "stopped")
+            });
+            services
+                .iter()
+                .filter(|service| service.status != "stopped" && service.probe_error.is_none())
+                .filter(|service| match entries.get(&service.name) {
+                    None => true,
+                    Some(entry) => {
+                        entry.checked_at.elapsed() >= REFRESH_AFTER
+                            || matches!(
+                                (entry.uptime_seconds, service.uptime_seconds),
+                                (Some(before), Some(now)) if now < before
+                            )
+                    }
+                })
+                .map(|service| (service.name.clone(), PathBuf::from(&service.socket_path)))
+                .collect()
+        };
+
+        let queries = due.into_iter().map(|(name, socket_path)| {
+            let timeout = settings.health_timeout(&name);
+            let retries = settings.socket_retries;
+            async move {
+                let call = calls::blocking(move || {
+                    rpc::call(&socket_path, "methods", serde_json::json!({}), retries)
+                });
+                let capabilities = match tokio::time::timeout(timeout, call).await {
+                    Ok(Ok(result)) => Some(Capabilities::from_methods(&result)),
+                    Ok(Err(e)) => {
+                        tracing::debug!("'{}' does not support method discovery: {}", name, e);
+                        None
+                    }
+                    Err(_) => {
+                        tracing::debug!("Method discovery on '{}' timed out", name);
+                        None
+                    }
+                };
+                (name, capabilities)
+            }
+        });
+        let answers = futures::future::join_all(queries).await;
+
+        let mut entries = self.entries.lock().unwrap();
+        for (name, capabilities) in answers {
+            let uptime_seconds = services
+                .iter()
+                .find(|service| service.name == name)
+                .and_then(|service| service.uptime_seconds);
+            entries.insert(
+                name,
+                Entry {
+                    capabilities,
+                    uptime_seconds,
+                    checked_at: Instant::now(),
+                },
+            );
+        }
+        for service in services.iter_mut() {
+            if let Some(entry) = entries.get_mut(&service.name) {
+                if service.uptime_seconds.is_some() {
+                    entry.uptime_seconds = service.uptime_seconds;
+                }
+                service.capabilities = entry.capabilities.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Turn handler panics into JSON 500 responses.
+//!
+//! Without this a panic aborts the connection and the browser shows a bare
+//! network error. The response carries a reference ID that is also logged
+//! alongside the panic message.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::any::Any;
+use tower_http::catch_panic::CatchPanicLayer;
+
+use crate::api::ApiResponse;
+use crate::metrics;
+
+fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(|s| s.as_str())
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    let reference = uuid::Uuid::new_v4();
+
+    tracing::error!(%reference, "Handler panicked: {}", message);
+    metrics::record_panic();
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ApiResponse::<()>::error(&format!("Internal error (reference {})", reference)),
+    )
+        .into_response()
+}
+
+/// Layer that catches panics in handlers
+pub fn layer() -> CatchPanicLayer<fn(Box<dyn Any + Send + 'static>) -> Response> {
+    CatchPanicLayer::custom(handle_panic as fn(Box<dyn Any + Send + 'static>) -> Response)
+}
@@ -0,0 +1,438 @@
+//! Chaos testing: restarting and pausing services on purpose.
+//!
+//! Nothing happens until an operator starts an experiment with
+//! `PUT /api/admin/chaos`:
+//!
+//! ```json
+//! {
+//!   "enabled": true,
+//!   "services": ["worker-*", "cache"],
+//!   "schedule": "@every 10m",
+//!   "actions": ["restart", "pause"],
+//!   "pause_seconds": 30,
+//!   "duration": "2h"
+//! }
+//! ```
+//!
+//! Each time the schedule (as in [`scheduler`](crate::scheduler)) comes due,
+//! one running service matching `services` (names or `prefix*` patterns) is
+//! picked at random and either restarted or stopped for `pause_seconds` and
+//! started again. Services matching a `--protect` rule, by label or
+//! annotation, are never picked, so production services can't be hit by
+//! mistake; without any `--protect` rule no experiment starts, and a running
+//! one stops striking if a reload removes them. Every strike publishes a `chaos` event before it acts, and shows
+//! up as a run of the `chaos` task, so alerting and dependent services can
+//! be checked against it.
+//!
+//! An experiment ends after `duration` (1h by default, 24h at most), on
+//! `DELETE /api/admin/chaos` or a `PUT` with `"enabled": false`, and when
+//! the dashboard restarts; paused services are started again when it ends.
+//! `GET /api/admin/chaos` shows the experiment, the services it could pick
+//! and its recent strikes.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use ts_rs::TS;
+
+use crate::api::{self, ApiResponse};
+use crate::calls;
+use crate::config;
+use crate::events;
+use crate::namespaces;
+use crate::scheduler::Schedule;
+use crate::state::AppState;
+
+/// How long an experiment runs when the request doesn't say
+const DEFAULT_DURATION: Duration = Duration::from_secs(3600);
+
+/// Longest experiment that can be started
+const MAX_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+/// Longest a service can be paused
+const MAX_PAUSE_SECONDS: u64 = 3600;
+
+/// Strikes kept for `GET /api/admin/chaos`
+const RECENT_STRIKES: usize = 50;
+
+/// Why nothing is hit while no service can be protected
+const UNPROTECTED: &str =
+    "Chaos experiments need at least one --protect rule, so production services can't be hit";
+
+/// Something done to a service
+#[derive(Deserialize, Serialize, TS, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChaosAction {
+    Restart,
+    Pause,
+}
+
+fn default_actions() -> Vec<ChaosAction> {
+    vec![ChaosAction::Restart, ChaosAction::Pause]
+}
+
+fn default_pause_seconds() -> u64 {
+    30
+}
+
+/// Body of `PUT /api/admin/chaos`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChaosRequest {
+    pub enabled: bool,
+    /// Service names or `prefix*` patterns
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub schedule: String,
+    #[serde(default = "default_actions")]
+    pub actions: Vec<ChaosAction>,
+    #[serde(default = "default_pause_seconds")]
+    pub pause_seconds: u64,
+    /// How long the experiment runs, e.g. `2h`
+    #[serde(default)]
+    pub duration: Option<String>,
+}
+
+/// A running experiment
+#[derive(Clone)]
+struct Experiment {
+    services: Vec<String>,
+    spec: String,
+    schedule: Schedule,
+    actions: Vec<ChaosAction>,
+    pause_seconds: u64,
+    started_at: u64,
+    until: u64,
+}
+
+impl Experiment {
+    fn covers(&self, service: &str) -> bool {
+        self.services
+            .iter()
+            .any(|pattern| namespaces::matches(pattern, service))
+    }
+}
+
+/// One restart or pause
+#[derive(Serialize, TS, Clone)]
+pub struct ChaosStrike {
+    /// Unix timestamp in seconds
+    #[ts(type = "number")]
+    pub at: u64,
+    pub service: String,
+    pub action: ChaosAction,
+    pub ok: bool,
+    pub message: String,
+}
+
+/// The experiment as `GET /api/admin/chaos` shows it
+#[derive(Serialize, TS)]
+pub struct ChaosStatus {
+    pub enabled: bool,
+    pub services: Vec<String>,
+    pub schedule: Option<String>,
+    pub actions: Vec<ChaosAction>,
+    #[ts(type = "number")]
+    pub pause_seconds: u64,
+    /// Unix timestamps in seconds
+    #[ts(type = "number | null")]
+    pub started_at: Option<u64>,
+    #[ts(type = "number | null")]
+    pub until: Option<u64>,
+    /// Services a strike could pick now
+    pub targets: Vec<String>,
+    /// Paused services, with the Unix timestamp they start again
+    #[ts(type = "Record<string, number>")]
+    pub paused: BTreeMap<String, u64>,
+    /// Newest first
+    pub recent: Vec<ChaosStrike>,
+}
+
+/// The experiment, if one is running, and what it did
+#[derive(Default)]
+pub struct Chaos {
+    experiment: Mutex<Option<Experiment>>,
+    /// Paused services, with when they start again
+    paused: Mutex<BTreeMap<String, u64>>,
+    recent: Mutex<VecDeque<ChaosStrike>>,
+}
+
+impl Chaos {
+    /// The running experiment, ending it once its time is up
+    fn current(&self) -> Option<Experiment> {
+        let mut experiment = self.experiment.lock().unwrap();
+        if experiment
+            .as_ref()
+            .is_some_and(|running| running.until <= events::now())
+        {
+            tracing::info!("Chaos experiment ended");
+            *experiment = None;
+        }
+        experiment.clone()
+    }
+
+    /// The schedule of the running experiment, as written and parsed
+    pub fn schedule(&self) -> Option<(String, Schedule)> {
+        self.current()
+            .map(|running| (running.spec, running.schedule))
+    }
+
+    fn record(&self, strike: ChaosStrike) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_front(strike);
+        recent.truncate(RECENT_STRIKES);
+    }
+}
+
+/// A number below `n`, from the OS random source behind UUIDs
+fn random_below(n: usize) -> usize {
+    (uuid::Uuid::new_v4().as_u128() % n as u128) as usize
+}
+
+/// Running services the experiment may pick, leaving out protected ones
+async fn targets(state: &AppState, experiment: &Experiment) -> Result<Vec<String>, String> {
+    let snapshot = state.services().await;
+    let db = state.db.clone();
+    let annotations = calls::blocking(move || db.all_annotations())
+        .await
+        .map_err(|e| format!("{:#}", e))?;
+    let settings = state.settings();
+    if settings.protect.is_empty() {
+        return Err(UNPROTECTED.to_string());
+    }
+    let paused = state.chaos.paused.lock().unwrap().clone();
+
+    Ok(snapshot
+        .services
+        .iter()
+        .filter(|service| service.status != "stopped" && experiment.covers(&service.name))
+        .filter(|service| !paused.contains_key(&service.name))
+        .filter(|service| {
+            !settings.protect.iter().any(|(key, value)| {
+                service.labels.get(key) == Some(value)
+                    || annotations.get(&service.name).is_some_and(|annotations| {
+                        annotations
+                            .iter()
+                            .any(|annotation| annotation.key == *key && annotation.value == *value)
+                    })
+            })
+        })
+        .map(|service| service.name.clone())
+        .collect())
+}
+
+/// Start a paused service again, unless something else already did
+async fn resume(state: &AppState, service: &str) {
+    if state.chaos.paused.lock().unwrap().remove(service).is_none() {
+        return;
+    }
+    if let Err(e) = api::start(state, service).await {
+        tracing::warn!("Chaos failed to start paused service {}: {}", service, e);
+    }
+}
+
+/// End the experiment and start the services it paused
+async fn stop_experiment(state: &AppState) {
+    if state.chaos.experiment.lock().unwrap().take().is_some() {
+        tracing::info!("Chaos experiment stopped");
+    }
+    let paused: Vec<String> = state.chaos.paused.lock().unwrap().keys().cloned().collect();
+    for service in paused {
+        resume(state, &service).await;
+    }
+}
+
+/// Restart or pause one service picked at random; run by the `chaos` task
+pub async fn strike(state: &AppState) -> Result<String, String> {
+    let Some(experiment) = state.chaos.current() else {
+        return Ok("No chaos experiment is running".to_string());
+    };
+    let targets = targets(state, &experiment).await?;
+    if targets.is_empty() {
+        return Ok("No running, unprotected service matches the experiment".to_string());
+    }
+    let service = targets[random_below(targets.len())].clone();
+    let action = experiment.actions[random_below(experiment.actions.len())];
+
+    let verb = match action {
+        ChaosAction::Restart => "restarting".to_string(),
+        ChaosAction::Pause => format!("pausing for {}s", experiment.pause_seconds),
+    };
+    state.events.publish(
+        &service,
+        events::CHAOS,
+        format!("Chaos experiment {} '{}'", verb, service),
+    );
+    tracing::warn!("Chaos experiment {} {}", verb, service);
+
+    let result = match action {
+        ChaosAction::Restart => api::restart(state, &service).await,
+        ChaosAction::Pause => {
+            let resume_at = events::now() + experiment.pause_seconds;
+            state
+                .chaos
+                .paused
+                .lock()
+                .unwrap()
+                .insert(service.clone(), resume_at);
+            let result = api::stop(state, &service).await;
+            match &result {
+                Ok(_) => {
+                    let state = state.clone();
+                    let service = service.clone();
+                    let pause = Duration::from_secs(experiment.pause_seconds);
+                    tokio::spawn(async move {
+                        tokio::time::sleep(pause).await;
+                        resume(&state, &service).await;
+                    });
+                }
+                Err(_) => {
+                    state.chaos.paused.lock().unwrap().remove(&service);
+                }
+            }
+            result
+        }
+    };
+
+    let (ok, message) = match result {
+        Ok(message) => (true, format!("Chaos: {}", message)),
+        Err(e) => (false, e),
+    };
+    state.chaos.record(ChaosStrike {
+        at: events::now(),
+        service,
+        action,
+        ok,
+        message: message.clone(),
+    });
+    if ok {
+        Ok(message)
+    } else {
+        Err(message)
+    }
+}
+
+async fn status(state: &AppState) -> ChaosStatus {
+    let experiment = state.chaos.current();
+    let targets = match &experiment {
+        Some(experiment) => targets(state, experiment).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to list chaos targets: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let recent = state.chaos.recent.lock().unwrap().iter().cloned().collect();
+    let paused = state.chaos.paused.lock().unwrap().clone();
+    match experiment {
+        Some(experiment) => ChaosStatus {
+            enabled: true,
+            services: experiment.services,
+            schedule: Some(experiment.spec),
+            actions: experiment.actions,
+            pause_seconds: experiment.pause_seconds,
+            started_at: Some(experiment.started_at),
+            until: Some(experiment.until),
+            targets,
+            paused,
+            recent,
+        },
+        None => ChaosStatus {
+            enabled: false,
+            services: Vec::new(),
+            schedule: None,
+            actions: Vec::new(),
+            pause_seconds: 0,
+            started_at: None,
+            until: None,
+            targets,
+            paused,
+            recent,
+        },
+    }
+}
+
+fn parse(request: ChaosRequest) -> Result<Experiment, String> {
+    if request
+        .services
+        .iter()
+        .all(|pattern| pattern.trim().is_empty())
+    {
+        return Err("Name the services the experiment may hit in 'services'".to_string());
+    }
+    let schedule = Schedule::parse(&request.schedule)
+        .map_err(|e| format!("Invalid schedule '{}': {}", request.schedule, e))?;
+    if request.actions.is_empty() {
+        return Err("Give at least one action: restart or pause".to_string());
+    }
+    if request.pause_seconds == 0 || request.pause_seconds > MAX_PAUSE_SECONDS {
+        return Err(format!(
+            "pause_seconds must be between 1 and {}",
+            MAX_PAUSE_SECONDS
+        ));
+    }
+    let duration = match &request.duration {
+        Some(duration) => config::parse_duration(duration)?,
+        None => DEFAULT_DURATION,
+    };
+    if duration > MAX_DURATION {
+        return Err(format!(
+            "An experiment can run for {}h at most",
+            MAX_DURATION.as_secs() / 3600
+        ));
+    }
+    let now = events::now();
+    Ok(Experiment {
+        services: request
+            .services
+            .into_iter()
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect(),
+        spec: request.schedule.trim().to_string(),
+        schedule,
+        actions: request.actions,
+        pause_seconds: request.pause_seconds,
+        started_at: now,
+        until: now + duration.as_secs(),
+    })
+}
+
+/// The experiment and its recent strikes
+pub async fn get_chaos(State(state): State<AppState>) -> impl IntoResponse {
+    ApiResponse::success(status(&state).await)
+}
+
+/// Start, replace or (with `"enabled": false`) stop the experiment
+pub async fn update_chaos(
+    State(state): State<AppState>,
+    Json(request): Json<ChaosRequest>,
+) -> impl IntoResponse {
+    if !request.enabled {
+        stop_experiment(&state).await;
+        return (StatusCode::OK, ApiResponse::success(status(&state).await));
+    }
+    if state.settings().protect.is_empty() {
+        return (StatusCode::CONFLICT, ApiResponse::error(UNPROTECTED));
+    }
+    let experiment = match parse(request) {
+        Ok(experiment) => experiment,
+        Err(e) => return (StatusCode::BAD_REQUEST, ApiResponse::error(&e)),
+    };
+    tracing::warn!(
+        "Chaos experiment started on {} ({}) until {}",
+        experiment.services.join(", "),
+        experiment.spec,
+        experiment.until
+    );
+    *state.chaos.experiment.lock().unwrap() = Some(experiment);
+    (StatusCode::OK, ApiResponse::success(status(&state).await))
+}
+
+/// Stop the experiment
+pub async fn delete_chaos(State(state): State<AppState>) -> impl IntoResponse {
+    stop_experiment(&state).await;
+    ApiResponse::success(status(&state).await)
+}
@@ -0,0 +1,365 @@
+//! One-shot CLI subcommands that run without starting the server.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{CommandFactory, Subcommand};
+use clap_complete::Shell;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::api::{self, ApiResponse, ServiceInfo};
+use crate::backup;
+use crate::config::{Args, Config};
+use crate::db::{Database, DB_FILE};
+use crate::events::{self, Event};
+use crate::health_cache::{HealthCache, ProbeOptions};
+use crate::hosts::HostInfo;
+use crate::scaffold;
+use crate::state::Settings;
+use crate::transport;
+
+/// One-off commands always probe daemons directly
+const FRESH: ProbeOptions = ProbeOptions {
+    fresh: true,
+    timeout: None,
+};
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Probe all services once and print their status
+    ///
+    /// Exits with a non-zero code if any service is unhealthy or unreachable.
+    Status {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Dump services, health, recent events and a config summary to a file
+    Export {
+        /// Output file, or `-` for stdout
+        #[arg(long, default_value = "-")]
+        out: PathBuf,
+    },
+
+    /// Archive the database, webhook subscriptions and config file
+    Backup {
+        /// Output file, or `-` for stdout
+        #[arg(long, default_value = "-")]
+        out: PathBuf,
+    },
+
+    /// Restore an archive made by `backup` or `GET /api/admin/backup`
+    ///
+    /// Stop the dashboard first, or use `POST /api/admin/restore` instead.
+    Restore {
+        /// Archive to restore
+        archive: PathBuf,
+    },
+
+    /// Check for common setup problems
+    ///
+    /// Exits with 0 if everything looks fine, 1 on warnings and 2 on failures.
+    Doctor {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Create the skeleton of a new service in the first services dir
+    ///
+    /// Writes a manifest, socket config and start script from templates,
+    /// which files in `<data-dir>/templates` override.
+    NewService {
+        /// Service name: lowercase letters, digits, `-` and `_`
+        name: String,
+        /// One line describing the service
+        #[arg(long)]
+        description: Option<String>,
+    },
+
+    /// Install the dashboard as a systemd user unit or launchd agent
+    ///
+    /// Flags given before the subcommand are baked into the service.
+    InstallService {
+        /// Start (or restart) the service after installing it
+        #[arg(long)]
+        start: bool,
+    },
+
+    /// Print shell completions to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
+}
+
+/// Whether a status counts as a problem for scripted checks
+///
+/// Stopped services are reported but not treated as failures.
+pub fn is_unhealthy(status: &str) -> bool {
+    !matches!(status, "running" | "healthy" | "stopped")
+}
+
+/// Format an uptime the same way as the web UI
+pub fn format_uptime(seconds: Option<u64>) -> String {
+    match seconds {
+        None | Some(0) => "-".to_string(),
+        Some(s) if s < 60 => format!("{}s", s),
+        Some(s) if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        Some(s) if s < 86400 => format!("{}h {}m", s / 3600, (s % 3600) / 60),
+        Some(s) => format!("{}d {}h", s / 86400, (s % 86400) / 3600),
+    }
+}
+
+fn print_table(services: &[ServiceInfo]) {
+    let rows: Vec<[String; 4]> = services
+        .iter()
+        .map(|s| {
+            [
+                s.name.clone(),
+                s.status.clone(),
+                s.version.clone().unwrap_or_else(|| "-".to_string()),
+                format_uptime(s.uptime_seconds),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "STATUS", "VERSION", "UPTIME"].map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Run `fgp-dashboard status`, returning whether every service is healthy
+pub async fn status(config: &Config, json: bool) -> Result<bool> {
+    let services = api::scan_services(
+        &Settings::from_config(config),
+        &HealthCache::default(),
+        FRESH,
+    )
+    .await;
+    let healthy = !services.iter().any(|s| is_unhealthy(&s.status));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&services)?);
+    } else if services.is_empty() {
+        println!("No services installed");
+    } else {
+        print_table(&services);
+    }
+
+    Ok(healthy)
+}
+
+/// Non-secret summary of the configuration
+#[derive(Serialize)]
+pub struct ConfigSummary {
+    pub port: u16,
+    pub bind: String,
+    pub services_dirs: Vec<PathBuf>,
+    pub data_dir: PathBuf,
+    pub peers: Vec<String>,
+}
+
+/// A service together with its full health payload
+#[derive(Serialize)]
+pub struct ServiceSnapshot {
+    #[serde(flatten)]
+    pub info: ServiceInfo,
+    pub health: Option<serde_json::Value>,
+    pub health_error: Option<String>,
+}
+
+/// Everything `fgp-dashboard export` writes
+#[derive(Serialize)]
+pub struct Snapshot {
+    pub generated_at: u64,
+    pub dashboard_version: &'static str,
+    pub host: HostInfo,
+    pub config: ConfigSummary,
+    pub services: Vec<ServiceSnapshot>,
+    /// Recent events, fetched from the running dashboard if there is one
+    pub events: Vec<Event>,
+    pub events_error: Option<String>,
+}
+
+/// Fetch recent events from a dashboard running with this config
+async fn fetch_events(config: &Config) -> Result<Vec<Event>> {
+    let host = if config.bind.is_unspecified() {
+        "127.0.0.1".to_string()
+    } else {
+        config.bind.to_string()
+    };
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let url = format!("{}://{}:{}/api/events", scheme, host, config.port);
+
+    // The certificate names the host as others reach it, which this address
+    // may not match
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .danger_accept_invalid_certs(config.tls.is_some())
+        .build()?;
+    let mut request = client.get(&url);
+    if let Some(token) = config.auth_tokens.first() {
+        request = request.bearer_auth(token);
+    }
+    let response: ApiResponse<Vec<Event>> = request
+        .send()
+        .await
+        .with_context(|| format!("No dashboard reachable at {}", url))?
+        .json()
+        .await?;
+
+    response
+        .data
+        .ok_or_else(|| anyhow!(response.error.unwrap_or_default()))
+}
+
+/// Run `fgp-dashboard export`
+pub async fn export(config: &Config, out: &Path) -> Result<()> {
+    let settings = Settings::from_config(config);
+
+    let services = api::scan_services(&settings, &HealthCache::default(), FRESH)
+        .await
+        .into_iter()
+        .map(|info| {
+            let (health, health_error) = if transport::endpoint_exists(Path::new(&info.socket_path))
+            {
+                match api::probe_health(Path::new(&info.socket_path), settings.socket_retries) {
+                    Ok(health) => (Some(health), None),
+                    Err(e) => (None, Some(e)),
+                }
+            } else {
+                (None, None)
+            };
+            ServiceSnapshot {
+                info,
+                health,
+                health_error,
+            }
+        })
+        .collect();
+
+    let (events, events_error) = match fetch_events(config).await {
+        Ok(events) => (events, None),
+        Err(e) => (Vec::new(), Some(format!("{:#}", e))),
+    };
+
+    let snapshot = Snapshot {
+        generated_at: events::now(),
+        dashboard_version: env!("CARGO_PKG_VERSION"),
+        host: settings.host.clone(),
+        config: ConfigSummary {
+            port: config.port,
+            bind: config.bind.to_string(),
+            services_dirs: config.services_dirs.clone(),
+            data_dir: config.data_dir.clone(),
+            peers: config.peers.clone(),
+        },
+        services,
+        events,
+        events_error,
+    };
+
+    let json = serde_json::to_string_pretty(&snapshot)?;
+    if out == Path::new("-") {
+        println!("{}", json);
+    } else {
+        fs::write(out, json).with_context(|| format!("Failed to write {}", out.display()))?;
+        eprintln!("Snapshot written to {}", out.display());
+    }
+
+    Ok(())
+}
+
+fn open_backup_paths(config: &Config, args: &Args) -> Result<(Database, backup::Paths)> {
+    let db = Database::open(&config.data_dir.join(DB_FILE))?;
+    let paths = backup::Paths {
+        data_dir: config.data_dir.clone(),
+        config_file: args.config_path(),
+    };
+    Ok((db, paths))
+}
+
+/// Run `fgp-dashboard backup`
+pub fn backup(config: &Config, args: &Args, out: &Path) -> Result<()> {
+    let (db, paths) = open_backup_paths(config, args)?;
+    let archive = backup::create(&db, &paths)?;
+    if out == Path::new("-") {
+        std::io::stdout().write_all(&archive)?;
+    } else {
+        fs::write(out, archive).with_context(|| format!("Failed to write {}", out.display()))?;
+        eprintln!("Backup written to {}", out.display());
+    }
+    Ok(())
+}
+
+/// Run `fgp-dashboard restore <archive>`
+pub fn restore(config: &Config, args: &Args, archive: &Path) -> Result<()> {
+    let bytes =
+        fs::read(archive).with_context(|| format!("Failed to read {}", archive.display()))?;
+    let (db, paths) = open_backup_paths(config, args)?;
+    let restored = backup::restore(&db, &paths, &bytes)?;
+    eprintln!(
+        "Restored the database{}{} from {}",
+        if restored.subscriptions {
+            ", subscriptions"
+        } else {
+            ""
+        },
+        if restored.config { ", config" } else { "" },
+        archive.display()
+    );
+    Ok(())
+}
+
+/// Run `fgp-dashboard new-service <name>`
+pub fn new_service(config: &Config, name: &str, description: Option<&str>) -> Result<()> {
+    scaffold::validate_name(name).map_err(anyhow::Error::msg)?;
+    if let Some(dir) = scaffold::existing(&config.services_dirs, name) {
+        return Err(anyhow!(
+            "Service '{}' already exists at {}",
+            name,
+            dir.display()
+        ));
+    }
+    let services_dir = config
+        .services_dirs
+        .first()
+        .ok_or_else(|| anyhow!("No services directory is configured"))?;
+    let created = scaffold::scaffold(services_dir, &config.data_dir, name, description)
+        .map_err(anyhow::Error::msg)?;
+
+    eprintln!("Created {}", created.path);
+    for file in &created.files {
+        eprintln!("  {}", file);
+    }
+    eprintln!("Edit start.sh to launch the daemon, then start the service from the dashboard");
+    Ok(())
+}
+
+/// Run `fgp-dashboard completions <shell>`
+pub fn completions(shell: Shell) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+}
@@ -0,0 +1,182 @@
+//! Cluster-wide views across peer dashboards.
+//!
+//! Peers are other `fgp-dashboard` instances passed with `--peer <URL>`. Each
+//! peer reports its own host labels and services over the regular REST API,
+//! so the local dashboard can compare them without any extra agent protocol.
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::calls::BackendCall;
+use crate::hosts::{HostInfo, LabelQuery, LabelSelector};
+use crate::state::AppState;
+
+/// Services reported by a single host
+pub struct HostSnapshot {
+    pub host: HostInfo,
+    pub services: Vec<ServiceInfo>,
+}
+
+/// A peer that could not be queried
+#[derive(Serialize)]
+pub struct PeerError {
+    pub peer: String,
+    pub error: String,
+}
+
+/// How a single service differs across hosts
+#[derive(Serialize)]
+pub struct ServiceDrift {
+    pub name: String,
+    /// Reported version per host that has the service installed
+    pub versions: BTreeMap<String, Option<String>>,
+    /// Hosts that don't have the service installed
+    pub missing_on: Vec<String>,
+    pub version_mismatch: bool,
+}
+
+/// Result of comparing services across hosts
+#[derive(Serialize)]
+pub struct ClusterDiff {
+    pub hosts: Vec<String>,
+    pub services: Vec<ServiceDrift>,
+    pub unreachable: Vec<PeerError>,
+    /// True if any service is missing somewhere or runs different versions
+    pub drift: bool,
+}
+
+/// Fetch an `ApiResponse` payload from a peer
+async fn fetch<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+    let _call = BackendCall::start(format!("GET {}", url));
+    let response: ApiResponse<T> = client.get(url).send().await?.json().await?;
+
+    match response.data {
+        Some(data) if response.ok => Ok(data),
+        _ => Err(anyhow!(response
+            .error
+            .unwrap_or_else(|| "empty response".to_string()))),
+    }
+}
+
+/// Fetch a peer's host identity and services
+async fn fetch_peer(client: &reqwest::Client, base: &str) -> Result<HostSnapshot> {
+    let base = base.trim_end_matches('/');
+
+    let hosts: Vec<HostInfo> = fetch(client, &format!("{}/api/hosts", base)).await?;
+    let host = hosts
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("peer reported no hosts"))?;
+    let services = fetch(client, &format!("{}/api/services", base)).await?;
+
+    Ok(HostSnapshot { host, services })
+}
+
+/// Collect snapshots from the local host and every peer
+pub async fn snapshots(state: &AppState) -> (Vec<HostSnapshot>, Vec<PeerError>) {
+    let settings = state.settings();
+    let mut snapshots = vec![HostSnapshot {
+        host: settings.host.clone(),
+        services: state.services().await.services.clone(),
+    }];
+    let mut errors = Vec::new();
+
+    let results = futures::future::join_all(
+        settings
+            .peers
+            .iter()
+            .map(|peer| fetch_peer(&state.http, peer)),
+    )
+    .await;
+
+    for (peer, result) in settings.peers.iter().zip(results) {
+        match result {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => errors.push(PeerError {
+                peer: peer.clone(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    (snapshots, errors)
+}
+
+/// Compare installed services and versions across hosts
+pub fn compute_drift(snapshots: &[HostSnapshot]) -> Vec<ServiceDrift> {
+    let names: BTreeSet<&str> = snapshots
+        .iter()
+        .flat_map(|snapshot| snapshot.services.iter().map(|s| s.name.as_str()))
+        .collect();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let mut versions = BTreeMap::new();
+            let mut missing_on = Vec::new();
+
+            for snapshot in snapshots {
+                match snapshot.services.iter().find(|s| s.name == name) {
+                    Some(service) => {
+                        versions.insert(snapshot.host.name.clone(), service.version.clone());
+                    }
+                    None => missing_on.push(snapshot.host.name.clone()),
+                }
+            }
+
+            // Stopped services report no version, so only compare known ones
+            let known: BTreeSet<&String> = versions.values().flatten().collect();
+
+            ServiceDrift {
+                name: name.to_string(),
+                versions,
+                missing_on,
+                version_mismatch: known.len() > 1,
+            }
+        })
+        .collect()
+}
+
+/// Compare services across the local host and all peers
+pub async fn diff(
+    State(state): State<AppState>,
+    Query(query): Query<LabelQuery>,
+) -> impl IntoResponse {
+    let selector = match LabelSelector::from_query(query.label.as_deref()) {
+        Ok(selector) => selector,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<ClusterDiff>::error(&e),
+            )
+        }
+    };
+
+    let (snapshots, unreachable) = snapshots(&state).await;
+    let snapshots: Vec<HostSnapshot> = snapshots
+        .into_iter()
+        .filter(|snapshot| selector.matches(&snapshot.host.labels))
+        .collect();
+
+    let services = compute_drift(&snapshots);
+    let drift = services
+        .iter()
+        .any(|s| s.version_mismatch || !s.missing_on.is_empty());
+
+    (
+        StatusCode::OK,
+        ApiResponse::success(ClusterDiff {
+            hosts: snapshots.into_iter().map(|s| s.host.name).collect(),
+            services,
+            unreachable,
+            drift,
+        }),
+    )
+}
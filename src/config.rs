@@ -0,0 +1,913 @@
+//! Dashboard configuration.
+//!
+//! Every setting can come from four places, in order of precedence:
+//!
+//! 1. Command-line flags (`--port 9000`)
+//! 2. `FGP_DASHBOARD_*` environment variables (`FGP_DASHBOARD_PORT=9000`)
+//! 3. The TOML config file (`--config`, default `~/.fgp/dashboard/config.toml`)
+//! 4. Built-in defaults
+//!
+//! List settings (auth tokens, labels, peers, method patterns, services dirs, per-service
+//! health timeouts, protect rules) take a comma-separated list in environment variables and
+//! replace, rather than extend, lower layers.
+//!
+//! Sending SIGHUP or calling `POST /api/admin/reload-config` re-reads the
+//! config file. The bind address, port, TLS files, data dir, concurrency
+//! limits and request body limits need a restart, and the daemon and log output settings
+//! only matter at startup; everything else is applied immediately.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::api;
+use crate::binary;
+use crate::body_limit;
+use crate::cli::Command;
+use crate::health_cache;
+use crate::hosts;
+use crate::logging::{self, LogFormat, LogRotation};
+use crate::metrics;
+use crate::namespaces::Namespace;
+use crate::plugins;
+use crate::quotas::Quotas;
+use crate::redact;
+use crate::retention;
+use crate::retry;
+use crate::rpc;
+use crate::s3::{self, S3Config};
+use crate::scheduler::{Task, TaskConfig};
+use crate::scripts;
+use crate::secrets;
+use crate::shed;
+use crate::state;
+use crate::store;
+use crate::timeout;
+use crate::tls;
+use crate::ui::{self, Branding, ConfirmStop};
+use crate::updates;
+use crate::wasm;
+use crate::webhooks::WebhookConfig;
+use crate::widgets::Widget;
+
+/// Port used when none is configured
+pub const DEFAULT_PORT: u16 = 8765;
+
+/// FGP Dashboard - Web UI for monitoring daemon services
+#[derive(Parser, Clone)]
+#[command(name = "fgp-dashboard")]
+#[command(author, version, about)]
+#[command(
+    after_help = "Settings are resolved as: flags > FGP_DASHBOARD_* env vars > config file > defaults"
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file (defaults to ~/.fgp/dashboard/config.toml if it exists)
+    #[arg(long, global = true, env = "FGP_DASHBOARD_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Port to listen on [default: 8765]
+    #[arg(short, long, env = "FGP_DASHBOARD_PORT")]
+    pub port: Option<u16>,
+
+    /// Address to bind to [default: 127.0.0.1]
+    #[arg(long, env = "FGP_DASHBOARD_BIND")]
+    pub bind: Option<IpAddr>,
+
+    /// Open browser automatically
+    #[arg(short, long, env = "FGP_DASHBOARD_OPEN")]
+    pub open: bool,
+
+    /// Serve a synthetic fleet instead of the installed services, touching no files or sockets
+    #[arg(long, env = "FGP_DASHBOARD_DEMO")]
+    pub demo: bool,
+
+    /// Require this token on every request; repeat to accept several
+    #[arg(
+        long = "auth-token",
+        value_name = "TOKEN",
+        env = "FGP_DASHBOARD_AUTH_TOKENS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    pub auth_tokens: Vec<String>,
+
+    /// Serve HTTPS with this PEM certificate chain (needs `--tls-key`)
+    #[arg(long, value_name = "PATH", env = "FGP_DASHBOARD_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of `--tls-cert`
+    #[arg(long, value_name = "PATH", env = "FGP_DASHBOARD_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Allow a non-loopback `--bind` without authentication or TLS
+    #[arg(long, env = "FGP_DASHBOARD_INSECURE_LAN")]
+    pub insecure_lan: bool,
+
+    /// Name reported for this host (defaults to the system hostname)
+    #[arg(long, env = "FGP_DASHBOARD_HOST_NAME")]
+    pub host_name: Option<String>,
+
+    /// Label this host, e.g. `--label env=prod --label region=eu`
+    #[arg(
+        long = "label",
+        value_name = "KEY=VALUE",
+        value_parser = hosts::parse_label,
+        env = "FGP_DASHBOARD_LABELS",
+        value_delimiter = ','
+    )]
+    pub labels: Vec<(String, String)>,
+
+    /// Base URL of a peer dashboard to include in cluster views
+    #[arg(
+        long = "peer",
+        value_name = "URL",
+        env = "FGP_DASHBOARD_PEERS",
+        value_delimiter = ','
+    )]
+    pub peers: Vec<String>,
+
+    /// Only allow these methods through `/api/call` (`method`, `service.method`, or `prefix*`)
+    #[arg(
+        long = "allow-method",
+        value_name = "PATTERN",
+        env = "FGP_DASHBOARD_ALLOW_METHODS",
+        value_delimiter = ','
+    )]
+    pub allow_methods: Vec<String>,
+
+    /// Never allow these methods through `/api/call`
+    #[arg(
+        long = "deny-method",
+        value_name = "PATTERN",
+        env = "FGP_DASHBOARD_DENY_METHODS",
+        value_delimiter = ','
+    )]
+    pub deny_methods: Vec<String>,
+
+    /// Mask values of keys containing these in payloads, configs and logs
+    /// [default: password,token,secret,key]
+    #[arg(
+        long = "redact-key",
+        value_name = "PATTERN",
+        env = "FGP_DASHBOARD_REDACT_KEYS",
+        value_delimiter = ','
+    )]
+    pub redact_keys: Vec<String>,
+
+    /// Directory for the dashboard's own state (defaults to ~/.fgp/dashboard)
+    #[arg(long, env = "FGP_DASHBOARD_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// FGP services directory to monitor; repeat to merge several, earlier
+    /// ones winning on name collisions (defaults to ~/.fgp/services)
+    #[arg(
+        long = "services-dir",
+        value_name = "PATH",
+        env = "FGP_DASHBOARD_SERVICES_DIR",
+        value_delimiter = ','
+    )]
+    pub services_dirs: Vec<PathBuf>,
+
+    /// Fork into the background after startup (Unix only)
+    #[arg(long, env = "FGP_DASHBOARD_DAEMON")]
+    pub daemon: bool,
+
+    /// PID file written by `--daemon` (defaults to <data dir>/dashboard.pid)
+    #[arg(long, env = "FGP_DASHBOARD_PID_FILE")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Write logs to this file instead of stdout
+    #[arg(long, env = "FGP_DASHBOARD_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Start a new log file `hourly`, `daily`, `never`, or at a size like `10MB` [default: daily]
+    #[arg(long, value_parser = logging::parse_rotation, env = "FGP_DASHBOARD_LOG_ROTATION")]
+    pub log_rotation: Option<LogRotation>,
+
+    /// Number of rotated log files to keep [default: 7]
+    #[arg(long, env = "FGP_DASHBOARD_LOG_RETENTION")]
+    pub log_retention: Option<usize>,
+
+    /// Log line format [default: text]
+    #[arg(long, value_enum, env = "FGP_DASHBOARD_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Log every HTTP request (target `fgp_dashboard::access`)
+    #[arg(long, env = "FGP_DASHBOARD_ACCESS_LOG")]
+    pub access_log: bool,
+
+    /// Fail requests taking longer than this many seconds with 504; 0 disables [default: 10]
+    #[arg(long, value_name = "SECONDS", env = "FGP_DASHBOARD_REQUEST_TIMEOUT")]
+    pub request_timeout: Option<u64>,
+
+    /// Warn about requests taking longer than this many milliseconds; 0 disables [default: 1000]
+    #[arg(long, value_name = "MS", env = "FGP_DASHBOARD_SLOW_REQUEST_MS")]
+    pub slow_request_ms: Option<u64>,
+
+    /// Reuse daemon health results for this many milliseconds; 0 disables [default: 2000]
+    #[arg(long, value_name = "MS", env = "FGP_DASHBOARD_HEALTH_CACHE_TTL_MS")]
+    pub health_cache_ttl_ms: Option<u64>,
+
+    /// Rescan services in the background every this many seconds [default: 2]
+    #[arg(long, value_name = "SECONDS", env = "FGP_DASHBOARD_POLL_INTERVAL")]
+    pub poll_interval: Option<u64>,
+
+    /// Retry daemon calls failing with transient socket errors this many times [default: 2]
+    #[arg(long, value_name = "N", env = "FGP_DASHBOARD_SOCKET_RETRIES")]
+    pub socket_retries: Option<u32>,
+
+    /// Give up on a daemon's health call after this many milliseconds [default: 2000]
+    #[arg(long, value_name = "MS", env = "FGP_DASHBOARD_HEALTH_TIMEOUT_MS")]
+    pub health_timeout_ms: Option<u64>,
+
+    /// Health timeout for one service, e.g. `--service-health-timeout gateway=5000`
+    #[arg(
+        long = "service-health-timeout",
+        value_name = "SERVICE=MS",
+        value_parser = parse_service_timeout,
+        env = "FGP_DASHBOARD_SERVICE_HEALTH_TIMEOUTS",
+        value_delimiter = ','
+    )]
+    pub service_health_timeouts: Vec<(String, u64)>,
+
+    /// Don't send passthrough results larger than this to the client, e.g. `256MB`; they are still read whole from the daemon [default: 64MB]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "FGP_DASHBOARD_MAX_RPC_RESPONSE_SIZE")]
+    pub max_rpc_response_size: Option<u64>,
+
+    /// Concurrent read requests before shedding load [default: 256]
+    #[arg(long, value_name = "N", env = "FGP_DASHBOARD_MAX_CONCURRENT_READS")]
+    pub max_concurrent_reads: Option<usize>,
+
+    /// Concurrent start/stop/admin requests before shedding load [default: 16]
+    #[arg(long, value_name = "N", env = "FGP_DASHBOARD_MAX_CONCURRENT_CONTROL")]
+    pub max_concurrent_control: Option<usize>,
+
+    /// Concurrent passthrough calls before shedding load [default: 64]
+    #[arg(long, value_name = "N", env = "FGP_DASHBOARD_MAX_CONCURRENT_CALLS")]
+    pub max_concurrent_calls: Option<usize>,
+
+    /// Refuse request bodies larger than this on upload endpoints, e.g. `4MB` [default: 1MB]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "FGP_DASHBOARD_MAX_REQUEST_BODY")]
+    pub max_request_body: Option<u64>,
+
+    /// Refuse passthrough calls with bodies larger than this, e.g. `256MB` [default: 64MB]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, env = "FGP_DASHBOARD_MAX_RPC_UPLOAD_SIZE")]
+    pub max_rpc_upload_size: Option<u64>,
+
+    /// Keep a tier of history for this long: `raw`, `5m`, `1h` or `events`, e.g. `--retention raw=24h,1h=2y`
+    #[arg(
+        long,
+        value_name = "TIER=DURATION",
+        value_parser = parse_retention,
+        env = "FGP_DASHBOARD_RETENTION",
+        value_delimiter = ','
+    )]
+    pub retention: Vec<(String, Duration)>,
+
+    /// Serve UI assets from this directory, falling back to the built-in ones
+    #[arg(long, env = "FGP_DASHBOARD_ASSETS_DIR")]
+    pub assets_dir: Option<PathBuf>,
+
+    /// When the UI asks before stopping a service [default: always]
+    #[arg(long, value_enum, env = "FGP_DASHBOARD_CONFIRM_STOP")]
+    pub confirm_stop: Option<ConfirmStop>,
+
+    /// Make the UI ask for the name before stopping services with this label or annotation, e.g. `--protect env=production`
+    #[arg(
+        long = "protect",
+        value_name = "KEY=VALUE",
+        value_parser = hosts::parse_label,
+        env = "FGP_DASHBOARD_PROTECT",
+        value_delimiter = ','
+    )]
+    pub protect: Vec<(String, String)>,
+
+    /// Seconds the UI holds a confirmed stop so it can be undone; 0 disables [default: 5]
+    #[arg(long, value_name = "SECONDS", env = "FGP_DASHBOARD_STOP_UNDO_SECONDS")]
+    pub stop_undo_seconds: Option<u64>,
+
+    /// URL or file with the latest version of each service, to flag outdated ones
+    #[arg(long, value_name = "URL", env = "FGP_DASHBOARD_UPDATE_INDEX")]
+    pub update_index: Option<String>,
+
+    /// How often latest versions are looked up [default: 6h]
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        env = "FGP_DASHBOARD_UPDATE_CHECK_INTERVAL"
+    )]
+    pub update_check_interval: Option<Duration>,
+
+    /// Publish an `update_available` event when a service falls behind
+    #[arg(long, env = "FGP_DASHBOARD_UPDATE_ALERTS")]
+    pub update_alerts: bool,
+
+    /// Also show daemons running in Docker containers labelled `fgp.service`
+    #[arg(long, env = "FGP_DASHBOARD_DOCKER")]
+    pub docker: bool,
+
+    /// Show the state of each service's systemd unit and control it through
+    /// `systemctl`
+    #[arg(long, env = "FGP_DASHBOARD_SYSTEMD")]
+    pub systemd: bool,
+
+    /// Show the state of each service's launch agent and control it through
+    /// `launchctl` (macOS)
+    #[arg(long, env = "FGP_DASHBOARD_LAUNCHD")]
+    pub launchd: bool,
+
+    /// Also show daemons in Kubernetes pods matching this label selector and
+    /// labelled `fgp.service`; use `""` for every such pod
+    #[arg(long, value_name = "SELECTOR", env = "FGP_DASHBOARD_KUBERNETES")]
+    pub kubernetes: Option<String>,
+
+    /// Namespace to look for pods in, instead of the kubectl context's
+    #[arg(
+        long,
+        value_name = "NAMESPACE",
+        env = "FGP_DASHBOARD_KUBERNETES_NAMESPACE"
+    )]
+    pub kubernetes_namespace: Option<String>,
+
+    /// Directory of plugin libraries and WASM checks to load at startup
+    /// (defaults to <data-dir>/plugins)
+    #[arg(long, value_name = "PATH", env = "FGP_DASHBOARD_PLUGIN_DIR")]
+    pub plugin_dir: Option<PathBuf>,
+
+    /// Fuel a WASM check may burn per service, roughly instructions [default: 10000000]
+    #[arg(long, value_name = "UNITS", env = "FGP_DASHBOARD_WASM_FUEL")]
+    pub wasm_fuel: Option<u64>,
+
+    /// Memory a WASM check may use [default: 16]
+    #[arg(long, value_name = "MIB", env = "FGP_DASHBOARD_WASM_MEMORY_MB")]
+    pub wasm_memory_mb: Option<usize>,
+
+    /// Directory of Rhai scripts to run on events or a schedule (defaults to
+    /// <data-dir>/scripts)
+    #[arg(long, value_name = "PATH", env = "FGP_DASHBOARD_SCRIPT_DIR")]
+    pub script_dir: Option<PathBuf>,
+}
+
+/// Parse a duration such as `90s`, `30m`, `48h`, `30d`, `2w` or `1y`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        Some((i, 'd')) => (&s[..i], 86400),
+        Some((i, 'w')) => (&s[..i], 7 * 86400),
+        Some((i, 'y')) => (&s[..i], 365 * 86400),
+        _ => (s, 1),
+    };
+    match digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(unit))
+    {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(format!(
+            "invalid duration '{}', expected e.g. 48h or 30d",
+            s
+        )),
+    }
+}
+
+/// Parse a `tier=duration` retention rule
+fn parse_retention(s: &str) -> Result<(String, Duration), String> {
+    match s.split_once('=') {
+        Some((tier, keep)) => Ok((tier.trim().to_string(), parse_duration(keep)?)),
+        None => Err(format!("invalid retention '{}', expected TIER=DURATION", s)),
+    }
+}
+
+/// Parse a size such as `512KB`, `10MB`, `1GB` or a plain byte count
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let (digits, multiplier) = if let Some(n) = lower.strip_suffix("gb") {
+        (n, 1 << 30)
+    } else if let Some(n) = lower.strip_suffix("mb") {
+        (n, 1 << 20)
+    } else if let Some(n) = lower.strip_suffix("kb") {
+        (n, 1 << 10)
+    } else {
+        (lower.strip_suffix('b').unwrap_or(&lower), 1)
+    };
+
+    match digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(multiplier))
+    {
+        Some(size) => Ok(size),
+        None => Err(format!("invalid size '{}', expected a size like 10MB", s)),
+    }
+}
+
+/// Parse a `service=milliseconds` pair
+fn parse_service_timeout(s: &str) -> Result<(String, u64), String> {
+    match s.split_once('=') {
+        Some((service, ms)) if !service.trim().is_empty() => ms
+            .trim()
+            .parse()
+            .map(|ms| (service.trim().to_string(), ms))
+            .map_err(|_| format!("invalid timeout '{}', expected milliseconds", ms)),
+        _ => Err(format!("invalid timeout '{}', expected SERVICE=MS", s)),
+    }
+}
+
+/// Settings read from the config file; everything is optional
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub port: Option<u16>,
+    pub bind: Option<IpAddr>,
+    pub open: Option<bool>,
+    pub auth_tokens: Vec<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub insecure_lan: Option<bool>,
+    pub host_name: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub peers: Vec<String>,
+    pub allow_methods: Vec<String>,
+    pub deny_methods: Vec<String>,
+    pub redact_keys: Vec<String>,
+    pub data_dir: Option<PathBuf>,
+    pub services_dirs: Vec<PathBuf>,
+    pub daemon: Option<bool>,
+    pub pid_file: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub log_rotation: Option<String>,
+    pub log_retention: Option<usize>,
+    pub log_format: Option<LogFormat>,
+    pub access_log: Option<bool>,
+    pub request_timeout: Option<u64>,
+    pub slow_request_ms: Option<u64>,
+    pub health_cache_ttl_ms: Option<u64>,
+    pub poll_interval: Option<u64>,
+    pub socket_retries: Option<u32>,
+    pub health_timeout_ms: Option<u64>,
+    pub service_health_timeouts: BTreeMap<String, u64>,
+    pub max_rpc_response_size: Option<String>,
+    pub max_concurrent_reads: Option<usize>,
+    pub max_concurrent_control: Option<usize>,
+    pub max_concurrent_calls: Option<usize>,
+    pub max_request_body: Option<String>,
+    pub max_rpc_upload_size: Option<String>,
+    pub retention: BTreeMap<String, String>,
+    pub assets_dir: Option<PathBuf>,
+    pub confirm_stop: Option<ConfirmStop>,
+    /// `key=value` rules
+    pub protect: Vec<String>,
+    pub stop_undo_seconds: Option<u64>,
+    pub branding: Branding,
+    pub update_index: Option<String>,
+    pub update_check_interval: Option<String>,
+    pub update_alerts: Option<bool>,
+    pub docker: Option<bool>,
+    pub systemd: Option<bool>,
+    pub launchd: Option<bool>,
+    pub kubernetes: Option<String>,
+    pub kubernetes_namespace: Option<String>,
+    pub plugin_dir: Option<PathBuf>,
+    pub wasm_fuel: Option<u64>,
+    pub wasm_memory_mb: Option<usize>,
+    pub script_dir: Option<PathBuf>,
+    pub widgets: Vec<Widget>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub schedule: Vec<TaskConfig>,
+    pub s3: Option<S3Config>,
+    pub namespaces: Vec<Namespace>,
+    pub quotas: Quotas,
+}
+
+impl FileConfig {
+    /// Read a config file, resolving secret references (see [`secrets`])
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut value: toml::Value = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        secrets::resolve_toml(&mut value)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Failed to resolve a secret in {}", path.display()))?;
+        value
+            .try_into()
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// Fully resolved settings
+pub struct Config {
+    pub port: u16,
+    pub bind: IpAddr,
+    pub open: bool,
+    pub auth_tokens: Vec<String>,
+    pub tls: Option<tls::Identity>,
+    pub insecure_lan: bool,
+    pub demo: bool,
+    pub host_name: Option<String>,
+    pub labels: Vec<(String, String)>,
+    pub peers: Vec<String>,
+    pub allow_methods: Vec<String>,
+    pub deny_methods: Vec<String>,
+    pub redact_keys: Vec<String>,
+    pub data_dir: PathBuf,
+    pub services_dirs: Vec<PathBuf>,
+    pub daemon: bool,
+    pub pid_file: PathBuf,
+    pub log_file: Option<PathBuf>,
+    pub log_rotation: LogRotation,
+    pub log_retention: usize,
+    pub log_format: LogFormat,
+    pub access_log: bool,
+    pub request_timeout: u64,
+    pub slow_request_ms: u64,
+    pub health_cache_ttl_ms: u64,
+    pub poll_interval: u64,
+    pub socket_retries: u32,
+    pub health_timeout_ms: u64,
+    pub service_health_timeouts: Vec<(String, u64)>,
+    pub max_rpc_response_size: u64,
+    pub max_concurrent_reads: usize,
+    pub max_concurrent_control: usize,
+    pub max_concurrent_calls: usize,
+    pub max_request_body: usize,
+    pub max_rpc_upload_size: usize,
+    pub retention: retention::Policy,
+    pub assets_dir: Option<PathBuf>,
+    pub confirm_stop: ConfirmStop,
+    pub protect: Vec<(String, String)>,
+    pub stop_undo_seconds: u64,
+    pub branding: Branding,
+    pub update_index: Option<String>,
+    pub update_check_interval: Duration,
+    pub update_alerts: bool,
+    pub docker: bool,
+    pub systemd: bool,
+    pub launchd: bool,
+    pub kubernetes: Option<String>,
+    pub kubernetes_namespace: Option<String>,
+    pub plugin_dir: PathBuf,
+    pub wasm_limits: wasm::Limits,
+    pub script_dir: PathBuf,
+    pub widgets: Vec<Widget>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub schedule: Vec<Task>,
+    pub s3: Option<s3::Target>,
+    pub namespaces: Vec<Namespace>,
+    /// Limits of each token
+    pub quotas: Quotas,
+}
+
+/// Prefer the higher-precedence list unless it is empty
+fn first_non_empty<T>(preferred: Vec<T>, fallback: Vec<T>) -> Vec<T> {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+impl Args {
+    /// The config file in use, which may not exist
+    pub fn config_path(&self) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| state::default_data_dir().join("config.toml"))
+    }
+
+    /// Merge flags and environment with the config file and defaults
+    pub fn resolve(self) -> Result<Config> {
+        let path = self.config_path();
+        let file = if self.config.is_some() || path.exists() {
+            FileConfig::load(&path)?
+        } else {
+            FileConfig::default()
+        };
+
+        let log_rotation = match (self.log_rotation, &file.log_rotation) {
+            (Some(rotation), _) => rotation,
+            (None, Some(rotation)) => {
+                logging::parse_rotation(rotation).map_err(anyhow::Error::msg)?
+            }
+            (None, None) => LogRotation::Daily,
+        };
+
+        let max_rpc_response_size = match (self.max_rpc_response_size, &file.max_rpc_response_size)
+        {
+            (Some(size), _) => size,
+            (None, Some(size)) => parse_size(size).map_err(anyhow::Error::msg)?,
+            (None, None) => rpc::DEFAULT_MAX_RESPONSE_SIZE,
+        };
+
+        let max_request_body = match (self.max_request_body, &file.max_request_body) {
+            (Some(size), _) => size,
+            (None, Some(size)) => parse_size(size).map_err(anyhow::Error::msg)?,
+            (None, None) => body_limit::DEFAULT_MAX_BODY,
+        };
+
+        let max_rpc_upload_size = match (self.max_rpc_upload_size, &file.max_rpc_upload_size) {
+            (Some(size), _) => size,
+            (None, Some(size)) => parse_size(size).map_err(anyhow::Error::msg)?,
+            (None, None) => binary::DEFAULT_MAX_UPLOAD_SIZE,
+        };
+
+        // Flags override the file per tier, on top of the defaults
+        let mut retention = retention::Policy::default();
+        for (tier, keep) in &file.retention {
+            retention
+                .set(tier, parse_duration(keep).map_err(anyhow::Error::msg)?)
+                .map_err(anyhow::Error::msg)?;
+        }
+        for (tier, keep) in &self.retention {
+            retention.set(tier, *keep).map_err(anyhow::Error::msg)?;
+        }
+
+        let file_protect = file
+            .protect
+            .iter()
+            .map(|rule| hosts::parse_label(rule))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(anyhow::Error::msg)?;
+
+        file.branding.validate().map_err(anyhow::Error::msg)?;
+        for widget in &file.widgets {
+            widget.validate().map_err(anyhow::Error::msg)?;
+        }
+        for webhook in &file.webhooks {
+            webhook.validate().map_err(anyhow::Error::msg)?;
+            if file
+                .webhooks
+                .iter()
+                .filter(|other| other.name == webhook.name)
+                .count()
+                > 1
+            {
+                anyhow::bail!("more than one webhook is named '{}'", webhook.name);
+            }
+        }
+        let mut schedule = Vec::new();
+        for task in &file.schedule {
+            if file
+                .schedule
+                .iter()
+                .filter(|other| other.name == task.name)
+                .count()
+                > 1
+            {
+                anyhow::bail!("more than one scheduled task is named '{}'", task.name);
+            }
+            schedule.push(task.parse().map_err(anyhow::Error::msg)?);
+        }
+        for namespace in &file.namespaces {
+            namespace.validate().map_err(anyhow::Error::msg)?;
+            if file
+                .namespaces
+                .iter()
+                .filter(|other| other.name == namespace.name)
+                .count()
+                > 1
+            {
+                anyhow::bail!("more than one namespace is named '{}'", namespace.name);
+            }
+        }
+        let s3 = file
+            .s3
+            .clone()
+            .map(S3Config::resolve)
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+
+        let update_check_interval = match (self.update_check_interval, &file.update_check_interval)
+        {
+            (Some(interval), _) => interval,
+            (None, Some(interval)) => parse_duration(interval).map_err(anyhow::Error::msg)?,
+            (None, None) => Duration::from_secs(updates::DEFAULT_CHECK_INTERVAL_SECS),
+        };
+
+        let tls = match (
+            self.tls_cert.or(file.tls_cert),
+            self.tls_key.or(file.tls_key),
+        ) {
+            (Some(cert), Some(key)) => Some(tls::Identity { cert, key }),
+            (None, None) => None,
+            _ => anyhow::bail!("--tls-cert and --tls-key must be set together"),
+        };
+
+        let data_dir = self
+            .data_dir
+            .or(file.data_dir)
+            .unwrap_or_else(state::default_data_dir);
+
+        Ok(Config {
+            port: self.port.or(file.port).unwrap_or(DEFAULT_PORT),
+            bind: self
+                .bind
+                .or(file.bind)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            open: self.open || file.open.unwrap_or(false),
+            auth_tokens: first_non_empty(self.auth_tokens, file.auth_tokens)
+                .into_iter()
+                .map(|token| token.trim().to_string())
+                .filter(|token| !token.is_empty())
+                .collect(),
+            tls,
+            insecure_lan: self.insecure_lan || file.insecure_lan.unwrap_or(false),
+            demo: self.demo,
+            host_name: self.host_name.or(file.host_name),
+            labels: first_non_empty(self.labels, file.labels.into_iter().collect()),
+            peers: first_non_empty(self.peers, file.peers),
+            allow_methods: first_non_empty(self.allow_methods, file.allow_methods),
+            deny_methods: first_non_empty(self.deny_methods, file.deny_methods),
+            redact_keys: first_non_empty(
+                first_non_empty(self.redact_keys, file.redact_keys),
+                redact::DEFAULT_KEYS
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect(),
+            ),
+            services_dirs: first_non_empty(
+                first_non_empty(self.services_dirs, file.services_dirs),
+                vec![fgp_daemon::fgp_services_dir()],
+            ),
+            daemon: self.daemon || file.daemon.unwrap_or(false),
+            pid_file: self
+                .pid_file
+                .or(file.pid_file)
+                .unwrap_or_else(|| data_dir.join("dashboard.pid")),
+            log_file: self.log_file.or(file.log_file),
+            log_rotation,
+            log_retention: self
+                .log_retention
+                .or(file.log_retention)
+                .unwrap_or(logging::DEFAULT_RETENTION),
+            log_format: self.log_format.or(file.log_format).unwrap_or_default(),
+            access_log: self.access_log || file.access_log.unwrap_or(false),
+            request_timeout: self
+                .request_timeout
+                .or(file.request_timeout)
+                .unwrap_or(timeout::DEFAULT_REQUEST_TIMEOUT_SECS),
+            slow_request_ms: self
+                .slow_request_ms
+                .or(file.slow_request_ms)
+                .unwrap_or(metrics::DEFAULT_SLOW_REQUEST_MS),
+            health_cache_ttl_ms: self
+                .health_cache_ttl_ms
+                .or(file.health_cache_ttl_ms)
+                .unwrap_or(health_cache::DEFAULT_TTL_MS),
+            poll_interval: self
+                .poll_interval
+                .or(file.poll_interval)
+                .unwrap_or(store::DEFAULT_POLL_INTERVAL_SECS),
+            socket_retries: self
+                .socket_retries
+                .or(file.socket_retries)
+                .unwrap_or(retry::DEFAULT_RETRIES),
+            health_timeout_ms: self
+                .health_timeout_ms
+                .or(file.health_timeout_ms)
+                .unwrap_or(api::DEFAULT_HEALTH_TIMEOUT_MS),
+            service_health_timeouts: first_non_empty(
+                self.service_health_timeouts,
+                file.service_health_timeouts.into_iter().collect(),
+            ),
+            max_rpc_response_size,
+            max_concurrent_reads: self
+                .max_concurrent_reads
+                .or(file.max_concurrent_reads)
+                .unwrap_or(shed::DEFAULT_MAX_READS)
+                .max(1),
+            max_concurrent_control: self
+                .max_concurrent_control
+                .or(file.max_concurrent_control)
+                .unwrap_or(shed::DEFAULT_MAX_CONTROL)
+                .max(1),
+            max_concurrent_calls: self
+                .max_concurrent_calls
+                .or(file.max_concurrent_calls)
+                .unwrap_or(shed::DEFAULT_MAX_CALLS)
+                .max(1),
+            max_request_body: usize::try_from(max_request_body).unwrap_or(usize::MAX),
+            max_rpc_upload_size: usize::try_from(max_rpc_upload_size).unwrap_or(usize::MAX),
+            retention,
+            assets_dir: self.assets_dir.or(file.assets_dir),
+            confirm_stop: self.confirm_stop.or(file.confirm_stop).unwrap_or_default(),
+            protect: first_non_empty(self.protect, file_protect),
+            stop_undo_seconds: self
+                .stop_undo_seconds
+                .or(file.stop_undo_seconds)
+                .unwrap_or(ui::DEFAULT_STOP_UNDO_SECS),
+            branding: file.branding,
+            update_index: self.update_index.or(file.update_index),
+            update_check_interval,
+            update_alerts: self.update_alerts || file.update_alerts.unwrap_or(false),
+            docker: self.docker || file.docker.unwrap_or(false),
+            systemd: self.systemd || file.systemd.unwrap_or(false),
+            launchd: self.launchd || file.launchd.unwrap_or(false),
+            kubernetes: self.kubernetes.clone().or(file.kubernetes),
+            kubernetes_namespace: self
+                .kubernetes_namespace
+                .clone()
+                .or(file.kubernetes_namespace),
+            plugin_dir: self
+                .plugin_dir
+                .or(file.plugin_dir)
+                .unwrap_or_else(|| data_dir.join(plugins::PLUGIN_DIR)),
+            wasm_limits: wasm::Limits {
+                fuel: self
+                    .wasm_fuel
+                    .or(file.wasm_fuel)
+                    .unwrap_or(wasm::DEFAULT_FUEL),
+                memory_bytes: self
+                    .wasm_memory_mb
+                    .or(file.wasm_memory_mb)
+                    .unwrap_or(wasm::DEFAULT_MEMORY_MB)
+                    * 1024
+                    * 1024,
+            },
+            script_dir: self
+                .script_dir
+                .or(file.script_dir)
+                .unwrap_or_else(|| data_dir.join(scripts::SCRIPT_DIR)),
+            widgets: file.widgets,
+            webhooks: file.webhooks,
+            schedule,
+            s3,
+            namespaces: file.namespaces,
+            quotas: file.quotas,
+            data_dir,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512b"), Ok(512));
+        assert_eq!(parse_size("10MB"), Ok(10 << 20));
+        assert_eq!(parse_size(" 1gb "), Ok(1 << 30));
+        assert_eq!(parse_size("64 KB"), Ok(64 << 10));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for size in [
+            "",
+            "0",
+            "0MB",
+            "-1MB",
+            "1.5GB",
+            "MB",
+            "10TB",
+            "99999999999GB",
+        ] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration(" 48h"), Ok(Duration::from_secs(48 * 3600)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("1y"), Ok(Duration::from_secs(365 * 86400)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for duration in [
+            "",
+            "0",
+            "0h",
+            "h",
+            "-1h",
+            "1.5h",
+            "soon",
+            "99999999999999999y",
+        ] {
+            assert!(parse_duration(duration).is_err(), "{}", duration);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Running in the background on hosts without a service manager.
+//!
+//! `--daemon` forks before the async runtime starts, writes and locks the
+//! PID file, and sends stdout/stderr to `<data dir>/dashboard.log`. Logs
+//! go there too unless `--log-file` is set.
+
+use anyhow::Result;
+
+use crate::config::Config;
+
+/// Log file used when running detached
+pub const LOG_FILE: &str = "dashboard.log";
+
+/// Detach from the terminal
+///
+/// Must be called before the tokio runtime is built.
+#[cfg(unix)]
+pub fn daemonize(config: &Config) -> Result<()> {
+    use anyhow::Context;
+    use std::fs::{self, OpenOptions};
+
+    fs::create_dir_all(&config.data_dir)
+        .with_context(|| format!("Failed to create {}", config.data_dir.display()))?;
+
+    let log_path = config.data_dir.join(LOG_FILE);
+    let stdout = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("Failed to open {}", log_path.display()))?;
+    let stderr = stdout.try_clone()?;
+
+    // Keep relative paths (services dirs, config) meaning the same thing
+    let cwd = std::env::current_dir()?;
+
+    daemonize::Daemonize::new()
+        .pid_file(cwd.join(&config.pid_file))
+        .working_directory(&cwd)
+        .stdout(stdout)
+        .stderr(stderr)
+        .start()
+        .map_err(|e| anyhow::anyhow!("Failed to daemonize: {}", e))
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_config: &Config) -> Result<()> {
+    anyhow::bail!("--daemon is only supported on Unix")
+}
@@ -0,0 +1,666 @@
+//! Persistent dashboard state.
+//!
+//! Health history, events, preferences, pins, annotations and scheduled task
+//! runs live in one SQLite database, `<data dir>/dashboard.db`. The
+//! `incidents`, `audit_log` and `api_keys` tables of the first schema are
+//! unused, and kept because released migrations are never edited.
+//! The schema is versioned with `PRAGMA user_version`; [`MIGRATIONS`] are
+//! applied in order when the database is opened, so upgrading the dashboard
+//! upgrades the database in place.
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{backup::Progress, params, Connection, DatabaseName};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::annotations::Annotation;
+use crate::api::ServiceInfo;
+use crate::calls;
+use crate::events::Event;
+use crate::history::{HistoryPoint, Sample};
+use crate::scheduler::TaskRun;
+use crate::state::AppState;
+
+/// Database file inside the data dir
+pub const DB_FILE: &str = "dashboard.db";
+
+/// Schema migrations; entry `n` takes the schema from version `n` to `n + 1`
+///
+/// Never edit an entry once released, only append new ones.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE health_samples (
+        timestamp INTEGER NOT NULL,
+        service TEXT NOT NULL,
+        status TEXT NOT NULL,
+        version TEXT,
+        uptime_seconds INTEGER
+    );
+    CREATE INDEX health_samples_by_service ON health_samples (service, timestamp);
+
+    CREATE TABLE events (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        service TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        message TEXT NOT NULL
+    );
+
+    CREATE TABLE incidents (
+        id INTEGER PRIMARY KEY,
+        service TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        resolved_at INTEGER,
+        summary TEXT NOT NULL
+    );
+
+    CREATE TABLE audit_log (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        actor TEXT,
+        action TEXT NOT NULL,
+        target TEXT,
+        detail TEXT
+    );
+
+    CREATE TABLE api_keys (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        key_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        last_used_at INTEGER
+    );
+
+    CREATE TABLE preferences (
+        scope TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (scope, key)
+    );
+
+    CREATE TABLE annotations (
+        service TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (service, key)
+    );",
+    // 2: downsampled health history
+    "CREATE TABLE health_rollups (
+        resolution INTEGER NOT NULL,
+        bucket INTEGER NOT NULL,
+        service TEXT NOT NULL,
+        samples INTEGER NOT NULL,
+        healthy INTEGER NOT NULL,
+        max_uptime_seconds INTEGER,
+        PRIMARY KEY (resolution, service, bucket)
+    );",
+    // 3: pinned services
+    "CREATE TABLE pins (
+        scope TEXT NOT NULL,
+        service TEXT NOT NULL,
+        pinned_at INTEGER NOT NULL,
+        PRIMARY KEY (scope, service)
+    );",
+    // 4: probe latency and resource usage
+    "ALTER TABLE health_samples ADD COLUMN latency_ms REAL;
+    ALTER TABLE health_samples ADD COLUMN cpu_percent REAL;
+    ALTER TABLE health_samples ADD COLUMN memory_bytes INTEGER;
+    ALTER TABLE health_rollups ADD COLUMN avg_latency_ms REAL;
+    ALTER TABLE health_rollups ADD COLUMN avg_cpu_percent REAL;
+    ALTER TABLE health_rollups ADD COLUMN max_memory_bytes INTEGER;",
+    // 5: event ranges for the timeline
+    "CREATE INDEX events_by_timestamp ON events (timestamp);",
+    // 6: scheduled task runs
+    "CREATE TABLE task_runs (
+        id INTEGER PRIMARY KEY,
+        task TEXT NOT NULL,
+        trigger TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        ok INTEGER NOT NULL,
+        message TEXT NOT NULL
+    );
+    CREATE INDEX task_runs_by_task ON task_runs (task, id);",
+];
+
+/// Handle to the dashboard database
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Open (creating if needed) and migrate the database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut conn =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn).with_context(|| format!("Failed to migrate {}", path.display()))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// A migrated database that lives only in memory, for `--demo`
+    pub fn in_memory() -> Result<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn).context("Failed to migrate the in-memory database")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Run `f` with the connection
+    ///
+    /// Blocks, so call it off the async runtime.
+    pub fn with<T>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> Result<T> {
+        let mut conn = self.conn.lock().unwrap();
+        Ok(f(&mut conn)?)
+    }
+
+    /// Write a consistent copy of the database to `path`
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.with(|conn| conn.backup(DatabaseName::Main, path, None))
+            .with_context(|| format!("Failed to back up the database to {}", path.display()))
+    }
+
+    /// Replace the database's contents with the copy at `path`, migrating
+    /// it if it comes from an older dashboard
+    pub fn restore_from(&self, path: &Path) -> Result<()> {
+        let copy =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let version: usize = copy.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        drop(copy);
+        if version > MIGRATIONS.len() {
+            bail!(
+                "Backup has schema version {}, but this dashboard only knows up to {}",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+
+        self.with(|conn| {
+            conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+            migrate(conn)
+        })
+    }
+
+    /// Preferences stored under `scope`
+    pub fn preferences(&self, scope: &str) -> Result<Map<String, Value>> {
+        self.with(|conn| {
+            let mut query =
+                conn.prepare_cached("SELECT key, value FROM preferences WHERE scope = ?1")?;
+            let rows = query.query_map([scope], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            let mut preferences = Map::new();
+            for row in rows {
+                let (key, value) = row?;
+                preferences.insert(key, serde_json::from_str(&value).unwrap_or(Value::Null));
+            }
+            Ok(preferences)
+        })
+    }
+
+    /// Set preferences under `scope`, removing those set to `null`
+    pub fn update_preferences(&self, scope: &str, changes: &Map<String, Value>) -> Result<()> {
+        self.with(|conn| {
+            let tx = conn.transaction()?;
+            for (key, value) in changes {
+                if value.is_null() {
+                    tx.execute(
+                        "DELETE FROM preferences WHERE scope = ?1 AND key = ?2",
+                        params![scope, key],
+                    )?;
+                } else {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO preferences (scope, key, value) VALUES (?1, ?2, ?3)",
+                        params![scope, key, value.to_string()],
+                    )?;
+                }
+            }
+            tx.commit()
+        })
+    }
+
+    /// Annotations on `service`, by key
+    pub fn annotations(&self, service: &str) -> Result<Vec<Annotation>> {
+        Ok(self
+            .all_annotations_where(Some(service))?
+            .remove(service)
+            .unwrap_or_default())
+    }
+
+    /// Annotations on every service, by service name
+    pub fn all_annotations(&self) -> Result<BTreeMap<String, Vec<Annotation>>> {
+        self.all_annotations_where(None)
+    }
+
+    fn all_annotations_where(
+        &self,
+        service: Option<&str>,
+    ) -> Result<BTreeMap<String, Vec<Annotation>>> {
+        self.with(|conn| {
+            let mut query = conn.prepare_cached(
+                "SELECT service, key, value, updated_at FROM annotations
+                 WHERE ?1 IS NULL OR service = ?1 ORDER BY service, key",
+            )?;
+            let rows = query.query_map([service], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Annotation {
+                        key: row.get(1)?,
+                        value: row.get(2)?,
+                        updated_at: row.get(3)?,
+                    },
+                ))
+            })?;
+            let mut annotations = BTreeMap::<_, Vec<_>>::new();
+            for row in rows {
+                let (service, annotation) = row?;
+                annotations.entry(service).or_default().push(annotation);
+            }
+            Ok(annotations)
+        })
+    }
+
+    /// Set annotations on `service`, removing those set to `None`
+    ///
+    /// Returns how many rows changed.
+    pub fn update_annotations(
+        &self,
+        service: &str,
+        changes: &BTreeMap<String, Option<String>>,
+        timestamp: u64,
+    ) -> Result<usize> {
+        self.with(|conn| {
+            let tx = conn.transaction()?;
+            let mut changed = 0;
+            for (key, value) in changes {
+                changed += match value {
+                    Some(value) => tx.execute(
+                        "INSERT OR REPLACE INTO annotations (service, key, value, updated_at)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![service, key, value, timestamp],
+                    )?,
+                    None => tx.execute(
+                        "DELETE FROM annotations WHERE service = ?1 AND key = ?2",
+                        params![service, key],
+                    )?,
+                };
+            }
+            tx.commit()?;
+            Ok(changed)
+        })
+    }
+
+    /// Services pinned under `scope`
+    pub fn pinned(&self, scope: &str) -> Result<BTreeSet<String>> {
+        self.with(|conn| {
+            let mut query = conn.prepare_cached("SELECT service FROM pins WHERE scope = ?1")?;
+            let rows = query.query_map([scope], |row| row.get(0))?;
+            rows.collect()
+        })
+    }
+
+    /// Pin or unpin `service` under `scope`
+    pub fn set_pinned(
+        &self,
+        scope: &str,
+        service: &str,
+        pinned: bool,
+        timestamp: u64,
+    ) -> Result<()> {
+        self.with(|conn| {
+            if pinned {
+                conn.execute(
+                    "INSERT OR IGNORE INTO pins (scope, service, pinned_at) VALUES (?1, ?2, ?3)",
+                    params![scope, service, timestamp],
+                )?;
+            } else {
+                conn.execute(
+                    "DELETE FROM pins WHERE scope = ?1 AND service = ?2",
+                    params![scope, service],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Store one health sample per service
+    pub fn record_samples(&self, timestamp: u64, services: &[ServiceInfo]) -> Result<()> {
+        self.with(|conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO health_samples
+                         (timestamp, service, status, version, uptime_seconds,
+                          latency_ms, cpu_percent, memory_bytes)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )?;
+                for service in services {
+                    insert.execute(params![
+                        timestamp,
+                        service.name,
+                        service.status,
+                        service.version,
+                        service.uptime_seconds,
+                        service.latency_ms,
+                        service.cpu_percent,
+                        service.memory_bytes,
+                    ])?;
+                }
+            }
+            tx.commit()
+        })
+    }
+
+    pub fn insert_event(&self, event: &Event) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO events (id, timestamp, service, kind, message)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    event.id,
+                    event.timestamp,
+                    event.service,
+                    event.kind,
+                    event.message
+                ],
+            )
+            .map(|_| ())
+        })
+    }
+
+    /// The most recent events, oldest first
+    /// The last scan recorded at or before `timestamp`, by service
+    pub fn scan_at(&self, timestamp: u64) -> Result<Option<(u64, BTreeMap<String, Sample>)>> {
+        self.with(|conn| {
+            let recorded_at: Option<u64> = conn.query_row(
+                "SELECT MAX(timestamp) FROM health_samples WHERE timestamp <= ?1",
+                [timestamp],
+                |row| row.get(0),
+            )?;
+            let Some(recorded_at) = recorded_at else {
+                return Ok(None);
+            };
+
+            let mut select = conn.prepare_cached(
+                "SELECT service, status, version FROM health_samples WHERE timestamp = ?1",
+            )?;
+            let samples = select
+                .query_map([recorded_at], |row| {
+                    Ok((
+                        row.get(0)?,
+                        Sample {
+                            status: row.get(1)?,
+                            version: row.get(2)?,
+                        },
+                    ))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(Some((recorded_at, samples)))
+        })
+    }
+
+    /// A service's health since `since`, from raw samples when `resolution`
+    /// is 0 and from rollups of that resolution otherwise
+    pub fn history(&self, service: &str, resolution: u64, since: u64) -> Result<Vec<HistoryPoint>> {
+        self.with(|conn| {
+            let mut select = if resolution == 0 {
+                conn.prepare_cached(
+                    "SELECT timestamp, 1, status IN ('running', 'healthy'), uptime_seconds,
+                            latency_ms, cpu_percent, memory_bytes
+                     FROM health_samples WHERE service = ?1 AND timestamp >= ?2
+                     ORDER BY timestamp",
+                )?
+            } else {
+                conn.prepare_cached(
+                    "SELECT bucket, samples, healthy, max_uptime_seconds,
+                            avg_latency_ms, avg_cpu_percent, max_memory_bytes
+                     FROM health_rollups WHERE service = ?1 AND bucket >= ?2 AND resolution = ?3
+                     ORDER BY bucket",
+                )?
+            };
+            let points = if resolution == 0 {
+                select
+                    .query_map(params![service, since], |row| history_point_at(row, 0))?
+                    .collect()
+            } else {
+                select
+                    .query_map(params![service, since, resolution], |row| {
+                        history_point_at(row, 0)
+                    })?
+                    .collect()
+            };
+            points
+        })
+    }
+
+    /// Every service's raw samples since `since`, averaged into `step`-second
+    /// buckets
+    pub fn sparklines(&self, since: u64, step: u64) -> Result<BTreeMap<String, Vec<HistoryPoint>>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT service, timestamp / ?2 * ?2, COUNT(*),
+                        SUM(status IN ('running', 'healthy')), MAX(uptime_seconds),
+                        AVG(latency_ms), AVG(cpu_percent), MAX(memory_bytes)
+                 FROM health_samples WHERE timestamp >= ?1
+                 GROUP BY service, timestamp / ?2
+                 ORDER BY service, timestamp / ?2",
+            )?;
+            let mut rows = select.query(params![since, step])?;
+            let mut sparklines: BTreeMap<String, Vec<HistoryPoint>> = BTreeMap::new();
+            while let Some(row) = rows.next()? {
+                let service: String = row.get(0)?;
+                sparklines
+                    .entry(service)
+                    .or_default()
+                    .push(history_point_at(row, 1)?);
+            }
+            Ok(sparklines)
+        })
+    }
+
+    /// Rollups of every resolution whose buckets end within `(from, to]`,
+    /// as `(resolution, service, point)`
+    pub fn rollups_ending(&self, from: u64, to: u64) -> Result<Vec<(u64, String, HistoryPoint)>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT resolution, service, bucket, samples, healthy, max_uptime_seconds,
+                        avg_latency_ms, avg_cpu_percent, max_memory_bytes
+                 FROM health_rollups WHERE bucket + resolution > ?1 AND bucket + resolution <= ?2
+                 ORDER BY resolution, bucket, service",
+            )?;
+            let rollups = select
+                .query_map(params![from, to], |row| {
+                    Ok((row.get(0)?, row.get(1)?, history_point_at(row, 2)?))
+                })?
+                .collect();
+            rollups
+        })
+    }
+
+    /// How many times each service's recorded status changed within a range
+    pub fn status_flips(&self, from: u64, to: u64) -> Result<BTreeMap<String, u64>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT service, COUNT(*) FROM (
+                     SELECT service, status,
+                            LAG(status) OVER (PARTITION BY service ORDER BY timestamp) AS previous
+                     FROM health_samples WHERE timestamp BETWEEN ?1 AND ?2
+                 )
+                 WHERE previous IS NOT NULL AND previous != status
+                 GROUP BY service",
+            )?;
+            let flips = select
+                .query_map([from, to], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect();
+            flips
+        })
+    }
+
+    /// Events recorded within a range, oldest first, keeping the newest
+    /// `limit`
+    pub fn events_between(&self, from: u64, to: u64, limit: usize) -> Result<Vec<Event>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT id, timestamp, service, kind, message FROM
+                 (SELECT * FROM events WHERE timestamp BETWEEN ?1 AND ?2
+                  ORDER BY id DESC LIMIT ?3)
+                 ORDER BY id",
+            )?;
+            let events = select
+                .query_map(params![from, to, limit as i64], |row| {
+                    Ok(Event {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        service: row.get(2)?,
+                        kind: row.get(3)?,
+                        message: row.get(4)?,
+                    })
+                })?
+                .collect();
+            events
+        })
+    }
+
+    pub fn recent_events(&self, limit: usize) -> Result<Vec<Event>> {
+        self.with(|conn| {
+            let mut select = conn.prepare(
+                "SELECT id, timestamp, service, kind, message FROM
+                 (SELECT * FROM events ORDER BY id DESC LIMIT ?1) ORDER BY id",
+            )?;
+            let events = select
+                .query_map([limit as i64], |row| {
+                    Ok(Event {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        service: row.get(2)?,
+                        kind: row.get(3)?,
+                        message: row.get(4)?,
+                    })
+                })?
+                .collect();
+            events
+        })
+    }
+
+    /// Store a task run, returning its ID
+    pub fn insert_task_run(&self, run: &TaskRun) -> Result<u64> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT INTO task_runs (task, trigger, started_at, finished_at, ok, message)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    run.task,
+                    run.trigger,
+                    run.started_at,
+                    run.finished_at,
+                    run.ok,
+                    run.message
+                ],
+            )?;
+            Ok(conn.last_insert_rowid() as u64)
+        })
+    }
+
+    /// The newest `limit` runs of a task, newest first
+    pub fn task_runs(&self, task: &str, limit: usize) -> Result<Vec<TaskRun>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT id, task, trigger, started_at, finished_at, ok, message
+                 FROM task_runs WHERE task = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            let runs = select
+                .query_map(params![task, limit as i64], task_run)?
+                .collect();
+            runs
+        })
+    }
+
+    /// The last run of every task that has run
+    pub fn last_task_runs(&self) -> Result<BTreeMap<String, TaskRun>> {
+        self.with(|conn| {
+            let mut select = conn.prepare_cached(
+                "SELECT id, task, trigger, started_at, finished_at, ok, message
+                 FROM task_runs WHERE id IN (SELECT MAX(id) FROM task_runs GROUP BY task)",
+            )?;
+            let runs = select
+                .query_map([], task_run)?
+                .map(|run| run.map(|run| (run.task.clone(), run)))
+                .collect();
+            runs
+        })
+    }
+}
+
+fn task_run(row: &rusqlite::Row) -> rusqlite::Result<TaskRun> {
+    Ok(TaskRun {
+        id: row.get(0)?,
+        task: row.get(1)?,
+        trigger: row.get(2)?,
+        started_at: row.get(3)?,
+        finished_at: row.get(4)?,
+        ok: row.get(5)?,
+        message: row.get(6)?,
+    })
+}
+
+/// Read a [`HistoryPoint`] from the columns starting at `offset`
+fn history_point_at(row: &rusqlite::Row, offset: usize) -> rusqlite::Result<HistoryPoint> {
+    Ok(HistoryPoint {
+        timestamp: row.get(offset)?,
+        samples: row.get(offset + 1)?,
+        healthy: row.get(offset + 2)?,
+        uptime_seconds: row.get(offset + 3)?,
+        latency_ms: row.get(offset + 4)?,
+        cpu_percent: row.get(offset + 5)?,
+        memory_bytes: row.get(offset + 6)?,
+    })
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!("Migrating database to schema version {}", index + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Persist every published event
+pub fn spawn_event_writer(state: AppState) {
+    tokio::spawn(async move {
+        let mut events = state.events.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let db = state.db.clone();
+                    if let Err(e) = calls::blocking(move || db.insert_event(&event)).await {
+                        tracing::warn!("Failed to store event: {:#}", e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Event writer skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,134 @@
+//! Diagnostics for the dashboard process, behind the `debug-tools` feature.
+//!
+//! - tokio-console: build with `RUSTFLAGS="--cfg tokio_unstable"` and run
+//!   `tokio-console` against the default port (6669).
+//! - `GET /debug/pprof/profile?seconds=30` samples the CPU and returns a
+//!   pprof protobuf (`go tool pprof`), or an SVG with `?format=flamegraph`.
+//! - `GET /debug/pprof/heap` dumps a jemalloc heap profile as pprof.
+//!
+//! Profiling relies on Unix signals and jemalloc, so the feature is
+//! Unix-only.
+
+use axum::{
+    extract::Query,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::api::ApiResponse;
+use crate::state::AppState;
+
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Enable jemalloc heap sampling from startup
+#[allow(non_upper_case_globals)]
+#[export_name = "malloc_conf"]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+
+/// Longest CPU profile that can be requested
+const MAX_PROFILE_SECONDS: u64 = 300;
+
+#[derive(Deserialize)]
+pub struct ProfileQuery {
+    /// How long to sample for [default: 30]
+    pub seconds: Option<u64>,
+    /// Samples per second [default: 99]
+    pub frequency: Option<i32>,
+    /// `pprof` (default) or `flamegraph`
+    pub format: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, ApiResponse::<()>::error(message)).into_response()
+}
+
+fn cpu_profile_blocking(seconds: u64, frequency: i32, flamegraph: bool) -> anyhow::Result<Vec<u8>> {
+    use pprof::protos::Message;
+
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    std::thread::sleep(Duration::from_secs(seconds));
+    let report = guard.report().build()?;
+
+    let mut body = Vec::new();
+    if flamegraph {
+        report.flamegraph(&mut body)?;
+    } else {
+        report.pprof()?.encode(&mut body)?;
+    }
+    Ok(body)
+}
+
+/// Sample the CPU for a while and return the profile
+pub async fn cpu_profile(Query(query): Query<ProfileQuery>) -> Response {
+    let seconds = query.seconds.unwrap_or(30).clamp(1, MAX_PROFILE_SECONDS);
+    let frequency = query.frequency.unwrap_or(99).clamp(1, 1000);
+    let flamegraph = match query.format.as_deref() {
+        None | Some("pprof") => false,
+        Some("flamegraph") => true,
+        Some(other) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &format!("invalid format '{}', expected pprof or flamegraph", other),
+            )
+        }
+    };
+
+    let result =
+        tokio::task::spawn_blocking(move || cpu_profile_blocking(seconds, frequency, flamegraph))
+            .await;
+
+    match result {
+        Ok(Ok(body)) if flamegraph => {
+            ([(header::CONTENT_TYPE, "image/svg+xml")], body).into_response()
+        }
+        Ok(Ok(body)) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response()
+        }
+        Ok(Err(e)) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Profiling failed: {:#}", e),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Profiling failed: {}", e),
+        ),
+    }
+}
+
+/// Dump the current heap profile
+pub async fn heap_profile() -> Response {
+    let Some(prof_ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        return error(
+            StatusCode::NOT_IMPLEMENTED,
+            "Heap profiling is not available",
+        );
+    };
+
+    let mut prof_ctl = prof_ctl.lock().await;
+    if !prof_ctl.activated() {
+        return error(StatusCode::CONFLICT, "Heap profiling is not active");
+    }
+
+    match prof_ctl.dump_pprof() {
+        Ok(body) => ([(header::CONTENT_TYPE, "application/octet-stream")], body).into_response(),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Heap dump failed: {:#}", e),
+        ),
+    }
+}
+
+/// Routes for the profiling endpoints
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/debug/pprof/profile", get(cpu_profile))
+        .route("/debug/pprof/heap", get(heap_profile))
+}
@@ -0,0 +1,466 @@
+//! Demo mode: a synthetic fleet.
+//!
+//! `--demo` serves a dozen made-up services instead of scanning the
+//! services directories, for screenshots, UI development and trying the
+//! dashboard before installing FGP. Their latency, CPU and memory vary from
+//! scan to scan, and now and then one turns `degraded`, stops responding
+//! for a while or restarts on its own, so the history, sparklines, timeline
+//! and notifications all have something to show. They can be started,
+//! stopped and restarted, answer `health`, `methods` and calls through the
+//! passthrough, and have logs.
+//!
+//! Nothing is read from or written to the filesystem and no socket is
+//! opened: the database and webhook subscriptions are kept in memory and
+//! start empty, plugins and scripts aren't loaded, and only history
+//! compaction and [`chaos`](crate::chaos) experiments are scheduled. The
+//! routes in [`UNAVAILABLE_ROUTES`] need a real daemon's files or socket and
+//! answer 409.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::docker::Lifecycle;
+use crate::events;
+use crate::logs::LogTail;
+use crate::rpc::MethodInfo;
+use crate::state::{AppState, Settings};
+
+/// Source the synthetic services are listed under
+pub const SOURCE: &str = "demo";
+
+/// Routes that need a real daemon's files or socket
+pub const UNAVAILABLE_ROUTES: &[&str] = &[
+    "/api/cleanup/{service}",
+    "/api/upgrade/{service}",
+    "/api/scaffold",
+    "/api/config/{service}",
+    "/api/services/{service}/actions/{plugin}/{action}",
+    "/api/plugins/{plugin}/{*path}",
+    "/api/scripts/{script}/run",
+    "/api/bench/{service}",
+    "/api/admin/backup",
+    "/api/admin/restore",
+];
+
+/// Log lines kept per service
+const LOG_LINES: usize = 200;
+
+/// A service of the fleet
+struct Template {
+    name: &'static str,
+    version: &'static str,
+    latency_ms: f64,
+    cpu_percent: f64,
+    memory_mb: f64,
+    methods: &'static [&'static str],
+}
+
+const FLEET: &[Template] = &[
+    Template {
+        name: "calendar",
+        version: "1.8.0",
+        latency_ms: 2.1,
+        cpu_percent: 1.5,
+        memory_mb: 38.0,
+        methods: &["events.list", "events.create", "freebusy"],
+    },
+    Template {
+        name: "gmail",
+        version: "2.3.1",
+        latency_ms: 3.4,
+        cpu_percent: 4.0,
+        memory_mb: 72.0,
+        methods: &["inbox", "search", "thread", "send"],
+    },
+    Template {
+        name: "github",
+        version: "1.12.4",
+        latency_ms: 4.8,
+        cpu_percent: 2.5,
+        memory_mb: 55.0,
+        methods: &["repos", "issues", "pulls", "notifications"],
+    },
+    Template {
+        name: "slack",
+        version: "0.9.7",
+        latency_ms: 2.9,
+        cpu_percent: 3.0,
+        memory_mb: 61.0,
+        methods: &["channels", "history", "post"],
+    },
+    Template {
+        name: "browser",
+        version: "3.0.2",
+        latency_ms: 9.5,
+        cpu_percent: 18.0,
+        memory_mb: 410.0,
+        methods: &["open", "snapshot", "click", "fill", "screenshot"],
+    },
+    Template {
+        name: "search-indexer",
+        version: "1.4.0",
+        latency_ms: 6.2,
+        cpu_percent: 22.0,
+        memory_mb: 640.0,
+        methods: &["query", "reindex", "stats"],
+    },
+    Template {
+        name: "postgres-proxy",
+        version: "2.0.5",
+        latency_ms: 1.2,
+        cpu_percent: 6.0,
+        memory_mb: 96.0,
+        methods: &["query", "tables", "pool.stats"],
+    },
+    Template {
+        name: "cache",
+        version: "1.1.3",
+        latency_ms: 0.4,
+        cpu_percent: 1.0,
+        memory_mb: 128.0,
+        methods: &["get", "set", "stats", "flush"],
+    },
+    Template {
+        name: "payments-api",
+        version: "4.2.0",
+        latency_ms: 5.5,
+        cpu_percent: 8.0,
+        memory_mb: 180.0,
+        methods: &["charges.list", "charges.create", "refunds.create"],
+    },
+    Template {
+        name: "payments-ledger",
+        version: "4.1.9",
+        latency_ms: 3.8,
+        cpu_percent: 5.0,
+        memory_mb: 150.0,
+        methods: &["balance", "entries", "reconcile"],
+    },
+    Template {
+        name: "image-resizer",
+        version: "0.6.1",
+        latency_ms: 12.0,
+        cpu_percent: 35.0,
+        memory_mb: 260.0,
+        methods: &["resize", "thumbnail", "formats"],
+    },
+    Template {
+        name: "notifier",
+        version: "1.0.4",
+        latency_ms: 2.4,
+        cpu_percent: 0.8,
+        memory_mb: 30.0,
+        methods: &["send", "channels", "test"],
+    },
+];
+
+/// Services that start out stopped, so the list isn't all green
+const STOPPED_AT_START: &[&str] = &["image-resizer"];
+
+/// How a synthetic service is doing
+struct Sim {
+    running: bool,
+    started_at: u64,
+    /// Scans left degraded or not responding
+    trouble: u32,
+    status: &'static str,
+    logs: VecDeque<String>,
+}
+
+impl Sim {
+    fn log(&mut self, level: &str, message: &str) {
+        let at = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+        self.logs
+            .push_back(format!("{} {:<5} {}", at, level, message));
+        while self.logs.len() > LOG_LINES {
+            self.logs.pop_front();
+        }
+    }
+}
+
+/// The synthetic services and what happened to them
+pub struct Fleet {
+    sims: Mutex<BTreeMap<&'static str, Sim>>,
+}
+
+/// A number in `[0, 1)`, from the OS random source behind UUIDs
+fn random() -> f64 {
+    // The top 48 bits of a v4 UUID are random
+    (uuid::Uuid::new_v4().as_u128() >> 80) as f64 / (1u64 << 48) as f64
+}
+
+/// `value` varied by up to `spread` either way
+fn jitter(value: f64, spread: f64) -> f64 {
+    value * (1.0 + spread * (2.0 * random() - 1.0))
+}
+
+fn template(service: &str) -> Option<&'static Template> {
+    FLEET.iter().find(|template| template.name == service)
+}
+
+impl Default for Fleet {
+    fn default() -> Self {
+        let now = events::now();
+        let sims = FLEET
+            .iter()
+            .map(|template| {
+                let running = !STOPPED_AT_START.contains(&template.name);
+                let mut sim = Sim {
+                    running,
+                    // Up for anything from an hour to a few days
+                    started_at: now - 3600 - (random() * 3.0 * 86400.0) as u64,
+                    trouble: 0,
+                    status: if running { "running" } else { "stopped" },
+                    logs: VecDeque::new(),
+                };
+                if running {
+                    sim.log(
+                        "INFO",
+                        &format!("{} {} listening", template.name, template.version),
+                    );
+                }
+                (template.name, sim)
+            })
+            .collect();
+        Self {
+            sims: Mutex::new(sims),
+        }
+    }
+}
+
+impl Fleet {
+    /// Move the fleet on by one scan and list it
+    pub fn scan(&self, settings: &Settings) -> Vec<ServiceInfo> {
+        let now = events::now();
+        let mut sims = self.sims.lock().unwrap();
+        let mut services: Vec<ServiceInfo> = FLEET
+            .iter()
+            .map(|template| {
+                let sim = sims
+                    .get_mut(template.name)
+                    .expect("every template has a sim");
+                if sim.running {
+                    step(template, sim, now);
+                }
+                info(template, sim, settings, now)
+            })
+            .collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        services
+    }
+
+    /// Start, stop or restart a synthetic service
+    pub fn control(&self, service: &str, lifecycle: Lifecycle) -> Result<(), String> {
+        let template = template(service)
+            .ok_or_else(|| format!("Service '{}' is not in the demo fleet", service))?;
+        let mut sims = self.sims.lock().unwrap();
+        let sim = sims.get_mut(service).expect("every template has a sim");
+        match lifecycle {
+            Lifecycle::Stop => {
+                sim.log("INFO", "received stop, shutting down");
+                sim.running = false;
+                sim.status = "stopped";
+            }
+            Lifecycle::Start | Lifecycle::Restart => {
+                if matches!(lifecycle, Lifecycle::Restart) {
+                    sim.log("INFO", "received stop, shutting down");
+                }
+                sim.running = true;
+                sim.started_at = events::now();
+                sim.trouble = 0;
+                sim.status = "running";
+                sim.log(
+                    "INFO",
+                    &format!("{} {} listening", template.name, template.version),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The health payload of a running service
+    pub fn health(&self, service: &str) -> Result<serde_json::Value, String> {
+        let template =
+            template(service).ok_or_else(|| format!("Service '{}' not found", service))?;
+        let sims = self.sims.lock().unwrap();
+        let sim = &sims[service];
+        match sim.status {
+            "stopped" => Err(format!("Service '{}' is not running", service)),
+            "not_responding" => Err("health probe timed out".to_string()),
+            _ => Ok(health(template, sim, events::now())),
+        }
+    }
+
+    /// The methods a service advertises
+    pub fn methods(&self, service: &str) -> Result<Vec<MethodInfo>, String> {
+        let template =
+            template(service).ok_or_else(|| format!("Service '{}' not found", service))?;
+        Ok(template
+            .methods
+            .iter()
+            .map(|name| MethodInfo {
+                name: name.to_string(),
+                description: Some(format!("Demo method of {}", template.name)),
+                params: Some(serde_json::json!({ "type": "object" })),
+                allowed: true,
+            })
+            .collect())
+    }
+
+    /// Answer a passthrough call with a made-up result
+    pub fn call(
+        &self,
+        service: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        if method == "health" {
+            return self.health(service);
+        }
+        let template =
+            template(service).ok_or_else(|| format!("Service '{}' not found", service))?;
+        if !template.methods.contains(&method) {
+            return Err(format!("Unknown method '{}'", method));
+        }
+        let mut sims = self.sims.lock().unwrap();
+        let sim = sims.get_mut(service).expect("every template has a sim");
+        if !sim.running || sim.status == "not_responding" {
+            return Err(format!("Service '{}' is not responding", service));
+        }
+        sim.log("INFO", &format!("{} called", method));
+        Ok(serde_json::json!({
+            "demo": true,
+            "service": service,
+            "method": method,
+            "params": params,
+            "items": (1..=3)
+                .map(|n| serde_json::json!({ "id": n, "name": format!("{} {}", method, n) }))
+                .collect::<Vec<_>>(),
+        }))
+    }
+
+    /// The end of a service's log
+    pub fn logs(&self, service: &str, lines: usize) -> Option<LogTail> {
+        let sims = self.sims.lock().unwrap();
+        let sim = sims.get(service)?;
+        let skip = sim.logs.len().saturating_sub(lines);
+        let lines: Vec<String> = sim.logs.iter().skip(skip).cloned().collect();
+        Some(LogTail {
+            path: format!("demo:{}", service),
+            size: sim.logs.iter().map(|line| line.len() as u64 + 1).sum(),
+            lines,
+        })
+    }
+}
+
+/// What may happen to a running service between two scans
+fn step(template: &Template, sim: &mut Sim, now: u64) {
+    if sim.trouble > 0 {
+        sim.trouble -= 1;
+        if sim.trouble == 0 {
+            sim.status = "running";
+            sim.log("INFO", "recovered");
+        }
+        return;
+    }
+    let roll = random();
+    if roll < 0.004 {
+        sim.started_at = now;
+        sim.log("WARN", "worker crashed, supervisor restarted it");
+        sim.log(
+            "INFO",
+            &format!("{} {} listening", template.name, template.version),
+        );
+    } else if roll < 0.010 {
+        sim.status = "not_responding";
+        sim.trouble = 2 + (random() * 8.0) as u32;
+        sim.log("ERROR", "event loop blocked, health checks timing out");
+    } else if roll < 0.025 {
+        sim.status = "degraded";
+        sim.trouble = 3 + (random() * 20.0) as u32;
+        sim.log("WARN", "upstream slow, serving from cache");
+    } else if roll < 0.3 {
+        sim.log(
+            "INFO",
+            &format!(
+                "handled {} requests in the last poll",
+                (jitter(40.0, 0.8)) as u64
+            ),
+        );
+    }
+}
+
+fn health(template: &Template, sim: &Sim, now: u64) -> serde_json::Value {
+    let load = if sim.status == "degraded" { 2.5 } else { 1.0 };
+    serde_json::json!({
+        "status": sim.status,
+        "version": template.version,
+        "uptime_seconds": now.saturating_sub(sim.started_at),
+        "cpu_percent": (jitter(template.cpu_percent * load, 0.4) * 10.0).round() / 10.0,
+        "memory_bytes": (jitter(template.memory_mb, 0.05) * 1024.0 * 1024.0) as u64,
+        "protocol_version": "1.0",
+    })
+}
+
+fn info(template: &Template, sim: &Sim, settings: &Settings, now: u64) -> ServiceInfo {
+    let responding = sim.running && sim.status != "not_responding";
+    let health = responding.then(|| health(template, sim, now));
+    let latency = if sim.status == "degraded" { 6.0 } else { 1.0 };
+    ServiceInfo {
+        name: template.name.to_string(),
+        status: sim.status.to_string(),
+        version: responding.then(|| template.version.to_string()),
+        uptime_seconds: responding.then(|| now.saturating_sub(sim.started_at)),
+        probe_error: (sim.status == "not_responding")
+            .then(|| "health probe timed out after 2000ms".to_string()),
+        cache_age: None,
+        socket_path: format!("demo:{}", template.name),
+        source: SOURCE.to_string(),
+        host: settings.host.name.clone(),
+        labels: settings.host.labels.clone(),
+        pinned: false,
+        config_drift: false,
+        restarts: 0,
+        latency_ms: responding.then(|| jitter(template.latency_ms * latency, 0.35)),
+        probe_timing: None,
+        cpu_percent: health
+            .as_ref()
+            .and_then(|health| health["cpu_percent"].as_f64()),
+        memory_bytes: health
+            .as_ref()
+            .and_then(|health| health["memory_bytes"].as_u64()),
+        capabilities: None,
+        protocol_version: responding.then(|| "1.0".to_string()),
+        latest_version: None,
+        outdated: false,
+        supervisor: None,
+        health,
+    }
+}
+
+/// Answer the routes the demo can't serve with a 409
+pub async fn guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if state.demo.is_none() {
+        return next.run(req).await;
+    }
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|route| route.as_str())
+        .unwrap_or_default();
+    if UNAVAILABLE_ROUTES.contains(&route) {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<()>::error(&format!("{} is not available in demo mode", route)),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
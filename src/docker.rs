@@ -0,0 +1,212 @@
+//! FGP daemons running in Docker containers.
+//!
+//! With `--docker`, every scan also lists the containers labelled
+//! `fgp.service=<name>`, running or not, and shows each as a service of
+//! that name with `docker` as its source. Services directories win when a
+//! name is in both. The daemon's socket inside the container is
+//! `/run/fgp/daemon.sock` unless the `fgp.socket` label says otherwise, and
+//! is reached:
+//!
+//! - through the host side of a bind mount covering it, when there is one
+//! - otherwise through `docker exec -i <container> socat - UNIX-CONNECT:<socket>`,
+//!   which needs `socat` in the image; such services have a socket path of
+//!   `docker://<container><socket>`
+//!
+//! Starting, stopping and restarting such a service starts, stops and
+//! restarts its container. The `docker` CLI is used throughout, so
+//! `DOCKER_HOST` and contexts apply as usual.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// Source shown for services found in containers
+pub const SOURCE: &str = "docker";
+
+/// Label naming the service a container runs
+pub const SERVICE_LABEL: &str = "fgp.service";
+
+/// Label giving the daemon's socket path inside the container
+pub const SOCKET_LABEL: &str = "fgp.socket";
+
+/// Socket path inside the container when no label gives one
+pub const DEFAULT_SOCKET: &str = "/run/fgp/daemon.sock";
+
+/// Scheme of socket paths proxied through `docker exec`
+pub const SCHEME: &str = "docker://";
+
+/// The `docker` executable
+const DOCKER: &str = "docker";
+
+/// A labelled container
+pub struct Container {
+    /// Name without the leading `/`
+    pub name: String,
+    pub service: String,
+    pub running: bool,
+    /// How the dashboard reaches the daemon's socket
+    pub socket_path: PathBuf,
+}
+
+/// The parts of `docker inspect` output used here
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Inspected {
+    name: String,
+    state: InspectedState,
+    config: InspectedConfig,
+    #[serde(default)]
+    mounts: Vec<Mount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedState {
+    running: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectedConfig {
+    #[serde(default)]
+    labels: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Mount {
+    source: String,
+    destination: String,
+}
+
+/// Run a `docker` subcommand, returning its standard output
+fn docker(args: &[&str]) -> Result<String, String> {
+    let output = Command::new(DOCKER)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", DOCKER, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            DOCKER,
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// IDs of the containers labelled with `filter`
+fn container_ids(filter: &str) -> Result<Vec<String>, String> {
+    let ids = docker(&["ps", "--all", "--quiet", "--no-trunc", "--filter", filter])?;
+    Ok(ids.lines().map(str::to_string).collect())
+}
+
+/// Where the host sees the socket at `socket` inside a container, if a
+/// bind mount covers it
+fn host_path(mounts: &[Mount], socket: &str) -> Option<PathBuf> {
+    mounts
+        .iter()
+        .filter_map(|mount| {
+            let rest = Path::new(socket).strip_prefix(&mount.destination).ok()?;
+            Some((mount.destination.len(), Path::new(&mount.source).join(rest)))
+        })
+        // The deepest mount is the one that applies
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, path)| path)
+}
+
+/// Every container labelled with a service name
+///
+/// Blocks, so call it off the async runtime.
+pub fn containers() -> Result<Vec<Container>, String> {
+    let ids = container_ids(&format!("label={}", SERVICE_LABEL))?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec!["inspect"];
+    args.extend(ids.iter().map(String::as_str));
+    let inspected: Vec<Inspected> = serde_json::from_str(&docker(&args)?)
+        .map_err(|e| format!("Unexpected docker inspect output: {}", e))?;
+
+    Ok(inspected
+        .into_iter()
+        .filter_map(|container| {
+            let labels = container.config.labels.unwrap_or_default();
+            let service = labels.get(SERVICE_LABEL)?.trim().to_string();
+            if service.is_empty() {
+                return None;
+            }
+            let name = container.name.trim_start_matches('/').to_string();
+            let socket = labels
+                .get(SOCKET_LABEL)
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_SOCKET);
+            let socket_path = host_path(&container.mounts, socket)
+                .unwrap_or_else(|| PathBuf::from(format!("{}{}{}", SCHEME, name, socket)));
+            Some(Container {
+                name,
+                service,
+                running: container.state.running,
+                socket_path,
+            })
+        })
+        .collect())
+}
+
+/// `docker exec` arguments that connect stdin and stdout to a container's
+/// socket, for a `docker://` socket path
+pub fn proxy_command(socket_path: &str) -> Option<Command> {
+    let (container, socket) = socket_path.strip_prefix(SCHEME)?.split_once('/')?;
+    let mut command = Command::new(DOCKER);
+    command.args([
+        "exec",
+        "-i",
+        container,
+        "socat",
+        "-",
+        &format!("UNIX-CONNECT:/{}", socket),
+    ]);
+    Some(command)
+}
+
+/// A container lifecycle command
+#[derive(Clone, Copy)]
+pub enum Lifecycle {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl Lifecycle {
+    pub fn verb(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Restart => "restart",
+        }
+    }
+}
+
+/// Start, stop or restart the container running `service`
+///
+/// Returns `None` if no container is labelled with the service. Blocks, so
+/// call it off the async runtime.
+pub fn control(service: &str, lifecycle: Lifecycle) -> Option<Result<(), String>> {
+    let ids = match container_ids(&format!("label={}={}", SERVICE_LABEL, service)) {
+        Ok(ids) => ids,
+        Err(e) => return Some(Err(e)),
+    };
+    let id = ids.first()?;
+    if ids.len() > 1 {
+        return Some(Err(format!(
+            "{} containers are labelled {}={}; control them with docker directly",
+            ids.len(),
+            SERVICE_LABEL,
+            service
+        )));
+    }
+    Some(docker(&[lifecycle.verb(), id]).map(|_| ()))
+}
@@ -0,0 +1,380 @@
+//! `fgp-dashboard doctor`: diagnose common setup problems.
+//!
+//! Each check produces findings with a severity and, where possible, a hint
+//! on how to fix it. The exit code is 0 when everything is fine, 1 if there
+//! are warnings and 2 if anything failed.
+
+use serde::Serialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::docker;
+use crate::exposure;
+use crate::kubernetes;
+use crate::launchd;
+use crate::plugins;
+use crate::scripts;
+use crate::state;
+use crate::systemd;
+use crate::transport;
+
+/// Unix time of 2024-01-01; a clock earlier than this is certainly wrong
+const PLAUSIBLE_EPOCH: u64 = 1_704_067_200;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Result of a single check
+#[derive(Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: String) -> Self {
+        Self {
+            severity: Severity::Ok,
+            check,
+            message,
+            hint: None,
+        }
+    }
+
+    fn warn(check: &'static str, message: String, hint: &str) -> Self {
+        Self {
+            severity: Severity::Warn,
+            check,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn fail(check: &'static str, message: String, hint: &str) -> Self {
+        Self {
+            severity: Severity::Fail,
+            check,
+            message,
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+fn check_services_dir(dir: &Path, findings: &mut Vec<Finding>) {
+    match fs::read_dir(dir) {
+        Ok(_) => findings.push(Finding::ok(
+            "services_dir",
+            format!("{} is readable", dir.display()),
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => findings.push(Finding::warn(
+            "services_dir",
+            format!("{} does not exist", dir.display()),
+            "Install an FGP service or point --services-dir at an existing directory",
+        )),
+        Err(e) => findings.push(Finding::fail(
+            "services_dir",
+            format!("{} is not readable: {}", dir.display(), e),
+            "Check the directory's permissions or run the dashboard as its owner",
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn check_socket(service: &str, socket: &Path, findings: &mut Vec<Finding>) {
+    use std::os::unix::net::UnixStream;
+
+    use crate::sockets;
+
+    match UnixStream::connect(socket) {
+        Ok(_) => findings.push(Finding::ok(
+            "socket",
+            format!("{}: socket accepts connections", service),
+        )),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => findings.push(Finding::warn(
+            "socket",
+            format!("{}: stale socket at {}", service, socket.display()),
+            "The daemon is gone; remove the socket file and start the service again",
+        )),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            let diagnosis = sockets::diagnose(socket, &anyhow::Error::from(e))
+                .unwrap_or_else(|| "unknown cause".to_string());
+            findings.push(Finding::fail(
+                "socket",
+                format!(
+                    "{}: permission denied on {} ({})",
+                    service,
+                    socket.display(),
+                    diagnosis
+                ),
+                "Run the dashboard as the same user as the daemon, or adjust the socket's mode",
+            ));
+        }
+        Err(e) => findings.push(Finding::fail(
+            "socket",
+            format!("{}: cannot connect to {}: {}", service, socket.display(), e),
+            "Restart the service",
+        )),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_socket(_service: &str, _socket: &Path, _findings: &mut Vec<Finding>) {}
+
+fn check_docker(findings: &mut Vec<Finding>) {
+    match docker::containers() {
+        Ok(containers) => findings.push(Finding::ok(
+            "docker",
+            format!(
+                "Docker reachable, {} labelled container(s)",
+                containers.len()
+            ),
+        )),
+        Err(e) => findings.push(Finding::fail(
+            "docker",
+            e,
+            "Install the docker CLI and make sure the dashboard's user may use it",
+        )),
+    }
+}
+
+fn check_systemd(findings: &mut Vec<Finding>) {
+    match systemd::available() {
+        Ok(version) => findings.push(Finding::ok("systemd", version)),
+        Err(e) => findings.push(Finding::fail(
+            "systemd",
+            e,
+            "Run the dashboard on a host managed by systemd, or drop --systemd",
+        )),
+    }
+}
+
+fn check_launchd(findings: &mut Vec<Finding>) {
+    match launchd::available() {
+        Ok(summary) => findings.push(Finding::ok("launchd", summary)),
+        Err(e) => findings.push(Finding::fail(
+            "launchd",
+            e,
+            "Run the dashboard as the macOS user owning the launch agents, or drop --launchd",
+        )),
+    }
+}
+
+fn check_kubernetes(scope: &kubernetes::Scope, findings: &mut Vec<Finding>) {
+    match kubernetes::pods(scope) {
+        Ok(pods) => findings.push(Finding::ok(
+            "kubernetes",
+            format!("Kubernetes reachable, {} matching pod(s)", pods.len()),
+        )),
+        Err(e) => findings.push(Finding::fail(
+            "kubernetes",
+            e,
+            "Install kubectl and give its context or service account permission to list, exec into and delete pods",
+        )),
+    }
+}
+
+fn check_plugins(findings: &mut Vec<Finding>) {
+    let list = plugins::registry().list();
+    for failure in list.failed {
+        findings.push(Finding::fail(
+            "plugins",
+            format!("Cannot load {}: {}", failure.path, failure.error),
+            "Rebuild the plugin for this dashboard's plugin ABI or remove it",
+        ));
+    }
+    if !list.plugins.is_empty() {
+        let names: Vec<String> = list.plugins.into_iter().map(|plugin| plugin.name).collect();
+        findings.push(Finding::ok(
+            "plugins",
+            format!("Loaded plugins: {}", names.join(", ")),
+        ));
+    }
+    if !list.checks.is_empty() {
+        let names: Vec<String> = list.checks.into_iter().map(|check| check.name).collect();
+        findings.push(Finding::ok(
+            "plugins",
+            format!("Loaded WASM checks: {}", names.join(", ")),
+        ));
+    }
+}
+
+fn check_scripts(findings: &mut Vec<Finding>) {
+    let list = scripts::scripts().list();
+    for failure in list.failed {
+        findings.push(Finding::fail(
+            "scripts",
+            format!("Cannot load {}: {}", failure.path, failure.error),
+            "Fix the script or remove it from the script dir",
+        ));
+    }
+    if !list.scripts.is_empty() {
+        let names: Vec<String> = list.scripts.into_iter().map(|script| script.name).collect();
+        findings.push(Finding::ok(
+            "scripts",
+            format!("Loaded scripts: {}", names.join(", ")),
+        ));
+    }
+}
+
+fn check_port(addr: SocketAddr, findings: &mut Vec<Finding>) {
+    match TcpListener::bind(addr) {
+        Ok(_) => findings.push(Finding::ok("port", format!("{} is available", addr))),
+        Err(e) if e.kind() == ErrorKind::AddrInUse => findings.push(Finding::warn(
+            "port",
+            format!("{} is already in use", addr),
+            "Another dashboard may already be running; stop it or pick another --port",
+        )),
+        Err(e) => findings.push(Finding::fail(
+            "port",
+            format!("Cannot bind {}: {}", addr, e),
+            "Check --bind and --port",
+        )),
+    }
+}
+
+fn check_exposure(config: &Config, findings: &mut Vec<Finding>) {
+    let missing = exposure::missing(config);
+    if config.bind.is_loopback() {
+        findings.push(Finding::ok(
+            "exposure",
+            format!("Listening on {} only", config.bind),
+        ));
+    } else if missing.is_empty() {
+        findings.push(Finding::ok(
+            "exposure",
+            format!("Listening on {} with authentication and TLS", config.bind),
+        ));
+    } else if config.insecure_lan {
+        findings.push(Finding::warn(
+            "exposure",
+            format!(
+                "Listening on {} without {}",
+                config.bind,
+                missing.join(" or ")
+            ),
+            "--insecure-lan allows this; configure them unless every host on the network is trusted",
+        ));
+    } else {
+        findings.push(Finding::fail(
+            "exposure",
+            format!(
+                "Refusing to listen on {} without {}",
+                config.bind,
+                missing.join(" or ")
+            ),
+            "Set --auth-token, --tls-cert and --tls-key, bind to 127.0.0.1, or pass --insecure-lan",
+        ));
+    }
+}
+
+fn check_clock(findings: &mut Vec<Finding>) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    if now < PLAUSIBLE_EPOCH {
+        findings.push(Finding::fail(
+            "clock",
+            format!(
+                "System clock reads {} (Unix time), which is in the past",
+                now
+            ),
+            "Enable NTP or set the system time; uptimes and event times will be wrong",
+        ));
+    } else {
+        findings.push(Finding::ok("clock", "System clock looks sane".to_string()));
+    }
+}
+
+/// Run every check
+pub fn diagnose(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for dir in &config.services_dirs {
+        check_services_dir(dir, &mut findings);
+
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if !entry.path().is_dir() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(socket) = state::socket_path(dir, &name) else {
+                continue;
+            };
+            if transport::endpoint_exists(&socket) {
+                check_socket(&name, &socket, &mut findings);
+            }
+        }
+    }
+
+    if config.docker {
+        check_docker(&mut findings);
+    }
+    if config.systemd {
+        check_systemd(&mut findings);
+    }
+    if config.launchd {
+        check_launchd(&mut findings);
+    }
+    if let Some(selector) = &config.kubernetes {
+        let scope = kubernetes::Scope {
+            selector,
+            namespace: config.kubernetes_namespace.as_deref(),
+        };
+        check_kubernetes(&scope, &mut findings);
+    }
+    check_plugins(&mut findings);
+    check_scripts(&mut findings);
+    check_port(SocketAddr::new(config.bind, config.port), &mut findings);
+    check_exposure(config, &mut findings);
+    check_clock(&mut findings);
+
+    findings
+}
+
+/// Run `fgp-dashboard doctor`, returning the process exit code
+pub fn run(config: &Config, json: bool) -> anyhow::Result<i32> {
+    let findings = diagnose(config);
+    let worst = findings
+        .iter()
+        .map(|f| f.severity)
+        .max()
+        .unwrap_or(Severity::Ok);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            let tag = match finding.severity {
+                Severity::Ok => "[ OK ]",
+                Severity::Warn => "[WARN]",
+                Severity::Fail => "[FAIL]",
+            };
+            println!("{} {}", tag, finding.message);
+            if let Some(hint) = &finding.hint {
+                println!("       -> {}", hint);
+            }
+        }
+    }
+
+    Ok(match worst {
+        Severity::Ok => 0,
+        Severity::Warn => 1,
+        Severity::Fail => 2,
+    })
+}
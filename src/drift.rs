@@ -0,0 +1,176 @@
+//! Detecting manifests that changed under a running daemon.
+//!
+//! Every scan hashes each running service's manifest files (see
+//! [`MANIFEST_FILES`]) and compares them with the hash taken when the
+//! daemon was first seen running. A mismatch publishes a `config_drift`
+//! event, which webhooks can subscribe to, and flags the service with
+//! `config_drift` until the daemon restarts (its uptime goes backwards) or
+//! stops, at which point the current manifest becomes the new baseline.
+//!
+//! The same bookkeeping counts restarts: a daemon whose uptime went
+//! backwards, or that came back after being stopped, adds one to its
+//! `restarts`. The first case also publishes a `restarted` event, since no
+//! status change was seen.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::api::ServiceInfo;
+use crate::calls;
+use crate::events::{self, EventBus};
+
+/// Files in a service's directory that make up its manifest
+pub const MANIFEST_FILES: &[&str] = &[
+    "manifest.json",
+    "manifest.toml",
+    "config.json",
+    "config.toml",
+];
+
+/// A string field of the manifest in a service directory
+///
+/// `manifest.json` is read if present, `manifest.toml` otherwise. Blank
+/// values count as missing.
+pub fn manifest_field(dir: &Path, field: &str) -> Option<String> {
+    let value = if let Ok(contents) = fs::read_to_string(dir.join("manifest.json")) {
+        serde_json::from_str::<serde_json::Value>(&contents)
+            .ok()?
+            .get(field)?
+            .as_str()?
+            .to_string()
+    } else {
+        let contents = fs::read_to_string(dir.join("manifest.toml")).ok()?;
+        toml::from_str::<toml::Value>(&contents)
+            .ok()?
+            .get(field)?
+            .as_str()?
+            .to_string()
+    };
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Manifest state of a running service
+struct Baseline {
+    hash: String,
+    uptime_seconds: Option<u64>,
+    drifted: bool,
+}
+
+/// Manifest hashes of running services
+#[derive(Default)]
+pub struct DriftTracker {
+    baselines: Mutex<HashMap<String, Baseline>>,
+    /// Restart counts of every service seen running
+    restarts: Mutex<HashMap<String, u64>>,
+}
+
+/// Hash the manifest files present in a service directory
+fn manifest_hash(dir: &Path) -> String {
+    let mut hasher = Sha256::new();
+    for name in MANIFEST_FILES {
+        if let Ok(contents) = fs::read(dir.join(name)) {
+            hasher.update(name.as_bytes());
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+impl DriftTracker {
+    /// Compare manifests with their baselines, setting `config_drift` on
+    /// `services` and publishing an event for each newly drifted one
+    ///
+    /// Also fills in `restarts`.
+    pub async fn check(&self, services: &mut [ServiceInfo], events: &EventBus) {
+        let dirs: Vec<(String, PathBuf)> = services
+            .iter()
+            .filter(|service| service.status != "stopped")
+            .map(|service| {
+                let dir = Path::new(&service.source).join(&service.name);
+                (service.name.clone(), dir)
+            })
+            .collect();
+        let hashes: HashMap<String, String> = calls::blocking(move || {
+            dirs.into_iter()
+                .map(|(name, dir)| (name, manifest_hash(&dir)))
+                .collect()
+        })
+        .await;
+
+        let mut drifted = Vec::new();
+        let mut restarted_services = Vec::new();
+        {
+            let mut baselines = self.baselines.lock().unwrap();
+            let mut restarts = self.restarts.lock().unwrap();
+            // Stopped services start from their new manifest next time
+            baselines.retain(|name, _| hashes.contains_key(name));
+
+            for service in services.iter_mut() {
+                let Some(hash) = hashes.get(&service.name) else {
+                    service.restarts = restarts.get(&service.name).copied().unwrap_or(0);
+                    continue;
+                };
+                let restarted = |baseline: &Baseline| {
+                    matches!(
+                        (baseline.uptime_seconds, service.uptime_seconds),
+                        (Some(before), Some(now)) if now < before
+                    )
+                };
+
+                let uptime_reset = baselines.get(&service.name).is_some_and(restarted);
+                match baselines.get_mut(&service.name) {
+                    Some(baseline) if !uptime_reset => {
+                        if service.uptime_seconds.is_some() {
+                            baseline.uptime_seconds = service.uptime_seconds;
+                        }
+                        if baseline.hash != *hash && !baseline.drifted {
+                            baseline.drifted = true;
+                            drifted.push(service.name.clone());
+                        }
+                        service.config_drift = baseline.drifted;
+                    }
+                    _ => {
+                        if uptime_reset {
+                            restarted_services.push(service.name.clone());
+                        }
+                        // The first sighting isn't a restart
+                        restarts
+                            .entry(service.name.clone())
+                            .and_modify(|count| *count += 1)
+                            .or_insert(0);
+                        baselines.insert(
+                            service.name.clone(),
+                            Baseline {
+                                hash: hash.clone(),
+                                uptime_seconds: service.uptime_seconds,
+                                drifted: false,
+                            },
+                        );
+                    }
+                }
+                service.restarts = restarts.get(&service.name).copied().unwrap_or(0);
+            }
+        }
+
+        for service in restarted_services {
+            events.publish(
+                &service,
+                events::RESTARTED,
+                "Daemon restarted: its uptime went backwards".to_string(),
+            );
+        }
+        for service in drifted {
+            events.publish(
+                &service,
+                events::CONFIG_DRIFT,
+                "Manifest changed on disk while the daemon is running; restart it to apply"
+                    .to_string(),
+            );
+        }
+    }
+}
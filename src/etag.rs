@@ -0,0 +1,108 @@
+//! ETag generation and conditional GET handling.
+//!
+//! Responses are tagged with a hash of the serialized body, or, for the
+//! service list, a weak ETag over the fields that don't change on every scan.
+//! Clients that send a matching `If-None-Match` get an empty 304, so polling
+//! an unchanged service list costs almost nothing. The hash isn't stable
+//! across Rust releases, so after an upgrade clients fetch each body once.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Compute an ETag for a response body
+pub fn etag_for(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}{:08x}\"", hasher.finish(), body.len())
+}
+
+/// Check whether an `If-None-Match` header matches the given ETag
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| {
+            candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        })
+}
+
+/// Serialize `payload` as JSON under a weak ETag of `key`, answering with 304
+/// if the client already has it; other fields of `payload` may have changed
+pub fn json_response<T: Serialize, K: Serialize>(
+    headers: &HeaderMap,
+    payload: &T,
+    key: &K,
+) -> Response {
+    let (body, key) = match (serde_json::to_vec(payload), serde_json::to_vec(key)) {
+        (Ok(body), Ok(key)) => (body, key),
+        (Err(e), _) | (_, Err(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    };
+    let etag = format!("W/{}", etag_for(&key));
+    let etag_value = HeaderValue::from_str(&etag).expect("ETag is valid ASCII");
+
+    if if_none_match(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response();
+    }
+
+    (
+        StatusCode::OK,
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (header::ETAG, etag_value),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_if_none_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        let etag = "W/\"abc\"";
+        assert!(if_none_match(&with_if_none_match(&["W/\"abc\""]), etag));
+        assert!(if_none_match(&with_if_none_match(&["\"abc\""]), etag));
+        assert!(if_none_match(
+            &with_if_none_match(&["W/\"abc\""]),
+            "\"abc\""
+        ));
+        assert!(if_none_match(&with_if_none_match(&["*"]), etag));
+        assert!(!if_none_match(&with_if_none_match(&["W/\"abd\""]), etag));
+        assert!(!if_none_match(&HeaderMap::new(), etag));
+    }
+
+    #[test]
+    fn if_none_match_reads_every_listed_tag() {
+        let etag = "W/\"abc\"";
+        assert!(if_none_match(
+            &with_if_none_match(&["\"x\", W/\"abc\""]),
+            etag
+        ));
+        assert!(if_none_match(
+            &with_if_none_match(&["\"x\"", "W/\"abc\""]),
+            etag
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+//! Service events.
+//!
+//! Events are produced when a service changes status between scans, when
+//! its manifest changes while it runs, or when an operator starts or stops
+//! it. They are broadcast to in-process
+//! subscribers (webhooks, streaming endpoints) and the most recent ones are
+//! kept in memory for `GET /api/events`. Every event is also stored in the
+//! database, and the most recent ones are reloaded at startup.
+//!
+//! `GET /api/events/stream` pushes events as they are published, as
+//! server-sent events named `service`, for the UI's notifications.
+//!
+//! `?since=` and `?until=` (Unix timestamps or durations ago, e.g. `6h`)
+//! read a time range from the database instead, for the timeline page; at
+//! most [`MAX_EVENTS`] of the newest matching events are returned.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension,
+};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use ts_rs::TS;
+
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::calls;
+use crate::export::{self, Format};
+use crate::filter::{Expr, FieldValue, Filterable};
+use crate::history;
+use crate::namespaces::{Scope, View};
+use crate::state::AppState;
+
+/// Number of events kept in memory
+pub const RECENT_EVENTS: usize = 1000;
+
+/// A service has moved from one status to another
+pub const STATUS_CHANGED: &str = "status_changed";
+/// An operator started a service
+pub const STARTED: &str = "started";
+/// An operator stopped a service
+pub const STOPPED: &str = "stopped";
+/// A running service's manifest changed on disk
+pub const CONFIG_DRIFT: &str = "config_drift";
+/// A daemon restarted between two scans without being seen stopped
+pub const RESTARTED: &str = "restarted";
+/// An operator removed a socket its daemon left behind
+pub const CLEANED_UP: &str = "cleaned_up";
+/// A step of an upgrade was reached
+pub const UPGRADE_PROGRESS: &str = "upgrade_progress";
+/// An upgraded daemon came up healthy
+pub const UPGRADED: &str = "upgraded";
+/// An upgrade failed and the previous release was put back
+pub const UPGRADE_ROLLED_BACK: &str = "upgrade_rolled_back";
+/// A newer release of a service was found
+pub const UPDATE_AVAILABLE: &str = "update_available";
+/// An operator ran an action a plugin offers
+pub const PLUGIN_ACTION: &str = "plugin_action";
+/// A script sent a notice
+pub const SCRIPT: &str = "script";
+/// An operator edited a service's config file
+pub const CONFIG_EDITED: &str = "config_edited";
+/// A chaos experiment is about to restart or pause a service
+pub const CHAOS: &str = "chaos";
+
+/// Most events returned for a `since` query
+pub const MAX_EVENTS: usize = 10_000;
+
+/// Something that happened to a service
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct Event {
+    #[ts(type = "number")]
+    pub id: u64,
+    /// Unix timestamp in seconds
+    #[ts(type = "number")]
+    pub timestamp: u64,
+    pub service: String,
+    pub kind: String,
+    pub message: String,
+}
+
+impl Filterable for Event {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        match name {
+            "id" => Some(FieldValue::Number(self.id as f64)),
+            "timestamp" => Some(FieldValue::Number(self.timestamp as f64)),
+            "service" => Some(FieldValue::Text(self.service.clone())),
+            "kind" => Some(FieldValue::Text(self.kind.clone())),
+            "message" => Some(FieldValue::Text(self.message.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Current Unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Broadcasts events and remembers the most recent ones
+pub struct EventBus {
+    next_id: AtomicU64,
+    sender: broadcast::Sender<Event>,
+    recent: Mutex<VecDeque<Event>>,
+    last_status: Mutex<HashMap<String, String>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(256);
+
+        Self {
+            next_id: AtomicU64::new(1),
+            sender,
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_EVENTS)),
+            last_status: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl EventBus {
+    /// Start from previously stored events, oldest first, continuing their
+    /// IDs
+    pub fn with_history(history: Vec<Event>) -> Self {
+        let bus = Self::default();
+        if let Some(last) = history.last() {
+            bus.next_id.store(last.id + 1, Ordering::Relaxed);
+        }
+        let skip = history.len().saturating_sub(RECENT_EVENTS);
+        bus.recent
+            .lock()
+            .unwrap()
+            .extend(history.into_iter().skip(skip));
+        bus
+    }
+
+    /// Record and broadcast an event
+    pub fn publish(&self, service: &str, kind: &str, message: String) -> Event {
+        let event = Event {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: now(),
+            service: service.to_string(),
+            kind: kind.to_string(),
+            message,
+        };
+
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_EVENTS {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+
+        // No receivers is fine
+        let _ = self.sender.send(event.clone());
+
+        event
+    }
+
+    /// Receive events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Events currently held in memory, oldest first
+    pub fn recent(&self) -> Vec<Event> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Compare a fresh scan with the previous one and publish status changes
+    pub fn observe(&self, services: &[ServiceInfo]) {
+        let changes: Vec<(String, String, String)> = {
+            let mut last_status = self.last_status.lock().unwrap();
+            services
+                .iter()
+                .filter_map(|service| {
+                    let previous = last_status.insert(service.name.clone(), service.status.clone());
+                    match previous {
+                        Some(previous) if previous != service.status => {
+                            Some((service.name.clone(), previous, service.status.clone()))
+                        }
+                        _ => None,
+                    }
+                })
+                .collect()
+        };
+
+        for (service, from, to) in changes {
+            self.publish(
+                &service,
+                STATUS_CHANGED,
+                format!("Status changed from {} to {}", from, to),
+            );
+        }
+    }
+}
+
+/// Query parameters accepted by `GET /api/events`
+#[derive(Deserialize, Default)]
+pub struct EventsQuery {
+    /// Filter expression, e.g. `kind = status_changed AND service ~ gw`
+    pub q: Option<String>,
+    /// Output format: `json` (default), `csv` or `ndjson`
+    pub format: Option<String>,
+    /// Start of a stored range: a Unix timestamp or a duration ago
+    pub since: Option<String>,
+    /// End of a stored range; defaults to now
+    pub until: Option<String>,
+}
+
+/// Resolve `since`/`until` to a range of Unix timestamps
+fn range(query: &EventsQuery) -> Result<Option<(u64, u64)>, String> {
+    let Some(since) = &query.since else {
+        if query.until.is_some() {
+            return Err("'until' needs 'since'".to_string());
+        }
+        return Ok(None);
+    };
+    let now = now();
+    let from = history::parse_time(since, now)?;
+    let to = match &query.until {
+        Some(until) => history::parse_time(until, now)?,
+        None => now,
+    };
+    if from > to {
+        return Err("'since' is later than 'until'".to_string());
+    }
+    Ok(Some((from, to)))
+}
+
+/// List recent events
+pub async fn list_events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    scope: Option<Extension<Scope>>,
+    headers: HeaderMap,
+) -> Response {
+    let expr = match Expr::from_query(query.q.as_deref()) {
+        Ok(expr) => expr,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<Vec<Event>>::error(&e),
+            )
+                .into_response()
+        }
+    };
+
+    let events = match range(&query) {
+        Ok(None) => state.events.recent(),
+        Ok(Some((from, to))) => {
+            let db = state.db.clone();
+            match calls::blocking(move || db.events_between(from, to, MAX_EVENTS)).await {
+                Ok(events) => events,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        ApiResponse::<Vec<Event>>::error(&format!("{:#}", e)),
+                    )
+                        .into_response()
+                }
+            }
+        }
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<Vec<Event>>::error(&e),
+            )
+                .into_response()
+        }
+    };
+    let view = View::of(&state.settings(), scope);
+    let events: Vec<Event> = events
+        .into_iter()
+        .filter(|event| view.shows(&event.service))
+        .filter(|event| expr.as_ref().is_none_or(|expr| expr.matches(event)))
+        .collect();
+
+    match Format::negotiate(&headers, query.format.as_deref()) {
+        Ok(Format::Json) => ApiResponse::success(events).into_response(),
+        Ok(format) => export::render(format, &serde_json::to_value(events).unwrap_or_default()),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Vec<Event>>::error(&e),
+        )
+            .into_response(),
+    }
+}
+
+/// Stream events as they are published
+///
+/// Subscribers that fall behind skip the events they missed.
+pub async fn stream_events(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let view = View::of(&state.settings(), scope);
+    let receiver = state.events.subscribe();
+    let stream = futures::stream::unfold((receiver, view), |(mut receiver, view)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if !view.shows(&event.service) => continue,
+                Ok(event) => {
+                    let message = sse::Event::default()
+                        .event("service")
+                        .id(event.id.to_string())
+                        .json_data(&event)
+                        .expect("events serialize");
+                    return Some((Ok(message), (receiver, view)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,108 @@
+//! CSV and NDJSON renderings of list endpoints.
+//!
+//! The format is picked with `?format=json|csv|ndjson` or, failing that, the
+//! `Accept` header (`text/csv`, `application/x-ndjson`). Nested values such
+//! as labels are written as JSON strings in CSV cells.
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+/// Output format of a list endpoint
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    /// Choose a format from the `format` query parameter or `Accept` header
+    pub fn negotiate(headers: &HeaderMap, format: Option<&str>) -> Result<Self, String> {
+        if let Some(format) = format {
+            return match format {
+                "json" => Ok(Self::Json),
+                "csv" => Ok(Self::Csv),
+                "ndjson" => Ok(Self::Ndjson),
+                other => Err(format!(
+                    "invalid format '{}', expected json, csv or ndjson",
+                    other
+                )),
+            };
+        }
+
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if accept.contains("text/csv") {
+            Ok(Self::Csv)
+        } else if accept.contains("ndjson") {
+            Ok(Self::Ndjson)
+        } else {
+            Ok(Self::Json)
+        }
+    }
+}
+
+/// Render a list of items as CSV or NDJSON
+///
+/// `items` is expected to be a JSON array of objects; anything else is
+/// treated as a single row.
+pub fn render(format: Format, items: &serde_json::Value) -> Response {
+    let rows: Vec<&serde_json::Value> = match items {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+
+    match format {
+        Format::Csv => match to_csv(&rows) {
+            Ok(body) => ([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], body).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        Format::Ndjson => {
+            let mut body = String::new();
+            for row in rows {
+                body.push_str(&row.to_string());
+                body.push('\n');
+            }
+            ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+        }
+        Format::Json => axum::Json(items).into_response(),
+    }
+}
+
+fn to_csv(rows: &[&serde_json::Value]) -> Result<Vec<u8>, csv::Error> {
+    // Columns in order of first appearance
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        if let Some(fields) = row.as_object() {
+            for key in fields.keys() {
+                if !columns.contains(&key.as_str()) {
+                    columns.push(key);
+                }
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&columns)?;
+
+    for row in rows {
+        let record: Vec<String> = columns
+            .iter()
+            .map(|column| match row.get(*column) {
+                None | Some(serde_json::Value::Null) => String::new(),
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+        writer.write_record(&record)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
@@ -0,0 +1,67 @@
+//! Guarding the dashboard when it listens beyond loopback.
+//!
+//! Bound to a loopback address, the dashboard is only reachable from this
+//! host. Anyone who can reach any other `--bind` address could stop services
+//! and call daemon methods, and without TLS read tokens and payloads on the
+//! way, so such an address needs authentication (`--auth-token`, see
+//! [`auth`](crate::auth)) and TLS (`--tls-cert` and `--tls-key`, see
+//! [`tls`](crate::tls)). The dashboard refuses to start, and config reloads
+//! that drop either are refused, unless `--insecure-lan` says the network is
+//! trusted; it then logs what is missing at startup and on every reload.
+
+use anyhow::Result;
+
+use crate::config::Config;
+
+/// What the bind address needs but isn't configured
+pub fn missing(config: &Config) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if config.bind.is_loopback() {
+        return missing;
+    }
+    if config.auth_tokens.is_empty()
+        && config
+            .namespaces
+            .iter()
+            .all(|namespace| namespace.tokens.is_empty())
+    {
+        missing.push("authentication (--auth-token)");
+    }
+    if config.tls.is_none() {
+        missing.push("TLS (--tls-cert and --tls-key)");
+    }
+    missing
+}
+
+/// Fail if the bind address is exposed without protection or `--insecure-lan`
+pub fn check(config: &Config) -> Result<()> {
+    let missing = missing(config);
+    if missing.is_empty() || config.insecure_lan {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "Refusing to listen on {} without {}. Configure them, bind to 127.0.0.1, or pass --insecure-lan if every host on the network is trusted",
+        config.bind,
+        missing.join(" or ")
+    )
+}
+
+/// Log what an `--insecure-lan` dashboard is running without
+pub fn warn(config: &Config) {
+    let missing = missing(config);
+    if missing.is_empty() {
+        return;
+    }
+    tracing::warn!("==============================================================");
+    tracing::warn!(
+        "INSECURE: listening on {} without {}",
+        config.bind,
+        missing.join(" or ")
+    );
+    tracing::warn!(
+        "Anyone who can reach port {} can stop services and call daemon methods",
+        config.port
+    );
+    tracing::warn!("Allowed by --insecure-lan; only use it on a trusted network");
+    tracing::warn!("==============================================================");
+}
@@ -0,0 +1,432 @@
+//! Filter expressions for `?q=` on list endpoints.
+//!
+//! A small boolean language over the fields of a listed item:
+//!
+//! ```text
+//! status = running AND uptime > 1h AND label.env IN (prod, staging)
+//! NOT (name ~ test OR status != running)
+//! ```
+//!
+//! Comparison operators are `=`, `!=`, `<`, `<=`, `>`, `>=` and `~`
+//! (substring match). Numbers may carry a duration suffix (`s`, `m`, `h`,
+//! `d`) and are compared numerically; everything else compares as text.
+//! Keywords are case-insensitive, and `NOT`s and parentheses nest at most 64
+//! deep.
+
+use std::cmp::Ordering;
+
+/// A value of a field that can be filtered on
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+}
+
+/// Items that expose named fields to filter expressions
+pub trait Filterable {
+    fn field(&self, name: &str) -> Option<FieldValue>;
+}
+
+/// Comparison operator
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+/// A parsed filter expression
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, String),
+    In(String, Vec<String>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(other) => value.push(other),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let followed_by_eq = chars.peek() == Some(&'=');
+                let op = match (c, followed_by_eq) {
+                    ('=', _) => Op::Eq,
+                    ('~', _) => Op::Contains,
+                    ('!', true) => Op::Ne,
+                    ('<', true) => Op::Le,
+                    ('>', true) => Op::Ge,
+                    ('<', false) => Op::Lt,
+                    ('>', false) => Op::Gt,
+                    _ => return Err("expected '=' after '!'".to_string()),
+                };
+                if followed_by_eq && c != '~' {
+                    chars.next();
+                }
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()=!<>~,\"'".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Most `NOT`s and parentheses an expression can nest
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// `NOT`s and parentheses around the current position
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(format!("expected {:?}, found {:?}", expected, other)),
+        }
+    }
+
+    /// Go one level deeper, unless that nests too deeply
+    fn descend(&mut self) -> Result<(), String> {
+        if self.depth >= MAX_DEPTH {
+            return Err("filter nested too deeply".to_string());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            self.descend()?;
+            let expr = self.not()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            self.descend()?;
+            let expr = self.or()?;
+            self.expect(Token::RParen)?;
+            self.depth -= 1;
+            return Ok(expr);
+        }
+
+        let field = match self.advance() {
+            Some(Token::Word(word)) => word,
+            other => return Err(format!("expected field name, found {:?}", other)),
+        };
+
+        if self.keyword("in") {
+            self.expect(Token::LParen)?;
+            let mut values = vec![self.value()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.pos += 1;
+                values.push(self.value()?);
+            }
+            self.expect(Token::RParen)?;
+            return Ok(Expr::In(field, values));
+        }
+
+        match self.advance() {
+            Some(Token::Op(op)) => Ok(Expr::Compare(field, op, self.value()?)),
+            other => Err(format!(
+                "expected operator after '{}', found {:?}",
+                field, other
+            )),
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.advance() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => Ok(value),
+            other => Err(format!("expected value, found {:?}", other)),
+        }
+    }
+}
+
+impl Expr {
+    /// Parse a filter expression
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    /// Parse an optional expression, where a missing one matches everything
+    pub fn from_query(q: Option<&str>) -> Result<Option<Self>, String> {
+        q.filter(|q| !q.trim().is_empty())
+            .map(Self::parse)
+            .transpose()
+    }
+
+    pub fn matches(&self, item: &dyn Filterable) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(item) && b.matches(item),
+            Expr::Or(a, b) => a.matches(item) || b.matches(item),
+            Expr::Not(expr) => !expr.matches(item),
+            Expr::Compare(field, op, value) => item
+                .field(field)
+                .is_some_and(|actual| compare(&actual, *op, value)),
+            Expr::In(field, values) => item
+                .field(field)
+                .is_some_and(|actual| values.iter().any(|value| compare(&actual, Op::Eq, value))),
+        }
+    }
+}
+
+/// Parse a number, allowing a duration suffix (`90s`, `5m`, `1h`, `2d`)
+fn parse_number(s: &str) -> Option<f64> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1.0),
+        Some((i, 'm')) => (&s[..i], 60.0),
+        Some((i, 'h')) => (&s[..i], 3600.0),
+        Some((i, 'd')) => (&s[..i], 86400.0),
+        _ => (s, 1.0),
+    };
+    digits.parse::<f64>().ok().map(|n| n * multiplier)
+}
+
+fn compare(actual: &FieldValue, op: Op, expected: &str) -> bool {
+    let ordering = match actual {
+        FieldValue::Number(n) => match parse_number(expected) {
+            Some(expected) => n.partial_cmp(&expected),
+            None => return false,
+        },
+        FieldValue::Text(text) => {
+            if op == Op::Contains {
+                return text.contains(expected);
+            }
+            Some(text.as_str().cmp(expected))
+        }
+    };
+
+    let Some(ordering) = ordering else {
+        return false;
+    };
+
+    match op {
+        Op::Eq => ordering == Ordering::Equal,
+        Op::Ne => ordering != Ordering::Equal,
+        Op::Lt => ordering == Ordering::Less,
+        Op::Le => ordering != Ordering::Greater,
+        Op::Gt => ordering == Ordering::Greater,
+        Op::Ge => ordering != Ordering::Less,
+        Op::Contains => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item {
+        name: &'static str,
+        status: &'static str,
+        uptime: f64,
+    }
+
+    impl Filterable for Item {
+        fn field(&self, name: &str) -> Option<FieldValue> {
+            match name {
+                "name" => Some(FieldValue::Text(self.name.to_string())),
+                "status" => Some(FieldValue::Text(self.status.to_string())),
+                "uptime" => Some(FieldValue::Number(self.uptime)),
+                _ => None,
+            }
+        }
+    }
+
+    const GATEWAY: Item = Item {
+        name: "gw",
+        status: "running",
+        uptime: 7200.0,
+    };
+
+    fn matches(expr: &str, item: &Item) -> bool {
+        Expr::parse(expr).unwrap().matches(item)
+    }
+
+    #[test]
+    fn compares_text_and_numbers() {
+        assert!(matches("status = running AND uptime > 1h", &GATEWAY));
+        assert!(!matches("uptime > 2h", &GATEWAY));
+        assert!(matches("uptime >= 120m", &GATEWAY));
+        assert!(matches("uptime < 7201", &GATEWAY));
+        assert!(matches("name ~ g", &GATEWAY));
+        assert!(matches("name < h", &GATEWAY));
+        assert!(matches("status != 'stopped'", &GATEWAY));
+        // A number compared with text never matches
+        assert!(!matches("uptime > soon", &GATEWAY));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches(
+            "name = gw OR name = db AND status = stopped",
+            &GATEWAY
+        ));
+        assert!(!matches(
+            "(name = gw OR name = db) AND status = stopped",
+            &GATEWAY
+        ));
+    }
+
+    #[test]
+    fn supports_not_in_and_lowercase_keywords() {
+        assert!(matches("NOT (name ~ test OR status != running)", &GATEWAY));
+        assert!(matches("name in (db, \"gw\")", &GATEWAY));
+        assert!(!matches("not name IN (gw)", &GATEWAY));
+    }
+
+    #[test]
+    fn missing_fields_never_match() {
+        assert!(!matches("label.env = prod", &GATEWAY));
+        assert!(!matches("label.env != prod", &GATEWAY));
+        assert!(matches("NOT label.env = prod", &GATEWAY));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in [
+            "",
+            "status",
+            "status =",
+            "status running",
+            "(status = running",
+            "status = running)",
+            "status = 'running",
+            "name ! gw",
+            "name IN gw",
+            "name IN (gw",
+            "status = running AND",
+        ] {
+            assert!(Expr::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nots = |depth| format!("{}name = gw", "NOT ".repeat(depth));
+        assert!(Expr::parse(&nots(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Expr::parse(&nots(MAX_DEPTH + 1)).unwrap_err(),
+            "filter nested too deeply"
+        );
+
+        let parens = |depth| format!("{}name = gw{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(matches(&parens(MAX_DEPTH), &GATEWAY));
+        assert!(Expr::parse(&parens(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert!(Expr::from_query(None).unwrap().is_none());
+        assert!(Expr::from_query(Some("  ")).unwrap().is_none());
+        assert!(Expr::from_query(Some("name = gw")).unwrap().is_some());
+    }
+}
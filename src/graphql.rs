@@ -0,0 +1,402 @@
+//! GraphQL API for the FGP Dashboard.
+//!
+//! Exposes the same data as the REST API as a single schema, so custom
+//! frontends can fetch services, their health, host labels, events and
+//! recorded history in one round trip. Live updates are available as
+//! subscriptions over `/graphql/ws`: the service list on an interval, and
+//! events as they are published.
+//!
+//! Queries nested deeper than [`MAX_DEPTH`] or costing more than
+//! [`MAX_COMPLEXITY`] are refused before they run.
+
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, Json, Object, Result, Schema, Subscription,
+};
+use axum::response::{Html, IntoResponse};
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::api::{HealthOutcome, ServiceInfo};
+use crate::calls;
+use crate::config;
+use crate::events::{self, Event};
+use crate::health_cache::ProbeOptions;
+use crate::history::{self, HistoryPoint};
+use crate::hosts::{HostInfo, LabelSelector};
+use crate::state::AppState;
+
+pub type DashboardSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+/// Deepest nesting a query may use
+pub const MAX_DEPTH: usize = 8;
+
+/// Most fields a query may select
+pub const MAX_COMPLEXITY: usize = 500;
+
+/// Events returned by the `events` query when no limit is given
+const DEFAULT_EVENTS: usize = 100;
+
+/// Build the schema with the shared application state attached
+pub fn schema(state: AppState) -> DashboardSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .data(state)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Serve the GraphiQL playground
+pub async fn graphiql() -> impl IntoResponse {
+    Html(
+        GraphiQLSource::build()
+            .endpoint("/graphql")
+            .subscription_endpoint("/graphql/ws")
+            .finish(),
+    )
+}
+
+/// Stored services whose labels match the selector
+async fn services_matching(state: &AppState, label: Option<&str>) -> Result<Vec<Service>> {
+    let selector = LabelSelector::from_query(label)?;
+
+    Ok(state
+        .services()
+        .await
+        .services
+        .iter()
+        .filter(|service| selector.matches(&service.labels))
+        .cloned()
+        .map(Service)
+        .collect())
+}
+
+pub struct Service(ServiceInfo);
+
+#[Object]
+impl Service {
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn version(&self) -> Option<&str> {
+        self.0.version.as_deref()
+    }
+
+    async fn uptime_seconds(&self) -> Option<u64> {
+        self.0.uptime_seconds
+    }
+
+    /// Why the last health probe failed, if it did
+    async fn probe_error(&self) -> Option<&str> {
+        self.0.probe_error.as_deref()
+    }
+
+    async fn socket_path(&self) -> &str {
+        &self.0.socket_path
+    }
+
+    /// Services directory the service was found in
+    async fn source(&self) -> &str {
+        &self.0.source
+    }
+
+    async fn host(&self) -> &str {
+        &self.0.host
+    }
+
+    async fn labels(&self) -> Json<BTreeMap<String, String>> {
+        Json(self.0.labels.clone())
+    }
+
+    /// Full health payload, fetched from the daemon on demand
+    async fn health(&self, ctx: &Context<'_>) -> Result<Json<serde_json::Value>> {
+        let state = ctx.data::<AppState>()?;
+        let (outcome, _) = state
+            .health_cache
+            .probe(
+                &self.0.name,
+                Path::new(&self.0.socket_path),
+                &state.settings(),
+                ProbeOptions::default(),
+            )
+            .await;
+        match outcome {
+            HealthOutcome::Healthy(mut result) => {
+                state.settings().redact.json(&mut result);
+                Ok(Json(result))
+            }
+            HealthOutcome::Failed { error, .. } => Err(error.into()),
+        }
+    }
+}
+
+pub struct Host(HostInfo);
+
+#[Object]
+impl Host {
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn labels(&self) -> Json<BTreeMap<String, String>> {
+        Json(self.0.labels.clone())
+    }
+
+    /// Services installed on this host
+    async fn services(&self, ctx: &Context<'_>) -> Result<Vec<Service>> {
+        let state = ctx.data::<AppState>()?;
+        if self.0.name != state.settings().host.name {
+            return Ok(vec![]);
+        }
+        services_matching(state, None).await
+    }
+}
+
+pub struct EventItem(Event);
+
+#[Object(name = "Event")]
+impl EventItem {
+    async fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// Unix timestamp in seconds
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    async fn service(&self) -> &str {
+        &self.0.service
+    }
+
+    async fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    async fn message(&self) -> &str {
+        &self.0.message
+    }
+}
+
+/// Whether an event passes the optional service and kind filters
+fn event_matches(event: &Event, service: Option<&str>, kind: Option<&str>) -> bool {
+    service.is_none_or(|service| event.service == service)
+        && kind.is_none_or(|kind| event.kind == kind)
+}
+
+pub struct Point(HistoryPoint);
+
+#[Object(name = "HistoryPoint")]
+impl Point {
+    /// Start of the interval, as a Unix timestamp
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    /// Scans recorded in the interval
+    async fn samples(&self) -> u64 {
+        self.0.samples
+    }
+
+    /// Scans that found the service healthy
+    async fn healthy(&self) -> u64 {
+        self.0.healthy
+    }
+
+    async fn uptime_seconds(&self) -> Option<u64> {
+        self.0.uptime_seconds
+    }
+
+    /// Average health probe round trip
+    async fn latency_ms(&self) -> Option<f64> {
+        self.0.latency_ms
+    }
+
+    /// Average CPU usage reported by the daemon
+    async fn cpu_percent(&self) -> Option<f64> {
+        self.0.cpu_percent
+    }
+
+    /// Peak memory usage reported by the daemon
+    async fn memory_bytes(&self) -> Option<u64> {
+        self.0.memory_bytes
+    }
+}
+
+pub struct History {
+    service: String,
+    resolution: u64,
+    points: Vec<Point>,
+}
+
+#[Object]
+impl History {
+    async fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// Seconds covered by each point, 0 for raw samples
+    async fn resolution(&self) -> u64 {
+        self.resolution
+    }
+
+    async fn points(&self) -> &[Point] {
+        &self.points
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Installed services, optionally filtered by a label selector
+    async fn services(&self, ctx: &Context<'_>, label: Option<String>) -> Result<Vec<Service>> {
+        services_matching(ctx.data::<AppState>()?, label.as_deref()).await
+    }
+
+    /// A single service by name
+    async fn service(&self, ctx: &Context<'_>, name: String) -> Result<Option<Service>> {
+        Ok(services_matching(ctx.data::<AppState>()?, None)
+            .await?
+            .into_iter()
+            .find(|service| service.0.name == name))
+    }
+
+    /// Recent events, newest last, optionally for one service or of one kind
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        service: Option<String>,
+        kind: Option<String>,
+        #[graphql(default_with = "DEFAULT_EVENTS")] limit: usize,
+    ) -> Result<Vec<EventItem>> {
+        let events: Vec<Event> = ctx
+            .data::<AppState>()?
+            .events
+            .recent()
+            .into_iter()
+            .filter(|event| event_matches(event, service.as_deref(), kind.as_deref()))
+            .collect();
+        let skip = events.len().saturating_sub(limit.min(events::MAX_EVENTS));
+        Ok(events.into_iter().skip(skip).map(EventItem).collect())
+    }
+
+    /// A service's recorded health over the last `range`, e.g. `6h` or `30d`
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        service: String,
+        #[graphql(default_with = "history::DEFAULT_RANGE.to_string()")] range: String,
+    ) -> Result<History> {
+        let state = ctx.data::<AppState>()?;
+        let range = config::parse_duration(&range)?.as_secs();
+        let resolution = history::resolution(range);
+        let since = events::now().saturating_sub(range);
+
+        let db = state.db.clone();
+        let name = service.clone();
+        let points = calls::blocking(move || db.history(&name, resolution, since))
+            .await
+            .map_err(|e| format!("{:#}", e))?;
+        Ok(History {
+            service,
+            resolution,
+            points: points.into_iter().map(Point).collect(),
+        })
+    }
+
+    /// Known hosts
+    async fn hosts(&self, ctx: &Context<'_>) -> Result<Vec<Host>> {
+        Ok(ctx
+            .data::<AppState>()?
+            .hosts()
+            .into_iter()
+            .map(Host)
+            .collect())
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Emit the full service list every `interval_secs` seconds
+    async fn services(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 5)] interval_secs: u64,
+    ) -> Result<impl Stream<Item = Vec<Service>>> {
+        let state = ctx.data::<AppState>()?.clone();
+        let interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+
+        Ok(futures::stream::unfold(
+            (state, interval),
+            |(state, mut interval)| async move {
+                interval.tick().await;
+                let services = state
+                    .services()
+                    .await
+                    .services
+                    .iter()
+                    .cloned()
+                    .map(Service)
+                    .collect();
+                Some((services, (state, interval)))
+            },
+        ))
+    }
+    /// Emit events as they are published, optionally for one service or of
+    /// one kind
+    ///
+    /// Subscribers that fall behind skip the events they missed.
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        service: Option<String>,
+        kind: Option<String>,
+    ) -> Result<impl Stream<Item = EventItem>> {
+        let receiver = ctx.data::<AppState>()?.events.subscribe();
+
+        Ok(futures::stream::unfold(
+            (receiver, service, kind),
+            |(mut receiver, service, kind)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) if event_matches(&event, service.as_deref(), kind.as_deref()) => {
+                            return Some((EventItem(event), (receiver, service, kind)))
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_match_optional_filters() {
+        let event = Event {
+            id: 1,
+            timestamp: 0,
+            service: "gmail".to_string(),
+            kind: events::STOPPED.to_string(),
+            message: String::new(),
+        };
+        assert!(event_matches(&event, None, None));
+        assert!(event_matches(&event, Some("gmail"), Some(events::STOPPED)));
+        assert!(!event_matches(&event, Some("slack"), None));
+        assert!(!event_matches(&event, None, Some(events::STARTED)));
+    }
+}
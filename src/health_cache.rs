@@ -0,0 +1,110 @@
+//! Short-lived cache of daemon health responses.
+//!
+//! Browser tabs and API clients polling at the same time share one probe per
+//! service per TTL (`--health-cache-ttl-ms`, 2s by default, 0 disables)
+//! instead of each hitting the daemon's socket. `?fresh=true` on
+//! `/api/services` and `/api/health/{service}` bypasses the cache; the age
+//! of the data served is returned in `X-Cache-Age-Ms`.
+//!
+//! The round trip of each socket's last successful probe is kept too, for
+//! latency charts, along with the timing breakdown of the last probe that
+//! got an answer.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::api::{self, HealthOutcome};
+use crate::state::Settings;
+use crate::timing::CallTiming;
+
+/// Header carrying the age of cached health data, in milliseconds
+pub const CACHE_AGE_HEADER: &str = "x-cache-age-ms";
+
+/// TTL used when none is configured
+pub const DEFAULT_TTL_MS: u64 = 2000;
+
+/// How a health probe may be answered
+#[derive(Clone, Copy, Default)]
+pub struct ProbeOptions {
+    /// Skip the cache and probe the daemon now
+    pub fresh: bool,
+    /// Overrides the configured health timeout
+    pub timeout: Option<Duration>,
+}
+
+/// Recent health outcomes by socket path
+#[derive(Default)]
+pub struct HealthCache {
+    entries: Mutex<HashMap<PathBuf, (Instant, HealthOutcome)>>,
+    /// Round trip of the last successful probe by socket path
+    latencies: Mutex<HashMap<PathBuf, Duration>>,
+    /// Breakdown of the last answered probe by socket path
+    timings: Mutex<HashMap<PathBuf, CallTiming>>,
+}
+
+impl HealthCache {
+    /// Probe a socket unless a result younger than the configured TTL is
+    /// cached
+    ///
+    /// Returns the outcome and its age.
+    pub async fn probe(
+        &self,
+        service: &str,
+        socket_path: &Path,
+        settings: &Settings,
+        options: ProbeOptions,
+    ) -> (HealthOutcome, Duration) {
+        let ttl = settings.health_cache_ttl;
+        if !options.fresh && !ttl.is_zero() {
+            if let Some((at, outcome)) = self.entries.lock().unwrap().get(socket_path) {
+                let age = at.elapsed();
+                if age < ttl {
+                    return (outcome.clone(), age);
+                }
+            }
+        }
+
+        let started = Instant::now();
+        let (outcome, timing) = api::check_health(
+            socket_path.to_path_buf(),
+            settings.socket_retries,
+            options
+                .timeout
+                .unwrap_or_else(|| settings.health_timeout(service)),
+            options.timeout.is_none(),
+        )
+        .await;
+        if let Some(timing) = timing {
+            self.timings
+                .lock()
+                .unwrap()
+                .insert(socket_path.to_path_buf(), timing);
+        }
+        if matches!(outcome, HealthOutcome::Healthy(_)) {
+            self.latencies
+                .lock()
+                .unwrap()
+                .insert(socket_path.to_path_buf(), started.elapsed());
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (at, _)| at.elapsed() < ttl);
+        if !ttl.is_zero() {
+            entries.insert(socket_path.to_path_buf(), (Instant::now(), outcome.clone()));
+        }
+
+        (outcome, Duration::ZERO)
+    }
+
+    /// Round trip of the last successful probe of a socket
+    pub fn latency(&self, socket_path: &Path) -> Option<Duration> {
+        self.latencies.lock().unwrap().get(socket_path).copied()
+    }
+
+    /// Timing breakdown of the last answered probe of a socket
+    pub fn timing(&self, socket_path: &Path) -> Option<CallTiming> {
+        self.timings.lock().unwrap().get(socket_path).copied()
+    }
+}
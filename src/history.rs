@@ -0,0 +1,344 @@
+//! Recorded service history.
+//!
+//! `GET /api/services/{service}/history?range=24h` returns a service's
+//! health over time, from raw samples for ranges up to an hour, 5-minute
+//! rollups up to a week and hourly rollups beyond that.
+//!
+//! `GET /api/sparklines?range=1h` returns every service's recent history in
+//! [`SPARKLINE_POINTS`] buckets, small enough to draw a chart on each card
+//! from one request. It reads raw samples, so `range` is capped at a day.
+//!
+//! `GET /api/diff?from=<time>&to=<time>` compares the last scan recorded at
+//! or before each time: services added and removed, version changes, and
+//! status changes together with how often the status flipped in between.
+//! Times are Unix timestamps in seconds or durations ago (`2h`); `to`
+//! defaults to now. Only raw samples carry versions and statuses, so the
+//! range has to fall within the raw retention (48 hours by default).
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::config;
+use crate::db::Database;
+use crate::events;
+use crate::namespaces::{Scope, View};
+use crate::state::AppState;
+
+/// One service as recorded by a scan
+#[derive(Clone)]
+pub struct Sample {
+    pub status: String,
+    pub version: Option<String>,
+}
+
+/// The scan a diff endpoint was matched to
+#[derive(Serialize)]
+pub struct Point {
+    /// Time asked for
+    pub requested: u64,
+    /// Time of the scan used
+    pub recorded_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct Change {
+    pub service: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct StatusChange {
+    pub service: String,
+    pub from: String,
+    pub to: String,
+    /// Status changes recorded between the two scans
+    pub flips: u64,
+}
+
+#[derive(Serialize)]
+pub struct HistoryDiff {
+    pub from: Point,
+    pub to: Point,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub version_changed: Vec<Change>,
+    /// Services whose status differs, or flipped and came back
+    pub status_changed: Vec<StatusChange>,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub from: String,
+    pub to: Option<String>,
+}
+
+/// Longest range served from raw samples
+const RAW_RANGE: u64 = 60 * 60;
+
+/// Longest range served from 5-minute rollups
+const FIVE_MINUTE_RANGE: u64 = 7 * 24 * 60 * 60;
+
+/// Range used when none is given
+pub const DEFAULT_RANGE: &str = "24h";
+
+/// Points per service returned by the sparklines endpoint
+pub const SPARKLINE_POINTS: u64 = 30;
+
+/// Longest range the sparklines endpoint serves
+const MAX_SPARKLINE_RANGE: u64 = 24 * 60 * 60;
+
+/// Sparkline range used when none is given
+const DEFAULT_SPARKLINE_RANGE: &str = "1h";
+
+/// Health over one interval
+#[derive(Serialize, TS)]
+pub struct HistoryPoint {
+    /// Start of the interval, as a Unix timestamp
+    #[ts(type = "number")]
+    pub timestamp: u64,
+    /// Scans recorded in the interval
+    #[ts(type = "number")]
+    pub samples: u64,
+    /// Scans that found the service healthy
+    #[ts(type = "number")]
+    pub healthy: u64,
+    #[ts(type = "number | null")]
+    pub uptime_seconds: Option<u64>,
+    /// Average health probe round trip
+    pub latency_ms: Option<f64>,
+    /// Average CPU usage reported by the daemon
+    pub cpu_percent: Option<f64>,
+    /// Peak memory usage reported by the daemon
+    #[ts(type = "number | null")]
+    pub memory_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ServiceHistory {
+    pub service: String,
+    /// Seconds covered by each point
+    pub resolution: u64,
+    pub points: Vec<HistoryPoint>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// How far back to go, e.g. `6h` or `30d`
+    pub range: Option<String>,
+}
+
+/// Seconds covered by each point of a history spanning `range` seconds, 0
+/// for raw samples
+pub fn resolution(range: u64) -> u64 {
+    if range <= RAW_RANGE {
+        0
+    } else if range <= FIVE_MINUTE_RANGE {
+        5 * 60
+    } else {
+        60 * 60
+    }
+}
+
+/// A service's health over time
+pub async fn service_history(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let range = match config::parse_duration(query.range.as_deref().unwrap_or(DEFAULT_RANGE)) {
+        Ok(range) => range.as_secs(),
+        Err(e) => return (StatusCode::BAD_REQUEST, ApiResponse::<()>::error(&e)).into_response(),
+    };
+    let resolution = resolution(range);
+    let since = events::now().saturating_sub(range);
+
+    let db = state.db.clone();
+    let name = service.clone();
+    match calls::blocking(move || db.history(&name, resolution, since)).await {
+        Ok(points) => (
+            StatusCode::OK,
+            ApiResponse::success(ServiceHistory {
+                service,
+                resolution,
+                points,
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<()>::error(&format!("{:#}", e)),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct Sparklines {
+    /// Seconds covered by each point
+    pub resolution: u64,
+    pub services: BTreeMap<String, Vec<HistoryPoint>>,
+}
+
+/// Recent history of every service, for inline charts
+pub async fn sparklines(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    scope: Option<Extension<Scope>>,
+) -> Response {
+    let range = query.range.as_deref().unwrap_or(DEFAULT_SPARKLINE_RANGE);
+    let range = match config::parse_duration(range) {
+        Ok(range) if range.as_secs() <= MAX_SPARKLINE_RANGE => range.as_secs().max(1),
+        Ok(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<()>::error("range is limited to 24h"),
+            )
+                .into_response()
+        }
+        Err(e) => return (StatusCode::BAD_REQUEST, ApiResponse::<()>::error(&e)).into_response(),
+    };
+    let resolution = range.div_ceil(SPARKLINE_POINTS);
+    let since = events::now().saturating_sub(range);
+
+    let db = state.db.clone();
+    let view = View::of(&state.settings(), scope);
+    match calls::blocking(move || db.sparklines(since, resolution)).await {
+        Ok(mut services) => {
+            services.retain(|service, _| view.shows(service));
+            (
+                StatusCode::OK,
+                ApiResponse::success(Sparklines {
+                    resolution,
+                    services,
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<()>::error(&format!("{:#}", e)),
+        )
+            .into_response(),
+    }
+}
+
+/// Parse a Unix timestamp or a duration ago
+pub fn parse_time(s: &str, now: u64) -> Result<u64, String> {
+    if let Ok(timestamp) = s.trim().parse::<u64>() {
+        return Ok(timestamp);
+    }
+    config::parse_duration(s)
+        .map(|ago| now.saturating_sub(ago.as_secs()))
+        .map_err(|_| format!("invalid time '{}', expected a Unix timestamp or e.g. 2h", s))
+}
+
+fn diff(db: &Database, from: u64, to: u64) -> Result<Option<HistoryDiff>> {
+    let (Some((from_at, before)), Some((to_at, after))) = (db.scan_at(from)?, db.scan_at(to)?)
+    else {
+        return Ok(None);
+    };
+    let flips = db.status_flips(from_at, to_at)?;
+
+    let added = after
+        .keys()
+        .filter(|name| !before.contains_key(*name))
+        .cloned()
+        .collect();
+    let removed = before
+        .keys()
+        .filter(|name| !after.contains_key(*name))
+        .cloned()
+        .collect();
+
+    let mut version_changed = Vec::new();
+    let mut status_changed = Vec::new();
+    for (name, old) in &before {
+        let Some(new) = after.get(name) else {
+            continue;
+        };
+        if old.version != new.version {
+            version_changed.push(Change {
+                service: name.clone(),
+                from: old.version.clone(),
+                to: new.version.clone(),
+            });
+        }
+        let flips = flips.get(name).copied().unwrap_or(0);
+        if old.status != new.status || flips > 0 {
+            status_changed.push(StatusChange {
+                service: name.clone(),
+                from: old.status.clone(),
+                to: new.status.clone(),
+                flips,
+            });
+        }
+    }
+
+    Ok(Some(HistoryDiff {
+        from: Point {
+            requested: from,
+            recorded_at: from_at,
+        },
+        to: Point {
+            requested: to,
+            recorded_at: to_at,
+        },
+        added,
+        removed,
+        version_changed,
+        status_changed,
+    }))
+}
+
+/// What changed between two points in the recorded history
+pub async fn history_diff(
+    State(state): State<AppState>,
+    Query(query): Query<DiffQuery>,
+) -> Response {
+    let now = events::now();
+    let times = parse_time(&query.from, now).and_then(|from| {
+        let to = match &query.to {
+            Some(to) => parse_time(to, now)?,
+            None => now,
+        };
+        if from > to {
+            return Err("'from' is later than 'to'".to_string());
+        }
+        Ok((from, to))
+    });
+    let (from, to) = match times {
+        Ok(times) => times,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, ApiResponse::<()>::error(&e)).into_response();
+        }
+    };
+
+    let db = state.db.clone();
+    match calls::blocking(move || diff(&db, from, to)).await {
+        Ok(Some(diff)) => (StatusCode::OK, ApiResponse::success(diff)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<()>::error(&format!(
+                "No scan recorded at or before {}; raw history may have expired",
+                from
+            )),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<()>::error(&format!("{:#}", e)),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,130 @@
+//! Host identity and labels.
+//!
+//! Every dashboard instance describes the host it runs on with a name and a
+//! set of free-form labels (region, environment, rack, ...). Labels are
+//! attached to the services reported by the host and can be used to filter
+//! API queries, e.g. `?label=env=prod,region=eu`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::state::AppState;
+
+/// Host name and labels
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct HostInfo {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl HostInfo {
+    /// Describe the local host, falling back to the system hostname
+    pub fn local(name: Option<String>, labels: Vec<(String, String)>) -> Self {
+        let name =
+            name.unwrap_or_else(|| gethostname::gethostname().to_string_lossy().into_owned());
+
+        Self {
+            name,
+            labels: labels.into_iter().collect(),
+        }
+    }
+}
+
+/// Parse a `KEY=VALUE` label from the command line
+pub fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("invalid label '{}', expected KEY=VALUE", s)),
+    }
+}
+
+/// A set of `key=value` requirements that must all match
+#[derive(Default)]
+pub struct LabelSelector {
+    requirements: Vec<(String, String)>,
+}
+
+impl LabelSelector {
+    /// Parse a comma-separated selector such as `env=prod,region=eu`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let requirements = s
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(parse_label)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { requirements })
+    }
+
+    /// Parse an optional selector, treating a missing one as "match everything"
+    pub fn from_query(s: Option<&str>) -> Result<Self, String> {
+        s.map(Self::parse)
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+/// Query parameters accepted by label-aware endpoints
+#[derive(Deserialize, Default)]
+pub struct LabelQuery {
+    pub label: Option<String>,
+}
+
+/// List known hosts and their labels
+pub async fn list_hosts(
+    State(state): State<AppState>,
+    Query(query): Query<LabelQuery>,
+) -> impl IntoResponse {
+    let selector = match LabelSelector::from_query(query.label.as_deref()) {
+        Ok(selector) => selector,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                ApiResponse::<Vec<HostInfo>>::error(&e),
+            )
+        }
+    };
+
+    let hosts: Vec<HostInfo> = state
+        .hosts()
+        .into_iter()
+        .filter(|host| selector.matches(&host.labels))
+        .collect();
+
+    (StatusCode::OK, ApiResponse::success(hosts))
+}
+
+/// Group host names by the value of a label
+pub async fn group_hosts(
+    State(state): State<AppState>,
+    Path(label): Path<String>,
+) -> impl IntoResponse {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for host in state.hosts() {
+        let value = host
+            .labels
+            .get(&label)
+            .cloned()
+            .unwrap_or_else(|| "(none)".to_string());
+        groups.entry(value).or_default().push(host.name);
+    }
+
+    ApiResponse::success(groups)
+}
@@ -0,0 +1,135 @@
+//! Translations for the web UI.
+//!
+//! Catalogs live in `assets/i18n/<locale>.json` as objects mapping message
+//! keys to strings, which may contain `{name}` placeholders filled in by the
+//! UI. `GET /api/i18n` picks a locale from `?lang=`, then `Accept-Language`,
+//! falling back to English, and returns its messages layered over the
+//! English ones so untranslated keys still read sensibly. An
+//! `--assets-dir` can replace catalogs or add locales.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::api::ApiResponse;
+use crate::assets;
+use crate::state::AppState;
+
+/// Locale used when nothing better matches, and for missing messages
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Directory of the catalogs among the assets
+const CATALOG_DIR: &str = "i18n";
+
+#[derive(Deserialize)]
+pub struct I18nQuery {
+    /// Locale to use regardless of `Accept-Language`
+    pub lang: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Catalog {
+    pub locale: String,
+    /// Every locale with a catalog
+    pub available: Vec<String>,
+    pub messages: Map<String, Value>,
+}
+
+/// Find the available locale matching a language tag, exactly or by its
+/// primary language (`de-AT` matches `de`)
+fn matching<'a>(tag: &str, available: &'a [String]) -> Option<&'a String> {
+    let tag = tag.trim().to_ascii_lowercase();
+    let primary = tag.split(['-', '_']).next().unwrap_or_default();
+    available
+        .iter()
+        .find(|locale| locale.to_ascii_lowercase() == tag)
+        .or_else(|| available.iter().find(|locale| locale.as_str() == primary))
+}
+
+/// Languages in an `Accept-Language` header, most preferred first
+fn accepted(header: &str) -> Vec<&str> {
+    let mut languages: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let tag = pieces.next()?.trim();
+            let quality = pieces
+                .find_map(|piece| piece.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equal weights keep header order
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Choose a locale for a request
+pub fn negotiate(
+    lang: Option<&str>,
+    accept_language: Option<&str>,
+    available: &[String],
+) -> String {
+    lang.into_iter()
+        .chain(accept_language.map(accepted).unwrap_or_default())
+        .find_map(|tag| matching(tag, available))
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+async fn messages(dir: Option<&Path>, locale: &str) -> Result<Map<String, Value>, String> {
+    let path = format!("{}/{}.json", CATALOG_DIR, locale);
+    let Some((body, _)) = assets::load(dir, &path).await else {
+        return Ok(Map::new());
+    };
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid catalog {}: {}", path, e))
+}
+
+/// Messages for the negotiated locale
+pub async fn catalog(
+    State(state): State<AppState>,
+    Query(query): Query<I18nQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let dir = state.settings().assets_dir.clone();
+    let available: Vec<String> = assets::list(dir.as_deref(), CATALOG_DIR)
+        .await
+        .into_iter()
+        .filter_map(|name| name.strip_suffix(".json").map(str::to_string))
+        .collect();
+    let accept_language = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok());
+    let locale = negotiate(query.lang.as_deref(), accept_language, &available);
+
+    let layered = async {
+        let mut merged = messages(dir.as_deref(), DEFAULT_LOCALE).await?;
+        if locale != DEFAULT_LOCALE {
+            merged.extend(messages(dir.as_deref(), &locale).await?);
+        }
+        Ok::<_, String>(merged)
+    };
+    match layered.await {
+        Ok(messages) => (
+            StatusCode::OK,
+            [(header::VARY, "Accept-Language")],
+            ApiResponse::success(Catalog {
+                locale,
+                available,
+                messages,
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<()>::error(&e),
+        )
+            .into_response(),
+    }
+}
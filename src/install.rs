@@ -0,0 +1,211 @@
+//! `fgp-dashboard install-service`: run the dashboard under the user's
+//! service manager.
+//!
+//! Writes a systemd user unit on Linux or a launchd agent on macOS. The
+//! flags given before `install-service` are baked into the unit, minus
+//! `--daemon` and `--open`, which make no sense under a service manager.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the subcommand, used to split the command line
+const SUBCOMMAND: &str = "install-service";
+
+/// Service name used for both systemd and launchd
+const SERVICE_NAME: &str = "fgp-dashboard";
+
+/// launchd job label
+const LAUNCHD_LABEL: &str = "com.fgp.dashboard";
+
+/// Flags dropped from the baked-in command line
+const SKIPPED_FLAGS: &[&str] = &["--daemon", "--open", "-o"];
+
+/// Arguments to run the dashboard with, taken from the current command line
+fn baked_args(config: Option<&Path>) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for arg in std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg != SUBCOMMAND)
+    {
+        let arg = arg
+            .into_string()
+            .map_err(|arg: OsString| anyhow::anyhow!("Argument is not UTF-8: {:?}", arg))?;
+        if !SKIPPED_FLAGS.contains(&arg.as_str()) {
+            args.push(arg);
+        }
+    }
+
+    // `--config` is global and may have been given after the subcommand
+    if let Some(config) = config {
+        if !args.iter().any(|arg| arg.starts_with("--config")) {
+            let config = std::env::current_dir()?.join(config);
+            args.push("--config".to_string());
+            args.push(config.display().to_string());
+        }
+    }
+
+    Ok(args)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("`{} {}` failed with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .context("HOME is not set")
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Quote an argument for a systemd `ExecStart=` line
+fn systemd_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\$%;".contains(c)) {
+        return arg.to_string();
+    }
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+        .replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+fn systemd_unit(exe: &Path, args: &[String]) -> String {
+    let mut exec = systemd_quote(&exe.display().to_string());
+    for arg in args {
+        exec.push(' ');
+        exec.push_str(&systemd_quote(arg));
+    }
+
+    format!(
+        "[Unit]\n\
+         Description=FGP Dashboard\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exec
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn launchd_plist(exe: &Path, args: &[String], log: &Path) -> String {
+    let mut program_args = format!(
+        "        <string>{}</string>\n",
+        xml_escape(&exe.display().to_string())
+    );
+    for arg in args {
+        program_args.push_str(&format!("        <string>{}</string>\n", xml_escape(arg)));
+    }
+    let log = xml_escape(&log.display().to_string());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{program_args}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        label = LAUNCHD_LABEL,
+        program_args = program_args,
+        log = log,
+    )
+}
+
+fn install_systemd(exe: &Path, args: &[String], start: bool) -> Result<()> {
+    let unit = format!("{}.service", SERVICE_NAME);
+    let path = home()?.join(".config/systemd/user").join(&unit);
+    write(&path, &systemd_unit(exe, args))?;
+
+    run("systemctl", &["--user", "daemon-reload"])?;
+    run("systemctl", &["--user", "enable", &unit])?;
+    if start {
+        run("systemctl", &["--user", "restart", &unit])?;
+        println!("Started {}", unit);
+    } else {
+        println!(
+            "Enabled {}; start it with `systemctl --user start {}`",
+            unit, unit
+        );
+    }
+    Ok(())
+}
+
+fn install_launchd(exe: &Path, args: &[String], data_dir: &Path, start: bool) -> Result<()> {
+    let path = home()?
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", LAUNCHD_LABEL));
+    let log = data_dir.join(crate::daemon::LOG_FILE);
+    write(&path, &launchd_plist(exe, args, &log))?;
+
+    let path = path.display().to_string();
+    if start {
+        // Reload so an existing agent picks up the new flags
+        let _ = run("launchctl", &["unload", &path]);
+        run("launchctl", &["load", "-w", &path])?;
+        println!("Loaded {}", LAUNCHD_LABEL);
+    } else {
+        println!(
+            "{} will start at next login; start it now with `launchctl load -w {}`",
+            LAUNCHD_LABEL, path
+        );
+    }
+    Ok(())
+}
+
+/// Install the dashboard as a user service, optionally starting it
+pub fn install_service(config_file: Option<&Path>, data_dir: &Path, start: bool) -> Result<()> {
+    let exe = std::env::current_exe().context("Cannot locate the dashboard executable")?;
+    let args = baked_args(config_file)?;
+
+    if cfg!(target_os = "macos") {
+        install_launchd(&exe, &args, data_dir, start)
+    } else if cfg!(target_os = "linux") {
+        install_systemd(&exe, &args, start)
+    } else {
+        bail!("install-service supports systemd (Linux) and launchd (macOS) only")
+    }
+}
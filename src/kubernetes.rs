@@ -0,0 +1,474 @@
+//! FGP daemons running in Kubernetes pods.
+//!
+//! With `--kubernetes <selector>`, every scan lists the pods matching the
+//! label selector that also carry an `fgp.service=<name>` label, in
+//! `--kubernetes-namespace` or the current context's namespace, and shows
+//! each as a service of that name with `kubernetes` as its source. Pods
+//! sharing a service label are shown under their pod names instead. The
+//! daemon is reached:
+//!
+//! - through a shared volume, at the path the `fgp.socket-path` annotation
+//!   gives as the dashboard sees it
+//! - through `kubectl port-forward` to the port in the `fgp.port`
+//!   annotation, for daemons listening on TCP; one forward is kept open per
+//!   pod while it is seen
+//! - otherwise through `kubectl exec -i <pod> -- socat - UNIX-CONNECT:<socket>`
+//!   in the `fgp.container` container (the first by default), where the
+//!   socket is `/run/fgp/daemon.sock` unless the `fgp.socket` annotation says
+//!   otherwise; such services have a socket path of
+//!   `k8s://<namespace>/<pod>/<container><socket>`
+//!
+//! Pods can't be started or stopped in place, so restarting a service
+//! deletes its pod for its controller to replace, and stopping deletes a
+//! pod no controller owns. The `kubectl` CLI is used throughout, so
+//! kubeconfig contexts and in-cluster service accounts apply as usual.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use crate::docker::Lifecycle;
+use crate::systemd::Supervision;
+
+/// Source shown for services found in pods
+pub const SOURCE: &str = "kubernetes";
+
+/// `manager` of pods
+pub const MANAGER: &str = "kubernetes";
+
+/// Label naming the service a pod runs
+pub const SERVICE_LABEL: &str = "fgp.service";
+
+/// Annotation giving the daemon's socket path inside the pod
+const SOCKET_ANNOTATION: &str = "fgp.socket";
+
+/// Annotation giving the socket's path on a volume the dashboard shares
+const SOCKET_PATH_ANNOTATION: &str = "fgp.socket-path";
+
+/// Annotation giving the TCP port the daemon listens on
+const PORT_ANNOTATION: &str = "fgp.port";
+
+/// Annotation naming the container the daemon runs in
+const CONTAINER_ANNOTATION: &str = "fgp.container";
+
+/// Socket path inside the pod when no annotation gives one
+pub const DEFAULT_SOCKET: &str = "/run/fgp/daemon.sock";
+
+/// Scheme of socket paths proxied through `kubectl exec`
+pub const SCHEME: &str = "k8s://";
+
+/// The `kubectl` executable
+const KUBECTL: &str = "kubectl";
+
+/// A pod running an FGP daemon
+pub struct Pod {
+    /// Service name: the `fgp.service` label, or the pod name when several
+    /// pods share it
+    pub service: String,
+    pub namespace: String,
+    pub name: String,
+    /// Running, and with a way in
+    pub reachable: bool,
+    /// How the dashboard reaches the daemon
+    pub socket_path: PathBuf,
+    /// What Kubernetes reports about the pod
+    pub supervision: Supervision,
+    /// `<kind>/<name>` of the controller owning the pod, if any
+    owner: Option<String>,
+}
+
+/// Where to look for pods
+pub struct Scope<'a> {
+    pub selector: &'a str,
+    pub namespace: Option<&'a str>,
+}
+
+/// The parts of `kubectl get pods -o json` output used here
+#[derive(Deserialize)]
+struct PodList {
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    metadata: Metadata,
+    spec: Spec,
+    #[serde(default)]
+    status: Status,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    name: String,
+    namespace: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    #[serde(default)]
+    owner_references: Vec<OwnerReference>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OwnerReference {
+    kind: String,
+    name: String,
+    #[serde(default)]
+    controller: bool,
+}
+
+#[derive(Deserialize)]
+struct Spec {
+    containers: Vec<Container>,
+}
+
+#[derive(Deserialize)]
+struct Container {
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    #[serde(default)]
+    phase: String,
+    #[serde(default)]
+    container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerStatus {
+    name: String,
+    restart_count: u64,
+}
+
+/// Run a `kubectl` subcommand, returning its standard output
+fn kubectl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new(KUBECTL)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", KUBECTL, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            KUBECTL,
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// A `kubectl port-forward` kept open to one pod
+struct Forward {
+    child: Child,
+    remote_port: u16,
+    local_port: u16,
+}
+
+impl Drop for Forward {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Open forwards, by `<namespace>/<pod>`
+static FORWARDS: Mutex<Option<HashMap<String, Forward>>> = Mutex::new(None);
+
+/// Start forwarding a local port to `remote_port` of a pod
+fn start_forward(namespace: &str, pod: &str, remote_port: u16) -> Result<Forward, String> {
+    let mut child = Command::new(KUBECTL)
+        .args([
+            "port-forward",
+            "--namespace",
+            namespace,
+            "--address",
+            "127.0.0.1",
+            &format!("pod/{}", pod),
+            &format!(":{}", remote_port),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {} port-forward: {}", KUBECTL, e))?;
+    let Some(stdout) = child.stdout.take() else {
+        let _ = child.kill();
+        return Err("kubectl port-forward has no stdout".to_string());
+    };
+
+    // `Forwarding from 127.0.0.1:40123 -> 7000`
+    let mut stdout = BufReader::new(stdout);
+    let mut line = String::new();
+    let local_port = match stdout.read_line(&mut line) {
+        Ok(read) if read > 0 => line
+            .split("->")
+            .next()
+            .and_then(|from| from.trim().rsplit(':').next())
+            .and_then(|port| port.trim().parse().ok()),
+        _ => None,
+    };
+    let Some(local_port) = local_port else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(format!("kubectl port-forward to pod/{} failed", pod));
+    };
+
+    // It logs every connection, so keep reading or it blocks on a full pipe
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut stdout.into_inner(), &mut std::io::sink());
+    });
+
+    Ok(Forward {
+        child,
+        remote_port,
+        local_port,
+    })
+}
+
+/// Local port forwarded to a pod's `remote_port`, opening a forward if
+/// there is none yet or the last one died
+fn forwarded_port(
+    forwards: &mut HashMap<String, Forward>,
+    namespace: &str,
+    pod: &str,
+    remote_port: u16,
+) -> Result<u16, String> {
+    let key = format!("{}/{}", namespace, pod);
+    if let Some(forward) = forwards.get_mut(&key) {
+        let alive = matches!(forward.child.try_wait(), Ok(None));
+        if alive && forward.remote_port == remote_port {
+            return Ok(forward.local_port);
+        }
+        forwards.remove(&key);
+    }
+    let forward = start_forward(namespace, pod, remote_port)?;
+    let port = forward.local_port;
+    forwards.insert(key, forward);
+    Ok(port)
+}
+
+/// How the dashboard reaches the daemon in a pod, and whether it can now
+fn socket_path(
+    item: &Item,
+    running: bool,
+    forwards: &mut HashMap<String, Forward>,
+) -> (PathBuf, bool) {
+    let metadata = &item.metadata;
+    let annotations = &metadata.annotations;
+    if let Some(path) = annotations.get(SOCKET_PATH_ANNOTATION) {
+        return (PathBuf::from(path), running);
+    }
+    if let Some(port) = annotations.get(PORT_ANNOTATION) {
+        let forwarded = match port.trim().parse() {
+            Ok(port) if running => {
+                forwarded_port(forwards, &metadata.namespace, &metadata.name, port)
+            }
+            Ok(_) => Err("the pod isn't running".to_string()),
+            Err(_) => Err(format!("invalid {} annotation '{}'", PORT_ANNOTATION, port)),
+        };
+        return match forwarded {
+            Ok(local) => (PathBuf::from(format!("tcp://127.0.0.1:{}", local)), true),
+            Err(e) => {
+                tracing::debug!("Cannot forward to pod {}: {}", metadata.name, e);
+                let pod = format!(
+                    "{}{}/{}:{}",
+                    SCHEME, metadata.namespace, metadata.name, port
+                );
+                (PathBuf::from(pod), false)
+            }
+        };
+    }
+    let container = annotations
+        .get(CONTAINER_ANNOTATION)
+        .cloned()
+        .or_else(|| item.spec.containers.first().map(|c| c.name.clone()))
+        .unwrap_or_default();
+    let socket = annotations
+        .get(SOCKET_ANNOTATION)
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_SOCKET);
+    let path = format!(
+        "{}{}/{}/{}{}",
+        SCHEME, metadata.namespace, metadata.name, container, socket
+    );
+    (PathBuf::from(path), running)
+}
+
+/// What Kubernetes reports about a pod, in the terms used for units
+fn supervision(item: &Item) -> Supervision {
+    let phase = item.status.phase.as_str();
+    let restarts: u64 = item
+        .status
+        .container_statuses
+        .iter()
+        .map(|status| status.restart_count)
+        .sum();
+    let containers = item
+        .status
+        .container_statuses
+        .iter()
+        .map(|status| status.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Supervision {
+        manager: MANAGER.to_string(),
+        unit: format!("{}/{}", item.metadata.namespace, item.metadata.name),
+        user: false,
+        state: match phase {
+            "Running" => "active",
+            "Failed" => "failed",
+            _ => "inactive",
+        }
+        .to_string(),
+        sub_state: Some(format!(
+            "{}, {} restart(s){}",
+            phase.to_lowercase(),
+            restarts,
+            if containers.is_empty() {
+                String::new()
+            } else {
+                format!(" of {}", containers)
+            }
+        )),
+        enabled: None,
+        pid: None,
+    }
+}
+
+fn list(scope: &Scope) -> Result<Vec<Item>, String> {
+    let selector = if scope.selector.trim().is_empty() {
+        SERVICE_LABEL.to_string()
+    } else {
+        format!("{},{}", scope.selector, SERVICE_LABEL)
+    };
+    let mut args = vec!["get", "pods", "--output", "json", "--selector", &selector];
+    if let Some(namespace) = scope.namespace {
+        args.extend(["--namespace", namespace]);
+    }
+    let list: PodList = serde_json::from_str(&kubectl(&args)?)
+        .map_err(|e| format!("Unexpected kubectl output: {}", e))?;
+    Ok(list.items)
+}
+
+/// Every pod matching the selector that is labelled with a service name
+///
+/// Port forwards are opened for new pods and closed for pods that are
+/// gone. Blocks, so call it off the async runtime.
+pub fn pods(scope: &Scope) -> Result<Vec<Pod>, String> {
+    let items = list(scope)?;
+    let service_of = |item: &Item| {
+        item.metadata
+            .labels
+            .get(SERVICE_LABEL)
+            .map(|service| service.trim().to_string())
+            .filter(|service| !service.is_empty())
+    };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for service in items.iter().filter_map(service_of) {
+        *counts.entry(service).or_default() += 1;
+    }
+
+    let mut forwards = FORWARDS.lock().unwrap();
+    let forwards = forwards.get_or_insert_with(HashMap::new);
+    let mut seen = HashSet::new();
+    let pods = items
+        .iter()
+        .filter_map(|item| {
+            let service = service_of(item)?;
+            let metadata = &item.metadata;
+            seen.insert(format!("{}/{}", metadata.namespace, metadata.name));
+            let (socket_path, reachable) =
+                socket_path(item, item.status.phase == "Running", forwards);
+            Some(Pod {
+                service: if counts[&service] > 1 {
+                    metadata.name.clone()
+                } else {
+                    service
+                },
+                namespace: metadata.namespace.clone(),
+                name: metadata.name.clone(),
+                reachable,
+                socket_path,
+                supervision: supervision(item),
+                owner: metadata
+                    .owner_references
+                    .iter()
+                    .find(|owner| owner.controller)
+                    .map(|owner| format!("{}/{}", owner.kind, owner.name)),
+            })
+        })
+        .collect();
+    forwards.retain(|key, _| seen.contains(key));
+    Ok(pods)
+}
+
+/// `kubectl exec` arguments that connect stdin and stdout to a pod's
+/// socket, for a `k8s://` socket path
+pub fn proxy_command(socket_path: &str) -> Option<Command> {
+    let mut parts = socket_path.strip_prefix(SCHEME)?.splitn(4, '/');
+    let (namespace, pod, container, socket) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let mut command = Command::new(KUBECTL);
+    command.args([
+        "exec",
+        "-i",
+        "--namespace",
+        namespace,
+        pod,
+        "--container",
+        container,
+        "--",
+        "socat",
+        "-",
+        &format!("UNIX-CONNECT:/{}", socket),
+    ]);
+    Some(command)
+}
+
+/// Restart or stop the pod running `service` by deleting it
+///
+/// Returns `None` if no pod runs the service. Blocks, so call it off the
+/// async runtime.
+pub fn control(scope: &Scope, service: &str, lifecycle: Lifecycle) -> Option<Result<(), String>> {
+    let pods = match pods(scope) {
+        Ok(pods) => pods,
+        Err(e) => return Some(Err(e)),
+    };
+    let pod = pods.into_iter().find(|pod| pod.service == service)?;
+    let target = format!("pod/{}", pod.name);
+    let delete = || {
+        kubectl(&[
+            "delete",
+            "--namespace",
+            &pod.namespace,
+            &target,
+            "--wait=false",
+        ])
+        .map(|_| ())
+    };
+    Some(match (lifecycle, &pod.owner) {
+        (Lifecycle::Start, _) => Err(format!(
+            "Pods are started by their controller; scale {} up with kubectl instead",
+            pod.owner.as_deref().unwrap_or(&target)
+        )),
+        (Lifecycle::Restart, Some(_)) | (Lifecycle::Stop, None) => delete(),
+        (Lifecycle::Restart, None) => Err(format!(
+            "No controller owns {}, so deleting it would not bring it back; stop it instead",
+            target
+        )),
+        (Lifecycle::Stop, Some(owner)) => Err(format!(
+            "{} would replace a deleted {}; scale it down with kubectl instead",
+            owner, target
+        )),
+    })
+}
@@ -0,0 +1,193 @@
+//! Services that launchd also supervises, on macOS.
+//!
+//! With `--launchd`, every scan looks up the launch agent of each service
+//! found in a services dir in the user's `gui/<uid>` domain: the manifest's
+//! `launchd_label`, or `com.fgp.<name>` when it names none. Agents that are
+//! loaded, or have a plist in `~/Library/LaunchAgents`, set the service's
+//! `supervisor` the same way systemd units do: `active` while the job has a
+//! process, `failed` after a non-zero exit, `inactive` otherwise.
+//!
+//! Starting such a service kickstarts its agent, bootstrapping the plist
+//! first if the agent isn't loaded; restarting kickstarts it with `-k`, and
+//! stopping boots it out so `KeepAlive` doesn't bring it back.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::api::{self, ServiceInfo};
+use crate::docker::Lifecycle;
+use crate::drift;
+use crate::systemd::Supervision;
+
+/// `manager` of agents supervised by launchd
+pub const MANAGER: &str = "launchd";
+
+/// Manifest field naming a service's agent
+const MANIFEST_FIELD: &str = "launchd_label";
+
+/// A loaded job, as `launchctl list` shows it
+struct Job {
+    pid: Option<u32>,
+    last_exit: i32,
+}
+
+/// The agent label expected to run a service
+fn agent_label(service: &ServiceInfo) -> String {
+    let dir = Path::new(&service.source).join(&service.name);
+    drift::manifest_field(&dir, MANIFEST_FIELD)
+        .filter(|label| !label.trim().is_empty())
+        .unwrap_or_else(|| format!("com.fgp.{}", service.name))
+}
+
+/// Run `launchctl`, returning its standard output
+fn launchctl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run launchctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "launchctl {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The user's launchd domain, `gui/<uid>`
+fn domain() -> Result<&'static str, String> {
+    static DOMAIN: OnceLock<String> = OnceLock::new();
+    if let Some(domain) = DOMAIN.get() {
+        return Ok(domain);
+    }
+    let output = Command::new("id")
+        .arg("-u")
+        .output()
+        .map_err(|e| format!("Failed to look up the user id: {}", e))?;
+    let uid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if uid.is_empty() {
+        return Err("Failed to look up the user id".to_string());
+    }
+    Ok(DOMAIN.get_or_init(|| format!("gui/{}", uid)))
+}
+
+/// Where the plist of the agent with `label` lives
+fn plist(label: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(
+        Path::new(&home)
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label)),
+    )
+}
+
+/// Loaded jobs, by label
+fn jobs() -> Result<HashMap<String, Job>, String> {
+    let output = launchctl(&["list"])?;
+    Ok(output
+        .lines()
+        // `PID Status Label` header
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            let pid = columns.next()?.trim().parse().ok();
+            let last_exit = columns.next()?.trim().parse().unwrap_or(0);
+            let label = columns.next()?.trim().to_string();
+            Some((label, Job { pid, last_exit }))
+        })
+        .collect())
+}
+
+/// Labels the user has disabled or enabled with `launchctl disable`/`enable`
+fn overrides(domain: &str) -> HashMap<String, bool> {
+    let Ok(output) = launchctl(&["print-disabled", domain]) else {
+        return HashMap::new();
+    };
+    output
+        .lines()
+        .filter_map(|line| {
+            let (label, value) = line.split_once("=>")?;
+            let disabled = matches!(value.trim(), "disabled" | "true");
+            Some((label.trim().trim_matches('"').to_string(), disabled))
+        })
+        .collect()
+}
+
+/// Set `supervisor` on services with a launch agent
+///
+/// Blocks, so call it off the async runtime.
+pub fn annotate(services: &mut [ServiceInfo]) {
+    let (jobs, domain) = match jobs().and_then(|jobs| domain().map(|domain| (jobs, domain))) {
+        Ok(found) => found,
+        Err(e) => {
+            tracing::debug!("Failed to query launchd: {}", e);
+            return;
+        }
+    };
+    let overrides = overrides(domain);
+
+    for service in services.iter_mut() {
+        if api::is_adapter_source(&service.source) {
+            continue;
+        }
+        let label = agent_label(service);
+        let disabled = overrides.get(&label).copied().unwrap_or(false);
+        let enabled = Some(if disabled { "disabled" } else { "enabled" }.to_string());
+        let (state, sub_state, pid) = match jobs.get(&label) {
+            Some(Job { pid: Some(pid), .. }) => ("active", "running".to_string(), Some(*pid)),
+            Some(Job { last_exit, .. }) if *last_exit != 0 => {
+                ("failed", format!("exited {}", last_exit), None)
+            }
+            Some(_) => ("inactive", "loaded".to_string(), None),
+            None if plist(&label).is_some_and(|path| path.is_file()) => {
+                ("inactive", "not loaded".to_string(), None)
+            }
+            None => continue,
+        };
+        service.supervisor = Some(Supervision {
+            manager: MANAGER.to_string(),
+            unit: label,
+            user: true,
+            state: state.to_string(),
+            sub_state: Some(sub_state),
+            enabled,
+            pid,
+        });
+    }
+}
+
+/// Kickstart or boot out a service's agent
+///
+/// Blocks, so call it off the async runtime.
+pub fn control(supervision: &Supervision, lifecycle: Lifecycle) -> Result<(), String> {
+    let domain = domain()?;
+    let target = format!("{}/{}", domain, supervision.unit);
+    match lifecycle {
+        Lifecycle::Start => {
+            if !jobs()?.contains_key(&supervision.unit) {
+                let path = plist(&supervision.unit)
+                    .filter(|path| path.is_file())
+                    .ok_or_else(|| format!("No plist found for {}", supervision.unit))?;
+                launchctl(&["bootstrap", domain, &path.to_string_lossy()])?;
+            }
+            launchctl(&["kickstart", &target])
+        }
+        Lifecycle::Restart => launchctl(&["kickstart", "-k", &target]),
+        Lifecycle::Stop => launchctl(&["bootout", &target]),
+    }
+    .map(|_| ())
+}
+
+/// Whether `launchctl` can be run
+pub fn available() -> Result<String, String> {
+    let domain = domain()?;
+    let jobs = jobs()?;
+    Ok(format!(
+        "launchd reachable, {} jobs loaded in {}",
+        jobs.len(),
+        domain
+    ))
+}
@@ -0,0 +1,379 @@
+//! Query options for the services list.
+//!
+//! Filtering, sorting and pagination run server-side so clients with large
+//! fleets don't have to download and sort the full list on every poll:
+//!
+//! ```text
+//! GET /api/services?status=running&name_contains=gw&sort=uptime&order=desc&page=2&per_page=50
+//! ```
+//!
+//! Pinned services always come first.
+//!
+//! List and detail endpoints also accept `?fields=name,status` to return only
+//! the requested top-level fields, which keeps payloads small for pollers.
+//! The list's ETag leaves out uptime, latency and resource usage, so pollers
+//! get a 304 until something else changes.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::api::ServiceInfo;
+use crate::filter::{Expr, FieldValue, Filterable};
+use crate::hosts::LabelSelector;
+
+/// Upper bound for `per_page`
+const MAX_PER_PAGE: usize = 1000;
+
+/// Query parameters accepted by `GET /api/services`
+#[derive(Deserialize, Default)]
+pub struct ServicesQuery {
+    /// Label selector, e.g. `env=prod,region=eu`
+    pub label: Option<String>,
+    /// Filter expression, e.g. `status = running AND uptime > 1h`
+    pub q: Option<String>,
+    /// Only services with exactly this status
+    pub status: Option<String>,
+    /// Only services whose name contains this substring
+    pub name_contains: Option<String>,
+    /// Sort key: `name` (default), `status`, `uptime`, `version` or `restarts`
+    pub sort: Option<String>,
+    /// Sort order: `asc` (default) or `desc`
+    pub order: Option<String>,
+    /// 1-based page number
+    pub page: Option<usize>,
+    /// Page size; the whole list is returned if neither this nor `page` is set
+    pub per_page: Option<usize>,
+    /// Comma-separated list of fields to return
+    pub fields: Option<String>,
+    /// Output format: `json` (default), `csv` or `ndjson`
+    pub format: Option<String>,
+    /// Skip the health cache and probe every daemon now
+    #[serde(default)]
+    pub fresh: bool,
+    /// Health probe timeout for a `fresh` scan, in milliseconds
+    pub timeout_ms: Option<u64>,
+}
+
+/// Query parameters accepted by detail endpoints
+#[derive(Deserialize, Default)]
+pub struct FieldsQuery {
+    /// Comma-separated list of fields to return
+    pub fields: Option<String>,
+    /// Skip the health cache and ask the daemon now
+    #[serde(default)]
+    pub fresh: bool,
+    /// Health probe timeout, in milliseconds
+    pub timeout_ms: Option<u64>,
+}
+
+impl Filterable for ServiceInfo {
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        let text = |s: &str| Some(FieldValue::Text(s.to_string()));
+
+        match name {
+            "name" => text(&self.name),
+            "status" => text(&self.status),
+            "version" => self.version.as_deref().and_then(text),
+            "uptime" | "uptime_seconds" => {
+                self.uptime_seconds.map(|u| FieldValue::Number(u as f64))
+            }
+            "probe_error" => self.probe_error.as_deref().and_then(text),
+            "host" => text(&self.host),
+            "socket_path" => text(&self.socket_path),
+            "source" => text(&self.source),
+            "restarts" => Some(FieldValue::Number(self.restarts as f64)),
+            "pinned" => text(if self.pinned { "true" } else { "false" }),
+            "config_drift" => text(if self.config_drift { "true" } else { "false" }),
+            "protocol_version" => self.protocol_version.as_deref().and_then(text),
+            "latest_version" => self.latest_version.as_deref().and_then(text),
+            "outdated" => text(if self.outdated { "true" } else { "false" }),
+            "unit" => self.supervisor.as_ref().and_then(|unit| text(&unit.unit)),
+            "unit_state" => self.supervisor.as_ref().and_then(|unit| text(&unit.state)),
+            "unit_enabled" => self
+                .supervisor
+                .as_ref()
+                .and_then(|unit| unit.enabled.as_deref())
+                .and_then(text),
+            _ => {
+                if let Some(capability) = name.strip_prefix("capability.") {
+                    let supported = self.capabilities.as_ref()?.supports(capability)?;
+                    return text(if supported { "true" } else { "false" });
+                }
+                let key = name
+                    .strip_prefix("label.")
+                    .or_else(|| name.strip_prefix("labels."))?;
+                self.labels.get(key).and_then(|value| text(value))
+            }
+        }
+    }
+}
+
+/// A page of services plus the number of services matching the filters
+pub struct Listing {
+    pub services: Vec<ServiceInfo>,
+    pub total: usize,
+}
+
+impl ServicesQuery {
+    /// Filter, sort and paginate a list of services
+    pub fn apply(&self, services: Vec<ServiceInfo>) -> Result<Listing, String> {
+        let selector = LabelSelector::from_query(self.label.as_deref())?;
+        let expr = Expr::from_query(self.q.as_deref())?;
+        let compare = sort_key(self.sort.as_deref())?;
+        let descending = match self.order.as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => return Err(format!("invalid order '{}', expected asc or desc", other)),
+        };
+
+        let mut services: Vec<ServiceInfo> = services
+            .into_iter()
+            .filter(|s| selector.matches(&s.labels))
+            .filter(|s| expr.as_ref().is_none_or(|expr| expr.matches(s)))
+            .filter(|s| {
+                self.status
+                    .as_ref()
+                    .is_none_or(|status| &s.status == status)
+            })
+            .filter(|s| {
+                self.name_contains
+                    .as_ref()
+                    .is_none_or(|needle| s.name.contains(needle.as_str()))
+            })
+            .collect();
+
+        // Pinned services stay on top in either order
+        services.sort_by(|a, b| {
+            let ordering = compare(a, b).then_with(|| a.name.cmp(&b.name));
+            let ordering = if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            b.pinned.cmp(&a.pinned).then(ordering)
+        });
+
+        let total = services.len();
+
+        if self.page.is_some() || self.per_page.is_some() {
+            let per_page = self.per_page.unwrap_or(50).clamp(1, MAX_PER_PAGE);
+            let page = self.page.unwrap_or(1).max(1);
+            services = services
+                .into_iter()
+                .skip((page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .collect();
+        }
+
+        Ok(Listing { services, total })
+    }
+}
+
+type Compare = fn(&ServiceInfo, &ServiceInfo) -> Ordering;
+
+fn sort_key(sort: Option<&str>) -> Result<Compare, String> {
+    match sort {
+        None | Some("name") => Ok(|a: &ServiceInfo, b: &ServiceInfo| a.name.cmp(&b.name)),
+        Some("status") => Ok(|a: &ServiceInfo, b: &ServiceInfo| a.status.cmp(&b.status)),
+        Some("uptime") => {
+            Ok(|a: &ServiceInfo, b: &ServiceInfo| a.uptime_seconds.cmp(&b.uptime_seconds))
+        }
+        Some("version") => Ok(|a: &ServiceInfo, b: &ServiceInfo| a.version.cmp(&b.version)),
+        Some("restarts") => Ok(|a: &ServiceInfo, b: &ServiceInfo| a.restarts.cmp(&b.restarts)),
+        Some(other) => Err(format!(
+            "invalid sort '{}', expected name, status, uptime, version or restarts",
+            other
+        )),
+    }
+}
+
+/// Serialize `data`, keeping only the requested top-level fields
+///
+/// Arrays are filtered element by element. Without a field list the value is
+/// returned unchanged.
+pub fn select_fields<T: Serialize>(data: T, fields: Option<&str>) -> serde_json::Value {
+    let value = serde_json::to_value(data).unwrap_or_default();
+
+    match fields {
+        Some(fields) => {
+            let fields: Vec<&str> = fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect();
+            retain_fields(value, &fields)
+        }
+        None => value,
+    }
+}
+
+/// Fields of a listed service that change on nearly every scan
+const VOLATILE_FIELDS: &[&str] = &[
+    "uptime_seconds",
+    "latency_ms",
+    "probe_timing",
+    "cpu_percent",
+    "memory_bytes",
+];
+
+/// A list without its volatile fields, to tag it by what changes rarely
+pub fn stable_fields(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(stable_fields).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .filter(|(key, _)| !VOLATILE_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn retain_fields(value: serde_json::Value, fields: &[&str]) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .into_iter()
+                .map(|item| retain_fields(item, fields))
+                .collect(),
+        ),
+        serde_json::Value::Object(mut map) => {
+            map.retain(|key, _| fields.contains(&key.as_str()));
+            serde_json::Value::Object(map)
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, status: &str, uptime: u64, env: &str) -> ServiceInfo {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "status": status,
+            "uptime_seconds": uptime,
+            "socket_path": format!("/tmp/{}/daemon.sock", name),
+            "source": "/tmp",
+            "host": "local",
+            "labels": { "env": env },
+        }))
+        .unwrap()
+    }
+
+    fn fleet() -> Vec<ServiceInfo> {
+        let mut cache = service("cache", "running", 30, "staging");
+        cache.pinned = true;
+        vec![
+            service("gateway", "running", 7200, "prod"),
+            service("worker", "stopped", 0, "prod"),
+            cache,
+            service("gateway-canary", "running", 600, "staging"),
+        ]
+    }
+
+    fn names(query: ServicesQuery) -> Vec<String> {
+        let listing = query.apply(fleet()).unwrap();
+        listing.services.into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn sorts_by_name_with_pinned_first() {
+        assert_eq!(
+            names(ServicesQuery::default()),
+            ["cache", "gateway", "gateway-canary", "worker"]
+        );
+        let query = ServicesQuery {
+            sort: Some("uptime".to_string()),
+            order: Some("desc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(query),
+            ["cache", "gateway", "gateway-canary", "worker"]
+        );
+        let query = ServicesQuery {
+            sort: Some("uptime".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(query),
+            ["cache", "worker", "gateway-canary", "gateway"]
+        );
+    }
+
+    #[test]
+    fn filters_by_every_criterion() {
+        let query = ServicesQuery {
+            label: Some("env=prod".to_string()),
+            status: Some("running".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(query), ["gateway"]);
+        let query = ServicesQuery {
+            name_contains: Some("gateway".to_string()),
+            q: Some("uptime < 1h".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(query), ["gateway-canary"]);
+    }
+
+    #[test]
+    fn pages_and_counts_the_matches() {
+        let query = ServicesQuery {
+            page: Some(2),
+            per_page: Some(3),
+            ..Default::default()
+        };
+        let listing = query.apply(fleet()).unwrap();
+        assert_eq!(listing.total, 4);
+        assert_eq!(listing.services.len(), 1);
+        assert_eq!(listing.services[0].name, "worker");
+
+        // Far past the end is an empty page, not an overflow
+        let query = ServicesQuery {
+            page: Some(usize::MAX),
+            per_page: Some(1000),
+            ..Default::default()
+        };
+        let listing = query.apply(fleet()).unwrap();
+        assert_eq!(listing.total, 4);
+        assert!(listing.services.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        for query in [
+            ServicesQuery {
+                sort: Some("size".to_string()),
+                ..Default::default()
+            },
+            ServicesQuery {
+                order: Some("up".to_string()),
+                ..Default::default()
+            },
+            ServicesQuery {
+                q: Some("status =".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(query.apply(fleet()).is_err());
+        }
+    }
+
+    #[test]
+    fn stable_fields_leave_out_volatile_ones() {
+        let value = select_fields(fleet(), None);
+        let stable = stable_fields(&value);
+        assert!(stable[0].get("uptime_seconds").is_none());
+        assert_eq!(stable[0]["name"], "gateway");
+
+        let mut later = fleet();
+        later[0].uptime_seconds = Some(7260);
+        assert_eq!(stable_fields(&select_fields(later, None)), stable);
+    }
+}
@@ -0,0 +1,264 @@
+//! Log output: stdout by default, or a rotating file with `--log-file`.
+//!
+//! Files rotate daily by default, getting a date suffix
+//! (`dashboard.log.2024-05-01`); `--log-rotation` also accepts `hourly`,
+//! `never`, or a size such as `10MB`, in which case old files are numbered
+//! (`dashboard.log.1`). `--log-retention` caps how many rotated files are
+//! kept.
+//!
+//! `--log-format json` writes one JSON object per line, including the
+//! `request_id` and `service` of the request span an event was logged in.
+//! The request ID is taken from an incoming `X-Request-Id` header when
+//! present and returned in the response.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{RawPathParams, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::Instrument;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+use crate::config::{self, Config};
+
+/// Rotated files kept when `--log-retention` isn't given
+pub const DEFAULT_RETENTION: usize = 7;
+
+/// How log lines are written
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// When to start a new log file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+    /// Rotate once the file would exceed this many bytes
+    Size(u64),
+}
+
+/// Parse `hourly`, `daily`, `never` or a size (`10MB`, `512KB`, `1GB`)
+pub fn parse_rotation(s: &str) -> Result<LogRotation, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    match lower.as_str() {
+        "hourly" => return Ok(LogRotation::Hourly),
+        "daily" => return Ok(LogRotation::Daily),
+        "never" => return Ok(LogRotation::Never),
+        _ => {}
+    }
+
+    config::parse_size(s).map(LogRotation::Size).map_err(|_| {
+        format!(
+            "invalid rotation '{}', expected hourly, daily, never or a size like 10MB",
+            s
+        )
+    })
+}
+
+/// A log file that is renamed to `<name>.1`, `<name>.2`, ... when it grows
+/// past a size limit
+struct SizeRotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            keep,
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn file_writer(
+    path: &Path,
+    rotation: LogRotation,
+    keep: usize,
+) -> Result<(BoxMakeWriter, WorkerGuard)> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let (writer, guard) = match rotation {
+        LogRotation::Size(max_bytes) => {
+            let file = SizeRotatingFile::open(path.to_path_buf(), max_bytes, keep)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            tracing_appender::non_blocking(file)
+        }
+        LogRotation::Hourly | LogRotation::Daily | LogRotation::Never => {
+            let name = path
+                .file_name()
+                .with_context(|| format!("{} is not a file path", path.display()))?
+                .to_string_lossy()
+                .to_string();
+            let appender = Builder::new()
+                .rotation(match rotation {
+                    LogRotation::Hourly => Rotation::HOURLY,
+                    LogRotation::Daily => Rotation::DAILY,
+                    _ => Rotation::NEVER,
+                })
+                .filename_prefix(name)
+                .max_log_files(keep.max(1))
+                .build(dir)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            tracing_appender::non_blocking(appender)
+        }
+    };
+
+    Ok((BoxMakeWriter::new(writer), guard))
+}
+
+/// Install the global tracing subscriber
+///
+/// Hold on to the returned guard until exit; dropping it flushes pending
+/// lines to the log file.
+pub fn init(config: &Config) -> Result<Option<WorkerGuard>> {
+    let (writer, guard, ansi) = match &config.log_file {
+        Some(path) => {
+            let (writer, guard) = file_writer(path, config.log_rotation, config.log_retention)?;
+            (writer, Some(guard), false)
+        }
+        None => (BoxMakeWriter::new(io::stdout), None, true),
+    };
+
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    let layer = match config.log_format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    // Filter per layer so tokio-console still sees the runtime's own events
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "fgp_dashboard=info".into());
+    let registry = tracing_subscriber::registry().with(layer.with_filter(filter));
+
+    #[cfg(feature = "debug-tools")]
+    let registry = registry.with(console_subscriber::spawn());
+
+    registry.init();
+
+    Ok(guard)
+}
+
+/// Header carrying the request ID in both directions
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// ID of the current request, available as a request extension
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+/// Use the caller's `X-Request-Id` if it looks sane, otherwise generate one
+fn request_id(req: &Request) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Run each request inside a `request` span carrying its ID, and echo the
+/// ID back in `X-Request-Id`
+///
+/// `fgp_daemon`'s client has no way to attach metadata to a call, so the ID
+/// isn't sent to daemons; daemon calls are logged inside this span instead.
+pub async fn request_span(mut req: Request, next: Next) -> Response {
+    let id = request_id(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        service = tracing::field::Empty,
+    );
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(req).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Add the service name to the request span on per-service routes
+///
+/// Installed with `route_layer` so path parameters are available.
+pub async fn record_service(params: RawPathParams, req: Request, next: Next) -> Response {
+    if let Some((_, service)) = params.iter().find(|(key, _)| *key == "service") {
+        tracing::Span::current().record("service", service);
+    }
+    next.run(req).await
+}
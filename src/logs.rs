@@ -0,0 +1,170 @@
+//! Tailing a service's log file.
+//!
+//! `GET /api/services/{service}/logs?lines=200` returns the last lines of
+//! the first of [`LOG_FILES`] found in the service's directory. The detail
+//! page polls it to follow the log live. For a service whose systemd unit is
+//! known, `?source=journal` reads the unit's journal instead, as does a
+//! service without a log file.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::api::{self, ApiResponse};
+use crate::calls;
+use crate::state::AppState;
+use crate::systemd;
+
+/// Log file locations inside a service directory, in order of preference
+pub const LOG_FILES: &[&str] = &["daemon.log", "logs/daemon.log"];
+
+/// Lines returned when `lines` isn't given
+const DEFAULT_LINES: usize = 200;
+
+/// Upper bound for `lines`
+const MAX_LINES: usize = 5000;
+
+/// How far back from the end of the file to look for lines
+const MAX_TAIL_BYTES: u64 = 1 << 20;
+
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    pub lines: Option<usize>,
+    /// `journal` to read the systemd journal rather than the log file
+    pub source: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LogTail {
+    /// The log file, or `journal:<unit>`
+    pub path: String,
+    /// Size of the file, so clients can tell when it grew or was rotated
+    pub size: u64,
+    pub lines: Vec<String>,
+}
+
+/// Mask secrets in a tail before it is shown
+fn redacted(tail: LogTail, state: &AppState) -> LogTail {
+    let redact = &state.settings().redact;
+    LogTail {
+        lines: tail.lines.iter().map(|line| redact.text(line)).collect(),
+        ..tail
+    }
+}
+
+/// Last `lines` lines of the file at `path`
+fn tail(path: PathBuf, lines: usize) -> io::Result<LogTail> {
+    let mut file = File::open(&path)?;
+    let size = file.metadata()?.len();
+    let start = size.saturating_sub(MAX_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let text = String::from_utf8_lossy(&buffer);
+    let mut all: Vec<&str> = text.lines().collect();
+    // The first line is probably cut short unless we read from the start
+    if start > 0 && !all.is_empty() {
+        all.remove(0);
+    }
+    let skip = all.len().saturating_sub(lines);
+
+    Ok(LogTail {
+        path: path.to_string_lossy().to_string(),
+        size,
+        lines: all[skip..].iter().map(|line| line.to_string()).collect(),
+    })
+}
+
+/// Last `lines` lines of a unit's journal
+fn journal_tail(unit: &systemd::Supervision, lines: usize) -> Result<LogTail, String> {
+    let lines = systemd::journal(unit, lines)?;
+    Ok(LogTail {
+        path: format!("journal:{}", unit.unit),
+        // Not a file, so changes whenever the tail does
+        size: lines.iter().map(|line| line.len() as u64 + 1).sum(),
+        lines,
+    })
+}
+
+/// The end of a service's log
+pub async fn service_logs(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Query(query): Query<LogsQuery>,
+) -> impl IntoResponse {
+    let lines = query.lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+    if let Some(fleet) = &state.demo {
+        return match fleet.logs(&service, lines) {
+            Some(tail) => (StatusCode::OK, ApiResponse::success(tail)),
+            None => (
+                StatusCode::NOT_FOUND,
+                ApiResponse::<LogTail>::error(&format!("Service '{}' not found", service)),
+            ),
+        };
+    }
+    let Some(dir) = state.settings().service_dir(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<LogTail>::error(&format!("Service '{}' not found", service)),
+        );
+    };
+    let file = LOG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file());
+    let wants_journal = query.source.as_deref() == Some("journal");
+    if wants_journal || file.is_none() {
+        let unit = api::unit_of(&state, &service)
+            .await
+            .filter(|unit| unit.manager == systemd::MANAGER);
+        match unit {
+            Some(unit) => {
+                return match calls::blocking(move || journal_tail(&unit, lines)).await {
+                    Ok(tail) => (StatusCode::OK, ApiResponse::success(redacted(tail, &state))),
+                    Err(e) => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        ApiResponse::<LogTail>::error(&format!(
+                            "Failed to read the journal: {}",
+                            e
+                        )),
+                    ),
+                };
+            }
+            None if wants_journal => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    ApiResponse::<LogTail>::error(&format!(
+                        "No systemd unit is known for '{}'",
+                        service
+                    )),
+                );
+            }
+            None => {}
+        }
+    }
+    let Some(path) = file else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<LogTail>::error(&format!(
+                "No log file for '{}' (looked for {})",
+                service,
+                LOG_FILES.join(", ")
+            )),
+        );
+    };
+
+    match calls::blocking(move || tail(path, lines)).await {
+        Ok(tail) => (StatusCode::OK, ApiResponse::success(redacted(tail, &state))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<LogTail>::error(&format!("Failed to read log: {}", e)),
+        ),
+    }
+}
@@ -6,79 +6,424 @@
 //! fgp-dashboard                     # Start on default port 8765
 //! fgp-dashboard --port 9000         # Custom port
 //! fgp-dashboard --open              # Open browser automatically
+//! fgp-dashboard --daemon --pid-file /run/fgp-dashboard.pid  # Run in the background
+//! fgp-dashboard --log-file /var/log/fgp-dashboard.log --log-rotation 10MB
+//! fgp-dashboard --log-format json   # Structured logs for ingestion
+//! fgp-dashboard --access-log        # Log every HTTP request
+//! fgp-dashboard --poll-interval 10  # Rescan services every 10 seconds
+//! fgp-dashboard --service-health-timeout gateway=5000  # Slow daemon
+//! fgp-dashboard --retention raw=24h,1h=2y  # Keep less raw history, more rollups
+//! fgp-dashboard --label env=prod    # Label this host
+//! fgp-dashboard --peer http://web-2:8765  # Compare against another host
+//! fgp-dashboard --assets-dir ./ui   # Serve a customised web UI
+//! FGP_DASHBOARD_PORT=9000 fgp-dashboard    # Configure via environment
+//! fgp-dashboard status --json       # Print service status and exit
+//! fgp-dashboard export --out snapshot.json  # Dump state for an incident ticket
+//! fgp-dashboard backup --out backup.tar.gz  # Archive history, keys and config
+//! fgp-dashboard doctor              # Diagnose setup problems
+//! fgp-dashboard --port 9000 install-service --start  # Run under systemd/launchd
+//! fgp-dashboard completions zsh     # Print shell completions
 //! ```
+//!
+//! Settings can also be read from `~/.fgp/dashboard/config.toml`; see
+//! [`config`] for the precedence rules.
 
+mod access_log;
+mod admin;
+mod annotations;
 mod api;
+mod assets;
+mod auth;
+mod backup;
+mod batch;
+mod bench;
+mod binary;
+mod body_limit;
+mod breaker;
+mod calls;
+mod capabilities;
+mod catch_panic;
+mod chaos;
+mod cli;
+mod cluster;
+mod config;
+mod daemon;
+mod db;
+#[cfg(feature = "debug-tools")]
+mod debug;
+mod demo;
+mod docker;
+mod doctor;
+mod drift;
+mod etag;
+mod events;
+mod export;
+mod exposure;
+mod filter;
+mod graphql;
+mod health_cache;
+mod history;
+mod hosts;
+mod i18n;
+mod install;
+mod kubernetes;
+mod launchd;
+mod listing;
+mod logging;
+mod logs;
+mod metrics;
+mod namespaces;
+mod pins;
+mod plugins;
+mod pool;
+mod preferences;
+mod probes;
+mod protocol;
+mod qr;
+mod quotas;
+mod redact;
+mod retention;
+mod retry;
+mod rpc;
+mod s3;
+mod scaffold;
+mod scheduler;
+mod scripts;
+mod secrets;
+mod service_config;
+mod shed;
+mod sockets;
+mod state;
+mod store;
+mod systemd;
+mod timeout;
+mod timing;
+mod tls;
+mod transport;
+mod typescript;
+mod ui;
+mod updates;
+mod upgrade;
+mod version;
+mod wasm;
+mod watcher;
+mod webhooks;
+mod widgets;
 
 use anyhow::Result;
+use async_graphql_axum::{GraphQL, GraphQLSubscription};
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{any, delete, get, post, put},
     Router,
 };
 use clap::Parser;
 use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-/// FGP Dashboard - Web UI for monitoring daemon services
-#[derive(Parser)]
-#[command(name = "fgp-dashboard")]
-#[command(author, version, about)]
-struct Args {
-    /// Port to listen on
-    #[arg(short, long, default_value = "8765")]
-    port: u16,
-
-    /// Open browser automatically
-    #[arg(short, long)]
-    open: bool,
-}
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    let args = config::Args::parse();
+
+    // Completions don't need a valid config
+    if let Some(cli::Command::Completions { shell }) = &args.command {
+        cli::completions(*shell);
+        return Ok(());
+    }
+
+    let config = args.clone().resolve()?;
+    if args.command.is_none() {
+        exposure::check(&config)?;
+    }
+
+    // Fork before the runtime spawns any threads
+    if config.daemon && args.command.is_none() {
+        daemon::daemonize(&config)?;
+    }
+
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "fgp_dashboard=info".into()),
+    let _log_guard = logging::init(&config)?;
+
+    tokio::runtime::Runtime::new()?.block_on(run(args, config))
+}
+
+async fn run(args: config::Args, config: config::Config) -> Result<()> {
+    // Plugins may add services, so load them before anything scans
+    if !config.demo {
+        plugins::init(&config);
+        wasm::init(&config);
+        scripts::init(&config);
+    }
+
+    match &args.command {
+        Some(cli::Command::Status { json }) => {
+            let healthy = cli::status(&config, *json).await?;
+            std::process::exit(if healthy { 0 } else { 1 });
+        }
+        Some(cli::Command::Export { out }) => return cli::export(&config, out).await,
+        Some(cli::Command::Backup { out }) => return cli::backup(&config, &args, out),
+        Some(cli::Command::Restore { archive }) => return cli::restore(&config, &args, archive),
+        Some(cli::Command::Doctor { json }) => {
+            std::process::exit(doctor::run(&config, *json)?);
+        }
+        Some(cli::Command::NewService { name, description }) => {
+            return cli::new_service(&config, name, description.as_deref());
+        }
+        Some(cli::Command::InstallService { start }) => {
+            return install::install_service(args.config.as_deref(), &config.data_dir, *start);
+        }
+        Some(cli::Command::Completions { .. }) | None => {}
+    }
+
+    let (webhooks, db) = if config.demo {
+        tracing::info!("Demo mode: serving a synthetic fleet, nothing is saved");
+        (
+            webhooks::WebhookRegistry::in_memory(),
+            db::Database::in_memory()?,
+        )
+    } else {
+        (
+            webhooks::WebhookRegistry::load(config.data_dir.join(webhooks::SUBSCRIPTIONS_FILE))?,
+            db::Database::open(&config.data_dir.join(db::DB_FILE))?,
         )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    };
 
-    let args = Args::parse();
+    let state = state::AppState::new(args, &config, webhooks, db);
 
-    // Build router
-    let app = Router::new()
-        // API routes
+    webhooks::spawn_dispatcher(state.clone());
+    db::spawn_event_writer(state.clone());
+    admin::spawn_reload_on_sighup(state.clone());
+    // Keep service state current; `/readyz` waits for the first scan
+    store::spawn_poller(state.clone());
+    scripts::spawn_runner(state.clone());
+    scheduler::spawn(state.clone());
+    state.readiness.mark_tasks_started();
+
+    let schema = graphql::schema(state.clone());
+
+    // Routes sharing a concurrency limit per class
+    let reads = Router::new()
         .route("/api/services", get(api::list_services))
         .route("/api/health/{service}", get(api::service_health))
+        .route("/api/events", get(events::list_events))
+        .route("/api/events/stream", get(events::stream_events))
+        .route("/api/annotations", get(annotations::all_annotations))
+        .route("/api/diff", get(history::history_diff))
+        .route("/api/sparklines", get(history::sparklines))
+        .route(
+            "/api/services/{service}/history",
+            get(history::service_history),
+        )
+        .route("/api/services/{service}/logs", get(logs::service_logs))
+        .route(
+            "/api/services/{service}/actions",
+            get(plugins::service_actions),
+        )
+        .route("/api/plugins", get(plugins::list_plugins))
+        .route("/api/scripts", get(scripts::list_scripts))
+        .route("/api/schedule", get(scheduler::list_tasks))
+        .route("/api/schedule/{task}/runs", get(scheduler::task_runs))
+        .route("/api/widgets", get(widgets::list_widgets))
+        .route("/api/widgets/{id}/data", get(widgets::widget_data))
+        .route("/api/types.d.ts", get(typescript::type_definitions))
+        .route("/api/ui", get(ui::ui_settings))
+        .route("/api/ui/logo", get(ui::logo))
+        .route("/api/i18n", get(i18n::catalog))
+        .route("/api/qr", get(qr::qr_code))
+        .route("/api/namespaces", get(namespaces::list_namespaces))
+        .route("/api/hosts", get(hosts::list_hosts))
+        .route("/api/hosts/groups/{label}", get(hosts::group_hosts))
+        .route("/api/cluster/diff", get(cluster::diff))
+        .route(
+            "/graphql",
+            get(graphql::graphiql).post_service(GraphQL::new(schema.clone())),
+        )
+        .route_service("/graphql/ws", GraphQLSubscription::new(schema));
+    let control = Router::new()
         .route("/api/start/{service}", post(api::start_service))
         .route("/api/stop/{service}", post(api::stop_service))
-        // Static dashboard
-        .route("/", get(api::serve_dashboard))
+        .route("/api/cleanup/{service}", post(api::cleanup_service))
+        .route("/api/upgrade/{service}", post(upgrade::upgrade_service))
+        .route("/api/scaffold", post(scaffold::scaffold_service))
+        .route(
+            "/api/services/{service}/actions/{plugin}/{action}",
+            post(plugins::run_action),
+        )
+        .route(
+            "/api/plugins/{plugin}/{*path}",
+            any(plugins::plugin_request),
+        )
+        .route("/api/scripts/{script}/run", post(scripts::run_script))
+        .route("/api/schedule/{task}/run", post(scheduler::run_task))
+        .route("/api/batch", post(batch::batch))
+        .route(
+            "/api/subscriptions",
+            get(webhooks::list_subscriptions).post(webhooks::create_subscription),
+        )
+        .route(
+            "/api/subscriptions/{id}",
+            delete(webhooks::delete_subscription),
+        )
+        .route(
+            "/api/subscriptions/{id}/deliveries",
+            get(webhooks::subscription_deliveries),
+        )
+        .route(
+            "/api/services/{service}/annotations",
+            get(annotations::list_annotations).put(annotations::update_annotations),
+        )
+        .route(
+            "/api/services/{service}/annotations/{key}",
+            delete(annotations::delete_annotation),
+        )
+        .route(
+            "/api/config/{service}",
+            get(service_config::get_config).put(service_config::update_config),
+        )
+        .route(
+            "/api/services/{service}/pin",
+            put(pins::pin_service).delete(pins::unpin_service),
+        )
+        .route(
+            "/api/preferences",
+            get(preferences::get_preferences).put(preferences::update_preferences),
+        )
+        .route(
+            "/api/preferences/{key}",
+            delete(preferences::delete_preference),
+        )
+        .route("/api/admin/reload-config", post(admin::reload_config))
+        .route("/api/admin/backup", get(backup::backup))
+        .route("/api/admin/quotas", get(quotas::usage))
+        .route(
+            "/api/admin/chaos",
+            get(chaos::get_chaos)
+                .put(chaos::update_chaos)
+                .delete(chaos::delete_chaos),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            quotas::control,
+        ));
+    // Archives are far larger than other bodies, and restores run one at a time
+    let restore = Router::new()
+        .route("/api/admin/restore", post(backup::restore_backup))
+        .route_layer(DefaultBodyLimit::max(backup::MAX_ARCHIVE_SIZE));
+    let passthrough = Router::new()
+        .route("/api/call/{service}/{method}", post(rpc::call_method))
+        .route("/api/methods/{service}", get(rpc::list_methods))
+        .route("/api/bench/{service}", post(bench::bench_service))
+        .route_layer(middleware::from_fn_with_state(state.clone(), quotas::calls));
+
+    // Build router
+    let app = Router::new()
+        // Probes for the dashboard itself
+        .route("/healthz", get(probes::healthz))
+        .route("/readyz", get(probes::readyz))
+        // API routes
+        .merge(shed::limit(reads, config.max_concurrent_reads))
+        .merge(shed::limit(
+            body_limit::limit(control, config.max_request_body, "max-request-body"),
+            config.max_concurrent_control,
+        ))
+        .merge(shed::limit(restore, 1))
+        .merge(shed::limit(
+            body_limit::limit(
+                passthrough,
+                config.max_rpc_upload_size,
+                "max-rpc-upload-size",
+            ),
+            config.max_concurrent_calls,
+        ))
+        .route("/api/self/metrics", get(metrics::self_metrics))
+        .route("/api/version", get(version::version))
+        // Web UI
+        .route("/", get(assets::index))
+        .route("/service/{service}", get(assets::service_page))
+        .route("/timeline", get(assets::timeline_page))
+        .route("/explorer", get(assets::explorer_page))
+        .route("/assets/{*path}", get(assets::asset));
+
+    #[cfg(feature = "debug-tools")]
+    let app = app.merge(debug::routes());
+
+    let app = app
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track,
+        ))
+        .route_layer(middleware::from_fn(logging::record_service))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            namespaces::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), demo::guard))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            timeout::enforce,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), quotas::rate))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::require))
+        .layer(middleware::from_fn(calls::scope))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access_log::log,
+        ))
+        .layer(catch_panic::layer())
+        .layer(middleware::from_fn(logging::request_span))
         // CORS for local development
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
-        );
+                .allow_headers(Any)
+                .expose_headers(Any),
+        )
+        .with_state(state);
+    // Namespaced paths must be rewritten before they are routed
+    let app = Router::new()
+        .fallback_service(app)
+        .layer(middleware::from_fn(namespaces::rewrite));
 
-    // Bind to localhost only (security)
-    let addr = SocketAddr::from(([127, 0, 0, 1], args.port));
-    let url = format!("http://localhost:{}", args.port);
+    // Bind to localhost unless configured otherwise (security)
+    let addr = SocketAddr::new(config.bind, config.port);
+    let scheme = if config.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let url = if config.bind.is_loopback() {
+        format!("{}://localhost:{}", scheme, config.port)
+    } else {
+        format!("{}://{}", scheme, addr)
+    };
+    let tls = config.tls.as_ref().map(tls::server_config).transpose()?;
 
     tracing::info!("FGP Dashboard starting at {}", url);
+    exposure::warn(&config);
 
     // Open browser if requested
-    if args.open {
+    if config.open {
         tracing::info!("Opening browser...");
-        let _ = open::that(&url);
+        // The token sets the session cookie (see `auth`)
+        let _ = match config.auth_tokens.first() {
+            Some(token) => open::that(format!("{}/?token={}", url, token)),
+            None => open::that(&url),
+        };
     }
 
     // Start server
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    match tls {
+        Some(tls) => tls::serve(listener, app, tls).await?,
+        None => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?
+        }
+    }
 
     Ok(())
 }
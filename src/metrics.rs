@@ -0,0 +1,373 @@
+//! Operational metrics for the dashboard process itself.
+//!
+//! `GET /api/self/metrics` returns JSON, or the Prometheus text format with
+//! `?format=prometheus` or `Accept: text/plain`. Counters are process-wide,
+//! since socket calls are made from free functions without access to
+//! [`AppState`](crate::state::AppState).
+//!
+//! Request durations are kept as per-route histograms. Requests slower than
+//! `--slow-request-ms` are logged as warnings, along with their slowest
+//! backend call.
+
+use axum::{
+    extract::{MatchedPath, Query, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::state::AppState;
+
+/// Slow-request threshold used when none is configured
+pub const DEFAULT_SLOW_REQUEST_MS: u64 = 1000;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Request durations for one route
+#[derive(Clone, Serialize)]
+struct Histogram {
+    /// Cumulative counts per bucket in [`BUCKETS`]
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+}
+
+impl Histogram {
+    const EMPTY: Self = Self {
+        buckets: [0; BUCKETS.len()],
+        count: 0,
+        sum_seconds: 0.0,
+    };
+
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_seconds += seconds;
+    }
+}
+
+struct Metrics {
+    /// Completed requests by (route, status)
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    /// Request durations by route
+    latency: Mutex<BTreeMap<String, Histogram>>,
+    in_flight: AtomicU64,
+    socket_calls: AtomicU64,
+    socket_errors: AtomicU64,
+    connections_opened: AtomicU64,
+    connections_reused: AtomicU64,
+    panics: AtomicU64,
+}
+
+static METRICS: Metrics = Metrics {
+    requests: Mutex::new(BTreeMap::new()),
+    latency: Mutex::new(BTreeMap::new()),
+    in_flight: AtomicU64::new(0),
+    socket_calls: AtomicU64::new(0),
+    socket_errors: AtomicU64::new(0),
+    connections_opened: AtomicU64::new(0),
+    connections_reused: AtomicU64::new(0),
+    panics: AtomicU64::new(0),
+};
+
+/// Count a call to a daemon socket
+pub fn record_socket_call(ok: bool) {
+    METRICS.socket_calls.fetch_add(1, Ordering::Relaxed);
+    if !ok {
+        METRICS.socket_errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Count a daemon connection checked out of the pool
+pub fn record_connection(reused: bool) {
+    let counter = if reused {
+        &METRICS.connections_reused
+    } else {
+        &METRICS.connections_opened
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a handler panic
+pub fn record_panic() {
+    METRICS.panics.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Decrements the in-flight gauge even if the handler panics
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        METRICS.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        METRICS.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Count requests and record their duration by route, warning about slow
+/// ones
+///
+/// Installed with `route_layer` so the matched route is known.
+pub async fn track(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let _in_flight = InFlight::start();
+    let started = Instant::now();
+    let response = next.run(req).await;
+    let elapsed = started.elapsed();
+
+    let status = response.status().as_u16();
+    *METRICS
+        .requests
+        .lock()
+        .unwrap()
+        .entry((route.clone(), status))
+        .or_default() += 1;
+    METRICS
+        .latency
+        .lock()
+        .unwrap()
+        .entry(route.clone())
+        .or_insert(Histogram::EMPTY)
+        .observe(elapsed.as_secs_f64());
+
+    let threshold = state.settings().slow_request;
+    if !threshold.is_zero() && elapsed > threshold {
+        let (slowest_call, slowest_call_ms) = match calls::slowest() {
+            Some((call, duration)) => (Some(call), Some(millis(duration))),
+            None => (None, None),
+        };
+        tracing::warn!(
+            %route,
+            status,
+            duration_ms = millis(elapsed),
+            slowest_call = slowest_call.as_deref(),
+            slowest_call_ms,
+            "Slow request",
+        );
+    }
+
+    response
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[derive(Serialize)]
+struct RouteCount {
+    route: String,
+    status: u16,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct RouteLatency {
+    route: String,
+    /// Upper bounds of the buckets, in seconds
+    le: &'static [f64],
+    #[serde(flatten)]
+    histogram: Histogram,
+}
+
+#[derive(Serialize)]
+struct TaskCounts {
+    alive: usize,
+    workers: usize,
+}
+
+#[derive(Serialize)]
+struct SocketCalls {
+    total: u64,
+    errors: u64,
+    error_rate: f64,
+    /// New connections to daemon sockets
+    connections_opened: u64,
+    /// Calls served by a pooled connection
+    connections_reused: u64,
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+    requests: Vec<RouteCount>,
+    latency: Vec<RouteLatency>,
+    in_flight: u64,
+    tasks: TaskCounts,
+    socket_calls: SocketCalls,
+    panics: u64,
+}
+
+fn snapshot() -> Snapshot {
+    let requests = METRICS
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((route, status), count)| RouteCount {
+            route: route.clone(),
+            status: *status,
+            count: *count,
+        })
+        .collect();
+
+    let latency = METRICS
+        .latency
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(route, histogram)| RouteLatency {
+            route: route.clone(),
+            le: &BUCKETS,
+            histogram: histogram.clone(),
+        })
+        .collect();
+
+    let runtime = tokio::runtime::Handle::current().metrics();
+    let total = METRICS.socket_calls.load(Ordering::Relaxed);
+    let errors = METRICS.socket_errors.load(Ordering::Relaxed);
+
+    Snapshot {
+        requests,
+        latency,
+        in_flight: METRICS.in_flight.load(Ordering::Relaxed),
+        tasks: TaskCounts {
+            alive: runtime.num_alive_tasks(),
+            workers: runtime.num_workers(),
+        },
+        socket_calls: SocketCalls {
+            total,
+            errors,
+            error_rate: if total == 0 {
+                0.0
+            } else {
+                errors as f64 / total as f64
+            },
+            connections_opened: METRICS.connections_opened.load(Ordering::Relaxed),
+            connections_reused: METRICS.connections_reused.load(Ordering::Relaxed),
+        },
+        panics: METRICS.panics.load(Ordering::Relaxed),
+    }
+}
+
+fn prometheus(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# TYPE fgp_dashboard_http_requests_total counter");
+    for r in &snapshot.requests {
+        let _ = writeln!(
+            out,
+            "fgp_dashboard_http_requests_total{{route=\"{}\",status=\"{}\"}} {}",
+            r.route, r.status, r.count
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# TYPE fgp_dashboard_http_request_duration_seconds histogram"
+    );
+    for r in &snapshot.latency {
+        for (bound, count) in BUCKETS.iter().zip(r.histogram.buckets) {
+            let _ = writeln!(
+                out,
+                "fgp_dashboard_http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                r.route, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "fgp_dashboard_http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+            r.route, r.histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "fgp_dashboard_http_request_duration_seconds_sum{{route=\"{}\"}} {}",
+            r.route, r.histogram.sum_seconds
+        );
+        let _ = writeln!(
+            out,
+            "fgp_dashboard_http_request_duration_seconds_count{{route=\"{}\"}} {}",
+            r.route, r.histogram.count
+        );
+    }
+
+    let gauges = [
+        ("http_requests_in_flight", snapshot.in_flight as f64),
+        ("tokio_alive_tasks", snapshot.tasks.alive as f64),
+        ("tokio_workers", snapshot.tasks.workers as f64),
+    ];
+    for (name, value) in gauges {
+        let _ = writeln!(out, "# TYPE fgp_dashboard_{} gauge", name);
+        let _ = writeln!(out, "fgp_dashboard_{} {}", name, value);
+    }
+
+    let counters = [
+        ("socket_calls_total", snapshot.socket_calls.total),
+        ("socket_call_errors_total", snapshot.socket_calls.errors),
+        (
+            "socket_connections_opened_total",
+            snapshot.socket_calls.connections_opened,
+        ),
+        (
+            "socket_connections_reused_total",
+            snapshot.socket_calls.connections_reused,
+        ),
+        ("panics_total", snapshot.panics),
+    ];
+    for (name, value) in counters {
+        let _ = writeln!(out, "# TYPE fgp_dashboard_{} counter", name);
+        let _ = writeln!(out, "fgp_dashboard_{} {}", name, value);
+    }
+
+    out
+}
+
+#[derive(Deserialize)]
+pub struct MetricsQuery {
+    pub format: Option<String>,
+}
+
+/// The dashboard's own metrics
+pub async fn self_metrics(headers: HeaderMap, Query(query): Query<MetricsQuery>) -> Response {
+    let wants_text = match query.format.as_deref() {
+        Some(format) => format == "prometheus",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/plain")),
+    };
+
+    let snapshot = snapshot();
+    if wants_text {
+        (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            prometheus(&snapshot),
+        )
+            .into_response()
+    } else {
+        ApiResponse::success(snapshot).into_response()
+    }
+}
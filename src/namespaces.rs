@@ -0,0 +1,318 @@
+//! Namespaces partitioning one dashboard between teams.
+//!
+//! Each `[[namespaces]]` section of the config file names the services it
+//! holds, by name or `prefix*` pattern, and the tokens of the team using it:
+//!
+//! ```toml
+//! [[namespaces]]
+//! name = "payments"
+//! services = ["payments-*", "ledger"]
+//! tokens = ["${env:PAYMENTS_TOKEN}"]
+//! ```
+//!
+//! The routes in [`NAMESPACED_ROUTES`] are also served under
+//! `/api/ns/{ns}/`, limited to the namespace: `/api/ns/payments/services`
+//! lists its services, `/api/ns/payments/events` and `/events/stream` carry
+//! its events, and service routes such as `/api/ns/payments/stop/ledger`
+//! only reach its services. Webhook subscriptions created there only deliver
+//! its events and are only listed there.
+//!
+//! A namespace token opens its namespace's routes, the UI and the few routes
+//! in [`SHARED_ROUTES`], and nothing else (see [`auth`](crate::auth));
+//! `--auth-token` tokens open everything. A service may be in several
+//! namespaces, and a namespace may have [`quotas`](crate::quotas) of its own.
+
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request, State},
+    http::{StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::auth::Principal;
+use crate::quotas::Quotas;
+use crate::state::{is_service_name, AppState, Settings};
+
+/// Routes also served within a namespace, as matched once `/ns/{ns}` is
+/// dropped from the path
+pub const NAMESPACED_ROUTES: &[&str] = &[
+    "/api/services",
+    "/api/health/{service}",
+    "/api/events",
+    "/api/events/stream",
+    "/api/annotations",
+    "/api/sparklines",
+    "/api/services/{service}/history",
+    "/api/services/{service}/logs",
+    "/api/services/{service}/annotations",
+    "/api/services/{service}/annotations/{key}",
+    "/api/start/{service}",
+    "/api/stop/{service}",
+    "/api/cleanup/{service}",
+    "/api/call/{service}/{method}",
+    "/api/methods/{service}",
+    "/api/bench/{service}",
+    "/api/subscriptions",
+    "/api/subscriptions/{id}",
+    "/api/subscriptions/{id}/deliveries",
+];
+
+/// API routes open to namespace tokens outside their namespace
+pub const SHARED_ROUTES: &[&str] = &[
+    "/api/ui",
+    "/api/ui/logo",
+    "/api/i18n",
+    "/api/version",
+    "/api/namespaces",
+    "/api/qr",
+];
+
+/// A namespace from the config file
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Namespace {
+    pub name: String,
+    /// Service names or `prefix*` patterns
+    #[serde(default)]
+    pub services: Vec<String>,
+    /// Tokens that open only this namespace
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// Limits of everything done in this namespace
+    #[serde(default)]
+    pub quotas: Quotas,
+}
+
+impl Namespace {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "namespace name '{}' must be letters, digits, '-' and '_'",
+                self.name
+            ));
+        }
+        if self.tokens.iter().any(|token| token.trim().is_empty()) {
+            return Err(format!("namespace '{}' has an empty token", self.name));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, service: &str) -> bool {
+        is_service_name(service)
+            && self
+                .services
+                .iter()
+                .any(|pattern| matches(pattern, service))
+    }
+}
+
+/// Whether `service` is named by `pattern`, a name or `prefix*`
+pub fn matches(pattern: &str, service: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => service.starts_with(prefix),
+        None => pattern == service,
+    }
+}
+
+/// The namespace a request was made in, from its `/api/ns/{ns}/` prefix
+#[derive(Clone)]
+pub struct Scope(pub String);
+
+/// Which services a request may see
+pub struct View(Option<Namespace>);
+
+impl View {
+    pub fn of(settings: &Settings, scope: Option<Extension<Scope>>) -> Self {
+        Self(scope.map(|Extension(Scope(name))| {
+            settings.namespace(&name).cloned().unwrap_or(Namespace {
+                name,
+                services: Vec::new(),
+                tokens: Vec::new(),
+                quotas: Quotas::default(),
+            })
+        }))
+    }
+
+    /// The namespace seen, if the request is limited to one
+    pub fn namespace(&self) -> Option<&str> {
+        self.0.as_ref().map(|namespace| namespace.name.as_str())
+    }
+
+    pub fn shows(&self, service: &str) -> bool {
+        self.0
+            .as_ref()
+            .is_none_or(|namespace| namespace.contains(service))
+    }
+}
+
+fn not_found(message: &str) -> Response {
+    (StatusCode::NOT_FOUND, ApiResponse::<()>::error(message)).into_response()
+}
+
+/// Serve `/api/ns/{ns}/...` as `/api/...` scoped to the namespace
+///
+/// Routing happens after this, so it wraps the whole router.
+pub async fn rewrite(mut req: Request, next: Next) -> Response {
+    let scoped = req
+        .uri()
+        .path()
+        .strip_prefix("/api/ns/")
+        .and_then(|rest| rest.split_once('/'))
+        .map(|(name, rest)| (name.to_string(), format!("/api/{}", rest)));
+    if let Some((name, path)) = scoped {
+        let path_and_query = match req.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        let mut parts = req.uri().clone().into_parts();
+        match path_and_query.parse() {
+            Ok(path_and_query) => parts.path_and_query = Some(path_and_query),
+            Err(_) => return not_found("Not found"),
+        }
+        match Uri::from_parts(parts) {
+            Ok(uri) => *req.uri_mut() = uri,
+            Err(_) => return not_found("Not found"),
+        }
+        req.extensions_mut().insert(Scope(name));
+    }
+    next.run(req).await
+}
+
+/// Keep namespaced requests to namespaced routes and the namespace's services
+///
+/// A `{service}` that isn't a plain name, such as `a%2F..%2Fb`, is not found
+/// whether or not the request is namespaced.
+pub async fn enforce(
+    State(state): State<AppState>,
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Response {
+    let service = params
+        .iter()
+        .find(|(key, _)| *key == "service")
+        .map(|(_, service)| service.to_string());
+    if let Some(service) = &service {
+        if !is_service_name(service) {
+            return not_found(&format!("Service '{}' not found", service));
+        }
+    }
+    let Some(Scope(name)) = req.extensions().get::<Scope>().cloned() else {
+        return next.run(req).await;
+    };
+    let settings = state.settings();
+    let Some(namespace) = settings.namespace(&name) else {
+        return not_found(&format!("Namespace '{}' not found", name));
+    };
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|route| route.as_str())
+        .unwrap_or_default();
+    if !NAMESPACED_ROUTES.contains(&route) {
+        return not_found(&format!("{} is not available in a namespace", route));
+    }
+    if let Some(service) = &service {
+        if !namespace.contains(service) {
+            return not_found(&format!("Service '{}' not found", service));
+        }
+    }
+    next.run(req).await
+}
+
+/// Whether a token of namespace `name` may make `req`
+pub fn permits(name: &str, req: &Request) -> bool {
+    if let Some(Scope(scope)) = req.extensions().get::<Scope>() {
+        return scope == name;
+    }
+    let path = req.uri().path();
+    SHARED_ROUTES.contains(&path)
+        || path == "/"
+        || path == "/timeline"
+        || path == "/explorer"
+        || path.starts_with("/service/")
+        || path.starts_with("/assets/")
+}
+
+/// A namespace and the services in it
+#[derive(Serialize, TS)]
+pub struct NamespaceInfo {
+    pub name: String,
+    pub services: Vec<String>,
+}
+
+#[derive(Serialize, TS)]
+pub struct NamespaceList {
+    pub namespaces: Vec<NamespaceInfo>,
+    /// Whether the caller can only use these namespaces
+    pub restricted: bool,
+}
+
+/// The namespaces the caller can use
+pub async fn list_namespaces(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse {
+    let snapshot = state.services().await;
+    let settings = state.settings();
+    let namespaces = settings
+        .namespaces
+        .iter()
+        .filter(|namespace| match &principal {
+            Principal::Admin => true,
+            Principal::Namespace(name) => *name == namespace.name,
+        })
+        .map(|namespace| NamespaceInfo {
+            name: namespace.name.clone(),
+            services: snapshot
+                .services
+                .iter()
+                .filter(|service| namespace.contains(&service.name))
+                .map(|service| service.name.clone())
+                .collect(),
+        })
+        .collect();
+    ApiResponse::success(NamespaceList {
+        namespaces,
+        restricted: principal != Principal::Admin,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payments() -> Namespace {
+        Namespace {
+            name: "payments".to_string(),
+            services: vec!["payments-*".to_string(), "ledger".to_string()],
+            tokens: Vec::new(),
+            quotas: Quotas::default(),
+        }
+    }
+
+    #[test]
+    fn contains_matches_names_and_prefixes() {
+        let namespace = payments();
+        assert!(namespace.contains("payments-api"));
+        assert!(namespace.contains("ledger"));
+        assert!(!namespace.contains("ledger-replica"));
+        assert!(!namespace.contains("billing"));
+    }
+
+    #[test]
+    fn contains_refuses_paths_out_of_the_namespace() {
+        let namespace = payments();
+        assert!(!namespace.contains("payments-api/../billing"));
+        assert!(!namespace.contains("payments-..\\billing"));
+    }
+}
@@ -0,0 +1,68 @@
+//! Pinned services.
+//!
+//! `PUT /api/services/{service}/pin` pins a service and `DELETE` unpins
+//! it. Pinned services come first in `GET /api/services` whatever the sort
+//! order, and the dashboard shows them in their own section. Like
+//! preferences, pins are stored in the shared `global` scope until there are
+//! users.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::calls;
+use crate::events;
+use crate::preferences::GLOBAL_SCOPE;
+use crate::state::AppState;
+
+/// Set `pinned` on each service from the stored pins
+pub async fn mark_pinned(state: &AppState, services: &mut [ServiceInfo]) {
+    let db = state.db.clone();
+    match calls::blocking(move || db.pinned(GLOBAL_SCOPE)).await {
+        Ok(pinned) => {
+            for service in services {
+                service.pinned = pinned.contains(&service.name);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load pinned services: {:#}", e),
+    }
+}
+
+async fn set_pinned(state: AppState, service: String, pinned: bool) -> impl IntoResponse {
+    let db = state.db.clone();
+    let target = service.clone();
+    let result =
+        calls::blocking(move || db.set_pinned(GLOBAL_SCOPE, &target, pinned, events::now())).await;
+    match result {
+        Ok(()) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({
+                "service": service,
+                "pinned": pinned,
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<serde_json::Value>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Pin a service
+pub async fn pin_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    set_pinned(state, service, true).await
+}
+
+/// Unpin a service
+pub async fn unpin_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    set_pinned(state, service, false).await
+}
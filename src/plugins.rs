@@ -0,0 +1,573 @@
+//! Plugins adding service sources, per-service actions and API routes.
+//!
+//! A plugin implements [`Plugin`]. Plugins compiled into the dashboard are
+//! listed in [`builtin`]; others are loaded at startup from the dynamic
+//! libraries (`.so`, `.dylib` or `.dll`) in `--plugin-dir`, by default
+//! `<data_dir>/plugins`. Such a library speaks JSON over a C ABI, so it can
+//! be written in any language and needs nothing from this crate:
+//!
+//! - `uint32_t fgp_plugin_abi(void)` returns [`ABI_VERSION`]
+//! - `char *fgp_plugin_call(const char *request)` answers a request
+//! - `void fgp_plugin_free(char *response)` frees an answer
+//!
+//! Requests are JSON objects with an `op`:
+//!
+//! - `describe`: answer `{"name": ..., "version": ...}`
+//! - `discover`: answer `[{"name": ..., "socket_path": ...}]`
+//! - `actions`, with the `service` as `/api/services` lists it: answer
+//!   `[{"id": ..., "label": ...}]`
+//! - `run`, with the `service` and the `action` id: answer
+//!   `{"ok": true, "message": ...}` or `{"ok": false, "error": ...}`
+//! - `request`, with the `method`, the `path` below the plugin's prefix, the
+//!   raw `query` and the JSON `body`: answer `{"status": 200, "body": ...}`
+//! - `widgets`: answer `[{"id": ..., "title": ..., "path": ...}]`, see
+//!   [`widgets`](crate::widgets)
+//!
+//! Calls may come from several threads at once, and a plugin that crashes
+//! takes the dashboard down with it. Services a plugin discovers have
+//! `plugin:<name>` as their source; services dirs win when a name is in
+//! both. Actions run through
+//! `POST /api/services/{service}/actions/{plugin}/{action}`, and
+//! `/api/plugins/{plugin}/{*path}` is routed to the plugin's `request`.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{Method, StatusCode, Uri},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::OnceLock;
+use ts_rs::TS;
+
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::calls;
+use crate::config::Config;
+use crate::events;
+use crate::state::AppState;
+use crate::wasm::{self, CheckInfo};
+use crate::widgets::Widget;
+
+/// Version of the C ABI this dashboard speaks
+pub const ABI_VERSION: u32 = 1;
+
+/// Default plugin directory, inside the data dir
+pub const PLUGIN_DIR: &str = "plugins";
+
+/// Prefix of the source of services found by plugins
+pub const SOURCE_PREFIX: &str = "plugin:";
+
+/// A service a plugin found
+#[derive(Serialize, Deserialize)]
+pub struct PluginService {
+    pub name: String,
+    pub socket_path: PathBuf,
+}
+
+/// An action a plugin offers for a service
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct PluginAction {
+    /// Plugin offering it, filled in by the dashboard
+    #[serde(default)]
+    pub plugin: String,
+    pub id: String,
+    pub label: String,
+}
+
+/// A request to a plugin's routes
+#[derive(Serialize)]
+pub struct PluginRequest {
+    pub method: String,
+    /// Path below `/api/plugins/<plugin>/`
+    pub path: String,
+    pub query: Option<String>,
+    /// The JSON body, or null
+    pub body: serde_json::Value,
+}
+
+/// A plugin's answer to a request
+#[derive(Serialize, Deserialize)]
+pub struct PluginResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+impl PluginResponse {
+    fn not_found() -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND.as_u16(),
+            body: serde_json::json!({ "error": "Not found" }),
+        }
+    }
+}
+
+/// An extension of the dashboard
+///
+/// Every method but `name` has a default doing nothing, so a plugin only
+/// implements what it adds. Methods are called off the async runtime and
+/// may block.
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn version(&self) -> &str {
+        ""
+    }
+
+    /// Services to show besides those in the services dirs
+    fn discover(&self) -> Result<Vec<PluginService>, String> {
+        Ok(Vec::new())
+    }
+
+    /// Actions offered for a service
+    fn actions(&self, _service: &ServiceInfo) -> Vec<PluginAction> {
+        Vec::new()
+    }
+
+    /// Run one of the service's actions, returning a message for the operator
+    fn run(&self, _service: &ServiceInfo, action: &str) -> Result<String, String> {
+        Err(format!("Unknown action '{}'", action))
+    }
+
+    /// Answer a request to `/api/plugins/<name>/...`
+    fn request(&self, _request: &PluginRequest) -> PluginResponse {
+        PluginResponse::not_found()
+    }
+
+    /// Panels to show on the main page, fed by `request` unless they name a
+    /// daemon method
+    fn widgets(&self) -> Vec<Widget> {
+        Vec::new()
+    }
+}
+
+/// Plugins compiled into this build
+///
+/// Builds carrying their own plugins add them here.
+pub fn builtin() -> Vec<Box<dyn Plugin>> {
+    Vec::new()
+}
+
+/// A plugin as `/api/plugins` lists it
+#[derive(Serialize, Deserialize, TS)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    /// Library it was loaded from; none for built-in plugins
+    pub path: Option<String>,
+}
+
+/// A library that failed to load
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct PluginFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Everything `/api/plugins` returns
+#[derive(Serialize, Deserialize, TS)]
+pub struct PluginList {
+    pub plugins: Vec<PluginInfo>,
+    pub failed: Vec<PluginFailure>,
+    /// WASM checks, see [`wasm`]
+    pub checks: Vec<CheckInfo>,
+}
+
+/// The plugins loaded at startup
+#[derive(Default)]
+pub struct Registry {
+    plugins: Vec<(Box<dyn Plugin>, Option<PathBuf>)>,
+    failed: Vec<PluginFailure>,
+}
+
+impl Registry {
+    /// Built-in plugins plus the libraries in `dir`
+    pub fn load(dir: &FsPath) -> Self {
+        let mut registry = Self::default();
+        for plugin in builtin() {
+            registry.register(plugin, None);
+        }
+
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| is_library(path))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        for path in paths {
+            match DylibPlugin::load(&path) {
+                Ok(plugin) => registry.register(Box::new(plugin), Some(path)),
+                Err(error) => {
+                    tracing::warn!("Failed to load plugin {}: {}", path.display(), error);
+                    registry.failed.push(PluginFailure {
+                        path: path.to_string_lossy().to_string(),
+                        error,
+                    });
+                }
+            }
+        }
+        registry
+    }
+
+    fn register(&mut self, plugin: Box<dyn Plugin>, path: Option<PathBuf>) {
+        if self.get(plugin.name()).is_some() {
+            tracing::warn!("Ignoring a second plugin named '{}'", plugin.name());
+            return;
+        }
+        tracing::info!("Loaded plugin {}", plugin.name());
+        self.plugins.push((plugin, path));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Plugin> {
+        self.plugins
+            .iter()
+            .find(|(plugin, _)| plugin.name() == name)
+            .map(|(plugin, _)| plugin.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Plugin> {
+        self.plugins.iter().map(|(plugin, _)| plugin.as_ref())
+    }
+
+    pub fn list(&self) -> PluginList {
+        PluginList {
+            plugins: self
+                .plugins
+                .iter()
+                .map(|(plugin, path)| PluginInfo {
+                    name: plugin.name().to_string(),
+                    version: plugin.version().to_string(),
+                    path: path.as_ref().map(|path| path.to_string_lossy().to_string()),
+                })
+                .collect(),
+            failed: self.failed.clone(),
+            checks: wasm::checks().list(),
+        }
+    }
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Load the plugins once, before anything scans for services
+pub fn init(config: &Config) {
+    REGISTRY.get_or_init(|| Registry::load(&config.plugin_dir));
+}
+
+/// The loaded plugins; none before [`init`]
+pub fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(Registry::default)
+}
+
+fn is_library(path: &FsPath) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension, "so" | "dylib" | "dll"))
+}
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type CallFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+/// A plugin in a dynamic library, called over the C ABI
+struct DylibPlugin {
+    name: String,
+    version: String,
+    call: CallFn,
+    free: FreeFn,
+    /// Keeps `call` and `free` valid
+    _library: libloading::Library,
+}
+
+#[derive(Deserialize)]
+struct Description {
+    name: String,
+    #[serde(default)]
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct RunResult {
+    ok: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl DylibPlugin {
+    fn load(path: &FsPath) -> Result<Self, String> {
+        // SAFETY: loading runs the library's initialisers; plugin
+        // directories are trusted like the dashboard binary itself
+        let library = unsafe { libloading::Library::new(path) }.map_err(|e| e.to_string())?;
+        // SAFETY: the symbols are declared with these signatures by the ABI
+        let (abi, call, free) = unsafe {
+            let abi = *library
+                .get::<AbiFn>(b"fgp_plugin_abi\0")
+                .map_err(|e| e.to_string())?;
+            let call = *library
+                .get::<CallFn>(b"fgp_plugin_call\0")
+                .map_err(|e| e.to_string())?;
+            let free = *library
+                .get::<FreeFn>(b"fgp_plugin_free\0")
+                .map_err(|e| e.to_string())?;
+            (abi(), call, free)
+        };
+        if abi != ABI_VERSION {
+            return Err(format!(
+                "built for plugin ABI {}, this dashboard speaks {}",
+                abi, ABI_VERSION
+            ));
+        }
+
+        let mut plugin = Self {
+            name: String::new(),
+            version: String::new(),
+            call,
+            free,
+            _library: library,
+        };
+        let description: Description =
+            serde_json::from_value(plugin.send(serde_json::json!({ "op": "describe" }))?)
+                .map_err(|e| format!("invalid describe answer: {}", e))?;
+        if description.name.trim().is_empty() {
+            return Err("the plugin has no name".to_string());
+        }
+        plugin.name = description.name;
+        plugin.version = description.version;
+        Ok(plugin)
+    }
+
+    /// Send one request and parse the answer
+    fn send(&self, request: serde_json::Value) -> Result<serde_json::Value, String> {
+        let request = CString::new(request.to_string()).map_err(|e| e.to_string())?;
+        // SAFETY: the request is a valid C string for the duration of the
+        // call, and the answer is read before being handed back to the
+        // plugin to free
+        let answer = unsafe {
+            let response = (self.call)(request.as_ptr());
+            if response.is_null() {
+                return Err("the plugin gave no answer".to_string());
+            }
+            let answer = CStr::from_ptr(response).to_string_lossy().into_owned();
+            (self.free)(response);
+            answer
+        };
+        serde_json::from_str(&answer).map_err(|e| format!("invalid answer from the plugin: {}", e))
+    }
+}
+
+impl Plugin for DylibPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn discover(&self) -> Result<Vec<PluginService>, String> {
+        let answer = self.send(serde_json::json!({ "op": "discover" }))?;
+        serde_json::from_value(answer).map_err(|e| format!("invalid discover answer: {}", e))
+    }
+
+    fn actions(&self, service: &ServiceInfo) -> Vec<PluginAction> {
+        let answer = self.send(serde_json::json!({ "op": "actions", "service": service }));
+        match answer.and_then(|answer| serde_json::from_value(answer).map_err(|e| e.to_string())) {
+            Ok(actions) => actions,
+            Err(e) => {
+                tracing::warn!("Plugin {} failed to list actions: {}", self.name, e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn run(&self, service: &ServiceInfo, action: &str) -> Result<String, String> {
+        let answer = self.send(serde_json::json!({
+            "op": "run",
+            "service": service,
+            "action": action,
+        }))?;
+        let result: RunResult =
+            serde_json::from_value(answer).map_err(|e| format!("invalid run answer: {}", e))?;
+        if result.ok {
+            Ok(result.message.unwrap_or_default())
+        } else {
+            Err(result
+                .error
+                .unwrap_or_else(|| "the action failed".to_string()))
+        }
+    }
+
+    fn request(&self, request: &PluginRequest) -> PluginResponse {
+        let answer = self.send(serde_json::json!({
+            "op": "request",
+            "method": request.method,
+            "path": request.path,
+            "query": request.query,
+            "body": request.body,
+        }));
+        match answer.and_then(|answer| serde_json::from_value(answer).map_err(|e| e.to_string())) {
+            Ok(response) => response,
+            Err(e) => PluginResponse {
+                status: StatusCode::BAD_GATEWAY.as_u16(),
+                body: serde_json::json!({ "error": e }),
+            },
+        }
+    }
+
+    fn widgets(&self) -> Vec<Widget> {
+        let answer = self.send(serde_json::json!({ "op": "widgets" }));
+        match answer.and_then(|answer| serde_json::from_value(answer).map_err(|e| e.to_string())) {
+            Ok(widgets) => widgets,
+            Err(e) => {
+                tracing::warn!("Plugin {} failed to list widgets: {}", self.name, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// The service of this name in the last scan
+async fn find_service(state: &AppState, service: &str) -> Option<ServiceInfo> {
+    state
+        .services()
+        .await
+        .services
+        .iter()
+        .find(|info| info.name == service)
+        .cloned()
+}
+
+/// List the loaded plugins and the libraries that failed to load
+pub async fn list_plugins() -> impl IntoResponse {
+    (StatusCode::OK, ApiResponse::success(registry().list()))
+}
+
+/// Actions the plugins offer for a service
+pub async fn service_actions(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    let Some(info) = find_service(&state, &service).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<Vec<PluginAction>>::error(&format!("Service '{}' not found", service)),
+        );
+    };
+    let actions = calls::blocking(move || {
+        registry()
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .actions(&info)
+                    .into_iter()
+                    .map(move |action| PluginAction {
+                        plugin: plugin.name().to_string(),
+                        ..action
+                    })
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+    (StatusCode::OK, ApiResponse::success(actions))
+}
+
+/// Run a plugin's action on a service and publish the event
+pub async fn run_action(
+    State(state): State<AppState>,
+    Path((service, plugin, action)): Path<(String, String, String)>,
+) -> impl IntoResponse {
+    let Some(info) = find_service(&state, &service).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Service '{}' not found", service)),
+        );
+    };
+    if registry().get(&plugin).is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("No plugin named '{}'", plugin)),
+        );
+    }
+
+    let (name, id) = (plugin.clone(), action.clone());
+    let result = calls::blocking(move || match registry().get(&name) {
+        Some(plugin) => plugin.run(&info, &id),
+        None => Err(format!("No plugin named '{}'", name)),
+    })
+    .await;
+    match result {
+        Ok(message) => {
+            let message = if message.is_empty() {
+                format!("Ran {} from {}", action, plugin)
+            } else {
+                message
+            };
+            state
+                .events
+                .publish(&service, events::PLUGIN_ACTION, message.clone());
+            state.store.request_refresh();
+            (
+                StatusCode::OK,
+                ApiResponse::success(serde_json::json!({ "message": message })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<serde_json::Value>::error(&e),
+        ),
+    }
+}
+
+/// Hand a request under `/api/plugins/{plugin}/` to the plugin
+pub async fn plugin_request(
+    Path((plugin, path)): Path<(String, String)>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> impl IntoResponse {
+    if registry().get(&plugin).is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("No plugin named '{}'", plugin) })),
+        );
+    }
+    let body = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(body) => body,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": format!("Invalid JSON body: {}", e) })),
+                );
+            }
+        }
+    };
+    let request = PluginRequest {
+        method: method.to_string(),
+        path,
+        query: uri.query().map(str::to_string),
+        body,
+    };
+
+    let response = calls::blocking(move || match registry().get(&plugin) {
+        Some(plugin) => plugin.request(&request),
+        None => PluginResponse::not_found(),
+    })
+    .await;
+    (
+        StatusCode::from_u16(response.status).unwrap_or(StatusCode::BAD_GATEWAY),
+        Json(response.body),
+    )
+}
@@ -0,0 +1,158 @@
+//! Persistent connections to daemon sockets.
+//!
+//! Socket calls check a connection out of a per-socket pool and hand it
+//! back afterwards instead of connecting for every call. Connections idle
+//! for longer than [`IDLE_TIMEOUT`] are closed, ones idle for longer than
+//! [`VALIDATE_AFTER`] are checked with a `health` call before being reused,
+//! and all of a socket's connections are dropped once the socket file is
+//! replaced, which means the daemon restarted. A connection that fails
+//! mid-call is discarded rather than returned.
+//!
+//! Like the metrics, the pool is process-wide, since socket calls are made
+//! from free functions.
+
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::metrics;
+use crate::transport::{self, Client};
+
+/// Idle connections older than this are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Idle connections older than this are health-checked before reuse
+const VALIDATE_AFTER: Duration = Duration::from_secs(10);
+
+/// Most idle connections kept per socket
+const MAX_IDLE: usize = 4;
+
+struct Idle {
+    client: Client,
+    since: Instant,
+}
+
+/// Idle connections to one socket
+struct Slot {
+    /// Modification time of the socket file the connections were made to
+    socket: Option<SystemTime>,
+    idle: Vec<Idle>,
+}
+
+static POOL: Mutex<BTreeMap<PathBuf, Slot>> = Mutex::new(BTreeMap::new());
+
+/// Close connections that have been idle too long
+fn prune(pool: &mut BTreeMap<PathBuf, Slot>) {
+    for slot in pool.values_mut() {
+        slot.idle
+            .retain(|idle| idle.since.elapsed() <= IDLE_TIMEOUT);
+    }
+    pool.retain(|_, slot| !slot.idle.is_empty());
+}
+
+/// A connection checked out of the pool, returned to it when dropped
+pub struct Connection {
+    socket_path: PathBuf,
+    socket: Option<SystemTime>,
+    client: Option<Client>,
+    /// Came from the pool rather than a fresh connect
+    pub reused: bool,
+}
+
+impl Connection {
+    /// Close the connection instead of returning it to the pool
+    ///
+    /// Call this when the connection itself failed, as opposed to the
+    /// daemon returning an error.
+    pub fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl Deref for Connection {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("connection used after discard")
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let mut pool = POOL.lock().unwrap();
+        prune(&mut pool);
+        let slot = pool
+            .entry(self.socket_path.clone())
+            .or_insert_with(|| Slot {
+                socket: self.socket,
+                idle: Vec::new(),
+            });
+        if slot.socket == self.socket && slot.idle.len() < MAX_IDLE {
+            slot.idle.push(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+/// Get a connection to the daemon at `socket_path`, reusing an idle one if
+/// possible
+pub fn checkout(socket_path: &Path) -> anyhow::Result<Connection> {
+    // Identifies the daemon instance behind the socket
+    let socket = transport::endpoint_stamp(socket_path);
+
+    loop {
+        let idle = {
+            let mut pool = POOL.lock().unwrap();
+            let slot = pool
+                .entry(socket_path.to_path_buf())
+                .or_insert_with(|| Slot {
+                    socket,
+                    idle: Vec::new(),
+                });
+            if slot.socket != socket {
+                tracing::debug!(
+                    "Socket {} was replaced, closing pooled connections",
+                    socket_path.display()
+                );
+                slot.socket = socket;
+                slot.idle.clear();
+            }
+            slot.idle.pop()
+        };
+        let Some(idle) = idle else {
+            break;
+        };
+
+        let idle_for = idle.since.elapsed();
+        if idle_for > IDLE_TIMEOUT {
+            continue;
+        }
+        if idle_for > VALIDATE_AFTER && !idle.client.health().is_ok_and(|r| r.ok) {
+            continue;
+        }
+
+        metrics::record_connection(true);
+        return Ok(Connection {
+            socket_path: socket_path.to_path_buf(),
+            socket,
+            client: Some(idle.client),
+            reused: true,
+        });
+    }
+
+    let client = Client::connect(socket_path)?;
+    metrics::record_connection(false);
+    Ok(Connection {
+        socket_path: socket_path.to_path_buf(),
+        socket,
+        client: Some(client),
+        reused: false,
+    })
+}
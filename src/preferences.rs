@@ -0,0 +1,134 @@
+//! UI preferences stored server-side.
+//!
+//! `GET /api/preferences` returns the stored preferences as a JSON object,
+//! `PUT /api/preferences` merges an object into them (a `null` value removes
+//! a key), and `DELETE /api/preferences/{key}` removes one. Keys are
+//! free-form so the UI can add settings without a dashboard upgrade, but the
+//! well-known ones are type-checked:
+//!
+//! - `refresh_interval`: seconds between UI refreshes (positive number)
+//! - `refresh_paused`: whether auto-refresh is paused (boolean)
+//! - `theme`: theme name (string)
+//! - `hidden_services`: services left off the dashboard (array of strings)
+//! - `default_sort`: sort key for the services list, prefixed with `-` for
+//!   descending order (string)
+//! - `group_by`: how to group the services list: `host`, `tag:<key>` or
+//!   `label:<key>` (string)
+//!
+//! Auth tokens (see [`auth`](crate::auth)) don't name users, so every
+//! client shares the `global` scope; rows are keyed by scope so per-user
+//! preferences only need a user to key them by.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::{Map, Value};
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::state::AppState;
+
+/// Scope shared by all clients
+pub const GLOBAL_SCOPE: &str = "global";
+
+/// Longest accepted key
+const MAX_KEY_LEN: usize = 64;
+
+/// Check a preference key and, for well-known keys, the value's type
+fn validate(key: &str, value: &Value) -> Result<(), String> {
+    if key.is_empty()
+        || key.len() > MAX_KEY_LEN
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(format!(
+            "Invalid preference key '{}': use up to {} letters, digits, '_', '-' or '.'",
+            key, MAX_KEY_LEN
+        ));
+    }
+    if value.is_null() {
+        return Ok(());
+    }
+
+    let ok = match key {
+        "refresh_interval" => value.as_f64().is_some_and(|seconds| seconds > 0.0),
+        "refresh_paused" => value.is_boolean(),
+        "theme" | "default_sort" | "group_by" => value.is_string(),
+        "hidden_services" => value
+            .as_array()
+            .is_some_and(|services| services.iter().all(Value::is_string)),
+        _ => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("Invalid value for preference '{}'", key))
+    }
+}
+
+/// Stored preferences
+pub async fn get_preferences(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.db.clone();
+    match calls::blocking(move || db.preferences(GLOBAL_SCOPE)).await {
+        Ok(preferences) => (StatusCode::OK, ApiResponse::success(preferences)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Map<String, Value>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Merge preferences, returning the result
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    Json(changes): Json<Map<String, Value>>,
+) -> impl IntoResponse {
+    if let Err(e) = changes
+        .iter()
+        .try_for_each(|(key, value)| validate(key, value))
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Map<String, Value>>::error(&e),
+        );
+    }
+
+    let db = state.db.clone();
+    let result = calls::blocking(move || {
+        db.update_preferences(GLOBAL_SCOPE, &changes)?;
+        db.preferences(GLOBAL_SCOPE)
+    })
+    .await;
+    match result {
+        Ok(preferences) => (StatusCode::OK, ApiResponse::success(preferences)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Map<String, Value>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Remove one preference
+pub async fn delete_preference(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    let db = state.db.clone();
+    let changes = Map::from_iter([(key.clone(), Value::Null)]);
+    match calls::blocking(move || db.update_preferences(GLOBAL_SCOPE, &changes)).await {
+        Ok(()) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({
+                "message": format!("Preference '{}' removed", key)
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Value>::error(&format!("{:#}", e)),
+        ),
+    }
+}
@@ -0,0 +1,67 @@
+//! Liveness and readiness probes for the dashboard itself.
+//!
+//! `/healthz` answers 200 as long as the process is serving requests.
+//! `/readyz` answers 503 until the first service scan has completed and the
+//! background tasks have been spawned.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::api::ApiResponse;
+use crate::state::AppState;
+
+/// Startup milestones `/readyz` waits for
+#[derive(Default)]
+pub struct Readiness {
+    scanned: AtomicBool,
+    tasks_started: AtomicBool,
+}
+
+impl Readiness {
+    pub fn mark_scanned(&self) {
+        self.scanned.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark_tasks_started(&self) {
+        self.tasks_started.store(true, Ordering::Relaxed);
+    }
+
+    fn checks(&self) -> ReadinessChecks {
+        ReadinessChecks {
+            first_scan: self.scanned.load(Ordering::Relaxed),
+            background_tasks: self.tasks_started.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReadinessChecks {
+    first_scan: bool,
+    background_tasks: bool,
+}
+
+/// Liveness probe
+pub async fn healthz() -> impl IntoResponse {
+    ApiResponse::success("ok")
+}
+
+/// Readiness probe
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let checks = state.readiness.checks();
+    if checks.first_scan && checks.background_tasks {
+        (StatusCode::OK, ApiResponse::success(checks))
+    } else {
+        let mut pending = Vec::new();
+        if !checks.first_scan {
+            pending.push("first service scan");
+        }
+        if !checks.background_tasks {
+            pending.push("background tasks");
+        }
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            ApiResponse::error(&format!("Waiting for {}", pending.join(" and "))),
+        )
+    }
+}
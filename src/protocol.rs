@@ -0,0 +1,78 @@
+//! FGP protocol version checks.
+//!
+//! Daemons report the protocol they speak as `protocol_version` in their
+//! health payload (`"1"`, `"1.2"` or a bare number). One whose major version
+//! differs from [`SUPPORTED_MAJOR`] is reported with the `incompatible`
+//! status, and stopping it, restarting it or calling its methods through the
+//! passthrough is refused with 409 Conflict rather than sent in a format it
+//! may misread. Daemons that don't report a version predate the field and
+//! are assumed compatible.
+//!
+//! `fgp_daemon` doesn't export the version its client speaks, so it is kept
+//! here and must be bumped along with the dependency.
+
+use crate::state::AppState;
+
+/// Major protocol version spoken by the `fgp_daemon` client
+pub const SUPPORTED_MAJOR: u64 = 1;
+
+/// Status of a daemon speaking another major version
+pub const INCOMPATIBLE: &str = "incompatible";
+
+/// The protocol version in a health payload, as reported
+pub fn reported_version(health: &serde_json::Value) -> Option<String> {
+    match &health["protocol_version"] {
+        serde_json::Value::String(version) => Some(version.trim().to_string()),
+        serde_json::Value::Number(version) => Some(version.to_string()),
+        _ => None,
+    }
+}
+
+/// Why a daemon speaking `version` can't be managed, if it can't
+pub fn mismatch(version: &str) -> Option<String> {
+    let major = version
+        .trim_start_matches(['v', 'V'])
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u64>().ok());
+    match major {
+        Some(SUPPORTED_MAJOR) => None,
+        Some(_) => Some(format!(
+            "daemon speaks FGP protocol {}, but this dashboard supports {}.x; \
+             upgrade whichever is older",
+            version, SUPPORTED_MAJOR
+        )),
+        None => Some(format!(
+            "daemon reports an unrecognised FGP protocol version '{}'",
+            version
+        )),
+    }
+}
+
+/// Start of the error [`check`] refuses with
+const REFUSAL: &str = "Refusing to act on";
+
+/// Whether an error is [`check`] refusing an incompatible daemon
+pub fn is_refusal(error: &str) -> bool {
+    error.starts_with(REFUSAL)
+}
+
+/// Refuse to act on a service last seen speaking an incompatible protocol
+pub async fn check(state: &AppState, service: &str) -> Result<(), String> {
+    let snapshot = state.services().await;
+    match snapshot
+        .services
+        .iter()
+        .find(|info| info.name == service && info.status == INCOMPATIBLE)
+    {
+        Some(info) => Err(format!(
+            "{} '{}': {}",
+            REFUSAL,
+            service,
+            info.probe_error
+                .as_deref()
+                .unwrap_or("incompatible protocol version")
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,87 @@
+//! QR code for opening the dashboard on a phone.
+//!
+//! `GET /api/qr` returns an SVG QR code of the dashboard's URL, as the
+//! requesting browser reached it. When tokens are required, the URL carries
+//! a one-time link token (see [`auth`](crate::auth)) that starts a session
+//! with the access of whoever asked for the code; the `X-Token-Expires-In`
+//! header says how many seconds it is good for. Open the dashboard by an
+//! address the phone can reach, not `localhost`, before showing the code.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use qrcode::render::svg;
+use qrcode::QrCode;
+
+use crate::api::ApiResponse;
+use crate::auth::{self, Principal};
+use crate::state::AppState;
+
+/// Smallest width and height of the image, in pixels
+const MIN_SIZE: u32 = 256;
+
+/// The dashboard's URL, with a link token when tokens are required
+pub async fn qr_code(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    // HTTP/2 requests name the host in the URI rather than a header
+    let Some(host) = uri
+        .authority()
+        .map(|authority| authority.to_string())
+        .or_else(|| {
+            headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(str::to_string)
+        })
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<()>::error("The request names no host to link to"),
+        )
+            .into_response();
+    };
+    let scheme = if state.tls { "https" } else { "http" };
+    let mut url = format!("{}://{}/", scheme, host);
+
+    let requires_token = auth::required(&state.settings());
+    if requires_token {
+        url.push_str(&format!("?token={}", state.grants.link(principal)));
+    }
+
+    let code = match QrCode::new(url.as_bytes()) {
+        Ok(code) => code,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ApiResponse::<()>::error(&format!("Failed to encode {}: {}", host, e)),
+            )
+                .into_response()
+        }
+    };
+    let image = code
+        .render::<svg::Color>()
+        .min_dimensions(MIN_SIZE, MIN_SIZE)
+        .build();
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml".to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        image,
+    )
+        .into_response();
+    if requires_token {
+        response
+            .headers_mut()
+            .insert("x-token-expires-in", auth::LINK_TTL.as_secs().into());
+    }
+    response
+}
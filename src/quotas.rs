@@ -0,0 +1,367 @@
+//! Quotas per token and per namespace.
+//!
+//! `[quotas]` in the config file limits each token, and the `quotas` of a
+//! `[[namespaces]]` section limit everything done under that namespace's
+//! `/api/ns/{ns}/` routes, whichever token does it:
+//!
+//! ```toml
+//! [quotas]
+//! requests_per_minute = 600
+//! control_per_hour = 100
+//!
+//! [[namespaces]]
+//! name = "payments"
+//! services = ["payments-*"]
+//! quotas = { control_per_hour = 20, concurrent_calls = 4 }
+//! ```
+//!
+//! Control operations are the start/stop, annotation, config and other
+//! changes of the control routes; passthrough calls are `/api/call` and
+//! `/api/methods`. Requests over a quota get 429 with `Retry-After`. Each
+//! session started from a one-time link counts as a token of its own, and
+//! token quotas only apply when tokens are configured.
+//!
+//! `GET /api/admin/quotas` shows the limits and usage. Counters are kept in
+//! memory and start over when the dashboard restarts.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::auth::{Principal, TokenId};
+use crate::events;
+use crate::namespaces::Scope;
+use crate::state::{AppState, Settings};
+
+/// Limits of one token or namespace; unset ones don't apply
+#[derive(Deserialize, Serialize, Clone, Copy, Default, TS)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    /// Requests of any kind per minute
+    pub requests_per_minute: Option<u32>,
+    /// Control operations in any hour
+    pub control_per_hour: Option<u32>,
+    /// Passthrough calls in flight at once
+    pub concurrent_calls: Option<u32>,
+}
+
+#[derive(Default)]
+struct Counter {
+    /// Namespace of the token, for tokens of one
+    namespace: Option<String>,
+    /// Minute of the current request count, as Unix minutes
+    minute: u64,
+    requests: u32,
+    /// Unix times of the control operations in the last hour
+    control: VecDeque<u64>,
+    calls: u32,
+    rejected: u64,
+}
+
+/// What a token or namespace is counted as
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Subject {
+    Token(String),
+    Namespace(String),
+}
+
+/// Usage of every token and namespace since startup
+#[derive(Default)]
+pub struct QuotaTracker {
+    counters: Mutex<HashMap<Subject, Counter>>,
+}
+
+/// A request over quota, with the seconds until it could succeed
+struct Exceeded(String, u64);
+
+impl Exceeded {
+    fn reply(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, self.1.to_string())],
+            ApiResponse::<()>::error(&self.0),
+        )
+            .into_response()
+    }
+}
+
+fn describe(subject: &Subject) -> String {
+    match subject {
+        Subject::Token(_) => "this token".to_string(),
+        Subject::Namespace(name) => format!("namespace '{}'", name),
+    }
+}
+
+/// Who a request counts against, with their limits
+fn subjects(settings: &Settings, req: &Request) -> Vec<(Subject, Quotas, Option<String>)> {
+    let mut subjects = Vec::new();
+    if let Some(TokenId(id)) = req.extensions().get::<TokenId>() {
+        let namespace = match req.extensions().get::<Principal>() {
+            Some(Principal::Namespace(name)) => Some(name.clone()),
+            _ => None,
+        };
+        subjects.push((Subject::Token(id.clone()), settings.quotas, namespace));
+    }
+    if let Some(Scope(name)) = req.extensions().get::<Scope>() {
+        if let Some(namespace) = settings.namespace(name) {
+            subjects.push((Subject::Namespace(name.clone()), namespace.quotas, None));
+        }
+    }
+    subjects
+}
+
+impl QuotaTracker {
+    /// Count one request or control operation, unless a quota forbids it
+    fn count(
+        &self,
+        subjects: &[(Subject, Quotas, Option<String>)],
+        control: bool,
+    ) -> Result<(), Exceeded> {
+        let now = events::now();
+        let minute = now / 60;
+        let mut counters = self.counters.lock().unwrap();
+
+        // Check every subject before counting against any of them
+        for (subject, quotas, namespace) in subjects {
+            let counter = counters.entry(subject.clone()).or_default();
+            counter.namespace.clone_from(namespace);
+            if counter.minute != minute {
+                counter.minute = minute;
+                counter.requests = 0;
+            }
+            while counter.control.front().is_some_and(|at| *at + 3600 <= now) {
+                counter.control.pop_front();
+            }
+
+            if let (false, Some(limit)) = (control, quotas.requests_per_minute) {
+                if counter.requests >= limit {
+                    counter.rejected += 1;
+                    return Err(Exceeded(
+                        format!(
+                            "Quota exceeded: {} requests per minute for {}",
+                            limit,
+                            describe(subject)
+                        ),
+                        60 - now % 60,
+                    ));
+                }
+            }
+            if let (true, Some(limit)) = (control, quotas.control_per_hour) {
+                if counter.control.len() >= limit as usize {
+                    counter.rejected += 1;
+                    let oldest = counter.control.front().copied().unwrap_or(now);
+                    return Err(Exceeded(
+                        format!(
+                            "Quota exceeded: {} control operations per hour for {}",
+                            limit,
+                            describe(subject)
+                        ),
+                        (oldest + 3600).saturating_sub(now).max(1),
+                    ));
+                }
+            }
+        }
+
+        for (subject, _, _) in subjects {
+            let counter = counters.entry(subject.clone()).or_default();
+            if control {
+                counter.control.push_back(now);
+            } else {
+                counter.requests += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a passthrough call, unless too many are in flight
+    fn start_call(
+        self: &Arc<Self>,
+        subjects: Vec<(Subject, Quotas, Option<String>)>,
+    ) -> Result<CallGuard, Exceeded> {
+        let mut counters = self.counters.lock().unwrap();
+        for (subject, quotas, _) in &subjects {
+            let counter = counters.entry(subject.clone()).or_default();
+            if let Some(limit) = quotas.concurrent_calls {
+                if counter.calls >= limit {
+                    counter.rejected += 1;
+                    return Err(Exceeded(
+                        format!(
+                            "Quota exceeded: {} concurrent calls for {}",
+                            limit,
+                            describe(subject)
+                        ),
+                        1,
+                    ));
+                }
+            }
+        }
+        for (subject, _, _) in &subjects {
+            counters.entry(subject.clone()).or_default().calls += 1;
+        }
+        Ok(CallGuard {
+            tracker: self.clone(),
+            subjects: subjects
+                .into_iter()
+                .map(|(subject, _, _)| subject)
+                .collect(),
+        })
+    }
+}
+
+/// Ends a passthrough call when dropped
+struct CallGuard {
+    tracker: Arc<QuotaTracker>,
+    subjects: Vec<Subject>,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        let mut counters = self.tracker.counters.lock().unwrap();
+        for subject in &self.subjects {
+            if let Some(counter) = counters.get_mut(subject) {
+                counter.calls = counter.calls.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Enforce `requests_per_minute`
+pub async fn rate(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let subjects = subjects(&state.settings(), &req);
+    if subjects.is_empty() {
+        return next.run(req).await;
+    }
+    match state.quotas.count(&subjects, false) {
+        Ok(()) => next.run(req).await,
+        Err(exceeded) => exceeded.reply(),
+    }
+}
+
+/// Enforce `control_per_hour` on changes made through the control routes
+pub async fn control(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+    let subjects = subjects(&state.settings(), &req);
+    if subjects.is_empty() {
+        return next.run(req).await;
+    }
+    match state.quotas.count(&subjects, true) {
+        Ok(()) => next.run(req).await,
+        Err(exceeded) => exceeded.reply(),
+    }
+}
+
+/// Enforce `concurrent_calls` on the passthrough routes
+pub async fn calls(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let subjects = subjects(&state.settings(), &req);
+    if subjects.is_empty() {
+        return next.run(req).await;
+    }
+    match state.quotas.start_call(subjects) {
+        Ok(_guard) => next.run(req).await,
+        Err(exceeded) => exceeded.reply(),
+    }
+}
+
+/// Usage of one token or namespace
+#[derive(Serialize, TS)]
+pub struct QuotaUsage {
+    /// Start of the token's SHA-256, or the namespace name
+    pub id: String,
+    /// Namespace of a namespace's token
+    pub namespace: Option<String>,
+    pub limits: Quotas,
+    pub requests_this_minute: u32,
+    pub control_last_hour: u32,
+    pub calls_in_flight: u32,
+    /// Requests refused for being over quota
+    #[ts(type = "number")]
+    pub rejected: u64,
+}
+
+#[derive(Serialize, TS)]
+pub struct QuotaReport {
+    pub tokens: Vec<QuotaUsage>,
+    pub namespaces: Vec<QuotaUsage>,
+}
+
+/// Limits and usage of every token and namespace seen since startup
+pub async fn usage(State(state): State<AppState>) -> impl IntoResponse {
+    let settings = state.settings();
+    let now = events::now();
+    let mut report = QuotaReport {
+        tokens: Vec::new(),
+        namespaces: Vec::new(),
+    };
+    for (subject, counter) in state.quotas.counters.lock().unwrap().iter() {
+        let requests_this_minute = if counter.minute == now / 60 {
+            counter.requests
+        } else {
+            0
+        };
+        let control_last_hour = counter
+            .control
+            .iter()
+            .filter(|at| **at + 3600 > now)
+            .count() as u32;
+        let (id, namespace, limits, list) = match subject {
+            Subject::Token(id) => (
+                id.clone(),
+                counter.namespace.clone(),
+                settings.quotas,
+                &mut report.tokens,
+            ),
+            Subject::Namespace(name) => (
+                name.clone(),
+                None,
+                settings
+                    .namespace(name)
+                    .map(|namespace| namespace.quotas)
+                    .unwrap_or_default(),
+                &mut report.namespaces,
+            ),
+        };
+        list.push(QuotaUsage {
+            id,
+            namespace,
+            limits,
+            requests_this_minute,
+            control_last_hour,
+            calls_in_flight: counter.calls,
+            rejected: counter.rejected,
+        });
+    }
+    report.tokens.sort_by(|a, b| a.id.cmp(&b.id));
+    report.namespaces.sort_by(|a, b| a.id.cmp(&b.id));
+    ApiResponse::success(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_operations_count_against_their_own_quota() {
+        let tracker = QuotaTracker::default();
+        let quotas = Quotas {
+            requests_per_minute: Some(1),
+            control_per_hour: Some(1),
+            concurrent_calls: None,
+        };
+        let subjects = [(Subject::Token("ci".to_string()), quotas, None)];
+
+        assert!(tracker.count(&subjects, false).is_ok());
+        assert!(tracker.count(&subjects, true).is_ok());
+        assert!(tracker.count(&subjects, true).is_err());
+        assert!(tracker.count(&subjects, false).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Masking secrets before they are shown.
+//!
+//! Values of keys whose name contains one of the `--redact-key` patterns
+//! (case-insensitive; `password`, `token`, `secret` and `key` by default)
+//! are replaced with [`MARKER`] in health payloads, `/api/call` results,
+//! widget data, config file views, log tails and the access log. JSON is
+//! masked field by field; text is masked wherever a matching name is
+//! followed by `=` or `:` and a value, as in `token=abc`, `password: abc`
+//! or `"secret": "abc"`.
+//!
+//! Saving a config file that still holds markers puts the original values
+//! back, so secrets survive edits made through the dashboard.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// What masked values are replaced with
+pub const MARKER: &str = "[redacted]";
+
+/// Patterns used when none are configured
+pub const DEFAULT_KEYS: &[&str] = &["password", "token", "secret", "key"];
+
+/// Masks values of secret-looking keys
+#[derive(Clone)]
+pub struct Redactor {
+    /// Lowercase substrings of secret key names
+    keys: Vec<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEYS.iter().map(|key| key.to_string()).collect())
+    }
+}
+
+fn is_name(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-' | b'.')
+}
+
+impl Redactor {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| key.trim().to_ascii_lowercase())
+                .filter(|key| !key.is_empty())
+                .collect(),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.keys.iter().any(|key| name.contains(key.as_str()))
+    }
+
+    /// Mask string and number fields of matching keys, at any depth
+    pub fn json(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, value) in fields.iter_mut() {
+                    if self.matches(name) && matches!(value, Value::String(_) | Value::Number(_)) {
+                        *value = Value::String(MARKER.to_string());
+                    } else {
+                        self.json(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.json(item)),
+            _ => {}
+        }
+    }
+
+    /// Secret values in text, with the lowercase name of their key
+    fn spans(&self, text: &str) -> Vec<(String, Range<usize>)> {
+        let bytes = text.as_bytes();
+        let mut spans = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if !is_name(bytes[i]) || (i > 0 && is_name(bytes[i - 1])) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < bytes.len() && is_name(bytes[i]) {
+                i += 1;
+            }
+            let name = &text[start..i];
+            if !self.matches(name) {
+                continue;
+            }
+
+            // An optional closing quote, then `=` or `:` but not `==` or `::`
+            let mut j = i;
+            if matches!(bytes.get(j), Some(b'"' | b'\'')) {
+                j += 1;
+            }
+            while matches!(bytes.get(j), Some(b' ' | b'\t')) {
+                j += 1;
+            }
+            if !matches!(bytes.get(j), Some(b'=' | b':'))
+                || matches!(bytes.get(j + 1), Some(b'=' | b':'))
+            {
+                continue;
+            }
+            j += 1;
+            while matches!(bytes.get(j), Some(b' ' | b'\t')) {
+                j += 1;
+            }
+
+            let value = match bytes.get(j) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let mut end = j + 1;
+                    while end < bytes.len() && bytes[end] != quote && bytes[end] != b'\n' {
+                        if bytes[end] == b'\\' {
+                            end += 1;
+                        }
+                        end += 1;
+                    }
+                    j + 1..end.min(bytes.len())
+                }
+                // A bare marker, as left by masking an unquoted value
+                Some(b'[') if text[j..].starts_with(MARKER) => j..j + MARKER.len(),
+                // Tables and arrays are masked by their own keys, if at all
+                Some(b'{' | b'[') | None => continue,
+                Some(_) => {
+                    let mut end = j;
+                    while end < bytes.len()
+                        && !bytes[end].is_ascii_whitespace()
+                        && !matches!(
+                            bytes[end],
+                            b',' | b';' | b'&' | b')' | b'}' | b']' | b'"' | b'\''
+                        )
+                    {
+                        end += 1;
+                    }
+                    j..end
+                }
+            };
+            if !value.is_empty() {
+                i = value.end;
+                spans.push((name.to_ascii_lowercase(), value));
+            }
+        }
+        spans
+    }
+
+    /// Mask secret values in text
+    pub fn text(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut copied = 0;
+        for (_, value) in self.spans(text) {
+            masked.push_str(&text[copied..value.start]);
+            masked.push_str(MARKER);
+            copied = value.end;
+        }
+        masked.push_str(&text[copied..]);
+        masked
+    }
+
+    /// Put back the values of `original` that `edited` still shows masked
+    ///
+    /// The nth masked value of a key takes the nth value of that key in
+    /// `original`.
+    pub fn restore(&self, edited: &str, original: &str) -> String {
+        let mut values: HashMap<String, Vec<&str>> = HashMap::new();
+        for (name, value) in self.spans(original) {
+            values.entry(name).or_default().push(&original[value]);
+        }
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut restored = String::with_capacity(edited.len());
+        let mut copied = 0;
+        for (name, value) in self.spans(edited) {
+            let index = seen.entry(name.clone()).or_default();
+            let previous = values.get(&name).and_then(|values| values.get(*index));
+            *index += 1;
+            if let (MARKER, Some(previous)) = (&edited[value.clone()], previous) {
+                restored.push_str(&edited[copied..value.start]);
+                restored.push_str(previous);
+                copied = value.end;
+            }
+        }
+        restored.push_str(&edited[copied..]);
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_text_values() {
+        let redactor = Redactor::default();
+        assert_eq!(
+            redactor.text("/api/services?token=abc&page=2"),
+            "/api/services?token=[redacted]&page=2"
+        );
+        assert_eq!(
+            redactor.text("password: hunter2\nuser: admin"),
+            "password: [redacted]\nuser: admin"
+        );
+        assert_eq!(
+            redactor.text(r#"{"client_secret": "s3\"cr3t", "id": 1}"#),
+            r#"{"client_secret": "[redacted]", "id": 1}"#
+        );
+        assert_eq!(redactor.text("API_KEY = 'abc'"), "API_KEY = '[redacted]'");
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        let redactor = Redactor::default();
+        for text in [
+            "token == expected",
+            "Token::parse",
+            "tokens = []",
+            "secret = { path = \"x\" }",
+            "no secrets here",
+        ] {
+            assert_eq!(redactor.text(text), text);
+        }
+        assert_eq!(
+            Redactor::new(vec!["pin".into()]).text("token=abc"),
+            "token=abc"
+        );
+    }
+
+    #[test]
+    fn restores_masked_values() {
+        let redactor = Redactor::default();
+        let original = "password = \"hunter2\"\nport = 80\napi_key = 1234\n";
+        let edited = redactor.text(original).replace("80", "81");
+        assert_eq!(
+            edited,
+            "password = \"[redacted]\"\nport = 81\napi_key = [redacted]\n"
+        );
+        assert_eq!(
+            redactor.restore(&edited, original),
+            "password = \"hunter2\"\nport = 81\napi_key = 1234\n"
+        );
+    }
+
+    #[test]
+    fn restores_repeated_keys_in_order() {
+        let redactor = Redactor::default();
+        let original = "[a]\ntoken = \"one\"\n[b]\ntoken = \"two\"\n";
+        let edited = "[a]\ntoken = \"[redacted]\"\n[b]\ntoken = \"new\"\n";
+        assert_eq!(
+            redactor.restore(edited, original),
+            "[a]\ntoken = \"one\"\n[b]\ntoken = \"new\"\n"
+        );
+        // A marker with no original value stays as it is
+        assert_eq!(
+            redactor.restore("secret = \"[redacted]\"", "port = 1"),
+            "secret = \"[redacted]\""
+        );
+    }
+
+    #[test]
+    fn masks_json_fields() {
+        let mut value = serde_json::json!({
+            "db": { "Password": "hunter2", "port": 5432 },
+            "tokens": [{ "token": 42 }],
+            "keys": ["a", "b"],
+        });
+        Redactor::default().json(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "db": { "Password": MARKER, "port": 5432 },
+                "tokens": [{ "token": MARKER }],
+                "keys": ["a", "b"],
+            })
+        );
+    }
+}
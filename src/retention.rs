@@ -0,0 +1,141 @@
+//! Retention and downsampling of the health history.
+//!
+//! A background task rolls raw health samples up into 5-minute buckets and
+//! those into hourly buckets, then deletes whatever has outlived its tier's
+//! retention. Defaults keep raw samples for 48 hours, 5-minute rollups for
+//! 30 days, hourly rollups for a year and events for 90 days; override them
+//! with `--retention raw=24h,1h=2y` or a `[retention]` table in the config
+//! file.
+
+use anyhow::Result;
+use rusqlite::params;
+use std::time::Duration;
+
+use crate::calls;
+use crate::db::Database;
+use crate::events;
+use crate::state::AppState;
+
+/// How often the compaction task runs
+pub const COMPACT_EVERY: Duration = Duration::from_secs(5 * 60);
+
+/// Rollup resolutions, in seconds
+const FIVE_MINUTES: u64 = 5 * 60;
+const HOUR: u64 = 60 * 60;
+
+const DAY: u64 = 24 * HOUR;
+
+/// How long each tier of history is kept
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    pub raw: Duration,
+    pub five_minute: Duration,
+    pub hourly: Duration,
+    pub events: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            raw: Duration::from_secs(2 * DAY),
+            five_minute: Duration::from_secs(30 * DAY),
+            hourly: Duration::from_secs(365 * DAY),
+            events: Duration::from_secs(90 * DAY),
+        }
+    }
+}
+
+impl Policy {
+    /// Override one tier: `raw`, `5m`, `1h` or `events`
+    pub fn set(&mut self, tier: &str, keep: Duration) -> Result<(), String> {
+        match tier {
+            "raw" => self.raw = keep,
+            "5m" => self.five_minute = keep,
+            "1h" => self.hourly = keep,
+            "events" => self.events = keep,
+            _ => {
+                return Err(format!(
+                    "unknown retention tier '{}', expected raw, 5m, 1h or events",
+                    tier
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Roll up recent samples and prune expired history
+fn compact(db: &Database, now: u64, policy: &Policy) -> Result<()> {
+    // Recompute the buckets the last few passes may have left incomplete
+    let lookback = 2 * COMPACT_EVERY.as_secs();
+    let five_minute_from =
+        now.saturating_sub(lookback + FIVE_MINUTES) / FIVE_MINUTES * FIVE_MINUTES;
+    let hourly_from = now.saturating_sub(lookback + HOUR) / HOUR * HOUR;
+
+    db.with(|conn| {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO health_rollups
+                 (resolution, bucket, service, samples, healthy, max_uptime_seconds,
+                  avg_latency_ms, avg_cpu_percent, max_memory_bytes)
+             SELECT ?1, timestamp / ?1 * ?1, service, COUNT(*),
+                    SUM(status IN ('running', 'healthy')), MAX(uptime_seconds),
+                    AVG(latency_ms), AVG(cpu_percent), MAX(memory_bytes)
+             FROM health_samples WHERE timestamp >= ?2
+             GROUP BY timestamp / ?1, service",
+            params![FIVE_MINUTES, five_minute_from],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO health_rollups
+                 (resolution, bucket, service, samples, healthy, max_uptime_seconds,
+                  avg_latency_ms, avg_cpu_percent, max_memory_bytes)
+             SELECT ?1, bucket / ?1 * ?1, service, SUM(samples), SUM(healthy),
+                    MAX(max_uptime_seconds),
+                    SUM(avg_latency_ms * samples)
+                        / SUM(IIF(avg_latency_ms IS NULL, 0, samples)),
+                    SUM(avg_cpu_percent * samples)
+                        / SUM(IIF(avg_cpu_percent IS NULL, 0, samples)),
+                    MAX(max_memory_bytes)
+             FROM health_rollups WHERE resolution = ?2 AND bucket >= ?3
+             GROUP BY bucket / ?1, service",
+            params![HOUR, FIVE_MINUTES, hourly_from],
+        )?;
+
+        let cutoff = |keep: Duration| now.saturating_sub(keep.as_secs());
+        let raw = tx.execute(
+            "DELETE FROM health_samples WHERE timestamp < ?1",
+            [cutoff(policy.raw)],
+        )?;
+        let rollups = tx.execute(
+            "DELETE FROM health_rollups WHERE resolution = ?1 AND bucket < ?2",
+            params![FIVE_MINUTES, cutoff(policy.five_minute)],
+        )? + tx.execute(
+            "DELETE FROM health_rollups WHERE resolution = ?1 AND bucket < ?2",
+            params![HOUR, cutoff(policy.hourly)],
+        )?;
+        let events = tx.execute(
+            "DELETE FROM events WHERE timestamp < ?1",
+            [cutoff(policy.events)],
+        )?;
+        // Task runs are kept as long as events
+        let runs = tx.execute(
+            "DELETE FROM task_runs WHERE started_at < ?1",
+            [cutoff(policy.events)],
+        )?;
+        tx.commit()?;
+
+        tracing::debug!(raw, rollups, events, runs, "Compacted history");
+        Ok(())
+    })
+}
+
+/// Compact the history once; the scheduler runs this every
+/// [`COMPACT_EVERY`]
+pub async fn compact_history(state: &AppState) -> Result<String, String> {
+    let db = state.db.clone();
+    let policy = state.settings().retention;
+    calls::blocking(move || compact(&db, events::now(), &policy))
+        .await
+        .map(|()| "Compacted history".to_string())
+        .map_err(|e| format!("History compaction failed: {:#}", e))
+}
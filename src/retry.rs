@@ -0,0 +1,64 @@
+//! Retrying daemon calls that failed for transient reasons.
+//!
+//! A socket refusing connections right after its daemon (re)started, or
+//! answering EAGAIN while its backlog is full (or, on Windows, a named pipe
+//! with every instance busy), usually works a moment later. Such failures are retried up to `--socket-retries` times (2 by
+//! default) with jittered exponential backoff before being reported; other
+//! errors are reported straight away.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+use crate::transport;
+
+/// Retries used when none are configured
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Delay before the first retry; doubles with every attempt
+const BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Whether an error is likely to go away if the call is repeated
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<io::Error>().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::Interrupted
+            ) || transport::is_busy(e)
+        })
+    })
+}
+
+/// Delay before retry number `attempt` (0-based), between half and all of
+/// the exponential step
+fn backoff(attempt: u32) -> Duration {
+    let step = BASE_DELAY * 2u32.pow(attempt.min(6));
+    let random = RandomState::new().build_hasher().finish();
+    step / 2 + step.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+/// Run `f` until it succeeds, fails for good, or runs out of retries
+///
+/// Blocks between attempts, so call it off the async runtime.
+pub fn retry<T, E>(
+    retries: u32,
+    mut f: impl FnMut() -> Result<T, E>,
+    transient: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(ref e) if attempt < retries && transient(e) => {
+                let delay = backoff(attempt);
+                tracing::debug!(attempt, "Transient socket error, retrying in {:?}", delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Generic RPC passthrough to daemons.
+//!
+//! `POST /api/call/{service}/{method}` forwards a JSON params payload to the
+//! daemon over its FGP socket and returns the raw result. Which methods may
+//! be called is controlled by `--allow-method` / `--deny-method` patterns.
+//!
+//! Results larger than 64KB are streamed to the client in chunks, and
+//! results larger than `--max-rpc-response-size` (64MB by default) are not
+//! sent. The limit only caps what goes to the client: `fgp_daemon`'s client
+//! reads each result whole, so the dashboard holds one parsed copy of
+//! however much the daemon answers, but never a serialized one.
+//!
+//! Each response carries the call's timing breakdown (see
+//! [`timing`](crate::timing)) in a `Server-Timing` header.
+//!
+//! Binary params and results are converted to and from base64 by
+//! [`binary`](crate::binary).
+//!
+//! `GET /api/methods/{service}` lists the methods a daemon advertises through
+//! its built-in `methods` call, for daemons that support introspection.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path as FsPath;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use crate::api::ApiResponse;
+use crate::binary::{self, CallQuery};
+use crate::calls::{self, BackendCall};
+use crate::metrics;
+use crate::pool;
+use crate::protocol;
+use crate::retry;
+use crate::sockets;
+use crate::state::AppState;
+use crate::timing::{self, CallTiming};
+use crate::transport;
+
+/// Result size limit used when none is configured
+pub const DEFAULT_MAX_RESPONSE_SIZE: u64 = 64 << 20;
+
+/// Results up to this size are sent in one piece; larger ones are streamed
+/// in chunks of this size
+pub const CHUNK_SIZE: usize = 64 << 10;
+
+/// Allow/deny rules for passthrough methods
+///
+/// Patterns match either `method` or `service.method`, and may end in `*` to
+/// match a prefix. The denylist always wins; an empty allowlist allows every
+/// method that isn't denied.
+#[derive(Default)]
+pub struct MethodPolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl MethodPolicy {
+    pub fn permits(&self, service: &str, method: &str) -> bool {
+        let qualified = format!("{}.{}", service, method);
+        let matches = |pattern: &String| {
+            pattern_matches(pattern, method) || pattern_matches(pattern, &qualified)
+        };
+
+        if self.deny.iter().any(matches) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(matches)
+    }
+}
+
+fn pattern_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+/// Call a daemon method and return its result
+///
+/// Only connecting is retried on transient errors, since methods need not
+/// be safe to repeat.
+pub fn call(
+    socket_path: &FsPath,
+    method: &str,
+    params: serde_json::Value,
+    retries: u32,
+) -> Result<serde_json::Value, String> {
+    call_timed(socket_path, method, params, retries).0
+}
+
+/// Like [`call`], also returning where the time went
+pub fn call_timed(
+    socket_path: &FsPath,
+    method: &str,
+    params: serde_json::Value,
+    retries: u32,
+) -> (Result<serde_json::Value, String>, CallTiming) {
+    tracing::debug!(method, "Calling daemon");
+    let _call = BackendCall::start(format!("{} on {}", method, socket_path.display()));
+    let mut timing = CallTiming::default();
+    let started = Instant::now();
+    let client = retry::retry(
+        retries,
+        || {
+            timing.attempts += 1;
+            pool::checkout(socket_path)
+        },
+        retry::is_transient,
+    );
+    timing.connected(started);
+
+    let result = client
+        .map_err(|e| sockets::explain(socket_path, e))
+        .and_then(|client| {
+            timing.reused = client.reused;
+            let started = Instant::now();
+            let response = client.call(method, params);
+            timing.answered(started);
+            match response {
+                Ok(response) if response.ok => Ok(response.result.unwrap_or_default()),
+                Ok(response) => Err(response.error.unwrap_or_default()),
+                Err(e) => {
+                    client.discard();
+                    Err(e.to_string())
+                }
+            }
+        });
+    metrics::record_socket_call(result.is_ok());
+    (result, timing)
+}
+
+/// Add a call's `Server-Timing` header to a response
+pub fn with_timing(mut response: Response, timing: &CallTiming) -> Response {
+    if let Ok(value) = HeaderValue::from_str(&timing.header_value()) {
+        response
+            .headers_mut()
+            .insert(timing::SERVER_TIMING_HEADER, value);
+    }
+    response
+}
+
+/// Forward an arbitrary method call to a daemon
+pub async fn call_method(
+    State(state): State<AppState>,
+    Path((service, method)): Path<(String, String)>,
+    Query(query): Query<CallQuery>,
+    request: Request,
+) -> Response {
+    let settings = state.settings();
+    if !settings.methods.permits(&service, &method) {
+        return (
+            StatusCode::FORBIDDEN,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "Method '{}' is not allowed on '{}'",
+                method, service
+            )),
+        )
+            .into_response();
+    }
+
+    if let Err(e) = protocol::check(&state, &service).await {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<serde_json::Value>::error(&e),
+        )
+            .into_response();
+    }
+
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"multipart/form-data"));
+    let params = if is_multipart {
+        let multipart = match Multipart::from_request(request, &state).await {
+            Ok(multipart) => multipart,
+            Err(e) => return e.into_response(),
+        };
+        match binary::params_from_multipart(multipart).await {
+            Ok(params) => params,
+            Err(response) => return response,
+        }
+    } else {
+        let body = match Bytes::from_request(request, &state).await {
+            Ok(body) => body,
+            Err(e) => return e.into_response(),
+        };
+        if body.is_empty() {
+            serde_json::json!({})
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(params) => params,
+                Err(e) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        ApiResponse::<serde_json::Value>::error(&format!("Invalid params: {}", e)),
+                    )
+                        .into_response()
+                }
+            }
+        }
+    };
+
+    if let Some(fleet) = &state.demo {
+        return match fleet.call(&service, &method, params) {
+            Ok(result) => send_result(result, settings.max_rpc_response_size).await,
+            Err(e) => (
+                StatusCode::BAD_GATEWAY,
+                ApiResponse::<serde_json::Value>::error(&e),
+            )
+                .into_response(),
+        };
+    }
+
+    let Some(socket_path) = settings.socket_path(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Service '{}' not found", service)),
+        )
+            .into_response();
+    };
+    if !transport::endpoint_exists(&socket_path) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "Service '{}' is not running",
+                service
+            )),
+        )
+            .into_response();
+    }
+
+    let retries = settings.socket_retries;
+    let filename = query
+        .filename
+        .unwrap_or_else(|| format!("{}.{}.bin", service, method));
+    let (result, timing) =
+        calls::blocking(move || call_timed(&socket_path, &method, params, retries)).await;
+    let response = match result {
+        Ok(mut result) => match query.download {
+            Some(pointer) => {
+                binary::send_download(result, &pointer, filename, settings.max_rpc_response_size)
+                    .await
+            }
+            None => {
+                settings.redact.json(&mut result);
+                send_result(result, settings.max_rpc_response_size).await
+            }
+        },
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            ApiResponse::<serde_json::Value>::error(&e),
+        )
+            .into_response(),
+    };
+    with_timing(response, &timing)
+}
+
+/// Counts serialized bytes, failing once past a limit
+struct SizeCounter {
+    written: u64,
+    limit: u64,
+}
+
+impl Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        if self.written > self.limit {
+            return Err(io::Error::other("result too large"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends serialized bytes to the response body in chunks
+struct ChunkSender {
+    buf: Vec<u8>,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(CHUNK_SIZE),
+        ));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::other("client went away"))
+    }
+}
+
+/// Send a call's result, streaming it if it is large
+///
+/// The result is measured before anything is sent, so an oversized one gets
+/// a clean error and a streamed one a `Content-Length`.
+async fn send_result(result: serde_json::Value, limit: u64) -> Response {
+    let payload = ApiResponse::success(result).0;
+    let (payload, size) = calls::blocking(move || {
+        let mut counter = SizeCounter { written: 0, limit };
+        let size = serde_json::to_writer(&mut counter, &payload).map(|_| counter.written);
+        (payload, size)
+    })
+    .await;
+
+    let size = match size {
+        Ok(size) => size,
+        Err(_) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                ApiResponse::<serde_json::Value>::error(&format!(
+                    "Result exceeds the {} byte limit (--max-rpc-response-size)",
+                    limit
+                )),
+            )
+                .into_response()
+        }
+    };
+    if size <= CHUNK_SIZE as u64 {
+        return (StatusCode::OK, axum::Json(payload)).into_response();
+    }
+
+    let (tx, rx) = mpsc::channel(4);
+    tokio::task::spawn_blocking(move || {
+        let mut sender = ChunkSender {
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            tx,
+        };
+        if serde_json::to_writer(&mut sender, &payload).is_ok() {
+            let _ = sender.flush();
+        }
+    });
+    let chunks = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+/// A method advertised by a daemon
+#[derive(Serialize)]
+pub struct MethodInfo {
+    pub name: String,
+    pub description: Option<String>,
+    /// Parameter schema, if the daemon provides one
+    pub params: Option<serde_json::Value>,
+    /// Whether the passthrough policy allows calling it
+    pub allowed: bool,
+}
+
+/// Parse the result of a daemon's `methods` call
+///
+/// Daemons may return either a list of names or a list of objects with
+/// `name`, `description` and `params` (or `schema`) fields.
+pub fn parse_methods(result: &serde_json::Value) -> Vec<MethodInfo> {
+    let entries = result
+        .get("methods")
+        .unwrap_or(result)
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            serde_json::Value::String(name) => Some(MethodInfo {
+                name,
+                description: None,
+                params: None,
+                allowed: false,
+            }),
+            serde_json::Value::Object(mut fields) => {
+                let name = fields.get("name")?.as_str()?.to_string();
+                let description = fields
+                    .get("description")
+                    .and_then(|d| d.as_str())
+                    .map(str::to_string);
+                let params = fields.remove("params").or_else(|| fields.remove("schema"));
+                Some(MethodInfo {
+                    name,
+                    description,
+                    params,
+                    allowed: false,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// List the methods a daemon exposes
+pub async fn list_methods(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    let settings = state.settings();
+    if let Some(fleet) = &state.demo {
+        return match fleet.methods(&service) {
+            Ok(mut methods) => {
+                for method in &mut methods {
+                    method.allowed = settings.methods.permits(&service, &method.name);
+                }
+                (StatusCode::OK, ApiResponse::success(methods))
+            }
+            Err(e) => (StatusCode::NOT_FOUND, ApiResponse::error(&e)),
+        };
+    }
+    let Some(socket_path) = settings.socket_path(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<Vec<MethodInfo>>::error(&format!("Service '{}' not found", service)),
+        );
+    };
+    if !transport::endpoint_exists(&socket_path) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<Vec<MethodInfo>>::error(&format!("Service '{}' is not running", service)),
+        );
+    }
+
+    let retries = settings.socket_retries;
+    match calls::blocking(move || call(&socket_path, "methods", serde_json::json!({}), retries))
+        .await
+    {
+        Ok(result) => {
+            let mut methods = parse_methods(&result);
+            for method in &mut methods {
+                method.allowed = settings.methods.permits(&service, &method.name);
+            }
+            (StatusCode::OK, ApiResponse::success(methods))
+        }
+        Err(e) => (
+            StatusCode::NOT_IMPLEMENTED,
+            ApiResponse::<Vec<MethodInfo>>::error(&format!(
+                "Service '{}' does not support method discovery: {}",
+                service, e
+            )),
+        ),
+    }
+}
@@ -0,0 +1,385 @@
+//! Export of reports and history to S3-compatible object storage.
+//!
+//! With an `[s3]` section in the config file, the scheduler task
+//! `export-s3` uploads, every `interval` (an hour by default):
+//!
+//! - `<prefix>snapshots/<timestamp>.json`, the state of every service
+//! - `<prefix>rollups/<timestamp>.ndjson`, the 5-minute and hourly health
+//!   rollups completed since the last successful export
+//! - `<prefix>reports/<file>`, reports written since then by `report` tasks
+//!
+//! so history outlives the local retention window. Any S3-compatible store
+//! works (AWS, MinIO, R2, ...); requests are signed with AWS Signature
+//! Version 4.
+//!
+//! ```toml
+//! [s3]
+//! endpoint = "https://s3.eu-west-1.amazonaws.com"
+//! bucket = "ops-archive"
+//! region = "eu-west-1"
+//! prefix = "fgp-dashboard/prod"
+//! # access_key_id and secret_access_key default to AWS_ACCESS_KEY_ID and
+//! # AWS_SECRET_ACCESS_KEY (and AWS_SESSION_TOKEN) from the environment
+//! # virtual_hosted = true # bucket.endpoint rather than endpoint/bucket
+//! # interval = "6h"
+//! ```
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::calls;
+use crate::config;
+use crate::events;
+use crate::history::HistoryPoint;
+use crate::retention;
+use crate::scheduler::REPORT_DIR;
+use crate::state::AppState;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Name of the scheduler task exporting
+pub const EXPORT_TASK: &str = "export-s3";
+
+/// How often to export when not configured
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Region signed for when not configured
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Time an upload may take
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Runs looked back through for the last successful export
+const RUNS_SEARCHED: usize = 100;
+
+/// The `[s3]` section of the config file
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Key prefix of every object
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    #[serde(default)]
+    pub session_token: Option<String>,
+    /// Address the bucket as `<bucket>.<endpoint host>`
+    #[serde(default)]
+    pub virtual_hosted: bool,
+    #[serde(default)]
+    pub interval: Option<String>,
+}
+
+impl S3Config {
+    /// Check the section and fill in credentials from the environment
+    pub fn resolve(self) -> Result<Target, String> {
+        let endpoint = reqwest::Url::parse(&self.endpoint)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+            .ok_or_else(|| {
+                format!(
+                    "invalid s3 endpoint '{}', expected e.g. https://s3.amazonaws.com",
+                    self.endpoint
+                )
+            })?;
+        let valid_bucket = !self.bucket.is_empty()
+            && self
+                .bucket
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '.'));
+        if !valid_bucket {
+            return Err(format!("invalid s3 bucket '{}'", self.bucket));
+        }
+        let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let access_key_id = self
+            .access_key_id
+            .or_else(|| from_env("AWS_ACCESS_KEY_ID"))
+            .ok_or("the s3 section needs access_key_id, or AWS_ACCESS_KEY_ID set")?;
+        let secret_access_key = self
+            .secret_access_key
+            .or_else(|| from_env("AWS_SECRET_ACCESS_KEY"))
+            .ok_or("the s3 section needs secret_access_key, or AWS_SECRET_ACCESS_KEY set")?;
+        let prefix = match self
+            .prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_matches('/'))
+        {
+            Some(prefix) if !prefix.is_empty() => format!("{}/", prefix),
+            _ => String::new(),
+        };
+
+        Ok(Target {
+            endpoint,
+            bucket: self.bucket,
+            region: self.region.unwrap_or_else(|| DEFAULT_REGION.to_string()),
+            prefix,
+            access_key_id,
+            secret_access_key,
+            session_token: self.session_token.or_else(|| from_env("AWS_SESSION_TOKEN")),
+            virtual_hosted: self.virtual_hosted,
+            interval: match &self.interval {
+                Some(interval) => config::parse_duration(interval)?,
+                None => DEFAULT_INTERVAL,
+            },
+        })
+    }
+}
+
+/// A bucket to export to
+#[derive(Clone)]
+pub struct Target {
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    /// Empty or ending in `/`
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    virtual_hosted: bool,
+    /// How often to export
+    pub interval: Duration,
+}
+
+/// Percent-encode as SigV4 wants, leaving `/` alone
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl Target {
+    /// `s3://bucket/prefix`, for messages
+    fn location(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    /// URL of an object
+    fn object_url(&self, key: &str) -> Result<reqwest::Url, String> {
+        let mut url = self.endpoint.clone();
+        let base = url.path().trim_end_matches('/').to_string();
+        let key = uri_encode(&format!("{}{}", self.prefix, key));
+        if self.virtual_hosted {
+            let host = format!("{}.{}", self.bucket, url.host_str().unwrap_or_default());
+            url.set_host(Some(&host)).map_err(|e| e.to_string())?;
+            url.set_path(&format!("{}/{}", base, key));
+        } else {
+            url.set_path(&format!("{}/{}/{}", base, self.bucket, key));
+        }
+        Ok(url)
+    }
+
+    /// Upload an object
+    async fn put(
+        &self,
+        http: &reqwest::Client,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), String> {
+        let url = self.object_url(key)?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        // Sorted by name, as signing requires
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "PUT\n{}\n\n{}\n{}\n{}",
+            url.path(),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [date, &self.region, "s3", "aws4_request"].iter().fold(
+            format!("AWS4{}", self.secret_access_key).into_bytes(),
+            |key, part| hmac(&key, part),
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&key, &string_to_sign))
+        );
+
+        let mut request = http
+            .put(url.clone())
+            .timeout(UPLOAD_TIMEOUT)
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(format!(
+                "Uploading {} failed with {}: {}",
+                url,
+                status,
+                detail.trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A line of an exported rollup file
+#[derive(Serialize)]
+struct RollupLine {
+    resolution: u64,
+    service: String,
+    #[serde(flatten)]
+    point: HistoryPoint,
+}
+
+/// Upload what is new since the last successful export
+pub async fn export(state: &AppState) -> Result<String, String> {
+    let settings = state.settings();
+    let target = settings
+        .s3
+        .as_ref()
+        .ok_or("No [s3] section is configured")?;
+    let now = events::now();
+    // Rollups are recomputed for a while after their bucket ends
+    let settled = 2 * retention::COMPACT_EVERY.as_secs();
+
+    let db = state.db.clone();
+    let dir = state.data_dir.join(REPORT_DIR);
+    let (rollups, reports) = calls::blocking(move || {
+        let since = db
+            .task_runs(EXPORT_TASK, RUNS_SEARCHED)
+            .map_err(|e| format!("{:#}", e))?
+            .into_iter()
+            .find(|run| run.ok)
+            .map(|run| run.started_at)
+            .unwrap_or(0);
+        let rollups = db
+            .rollups_ending(since.saturating_sub(settled), now.saturating_sub(settled))
+            .map_err(|e| format!("{:#}", e))?;
+
+        let mut reports = Vec::new();
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_secs());
+            if modified >= since {
+                let contents = fs::read(entry.path())
+                    .map_err(|e| format!("Failed to read {}: {}", entry.path().display(), e))?;
+                reports.push((entry.file_name().to_string_lossy().to_string(), contents));
+            }
+        }
+        Ok::<_, String>((rollups, reports))
+    })
+    .await?;
+
+    let snapshot = serde_json::json!({
+        "generated_at": now,
+        "host": settings.host,
+        "services": state.services().await.services,
+    });
+    let mut uploaded = 0;
+    target
+        .put(
+            &state.http,
+            &format!("snapshots/{}.json", now),
+            serde_json::to_vec_pretty(&snapshot).map_err(|e| e.to_string())?,
+            "application/json",
+        )
+        .await?;
+    uploaded += 1;
+
+    if !rollups.is_empty() {
+        let mut body = Vec::new();
+        for (resolution, service, point) in rollups {
+            let line = RollupLine {
+                resolution,
+                service,
+                point,
+            };
+            serde_json::to_writer(&mut body, &line).map_err(|e| e.to_string())?;
+            body.push(b'\n');
+        }
+        target
+            .put(
+                &state.http,
+                &format!("rollups/{}.ndjson", now),
+                body,
+                "application/x-ndjson",
+            )
+            .await?;
+        uploaded += 1;
+    }
+
+    for (name, contents) in reports {
+        target
+            .put(
+                &state.http,
+                &format!("reports/{}", name),
+                contents,
+                "application/json",
+            )
+            .await?;
+        uploaded += 1;
+    }
+
+    Ok(format!(
+        "Uploaded {} objects to {}",
+        uploaded,
+        target.location()
+    ))
+}
@@ -0,0 +1,282 @@
+//! Generating the skeleton of a new service.
+//!
+//! `fgp-dashboard new-service <name>` and `POST /api/scaffold` create a
+//! directory for the service in the first services dir holding:
+//!
+//! - `manifest.json`, naming the service and its entrypoint
+//! - `config.toml`, with the socket and PID file paths the dashboard
+//!   expects
+//! - `start.sh`, an executable placeholder for the command that starts the
+//!   daemon
+//!
+//! Each file comes from a template that `<data_dir>/templates/<file>`
+//! overrides, so a fleet can carry its own conventions. Templates may use
+//! `{{name}}`, `{{description}}`, `{{version}}`, `{{socket_path}}`,
+//! `{{pid_path}}`, `{{service_dir}}` and `{{protocol_major}}`, which are
+//! filled in escaped for JSON and TOML strings. Names are lowercase letters,
+//! digits, `-` and `_`, and must not be installed in any services dir yet.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::protocol;
+use crate::state::{self, AppState};
+
+/// Longest service name accepted
+const MAX_NAME_LEN: usize = 64;
+
+/// Version a new service starts at
+const INITIAL_VERSION: &str = "0.1.0";
+
+/// Subdirectory of the data dir whose files replace the built-in templates
+const TEMPLATES_DIR: &str = "templates";
+
+/// Generated files: name, built-in template and whether it is executable
+const TEMPLATES: &[(&str, &str, bool)] = &[
+    ("manifest.json", MANIFEST_TEMPLATE, false),
+    ("config.toml", CONFIG_TEMPLATE, false),
+    ("start.sh", START_TEMPLATE, true),
+];
+
+const MANIFEST_TEMPLATE: &str = r#"{
+    "name": "{{name}}",
+    "version": "{{version}}",
+    "description": "{{description}}",
+    "protocol_version": "{{protocol_major}}.0",
+    "entrypoint": "start.sh"
+}
+"#;
+
+const CONFIG_TEMPLATE: &str = r#"# Settings for the {{name}} daemon
+
+# Socket the daemon listens on for FGP requests
+socket = "{{socket_path}}"
+
+# File the daemon writes its PID to, so stale sockets can be told apart
+pid_file = "{{pid_path}}"
+
+log_level = "info"
+"#;
+
+const START_TEMPLATE: &str = r#"#!/bin/sh
+# Start the {{name}} daemon: {{description}}
+#
+# Replace the placeholder below with the daemon's command. It should listen
+# for FGP requests on the socket in config.toml, write its PID to the
+# pid_file and answer `health` with its status and version.
+set -eu
+cd "$(dirname "$0")"
+
+echo "{{name}}: no daemon configured yet; edit {{service_dir}}/start.sh" >&2
+exit 1
+"#;
+
+/// Body of `POST /api/scaffold`
+#[derive(Deserialize)]
+pub struct ScaffoldRequest {
+    pub name: String,
+    /// One line describing the service
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A generated service directory
+#[derive(Serialize, Deserialize, TS)]
+pub struct Scaffolded {
+    pub name: String,
+    pub path: String,
+    /// Files written, relative to `path`
+    pub files: Vec<String>,
+}
+
+/// Check that a name can be used as a service directory
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "Service names must be 1 to {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        || !name.chars().all(valid_char)
+    {
+        return Err(format!(
+            "Invalid service name '{}': use lowercase letters, digits, '-' and '_', \
+             starting with a letter or digit",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Escape a value for a JSON or TOML string
+fn escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Fill in a template's placeholders
+fn render(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |out, (key, value)| {
+            out.replace(&format!("{{{{{}}}}}", key), &escape(value))
+        })
+}
+
+/// Where an installed service of this name lives, if any
+pub fn existing(services_dirs: &[PathBuf], name: &str) -> Option<PathBuf> {
+    services_dirs
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|dir| dir.exists())
+}
+
+/// Generate a service directory in `services_dir`
+///
+/// Blocks, so call it off the async runtime.
+pub fn scaffold(
+    services_dir: &Path,
+    data_dir: &Path,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Scaffolded, String> {
+    validate_name(name)?;
+    let description = description
+        .map(|d| d.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| format!("The {} service", name));
+
+    let service_dir = services_dir.join(name);
+    let socket_path = state::socket_path(services_dir, name)
+        .ok_or_else(|| format!("Invalid service name '{}'", name))?;
+    let values = [
+        ("name", name.to_string()),
+        ("description", description),
+        ("version", INITIAL_VERSION.to_string()),
+        ("socket_path", socket_path.to_string_lossy().to_string()),
+        (
+            "pid_path",
+            service_dir.join("daemon.pid").to_string_lossy().to_string(),
+        ),
+        ("service_dir", service_dir.to_string_lossy().to_string()),
+        ("protocol_major", protocol::SUPPORTED_MAJOR.to_string()),
+    ];
+
+    fs::create_dir_all(services_dir)
+        .map_err(|e| format!("Failed to create {}: {}", services_dir.display(), e))?;
+    fs::create_dir(&service_dir).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            format!("{} already exists", service_dir.display())
+        }
+        _ => format!("Failed to create {}: {}", service_dir.display(), e),
+    })?;
+
+    let written = write_files(&service_dir, &data_dir.join(TEMPLATES_DIR), &values);
+    match written {
+        Ok(files) => Ok(Scaffolded {
+            name: name.to_string(),
+            path: service_dir.to_string_lossy().to_string(),
+            files,
+        }),
+        Err(e) => {
+            // Leave nothing half-made behind
+            let _ = fs::remove_dir_all(&service_dir);
+            Err(e)
+        }
+    }
+}
+
+fn write_files(
+    service_dir: &Path,
+    templates_dir: &Path,
+    values: &[(&str, String)],
+) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for (file, builtin, executable) in TEMPLATES {
+        let template = match fs::read_to_string(templates_dir.join(file)) {
+            Ok(template) => template,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => builtin.to_string(),
+            Err(e) => return Err(format!("Failed to read the {} template: {}", file, e)),
+        };
+        let path = service_dir.join(file);
+        fs::write(&path, render(&template, values))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        if *executable {
+            make_executable(&path)
+                .map_err(|e| format!("Failed to make {} executable: {}", path.display(), e))?;
+        }
+        files.push(file.to_string());
+    }
+    Ok(files)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Generate a new service directory
+///
+/// Invalid names get a 400 and names already installed a 409.
+pub async fn scaffold_service(
+    State(state): State<AppState>,
+    Json(request): Json<ScaffoldRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_name(&request.name) {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Scaffolded>::error(&e),
+        );
+    }
+    let settings = state.settings();
+    if let Some(dir) = existing(&settings.services_dirs, &request.name) {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<Scaffolded>::error(&format!(
+                "Service '{}' already exists at {}",
+                request.name,
+                dir.display()
+            )),
+        );
+    }
+    let Some(services_dir) = settings.services_dirs.first().cloned() else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Scaffolded>::error("No services directory is configured"),
+        );
+    };
+
+    let data_dir = state.data_dir.as_ref().clone();
+    let created = calls::blocking(move || {
+        scaffold(
+            &services_dir,
+            &data_dir,
+            &request.name,
+            request.description.as_deref(),
+        )
+    })
+    .await;
+    match created {
+        Ok(scaffolded) => {
+            state.store.request_refresh();
+            (StatusCode::CREATED, ApiResponse::success(scaffolded))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Scaffolded>::error(&e),
+        ),
+    }
+}
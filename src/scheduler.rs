@@ -0,0 +1,880 @@
+//! Tasks run on a schedule.
+//!
+//! One loop runs everything the dashboard does periodically, so features
+//! don't each grow their own timer:
+//!
+//! - `compact-history`, rolling up and pruning the history (see
+//!   [`retention`]) every five minutes
+//! - `check-updates`, looking up latest versions (see [`updates`]) every
+//!   `--update-check-interval`
+//! - `script:<name>`, the `on_schedule` hook of each script (see
+//!   [`scripts`])
+//! - `export-s3`, uploading history to object storage (see [`s3`]) when an
+//!   `[s3]` section is configured
+//! - `chaos`, striking a service (see [`chaos`]) while a chaos experiment
+//!   runs, on the experiment's schedule
+//! - the `[[schedule]]` sections of the config file:
+//!
+//! ```toml
+//! [[schedule]]
+//! name = "nightly-worker-restart"
+//! schedule = "30 3 * * *"
+//! job = "restart"    # or start, stop, backup, report, script
+//! service = "worker" # for start, stop and restart
+//! # script = "..."   # for script
+//! # keep = 7         # backups or reports to keep
+//! ```
+//!
+//! A schedule is a cron expression (minute, hour, day of month, month, day
+//! of week, in local time; `*`, lists, ranges, steps and three-letter names
+//! work), `@hourly`, `@daily`, `@weekly`, `@monthly`, `@yearly`, or
+//! `@every <duration>` such as `@every 15m`. Backups go to
+//! `<data-dir>/backups` and reports, a JSON summary of service states,
+//! status changes and events since the task's previous run, to
+//! `<data-dir>/reports`.
+//!
+//! Every run is kept in the database with its outcome, so `@every` tasks
+//! pick up where they left off after a restart (the history compaction and
+//! update check also run at startup). A task still running when it comes
+//! due again is skipped. `GET /api/schedule` lists the tasks,
+//! `GET /api/schedule/{task}/runs` their history, and
+//! `POST /api/schedule/{task}/run` runs one now.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path as FsPath;
+use std::sync::Mutex;
+use std::time::Duration;
+use ts_rs::TS;
+
+use crate::api::{self, ApiResponse};
+use crate::backup;
+use crate::calls;
+use crate::chaos;
+use crate::config;
+use crate::docker::Lifecycle;
+use crate::events;
+use crate::retention;
+use crate::s3;
+use crate::scripts;
+use crate::state::AppState;
+use crate::updates;
+
+/// Directory under the data dir backup tasks write to
+pub const BACKUP_DIR: &str = "backups";
+
+/// Directory under the data dir report tasks write to
+pub const REPORT_DIR: &str = "reports";
+
+/// Backups or reports kept when a task doesn't say
+const DEFAULT_KEEP: usize = 7;
+
+/// Runs `GET /api/schedule/{task}/runs` returns when not asked for fewer
+const DEFAULT_RUNS: usize = 50;
+
+/// Period a report covers when its task hasn't run before
+const FIRST_REPORT_PERIOD: u64 = 24 * 3600;
+
+/// Names of the built-in tasks
+const COMPACT_HISTORY: &str = "compact-history";
+const CHECK_UPDATES: &str = "check-updates";
+const CHAOS: &str = "chaos";
+const SCRIPT_PREFIX: &str = "script:";
+
+/// The values a cron field allows, as a bit set
+#[derive(Clone, Copy)]
+struct Field {
+    bits: u64,
+    /// Written as `*`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn contains(self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn parse(text: &str, min: u32, max: u32, names: &[&str]) -> Result<Self, String> {
+        let value = |part: &str| -> Result<u32, String> {
+            let lower = part.to_ascii_lowercase();
+            if let Some(index) = names.iter().position(|name| *name == lower) {
+                return Ok(index as u32 + min);
+            }
+            match part.parse() {
+                Ok(n) if (min..=max).contains(&n) => Ok(n),
+                _ => Err(format!("'{}' is not a value from {} to {}", part, min, max)),
+            }
+        };
+
+        let mut bits = 0;
+        for item in text.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => return Err(format!("invalid step in '{}'", item)),
+                },
+                None => (item, 1),
+            };
+            let (from, to) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((from, to)) => (value(from)?, value(to)?),
+                    None if step > 1 => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                },
+            };
+            if from > to {
+                return Err(format!("empty range '{}'", range));
+            }
+            for n in (from..=to).step_by(step as usize) {
+                bits |= 1 << n;
+            }
+        }
+        Ok(Self {
+            bits,
+            any: text == "*",
+        })
+    }
+}
+
+/// A parsed cron expression
+#[derive(Clone)]
+pub struct Cron {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Cron {
+    fn parse(text: &str) -> Result<Self, String> {
+        const MONTHS: &[&str] = &[
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ];
+        const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "invalid schedule '{}', expected 5 cron fields, @daily or @every <duration>",
+                text
+            ));
+        };
+        let mut weekday = Field::parse(weekday, 0, 7, WEEKDAYS)?;
+        // 7 is Sunday too
+        if weekday.contains(7) {
+            weekday.bits |= 1;
+        }
+        Ok(Self {
+            minute: Field::parse(minute, 0, 59, &[])?,
+            hour: Field::parse(hour, 0, 23, &[])?,
+            day: Field::parse(day, 1, 31, &[])?,
+            month: Field::parse(month, 1, 12, MONTHS)?,
+            weekday,
+        })
+    }
+
+    fn matches_day(&self, day: u32, weekday: u32) -> bool {
+        match (self.day.any, self.weekday.any) {
+            (true, true) => true,
+            (false, true) => self.day.contains(day),
+            (true, false) => self.weekday.contains(weekday),
+            // Either will do when both are given, as in cron
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+        }
+    }
+
+    /// The first matching minute after `after`, within a few years
+    fn next_after(&self, after: u64) -> Option<u64> {
+        let mut at = after / 60 * 60 + 60;
+        let until = after + 5 * 366 * 24 * 3600;
+        while at <= until {
+            let Some(local) = Local.timestamp_opt(at as i64, 0).earliest() else {
+                at += 60;
+                continue;
+            };
+            let day_matches = self.month.contains(local.month())
+                && self.matches_day(local.day(), local.weekday().num_days_from_sunday());
+            if !day_matches || !self.hour.contains(local.hour()) {
+                // On to the next hour
+                at += 3600 - u64::from(local.minute()) * 60;
+                continue;
+            }
+            if self.minute.contains(local.minute()) {
+                return Some(at);
+            }
+            at += 60;
+        }
+        None
+    }
+}
+
+/// When a task runs
+#[derive(Clone)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(every) = text.strip_prefix("@every") {
+            return config::parse_duration(every).map(Self::Every);
+        }
+        let cron = match text {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => text,
+        };
+        Cron::parse(cron).map(Self::Cron)
+    }
+
+    /// When to run next, given the last run
+    fn next(&self, last_run: Option<u64>, now: u64) -> u64 {
+        match self {
+            Self::Every(every) => last_run.unwrap_or(now) + every.as_secs(),
+            Self::Cron(cron) => cron.next_after(now).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// What a task does
+#[derive(Clone)]
+enum Job {
+    Control(String, Lifecycle),
+    Backup { keep: usize },
+    Report { keep: usize },
+    Script(String),
+    CompactHistory,
+    CheckUpdates,
+    ExportS3,
+    Chaos,
+}
+
+impl Job {
+    fn describe(&self) -> String {
+        match self {
+            Self::Control(service, lifecycle) => format!("{} {}", lifecycle.verb(), service),
+            Self::Backup { keep } => format!("back up, keeping {}", keep),
+            Self::Report { keep } => format!("write a report, keeping {}", keep),
+            Self::Script(script) => format!("run script {}", script),
+            Self::CompactHistory => "compact history".to_string(),
+            Self::CheckUpdates => "check for updates".to_string(),
+            Self::ExportS3 => "export to object storage".to_string(),
+            Self::Chaos => "restart or pause a service at random".to_string(),
+        }
+    }
+}
+
+/// A job of a `[[schedule]]` section
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Start,
+    Stop,
+    Restart,
+    Backup,
+    Report,
+    Script,
+}
+
+/// A `[[schedule]]` section of the config file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskConfig {
+    pub name: String,
+    pub schedule: String,
+    pub job: JobKind,
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub script: Option<String>,
+    /// Backups or reports to keep
+    #[serde(default)]
+    pub keep: Option<usize>,
+}
+
+impl TaskConfig {
+    /// Check the section and turn it into a task
+    pub fn parse(&self) -> Result<Task, String> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        let reserved = [COMPACT_HISTORY, CHECK_UPDATES, CHAOS, s3::EXPORT_TASK];
+        if !valid_name || reserved.contains(&self.name.as_str()) {
+            return Err(format!("invalid or reserved task name '{}'", self.name));
+        }
+        let schedule =
+            Schedule::parse(&self.schedule).map_err(|e| format!("task '{}': {}", self.name, e))?;
+
+        let service = || {
+            self.service
+                .clone()
+                .ok_or_else(|| format!("task '{}' needs a service", self.name))
+        };
+        let keep = self.keep.unwrap_or(DEFAULT_KEEP).max(1);
+        let job = match self.job {
+            JobKind::Start => Job::Control(service()?, Lifecycle::Start),
+            JobKind::Stop => Job::Control(service()?, Lifecycle::Stop),
+            JobKind::Restart => Job::Control(service()?, Lifecycle::Restart),
+            JobKind::Backup => Job::Backup { keep },
+            JobKind::Report => Job::Report { keep },
+            JobKind::Script => Job::Script(
+                self.script
+                    .clone()
+                    .ok_or_else(|| format!("task '{}' needs a script", self.name))?,
+            ),
+        };
+        Ok(Task {
+            name: self.name.clone(),
+            spec: self.schedule.trim().to_string(),
+            schedule,
+            job,
+            run_at_start: false,
+            builtin: false,
+        })
+    }
+}
+
+/// Something the scheduler runs
+#[derive(Clone)]
+pub struct Task {
+    name: String,
+    /// The schedule as written
+    spec: String,
+    schedule: Schedule,
+    job: Job,
+    /// Run at startup whatever the history says
+    run_at_start: bool,
+    builtin: bool,
+}
+
+impl Task {
+    fn builtin(name: String, every: Duration, job: Job, run_at_start: bool) -> Self {
+        Self {
+            name,
+            spec: format!("@every {}s", every.as_secs()),
+            schedule: Schedule::Every(every),
+            job,
+            run_at_start,
+            builtin: true,
+        }
+    }
+}
+
+/// One run of a task
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct TaskRun {
+    #[ts(type = "number")]
+    pub id: u64,
+    pub task: String,
+    /// `schedule` or `manual`
+    pub trigger: String,
+    /// Unix timestamps in seconds
+    #[ts(type = "number")]
+    pub started_at: u64,
+    #[ts(type = "number")]
+    pub finished_at: u64,
+    pub ok: bool,
+    /// What the task did, or why it failed
+    pub message: String,
+}
+
+/// A task as `/api/schedule` lists it
+#[derive(Serialize, Deserialize, TS)]
+pub struct TaskInfo {
+    pub name: String,
+    pub schedule: String,
+    pub job: String,
+    pub builtin: bool,
+    /// Unix timestamp of the next run, if one is planned
+    #[ts(type = "number | null")]
+    pub next_run: Option<u64>,
+    pub running: bool,
+    pub last_run: Option<TaskRun>,
+}
+
+/// Every task, built-in ones first
+fn tasks(state: &AppState) -> Vec<Task> {
+    let settings = state.settings();
+    let mut tasks = vec![
+        Task::builtin(
+            COMPACT_HISTORY.to_string(),
+            retention::COMPACT_EVERY,
+            Job::CompactHistory,
+            true,
+        ),
+        Task::builtin(
+            CHECK_UPDATES.to_string(),
+            settings.update_check_interval,
+            Job::CheckUpdates,
+            true,
+        ),
+    ];
+    if let Some(target) = &settings.s3 {
+        tasks.push(Task::builtin(
+            s3::EXPORT_TASK.to_string(),
+            target.interval,
+            Job::ExportS3,
+            false,
+        ));
+    }
+    if let Some((spec, schedule)) = state.chaos.schedule() {
+        tasks.push(Task {
+            name: CHAOS.to_string(),
+            spec,
+            schedule,
+            job: Job::Chaos,
+            run_at_start: false,
+            builtin: true,
+        });
+    }
+    for (script, every) in scripts::scheduled() {
+        tasks.push(Task::builtin(
+            format!("{}{}", SCRIPT_PREFIX, script),
+            every,
+            Job::Script(script),
+            false,
+        ));
+    }
+    tasks.extend(settings.schedule.iter().cloned());
+    // The demo fleet has no files to back up, report to or update from
+    if state.demo.is_some() {
+        tasks.retain(|task| task.name == COMPACT_HISTORY || task.name == CHAOS);
+    }
+    tasks
+}
+
+/// Plans and runs tasks
+#[derive(Default)]
+pub struct Scheduler {
+    /// Next run of each task, with the schedule it was planned from
+    next: Mutex<HashMap<String, (String, u64)>>,
+    running: Mutex<HashSet<String>>,
+}
+
+impl Scheduler {
+    /// Tasks due now, planning the next run of each
+    fn due(&self, tasks: Vec<Task>, history: &BTreeMap<String, TaskRun>, now: u64) -> Vec<Task> {
+        let mut next = self.next.lock().unwrap();
+        let starting = next.is_empty();
+        next.retain(|name, _| tasks.iter().any(|task| task.name == *name));
+
+        tasks
+            .into_iter()
+            .filter(|task| {
+                let at = match next.get(&task.name) {
+                    Some((spec, at)) if *spec == task.spec => *at,
+                    _ if starting && task.run_at_start => now,
+                    _ => {
+                        let last_run = history.get(&task.name).map(|run| run.started_at);
+                        task.schedule.next(last_run, now)
+                    }
+                };
+                let due = at <= now;
+                let at = if due {
+                    task.schedule.next(Some(now), now)
+                } else {
+                    at
+                };
+                next.insert(task.name.clone(), (task.spec.clone(), at));
+                due
+            })
+            .collect()
+    }
+
+    /// Run a task and record the run, unless it is already running
+    async fn run(&self, state: &AppState, task: &Task, trigger: &str) -> Option<TaskRun> {
+        if !self.running.lock().unwrap().insert(task.name.clone()) {
+            tracing::debug!("Skipping task {}: still running", task.name);
+            return None;
+        }
+        let started_at = events::now();
+        let result = execute(state, task).await;
+        self.running.lock().unwrap().remove(&task.name);
+
+        if let Err(e) = &result {
+            tracing::warn!("Task {} failed: {}", task.name, e);
+        }
+        let (ok, message) = match result {
+            Ok(message) => (true, message),
+            Err(e) => (false, e),
+        };
+        let mut run = TaskRun {
+            id: 0,
+            task: task.name.clone(),
+            trigger: trigger.to_string(),
+            started_at,
+            finished_at: events::now(),
+            ok,
+            message,
+        };
+        let db = state.db.clone();
+        let stored = run.clone();
+        match calls::blocking(move || db.insert_task_run(&stored)).await {
+            Ok(id) => run.id = id,
+            Err(e) => tracing::warn!("Failed to record a run of task {}: {:#}", task.name, e),
+        }
+        Some(run)
+    }
+
+    pub fn list(&self, state: &AppState, history: &BTreeMap<String, TaskRun>) -> Vec<TaskInfo> {
+        let next = self.next.lock().unwrap();
+        let running = self.running.lock().unwrap();
+        tasks(state)
+            .into_iter()
+            .map(|task| TaskInfo {
+                next_run: next
+                    .get(&task.name)
+                    .map(|(_, at)| *at)
+                    .filter(|at| *at != u64::MAX),
+                running: running.contains(&task.name),
+                last_run: history.get(&task.name).cloned(),
+                schedule: task.spec,
+                job: task.job.describe(),
+                builtin: task.builtin,
+                name: task.name,
+            })
+            .collect()
+    }
+}
+
+async fn execute(state: &AppState, task: &Task) -> Result<String, String> {
+    match &task.job {
+        Job::Control(service, Lifecycle::Start) => api::start(state, service).await,
+        Job::Control(service, Lifecycle::Stop) => api::stop(state, service).await,
+        Job::Control(service, Lifecycle::Restart) => api::restart(state, service).await,
+        Job::Backup { keep } => write_backup(state, *keep).await,
+        Job::Report { keep } => write_report(state, &task.name, *keep).await,
+        Job::Script(script) => scripts::run_scheduled(state, script).await,
+        Job::CompactHistory => retention::compact_history(state).await,
+        Job::CheckUpdates => updates::check_now(state).await,
+        Job::ExportS3 => s3::export(state).await,
+        Job::Chaos => chaos::strike(state).await,
+    }
+}
+
+/// Delete all but the newest `keep` files in `dir` starting with `prefix`
+fn keep_newest(dir: &FsPath, prefix: &str, keep: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .collect();
+    // Names end in a timestamp, so they sort by age
+    files.sort();
+    let excess = files.len().saturating_sub(keep);
+    for path in files.into_iter().take(excess) {
+        if let Err(e) = fs::remove_file(&path) {
+            tracing::warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+async fn write_backup(state: &AppState, keep: usize) -> Result<String, String> {
+    let db = state.db.clone();
+    let paths = state.backup_paths();
+    let dir = state.data_dir.join(BACKUP_DIR);
+    calls::blocking(move || {
+        let archive = backup::create(&db, &paths).map_err(|e| format!("Backup failed: {:#}", e))?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let path = dir.join(backup::file_name());
+        fs::write(&path, archive)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        keep_newest(&dir, "fgp-dashboard-backup-", keep);
+        Ok(format!("Wrote {}", path.display()))
+    })
+    .await
+}
+
+/// What a report task writes
+#[derive(Serialize)]
+struct Report {
+    from: u64,
+    to: u64,
+    /// Status of each service when the report was written
+    services: BTreeMap<String, String>,
+    /// How often each service's status changed
+    status_changes: BTreeMap<String, u64>,
+    /// Events by kind
+    events: BTreeMap<String, u64>,
+}
+
+async fn write_report(state: &AppState, task: &str, keep: usize) -> Result<String, String> {
+    let services: BTreeMap<String, String> = state
+        .services()
+        .await
+        .services
+        .iter()
+        .map(|service| (service.name.clone(), service.status.clone()))
+        .collect();
+    let db = state.db.clone();
+    let dir = state.data_dir.join(REPORT_DIR);
+    let task = task.to_string();
+
+    calls::blocking(move || {
+        let to = events::now();
+        let from = db
+            .task_runs(&task, 1)
+            .map_err(|e| format!("{:#}", e))?
+            .first()
+            .map(|run| run.started_at)
+            .unwrap_or(to.saturating_sub(FIRST_REPORT_PERIOD));
+        let status_changes = db.status_flips(from, to).map_err(|e| format!("{:#}", e))?;
+        let mut counts = BTreeMap::new();
+        for event in db
+            .events_between(from, to, events::MAX_EVENTS)
+            .map_err(|e| format!("{:#}", e))?
+        {
+            *counts.entry(event.kind).or_insert(0) += 1;
+        }
+        let report = Report {
+            from,
+            to,
+            services,
+            status_changes,
+            events: counts,
+        };
+
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let prefix = format!("{}-", task);
+        let path = dir.join(format!("{}{}.json", prefix, to));
+        let json = serde_json::to_vec_pretty(&report).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        keep_newest(&dir, &prefix, keep);
+        Ok(format!("Wrote {}", path.display()))
+    })
+    .await
+}
+
+async fn last_runs(state: &AppState) -> BTreeMap<String, TaskRun> {
+    let db = state.db.clone();
+    calls::blocking(move || db.last_task_runs())
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to load task runs: {:#}", e);
+            BTreeMap::new()
+        })
+}
+
+/// Run tasks as they come due until the process exits
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let history = last_runs(&state).await;
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            for task in state.scheduler.due(tasks(&state), &history, events::now()) {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    state.scheduler.run(&state, &task, "schedule").await;
+                });
+            }
+        }
+    });
+}
+
+/// List the scheduled tasks
+pub async fn list_tasks(State(state): State<AppState>) -> impl IntoResponse {
+    let history = last_runs(&state).await;
+    (
+        StatusCode::OK,
+        ApiResponse::success(state.scheduler.list(&state, &history)),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct RunsQuery {
+    pub limit: Option<usize>,
+}
+
+/// Recent runs of a task, newest first
+pub async fn task_runs(
+    State(state): State<AppState>,
+    Path(task): Path<String>,
+    Query(query): Query<RunsQuery>,
+) -> impl IntoResponse {
+    if !tasks(&state).iter().any(|known| known.name == task) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<Vec<TaskRun>>::error(&format!("No task named '{}'", task)),
+        );
+    }
+    let db = state.db.clone();
+    let limit = query.limit.unwrap_or(DEFAULT_RUNS);
+    match calls::blocking(move || db.task_runs(&task, limit)).await {
+        Ok(runs) => (StatusCode::OK, ApiResponse::success(runs)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Vec<TaskRun>>::error(&format!("{:#}", e)),
+        ),
+    }
+}
+
+/// Run a task now
+pub async fn run_task(
+    State(state): State<AppState>,
+    Path(task): Path<String>,
+) -> impl IntoResponse {
+    let Some(task) = tasks(&state).into_iter().find(|known| known.name == task) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<TaskRun>::error(&format!("No task named '{}'", task)),
+        );
+    };
+    match state.scheduler.run(&state, &task, "manual").await {
+        Some(run) => (StatusCode::OK, ApiResponse::success(run)),
+        None => (
+            StatusCode::CONFLICT,
+            ApiResponse::<TaskRun>::error(&format!("Task '{}' is already running", task.name)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(field: Field, max: u32) -> Vec<u32> {
+        (0..=max).filter(|n| field.contains(*n)).collect()
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .earliest()
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    #[test]
+    fn field_parses_lists_ranges_and_steps() {
+        let field = Field::parse("1,5-7,*/20", 0, 59, &[]).unwrap();
+        assert_eq!(values(field, 59), [0, 1, 5, 6, 7, 20, 40]);
+        assert!(!field.any);
+
+        let field = Field::parse("10/15", 0, 59, &[]).unwrap();
+        assert_eq!(values(field, 59), [10, 25, 40, 55]);
+
+        let field = Field::parse("1-10/3", 0, 59, &[]).unwrap();
+        assert_eq!(values(field, 59), [1, 4, 7, 10]);
+
+        assert!(Field::parse("*", 0, 59, &[]).unwrap().any);
+    }
+
+    #[test]
+    fn field_parses_names() {
+        const DAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+        let field = Field::parse("MON-fri", 0, 7, DAYS).unwrap();
+        assert_eq!(values(field, 7), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn field_rejects_bad_input() {
+        for text in ["60", "5-1", "*/0", "*/x", "x", "", "1,,2", "-1"] {
+            assert!(Field::parse(text, 0, 59, &[]).is_err(), "{}", text);
+        }
+        assert!(Field::parse("0", 1, 31, &[]).is_err());
+    }
+
+    #[test]
+    fn cron_treats_7_as_sunday() {
+        let cron = Cron::parse("0 0 * * 7").unwrap();
+        assert!(cron.weekday.contains(0));
+        let cron = Cron::parse("0 0 * * fri-7").unwrap();
+        assert_eq!(values(cron.weekday, 6), [0, 5, 6]);
+    }
+
+    #[test]
+    fn cron_rejects_wrong_field_counts() {
+        assert!(Cron::parse("0 0 * *").is_err());
+        assert!(Cron::parse("0 0 * * * *").is_err());
+        assert!(Cron::parse("").is_err());
+    }
+
+    #[test]
+    fn cron_matches_day_of_month_or_weekday() {
+        // Either matches when both are restricted
+        let cron = Cron::parse("0 0 13 * 5").unwrap();
+        assert!(cron.matches_day(13, 2));
+        assert!(cron.matches_day(6, 5));
+        assert!(!cron.matches_day(6, 2));
+
+        let cron = Cron::parse("0 0 13 * *").unwrap();
+        assert!(cron.matches_day(13, 2));
+        assert!(!cron.matches_day(14, 5));
+
+        let cron = Cron::parse("0 0 * * 5").unwrap();
+        assert!(cron.matches_day(6, 5));
+        assert!(!cron.matches_day(13, 2));
+    }
+
+    #[test]
+    fn cron_finds_the_next_matching_minute() {
+        let cron = Cron::parse("30 3 * * *").unwrap();
+        let now = local(2026, 1, 5, 10, 7);
+        assert_eq!(cron.next_after(now), Some(local(2026, 1, 6, 3, 30)));
+        // Strictly after
+        let now = local(2026, 1, 6, 3, 30);
+        assert_eq!(cron.next_after(now), Some(local(2026, 1, 7, 3, 30)));
+
+        // February 2026 starts on a Sunday, so a Friday comes before the 13th
+        let cron = Cron::parse("0 12 13 feb fri").unwrap();
+        assert_eq!(
+            cron.next_after(local(2026, 1, 5, 0, 0)),
+            Some(local(2026, 2, 6, 12, 0))
+        );
+
+        let cron = Cron::parse("*/15 * * * *").unwrap();
+        assert_eq!(
+            cron.next_after(local(2026, 1, 5, 10, 7)),
+            Some(local(2026, 1, 5, 10, 15))
+        );
+
+        // February never has a 30th
+        let cron = Cron::parse("0 0 30 2 *").unwrap();
+        assert_eq!(cron.next_after(local(2026, 1, 5, 0, 0)), None);
+    }
+
+    #[test]
+    fn every_counts_from_the_last_run() {
+        let schedule = Schedule::parse("@every 15m").unwrap();
+        assert_eq!(schedule.next(Some(1000), 5000), 1900);
+        assert_eq!(schedule.next(None, 5000), 5900);
+
+        assert!(Schedule::parse("@every").is_err());
+        assert!(Schedule::parse("@every 0s").is_err());
+        assert!(Schedule::parse("@every soon").is_err());
+    }
+
+    #[test]
+    fn schedule_expands_aliases() {
+        let Schedule::Cron(cron) = Schedule::parse(" @daily ").unwrap() else {
+            panic!("@daily is a cron schedule");
+        };
+        assert_eq!(values(cron.minute, 59), [0]);
+        assert_eq!(values(cron.hour, 23), [0]);
+        assert!(cron.day.any && cron.month.any && cron.weekday.any);
+
+        assert!(Schedule::parse("@fortnightly").is_err());
+    }
+}
@@ -0,0 +1,512 @@
+//! Rhai scripts that react to events or run on a schedule.
+//!
+//! Every `.rhai` file in the script dir (`--script-dir`, by default
+//! `<data-dir>/scripts`) is compiled at startup. A script may define
+//!
+//! - `on_event(event)`, called with each event as a map of `id`,
+//!   `timestamp`, `service`, `kind` and `message`
+//! - `on_schedule()`, called every `SCHEDULE`, a top-level constant such as
+//!   `const SCHEDULE = "5m";` (one minute when absent), as the
+//!   [`scheduler`](crate::scheduler) task `script:<name>`
+//!
+//! and call back into the dashboard with:
+//!
+//! - `services()` and `service(name)`: the latest scan, as maps shaped like
+//!   `/api/services` entries plus the daemon's last `health` payload
+//! - `start(name)`, `stop(name)` and `restart(name)`
+//! - `notify(service, message)`, publishing a `script` event
+//! - `log(message)`, `now()`, and `hour()`, `minute()` and `weekday()` (1 for
+//!   Monday) in local time
+//!
+//! For example, restarting a backed-up worker during business hours:
+//!
+//! ```rhai
+//! const SCHEDULE = "1m";
+//!
+//! fn on_schedule() {
+//!     let worker = service("worker");
+//!     if worker != () && worker.health?.queue_depth > 1000
+//!         && weekday() <= 5 && hour() >= 9 && hour() < 18 {
+//!         restart("worker");
+//!     }
+//! }
+//! ```
+//!
+//! Scripts can't read files, import modules or make network calls, and a run
+//! is stopped after [`MAX_OPERATIONS`] operations. Starts, stops and
+//! restarts take effect once the hook returns, and a script may control the
+//! same service at most once per [`CONTROL_COOLDOWN`], so a script reacting
+//! to the events its own restarts cause can't loop. `script` events aren't
+//! passed to `on_event`. Top-level statements run before each hook.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{Datelike, Timelike};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use ts_rs::TS;
+
+use crate::api::{self, ApiResponse};
+use crate::calls;
+use crate::config::{self, Config};
+use crate::docker::Lifecycle;
+use crate::events::{self, Event};
+use crate::state::AppState;
+use crate::store::Snapshot;
+
+/// Directory under the data dir scripts are loaded from by default
+pub const SCRIPT_DIR: &str = "scripts";
+
+/// Operations a hook may run before it is stopped
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Least time between two controls of one service by one script
+pub const CONTROL_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// How often `on_schedule` runs when the script sets no `SCHEDULE`
+const DEFAULT_SCHEDULE: Duration = Duration::from_secs(60);
+
+/// A compiled script
+#[derive(Clone)]
+struct Script {
+    name: String,
+    path: PathBuf,
+    ast: AST,
+    on_event: bool,
+    /// How often `on_schedule` runs, if the script defines it
+    schedule: Option<Duration>,
+}
+
+/// What a hook asked for, done once it returns
+enum Action {
+    Control(String, Lifecycle),
+    Notify(String, String),
+}
+
+/// How a script last ran
+#[derive(Clone, Default)]
+struct RunStatus {
+    last_run: Option<u64>,
+    error: Option<String>,
+}
+
+/// A script as `/api/scripts` lists it
+#[derive(Serialize, Deserialize, TS)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub path: String,
+    pub on_event: bool,
+    /// Seconds between `on_schedule` runs, if the script defines it
+    #[ts(type = "number | null")]
+    pub schedule_seconds: Option<u64>,
+    /// Unix timestamp of the last run
+    #[ts(type = "number | null")]
+    pub last_run: Option<u64>,
+    /// Why the last run failed, if it did
+    pub error: Option<String>,
+}
+
+/// A script that didn't compile
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct ScriptFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Everything `/api/scripts` returns
+#[derive(Serialize, Deserialize, TS)]
+pub struct ScriptList {
+    pub scripts: Vec<ScriptInfo>,
+    pub failed: Vec<ScriptFailure>,
+}
+
+/// The scripts loaded at startup
+#[derive(Default)]
+pub struct Scripts {
+    scripts: Vec<Script>,
+    failed: Vec<ScriptFailure>,
+    status: Mutex<HashMap<String, RunStatus>>,
+    /// When each script last controlled each service
+    controlled: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Scripts {
+    /// Compile the scripts in `dir`
+    pub fn load(dir: &FsPath) -> Self {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let engine = sandboxed();
+        let mut scripts = Self::default();
+        for path in paths {
+            match compile(&engine, &path) {
+                Ok(script) => {
+                    tracing::info!("Loaded script {}", script.name);
+                    scripts.scripts.push(script);
+                }
+                Err(error) => {
+                    tracing::warn!("Failed to load script {}: {}", path.display(), error);
+                    scripts.failed.push(ScriptFailure {
+                        path: path.to_string_lossy().to_string(),
+                        error,
+                    });
+                }
+            }
+        }
+        scripts
+    }
+
+    pub fn list(&self) -> ScriptList {
+        let status = self.status.lock().unwrap();
+        ScriptList {
+            scripts: self
+                .scripts
+                .iter()
+                .map(|script| {
+                    let status = status.get(&script.name).cloned().unwrap_or_default();
+                    ScriptInfo {
+                        name: script.name.clone(),
+                        path: script.path.to_string_lossy().to_string(),
+                        on_event: script.on_event,
+                        schedule_seconds: script.schedule.map(|every| every.as_secs()),
+                        last_run: status.last_run,
+                        error: status.error,
+                    }
+                })
+                .collect(),
+            failed: self.failed.clone(),
+        }
+    }
+
+    /// Run one hook of a script and carry out what it asked for
+    async fn run(
+        &self,
+        state: &AppState,
+        script: &Script,
+        hook: &'static str,
+        args: Vec<Dynamic>,
+    ) -> Result<(), String> {
+        let snapshot = state.store.snapshot();
+        let task = script.clone();
+        let (result, actions) =
+            calls::blocking(move || evaluate(&task, hook, args, snapshot)).await;
+
+        let mut error = result.err();
+        for action in actions {
+            let outcome = match action {
+                Action::Control(service, lifecycle) => {
+                    self.control(state, &script.name, &service, lifecycle).await
+                }
+                Action::Notify(service, message) => {
+                    state.events.publish(&service, events::SCRIPT, message);
+                    Ok(())
+                }
+            };
+            if let Err(e) = outcome {
+                error.get_or_insert(e);
+            }
+        }
+
+        if let Some(e) = &error {
+            tracing::warn!("Script {} failed in {}: {}", script.name, hook, e);
+        }
+        self.status.lock().unwrap().insert(
+            script.name.clone(),
+            RunStatus {
+                last_run: Some(events::now()),
+                error: error.clone(),
+            },
+        );
+        error.map_or(Ok(()), Err)
+    }
+
+    async fn control(
+        &self,
+        state: &AppState,
+        script: &str,
+        service: &str,
+        lifecycle: Lifecycle,
+    ) -> Result<(), String> {
+        {
+            let mut controlled = self.controlled.lock().unwrap();
+            let key = (script.to_string(), service.to_string());
+            if let Some(last) = controlled.get(&key) {
+                if last.elapsed() < CONTROL_COOLDOWN {
+                    tracing::debug!(
+                        "Script {} skipped a {} of {}: controlled it {}s ago",
+                        script,
+                        lifecycle.verb(),
+                        service,
+                        last.elapsed().as_secs()
+                    );
+                    return Ok(());
+                }
+            }
+            controlled.insert(key, Instant::now());
+        }
+
+        tracing::info!(
+            "Script {} asked to {} {}",
+            script,
+            lifecycle.verb(),
+            service
+        );
+        match lifecycle {
+            Lifecycle::Start => api::start(state, service).await,
+            Lifecycle::Stop => api::stop(state, service).await,
+            Lifecycle::Restart => api::restart(state, service).await,
+        }
+        .map(|_| ())
+    }
+}
+
+/// An engine with no way out of the sandbox
+fn sandboxed() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.set_max_modules(0);
+    engine
+}
+
+fn compile(engine: &Engine, path: &FsPath) -> Result<Script, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let ast = engine.compile(&source).map_err(|e| e.to_string())?;
+
+    let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+    let on_event = defines("on_event");
+    let schedule = if defines("on_schedule") {
+        let every = ast
+            .iter_literal_variables(true, false)
+            .find(|(name, _, _)| *name == "SCHEDULE")
+            .map(|(_, _, value)| value.to_string());
+        Some(match every {
+            Some(every) => config::parse_duration(&every)?,
+            None => DEFAULT_SCHEDULE,
+        })
+    } else {
+        None
+    };
+    if !on_event && schedule.is_none() {
+        return Err("defines neither on_event nor on_schedule".to_string());
+    }
+
+    Ok(Script {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        path: path.to_path_buf(),
+        ast,
+        on_event,
+        schedule,
+    })
+}
+
+/// A service as scripts see it
+fn service_map(service: &api::ServiceInfo) -> Dynamic {
+    let mut value = serde_json::to_value(service).unwrap_or_default();
+    value["health"] = service.health.clone().unwrap_or_default();
+    rhai::serde::to_dynamic(value).unwrap_or_default()
+}
+
+/// Call `hook`, returning what it asked for
+///
+/// Blocks, so call it off the async runtime.
+fn evaluate(
+    script: &Script,
+    hook: &str,
+    args: Vec<Dynamic>,
+    snapshot: Option<Arc<Snapshot>>,
+) -> (Result<(), String>, Vec<Action>) {
+    let actions = Arc::new(Mutex::new(Vec::new()));
+    let mut engine = sandboxed();
+    let name = script.name.clone();
+    engine.on_print(move |message| tracing::info!("Script {}: {}", name, message));
+    let name = script.name.clone();
+    engine.on_debug(move |message, _, _| tracing::debug!("Script {}: {}", name, message));
+
+    let services = snapshot.clone();
+    engine.register_fn("services", move || -> Array {
+        services
+            .iter()
+            .flat_map(|snapshot| snapshot.services.iter().map(service_map))
+            .collect()
+    });
+    engine.register_fn("service", move |name: &str| -> Dynamic {
+        snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.services.iter().find(|s| s.name == name))
+            .map(service_map)
+            .unwrap_or_default()
+    });
+    for (verb, lifecycle) in [
+        ("start", Lifecycle::Start),
+        ("stop", Lifecycle::Stop),
+        ("restart", Lifecycle::Restart),
+    ] {
+        let actions = actions.clone();
+        engine.register_fn(verb, move |service: &str| {
+            actions
+                .lock()
+                .unwrap()
+                .push(Action::Control(service.to_string(), lifecycle));
+        });
+    }
+    let notices = actions.clone();
+    engine.register_fn("notify", move |service: &str, message: &str| {
+        notices
+            .lock()
+            .unwrap()
+            .push(Action::Notify(service.to_string(), message.to_string()));
+    });
+    let name = script.name.clone();
+    engine.register_fn("log", move |message: &str| {
+        tracing::info!("Script {}: {}", name, message);
+    });
+    engine.register_fn("now", || events::now() as i64);
+    engine.register_fn("hour", || chrono::Local::now().hour() as i64);
+    engine.register_fn("minute", || chrono::Local::now().minute() as i64);
+    engine.register_fn("weekday", || {
+        chrono::Local::now().weekday().number_from_monday() as i64
+    });
+
+    let result: Result<Dynamic, Box<EvalAltResult>> =
+        engine.call_fn(&mut Scope::new(), &script.ast, hook, args);
+    drop(engine);
+    let actions = Arc::try_unwrap(actions)
+        .map(|actions| actions.into_inner().unwrap())
+        .unwrap_or_default();
+    (result.map(|_| ()).map_err(|e| e.to_string()), actions)
+}
+
+static SCRIPTS: OnceLock<Scripts> = OnceLock::new();
+
+/// Load the scripts once, at startup
+pub fn init(config: &Config) {
+    SCRIPTS.get_or_init(|| Scripts::load(&config.script_dir));
+}
+
+/// The loaded scripts; none before [`init`]
+pub fn scripts() -> &'static Scripts {
+    SCRIPTS.get_or_init(Scripts::default)
+}
+
+/// Scripts defining `on_schedule`, and how often it runs
+pub fn scheduled() -> Vec<(String, Duration)> {
+    scripts()
+        .scripts
+        .iter()
+        .filter_map(|script| Some((script.name.clone(), script.schedule?)))
+        .collect()
+}
+
+/// Run a script's `on_schedule`; the scheduler calls this every `SCHEDULE`
+pub async fn run_scheduled(state: &AppState, name: &str) -> Result<String, String> {
+    let scripts = scripts();
+    let script = scripts
+        .scripts
+        .iter()
+        .find(|script| script.name == name && script.schedule.is_some())
+        .ok_or_else(|| format!("No script named '{}' defines on_schedule", name))?;
+    scripts
+        .run(state, script, "on_schedule", Vec::new())
+        .await?;
+    Ok(format!("Ran script {}", name))
+}
+
+/// Pass events to `on_event` until the process exits
+pub fn spawn_runner(state: AppState) {
+    let scripts = scripts();
+    if !scripts.scripts.iter().any(|script| script.on_event) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut events = state.events.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) if event.kind != events::SCRIPT => {
+                    let event = event_map(&event);
+                    for script in scripts.scripts.iter().filter(|script| script.on_event) {
+                        // Failures are kept in the script's status
+                        let _ = scripts
+                            .run(&state, script, "on_event", vec![event.clone()])
+                            .await;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Scripts skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// An event as `on_event` sees it
+fn event_map(event: &Event) -> Dynamic {
+    rhai::serde::to_dynamic(event).unwrap_or_default()
+}
+
+/// List the loaded scripts
+pub async fn list_scripts() -> impl IntoResponse {
+    (StatusCode::OK, ApiResponse::success(scripts().list()))
+}
+
+/// Run a script's `on_schedule` now
+pub async fn run_script(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let scripts = scripts();
+    let Some(script) = scripts.scripts.iter().find(|script| script.name == name) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<ScriptInfo>::error(&format!("No script named '{}'", name)),
+        );
+    };
+    if script.schedule.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<ScriptInfo>::error(&format!("Script '{}' defines no on_schedule", name)),
+        );
+    }
+
+    // The outcome is in the script's status
+    let _ = scripts.run(&state, script, "on_schedule", Vec::new()).await;
+    match scripts
+        .list()
+        .scripts
+        .into_iter()
+        .find(|info| info.name == name)
+    {
+        Some(info) => (StatusCode::OK, ApiResponse::success(info)),
+        None => (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<ScriptInfo>::error(&format!("No script named '{}'", name)),
+        ),
+    }
+}
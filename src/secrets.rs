@@ -0,0 +1,184 @@
+//! Credentials kept out of the config file.
+//!
+//! Any string in the config file, such as the `secret` of a `[[webhooks]]`
+//! entry, may instead be a reference resolved when it is loaded:
+//!
+//! - `${env:NAME}`, an environment variable
+//! - `${file:/run/secrets/s3-key}`, the contents of a file such as a
+//!   mounted Kubernetes or Docker secret, without the trailing newline
+//! - `${vault:secret/data/dashboard#s3_secret_key}`, field
+//!   `s3_secret_key` of a HashiCorp Vault secret, from KV version 1 or 2.
+//!   The server is `VAULT_ADDR`, the token `VAULT_TOKEN` or else the file
+//!   `VAULT_TOKEN_FILE` or `~/.vault-token`, and `VAULT_NAMESPACE` applies
+//!   if set.
+//!
+//! ```toml
+//! [s3]
+//! endpoint = "https://s3.eu-west-1.amazonaws.com"
+//! bucket = "ops-archive"
+//! access_key_id = "${file:/run/secrets/s3-key-id}"
+//! secret_access_key = "${vault:secret/data/dashboard#s3_secret_key}"
+//! ```
+//!
+//! Resolved values are cached until the config is reloaded, so a rotated
+//! secret is picked up by `SIGHUP` or `POST /api/admin/reload-config`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Time a Vault lookup may take
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+fn cache() -> &'static Mutex<HashMap<String, String>> {
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Forget resolved values, so the next lookups fetch them again
+pub fn clear_cache() {
+    cache().lock().unwrap().clear();
+}
+
+/// The provider and argument of a reference such as `${env:NAME}`
+fn parse(value: &str) -> Option<(&str, &str)> {
+    value.strip_prefix("${")?.strip_suffix('}')?.split_once(':')
+}
+
+/// Whether `value` is a reference rather than a value of its own
+pub fn is_reference(value: &str) -> bool {
+    parse(value).is_some()
+}
+
+/// The value a reference stands for, or `value` itself if it isn't one
+///
+/// Blocks, so call it off the async runtime.
+pub fn resolve(value: &str) -> Result<String, String> {
+    let Some((provider, argument)) = parse(value) else {
+        return Ok(value.to_string());
+    };
+    if let Some(resolved) = cache().lock().unwrap().get(value) {
+        return Ok(resolved.clone());
+    }
+
+    let resolved = match provider {
+        "env" => std::env::var(argument)
+            .map_err(|_| format!("the environment variable {} is not set", argument))?,
+        "file" => fs::read_to_string(argument)
+            .map(|contents| contents.trim_end_matches(['\r', '\n']).to_string())
+            .map_err(|e| format!("failed to read the secret file {}: {}", argument, e))?,
+        "vault" => vault(argument)?,
+        other => {
+            return Err(format!(
+                "unknown secret provider '{}' in {}, expected env, file or vault",
+                other, value
+            ))
+        }
+    };
+    cache()
+        .lock()
+        .unwrap()
+        .insert(value.to_string(), resolved.clone());
+    Ok(resolved)
+}
+
+/// Resolve every string reference in a parsed config file
+pub fn resolve_toml(value: &mut toml::Value) -> Result<(), String> {
+    match value {
+        toml::Value::String(text) => {
+            if parse(text).is_some() {
+                *text = resolve(text)?;
+            }
+        }
+        toml::Value::Array(items) => {
+            for item in items {
+                resolve_toml(item)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                resolve_toml(item).map_err(|e| format!("{}: {}", key, e))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn vault_token() -> Result<String, String> {
+    if let Ok(token) = std::env::var("VAULT_TOKEN") {
+        return Ok(token);
+    }
+    let path = match std::env::var_os("VAULT_TOKEN_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".vault-token"))
+            .ok_or("VAULT_TOKEN is not set")?,
+    };
+    fs::read_to_string(&path)
+        .map(|token| token.trim().to_string())
+        .map_err(|_| {
+            format!(
+                "VAULT_TOKEN is not set and {} can't be read",
+                path.display()
+            )
+        })
+}
+
+/// Read field `path#field` from Vault
+fn vault(reference: &str) -> Result<String, String> {
+    let (path, field) = reference
+        .split_once('#')
+        .ok_or_else(|| format!("vault reference '{}' names no #field", reference))?;
+    let address = std::env::var("VAULT_ADDR").map_err(|_| "VAULT_ADDR is not set".to_string())?;
+    let url = format!(
+        "{}/v1/{}",
+        address.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    );
+    let token = vault_token()?;
+    let namespace = std::env::var("VAULT_NAMESPACE").ok();
+
+    // Config loads happen inside and outside the async runtime alike, so
+    // the request gets a runtime of its own
+    let body = std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+        runtime.block_on(async {
+            let mut request = reqwest::Client::new()
+                .get(&url)
+                .timeout(VAULT_TIMEOUT)
+                .header("x-vault-token", token);
+            if let Some(namespace) = namespace {
+                request = request.header("x-vault-namespace", namespace);
+            }
+            request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Vault lookup of {} failed: {}", url, e))?
+                .json::<serde_json::Value>()
+                .await
+                .map_err(|e| format!("Vault answered {} with invalid JSON: {}", url, e))
+        })
+    })
+    .join()
+    .map_err(|_| "the Vault lookup panicked".to_string())??;
+
+    // KV version 2 nests the secret under data.data
+    let data = &body["data"];
+    let secret = data["data"].get(field).or_else(|| data.get(field));
+    match secret {
+        Some(serde_json::Value::String(secret)) => Ok(secret.clone()),
+        Some(other) => Ok(other.to_string()),
+        None => Err(format!(
+            "the Vault secret {} has no field '{}'",
+            path, field
+        )),
+    }
+}
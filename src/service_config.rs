@@ -0,0 +1,275 @@
+//! Viewing and editing a daemon's config file.
+//!
+//! `GET /api/config/{service}` returns the first of [`CONFIG_FILES`] in the
+//! service's directory, with a version tag of its contents. `PUT` replaces
+//! it with `{"contents": ..., "hash": ...}` once the new contents parse as
+//! the file's format; a `hash` that no longer matches the file means someone
+//! else changed it since it was read, and the write is refused. The tag is
+//! an HMAC under a key made at startup, so it can't be used to guess masked
+//! secrets, and tags from before a restart no longer match. Files are
+//! replaced atomically and keep their permissions.
+//!
+//! Secret values are masked in what is returned (see
+//! [`redact`](crate::redact)); masked values left in place when saving keep
+//! what the file had.
+//!
+//! The daemon reads its config when it starts, so the service shows config
+//! drift until it is restarted.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::OnceLock;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::events;
+use crate::redact::Redactor;
+use crate::state::{AppState, Settings};
+
+/// Config files looked for in a service directory, in order
+pub const CONFIG_FILES: &[&str] = &["config.toml", "config.json"];
+
+/// Largest config file served or accepted
+const MAX_CONFIG_SIZE: usize = 1024 * 1024;
+
+/// A daemon's config file
+#[derive(Serialize, Deserialize, TS)]
+pub struct ServiceConfig {
+    pub path: String,
+    /// `toml` or `json`
+    pub format: String,
+    pub contents: String,
+    /// Version tag of the file, unmasked, to send back with changes
+    pub hash: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfigUpdate {
+    pub contents: String,
+    /// Version tag of the contents the changes were made to
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+/// Why a config file couldn't be read or written
+enum ConfigError {
+    NotFound(String),
+    Invalid(String),
+    Conflict(String),
+    Io(String),
+}
+
+impl ConfigError {
+    fn reply<T: Serialize>(self) -> (StatusCode, Json<ApiResponse<T>>) {
+        let (status, message) = match self {
+            Self::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            Self::Invalid(message) => (StatusCode::UNPROCESSABLE_ENTITY, message),
+            Self::Conflict(message) => (StatusCode::CONFLICT, message),
+            Self::Io(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        };
+        (status, ApiResponse::error(&message))
+    }
+}
+
+/// Key of the version tags, new each time the dashboard starts
+fn tag_key() -> &'static [u8] {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()]
+            .iter()
+            .flat_map(|id| *id.as_bytes())
+            .collect()
+    })
+}
+
+/// Version tag of a file's contents
+fn hash(contents: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(tag_key()).expect("HMAC accepts any key");
+    mac.update(contents.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn format_of(path: &FsPath) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => "json",
+        _ => "toml",
+    }
+}
+
+/// Check that `contents` parse as `format`, saying where they don't
+fn validate(format: &str, contents: &str) -> Result<(), String> {
+    match format {
+        "json" => serde_json::from_str::<serde_json::Value>(contents)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid JSON: {}", e)),
+        _ => toml::from_str::<toml::Table>(contents)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid TOML: {}", e.to_string().trim())),
+    }
+}
+
+/// The config file of the service in `dir`
+fn find(service: &str, dir: Option<PathBuf>) -> Result<PathBuf, ConfigError> {
+    let dir =
+        dir.ok_or_else(|| ConfigError::NotFound(format!("Service '{}' not found", service)))?;
+    CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            ConfigError::NotFound(format!(
+                "'{}' has no {} in {}",
+                service,
+                CONFIG_FILES.join(" or "),
+                dir.display()
+            ))
+        })
+}
+
+fn read(path: &FsPath) -> Result<ServiceConfig, ConfigError> {
+    let size = fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if size > MAX_CONFIG_SIZE as u64 {
+        return Err(ConfigError::Invalid(format!(
+            "{} is larger than {} bytes",
+            path.display(),
+            MAX_CONFIG_SIZE
+        )));
+    }
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    Ok(ServiceConfig {
+        path: path.to_string_lossy().to_string(),
+        format: format_of(path).to_string(),
+        hash: hash(&contents),
+        contents,
+    })
+}
+
+/// Replace the file at `path`, unless it changed since `update.hash` was read
+fn write(
+    path: &FsPath,
+    update: &ConfigUpdate,
+    redact: &Redactor,
+) -> Result<ServiceConfig, ConfigError> {
+    if update.contents.len() > MAX_CONFIG_SIZE {
+        return Err(ConfigError::Invalid(format!(
+            "The config is larger than {} bytes",
+            MAX_CONFIG_SIZE
+        )));
+    }
+
+    let current = read(path)?;
+    if update
+        .hash
+        .as_ref()
+        .is_some_and(|hash| *hash != current.hash)
+    {
+        return Err(ConfigError::Conflict(format!(
+            "{} changed since it was read; reload it and reapply the changes",
+            path.display()
+        )));
+    }
+    let contents = redact.restore(&update.contents, &current.contents);
+    let format = format_of(path);
+    validate(format, &contents).map_err(ConfigError::Invalid)?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staged = path.with_file_name(format!(".{}.tmp", file_name));
+    let io =
+        |e: std::io::Error| ConfigError::Io(format!("Failed to write {}: {}", path.display(), e));
+    fs::write(&staged, &contents).map_err(io)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&staged, metadata.permissions()).map_err(io)?;
+    }
+    if let Err(e) = fs::rename(&staged, path) {
+        let _ = fs::remove_file(&staged);
+        return Err(io(e));
+    }
+
+    Ok(ServiceConfig {
+        path: path.to_string_lossy().to_string(),
+        format: format.to_string(),
+        hash: hash(&contents),
+        contents,
+    })
+}
+
+/// Mask secrets in a config before it is shown
+fn redacted(config: ServiceConfig, settings: &Settings) -> ServiceConfig {
+    ServiceConfig {
+        contents: settings.redact.text(&config.contents),
+        ..config
+    }
+}
+
+/// A service's config file
+pub async fn get_config(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+) -> impl IntoResponse {
+    let settings = state.settings();
+    let dir = settings.service_dir(&service);
+    match calls::blocking(move || read(&find(&service, dir)?)).await {
+        Ok(config) => (
+            StatusCode::OK,
+            ApiResponse::success(redacted(config, &settings)),
+        ),
+        Err(e) => e.reply(),
+    }
+}
+
+/// Replace a service's config file
+pub async fn update_config(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Json(update): Json<ConfigUpdate>,
+) -> impl IntoResponse {
+    let settings = state.settings();
+    let dir = settings.service_dir(&service);
+    let redact = settings.redact.clone();
+    let name = service.clone();
+    match calls::blocking(move || write(&find(&name, dir)?, &update, &redact)).await {
+        Ok(config) => {
+            state.events.publish(
+                &service,
+                events::CONFIG_EDITED,
+                format!("Edited {}", config.path),
+            );
+            (
+                StatusCode::OK,
+                ApiResponse::success(redacted(config, &settings)),
+            )
+        }
+        Err(e) => e.reply(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    #[test]
+    fn version_tags_follow_contents() {
+        assert_eq!(hash("port = 80\n"), hash("port = 80\n"));
+        assert_ne!(hash("port = 80\n"), hash("port = 81\n"));
+    }
+
+    #[test]
+    fn version_tags_are_not_plain_hashes() {
+        let contents = "password = \"hunter2\"\n";
+        assert_ne!(hash(contents), hex::encode(Sha256::digest(contents)));
+    }
+}
@@ -0,0 +1,53 @@
+//! Concurrency limits and load shedding.
+//!
+//! Routes are grouped into classes that each get their own limit: reads
+//! (listings, health, events, hosts, GraphQL), control (start/stop,
+//! subscriptions, config reload) and passthrough (`/api/call`,
+//! `/api/methods`). Requests beyond a class's limit are answered 503 with
+//! `Retry-After` straight away instead of queueing, so a misconfigured
+//! poller hammering one class can't starve the others. Probes, metrics and
+//! the dashboard page itself are not limited.
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Router,
+};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::ServiceBuilder;
+
+use crate::api::ApiResponse;
+
+/// Concurrent read requests allowed when no limit is configured
+pub const DEFAULT_MAX_READS: usize = 256;
+
+/// Concurrent control requests allowed when no limit is configured
+pub const DEFAULT_MAX_CONTROL: usize = 16;
+
+/// Concurrent passthrough calls allowed when no limit is configured
+pub const DEFAULT_MAX_CALLS: usize = 64;
+
+/// Seconds clients are told to wait before retrying
+const RETRY_AFTER_SECS: u64 = 1;
+
+async fn overloaded(_: BoxError) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+        ApiResponse::<()>::error("Too many concurrent requests, retry shortly"),
+    )
+        .into_response()
+}
+
+/// Share one limit of `max` concurrent requests between all of `router`'s
+/// routes, shedding the excess
+pub fn limit<S: Clone + Send + Sync + 'static>(router: Router<S>, max: usize) -> Router<S> {
+    router.route_layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(overloaded))
+            .layer(LoadShedLayer::new())
+            .layer(GlobalConcurrencyLimitLayer::new(max)),
+    )
+}
@@ -0,0 +1,254 @@
+//! Explaining why a daemon socket can't be connected to.
+//!
+//! A failed connect to a socket that exists usually means the dashboard and
+//! the daemon run as different users, which `fgp_daemon` reports as a bare
+//! "permission denied". [`diagnose`] looks at the socket's owner and mode,
+//! the directories above it and, when SELinux is enforcing, the socket's
+//! label, and names what stands in the way. Services failing this way get
+//! the `permission_denied` status with the diagnosis as their probe error.
+//!
+//! The other common case is a daemon that died without removing its
+//! socket: connecting is refused, and the PID in `daemon.pid` next to the
+//! socket, if the daemon keeps one, is gone. Such services get the `stale`
+//! status, and `POST /api/cleanup/{service}` (or the `cleanup` batch action)
+//! removes the socket so the service can be started cleanly.
+
+use std::path::Path;
+
+use crate::transport;
+
+/// Status of a service whose socket the dashboard may not connect to
+pub const PERMISSION_DENIED: &str = "permission_denied";
+
+/// Status of a service whose socket outlived its daemon
+pub const STALE: &str = "stale";
+
+/// PID file a daemon may keep next to its socket
+const PID_FILE: &str = "daemon.pid";
+
+/// Why connecting to `socket_path` failed with `error`, if something can be
+/// said beyond the error itself
+#[cfg(unix)]
+pub fn diagnose(socket_path: &Path, error: &anyhow::Error) -> Option<String> {
+    use std::io;
+    use std::os::unix::fs::FileTypeExt;
+
+    let denied = error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+    });
+    let metadata = std::fs::metadata(socket_path).ok()?;
+    if !metadata.file_type().is_socket() {
+        return Some(format!("{} is not a socket", socket_path.display()));
+    }
+    if !denied {
+        return None;
+    }
+
+    let me = Identity::current()?;
+    // Outermost first, since that is the one to fix
+    let dirs: Vec<&Path> = socket_path.ancestors().skip(1).collect();
+    for dir in dirs.into_iter().rev() {
+        let Ok(metadata) = std::fs::metadata(dir) else {
+            continue;
+        };
+        if !me.may(&metadata, 0o1) {
+            return Some(format!(
+                "directory {} is {}, so the dashboard (running as {}) can't reach the socket",
+                dir.display(),
+                describe(&metadata),
+                me
+            ));
+        }
+    }
+    if !me.may(&metadata, 0o2) {
+        return Some(format!(
+            "socket is {}, but the dashboard is running as {}",
+            describe(&metadata),
+            me
+        ));
+    }
+    match selinux_label(socket_path) {
+        Some(label) => Some(format!(
+            "file permissions allow access, but SELinux is enforcing and the socket is \
+             labelled {}; check the audit log for denials",
+            label
+        )),
+        None => Some(format!(
+            "file permissions allow access for {}; a security module may be denying it",
+            me
+        )),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn diagnose(_socket_path: &Path, _error: &anyhow::Error) -> Option<String> {
+    None
+}
+
+/// Whether nothing listens on `socket_path` and no daemon process owns it
+///
+/// Blocks, so call it off the async runtime.
+#[cfg(unix)]
+pub fn is_stale(socket_path: &Path) -> bool {
+    use std::os::unix::net::UnixStream;
+
+    match UnixStream::connect(socket_path) {
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => !owner_alive(socket_path),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_stale(_socket_path: &Path) -> bool {
+    false
+}
+
+/// Whether the process in the daemon's PID file is still running
+#[cfg(unix)]
+fn owner_alive(socket_path: &Path) -> bool {
+    let Some(pid) = socket_path
+        .parent()
+        .and_then(|dir| std::fs::read_to_string(dir.join(PID_FILE)).ok())
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+    else {
+        return false;
+    };
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+    std::process::Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Remove a stale socket
+///
+/// Refuses if anything still listens on it or owns it. Blocks, so call it
+/// off the async runtime.
+pub fn remove_stale(socket_path: &Path) -> Result<(), String> {
+    if !transport::endpoint_exists(socket_path) {
+        return Err(format!("No socket at {}", socket_path.display()));
+    }
+    if !is_stale(socket_path) {
+        return Err(format!(
+            "{} is not stale; stop the service instead",
+            socket_path.display()
+        ));
+    }
+    std::fs::remove_file(socket_path)
+        .map_err(|e| format!("Failed to remove {}: {}", socket_path.display(), e))
+}
+
+/// An error message with the diagnosis added, if there is one
+pub fn explain(socket_path: &Path, error: anyhow::Error) -> String {
+    match diagnose(socket_path, &error) {
+        Some(diagnosis) => format!("{}: {}", error, diagnosis),
+        None => error.to_string(),
+    }
+}
+
+/// The user and groups the dashboard runs as
+#[cfg(unix)]
+struct Identity {
+    uid: u32,
+    name: Option<String>,
+    groups: Vec<u32>,
+}
+
+#[cfg(unix)]
+impl Identity {
+    /// Ask `id`, which works the same on every Unix
+    fn current() -> Option<Self> {
+        let id = |flag: &str| {
+            std::process::Command::new("id")
+                .arg(flag)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        Some(Self {
+            uid: id("-u")?.parse().ok()?,
+            name: id("-un"),
+            groups: id("-G")?
+                .split_whitespace()
+                .filter_map(|group| group.parse().ok())
+                .collect(),
+        })
+    }
+
+    /// Whether the permission bits in `want` (as for "other") are granted
+    fn may(&self, metadata: &std::fs::Metadata, want: u32) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        if self.uid == 0 {
+            return true;
+        }
+        let mode = metadata.mode();
+        let bits = if metadata.uid() == self.uid {
+            mode >> 6
+        } else if self.groups.contains(&metadata.gid()) {
+            mode >> 3
+        } else {
+            mode
+        };
+        bits & want != 0
+    }
+}
+
+#[cfg(unix)]
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "user {} (uid {})", name, self.uid),
+            None => write!(f, "uid {}", self.uid),
+        }
+    }
+}
+
+/// "owned by root:root with mode 0600"
+#[cfg(unix)]
+fn describe(metadata: &std::fs::Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    format!(
+        "owned by {}:{} with mode {:04o}",
+        account_name("/etc/passwd", metadata.uid()).unwrap_or_else(|| metadata.uid().to_string()),
+        account_name("/etc/group", metadata.gid()).unwrap_or_else(|| metadata.gid().to_string()),
+        metadata.mode() & 0o7777
+    )
+}
+
+/// Name of a user or group from a passwd-style file
+#[cfg(unix)]
+fn account_name(file: &str, id: u32) -> Option<String> {
+    let contents = std::fs::read_to_string(file).ok()?;
+    contents.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let found: u32 = fields.nth(1)?.parse().ok()?;
+        (found == id).then(|| name.to_string())
+    })
+}
+
+/// The socket's SELinux label, when SELinux is enforcing
+#[cfg(unix)]
+fn selinux_label(socket_path: &Path) -> Option<String> {
+    let enforcing = std::fs::read_to_string("/sys/fs/selinux/enforce").ok()?;
+    if enforcing.trim() != "1" {
+        return None;
+    }
+    let output = std::process::Command::new("ls")
+        .arg("-Zd")
+        .arg(socket_path)
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
@@ -0,0 +1,455 @@
+//! Shared application state handed to every request handler.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::api;
+use crate::auth::Grants;
+use crate::backup;
+use crate::calls;
+use crate::capabilities::CapabilityTracker;
+use crate::chaos::Chaos;
+use crate::config::{Args, Config};
+use crate::db::Database;
+use crate::demo::Fleet;
+use crate::drift::DriftTracker;
+use crate::events::{self, EventBus};
+use crate::exposure;
+use crate::health_cache::{HealthCache, ProbeOptions};
+use crate::hosts::HostInfo;
+use crate::namespaces::Namespace;
+use crate::probes::Readiness;
+use crate::quotas::{QuotaTracker, Quotas};
+use crate::redact::Redactor;
+use crate::retention;
+use crate::rpc::MethodPolicy;
+use crate::s3;
+use crate::scheduler::{Scheduler, Task};
+use crate::secrets;
+use crate::store::{Snapshot, StateStore};
+use crate::transport;
+use crate::ui::{Branding, ConfirmStop};
+use crate::updates::UpdateChecker;
+use crate::upgrade::UpgradeLocks;
+use crate::wasm;
+use crate::webhooks::{Subscription, WebhookConfig, WebhookRegistry};
+use crate::widgets::Widget;
+
+/// Timeout for outgoing HTTP requests (peers, webhooks)
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default directory for the dashboard's own state (`~/.fgp/dashboard`)
+pub fn default_data_dir() -> PathBuf {
+    let services_dir = fgp_daemon::fgp_services_dir();
+    services_dir
+        .parent()
+        .map(|fgp_dir| fgp_dir.join("dashboard"))
+        .unwrap_or_else(|| services_dir.join(".dashboard"))
+}
+
+/// Settings that can change when the config is reloaded
+pub struct Settings {
+    /// The host this dashboard runs on
+    pub host: HostInfo,
+    /// Base URLs of peer dashboards
+    pub peers: Vec<String>,
+    /// Tokens accepted by the auth layer; none disables it
+    pub auth_tokens: Vec<String>,
+    /// Which methods may be called through the RPC passthrough
+    pub methods: MethodPolicy,
+    /// Directories containing one subdirectory per installed service, in
+    /// order of precedence
+    pub services_dirs: Vec<PathBuf>,
+    /// Whether to log every HTTP request
+    pub access_log: bool,
+    /// Longest a request may take; zero disables the limit
+    pub request_timeout: Duration,
+    /// Requests slower than this are logged; zero disables the warning
+    pub slow_request: Duration,
+    /// How long health results are reused; zero disables the cache
+    pub health_cache_ttl: Duration,
+    /// How often the poller rescans services
+    pub poll_interval: Duration,
+    /// How often transient socket errors are retried
+    pub socket_retries: u32,
+    /// Longest a health probe may take
+    pub health_timeout: Duration,
+    /// Per-service overrides of `health_timeout`
+    pub service_health_timeouts: BTreeMap<String, Duration>,
+    /// Largest passthrough result sent to a client, in bytes
+    pub max_rpc_response_size: u64,
+    /// How long each tier of history is kept
+    pub retention: retention::Policy,
+    /// Directory whose files override the built-in UI assets
+    pub assets_dir: Option<PathBuf>,
+    /// When the UI asks before stopping a service
+    pub confirm_stop: ConfirmStop,
+    /// Labels and annotations marking services whose stops need the name typed
+    pub protect: Vec<(String, String)>,
+    /// How long the UI holds a confirmed stop
+    pub stop_undo: Duration,
+    /// White-labelling of the UI
+    pub branding: Branding,
+    /// URL or file listing the latest version of each service
+    pub update_index: Option<String>,
+    /// How often latest versions are looked up
+    pub update_check_interval: Duration,
+    /// Whether to publish an event when a service falls behind
+    pub update_alerts: bool,
+    /// Whether to look for daemons in Docker containers
+    pub docker: bool,
+    /// Whether to look up each service's systemd unit
+    pub systemd: bool,
+    /// Whether to look up each service's launch agent
+    pub launchd: bool,
+    /// Label selector of pods to look for daemons in
+    pub kubernetes: Option<String>,
+    /// Namespace of those pods, if not the kubectl context's
+    pub kubernetes_namespace: Option<String>,
+    /// Panels from the config file
+    pub widgets: Vec<Widget>,
+    /// Webhooks from the config file, with their secrets resolved
+    pub webhooks: Vec<Subscription>,
+    /// Tasks from the config file
+    pub schedule: Vec<Task>,
+    /// Bucket to export history to
+    pub s3: Option<s3::Target>,
+    /// Masks secrets in what is shown
+    pub redact: Redactor,
+    /// Partitions of the services, with their tokens
+    pub namespaces: Vec<Namespace>,
+    /// Limits of each token
+    pub quotas: Quotas,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            host: HostInfo::local(config.host_name.clone(), config.labels.clone()),
+            peers: config.peers.clone(),
+            auth_tokens: config.auth_tokens.clone(),
+            methods: MethodPolicy {
+                allow: config.allow_methods.clone(),
+                deny: config.deny_methods.clone(),
+            },
+            services_dirs: config.services_dirs.clone(),
+            access_log: config.access_log,
+            request_timeout: Duration::from_secs(config.request_timeout),
+            slow_request: Duration::from_millis(config.slow_request_ms),
+            health_cache_ttl: Duration::from_millis(config.health_cache_ttl_ms),
+            poll_interval: Duration::from_secs(config.poll_interval.max(1)),
+            socket_retries: config.socket_retries,
+            health_timeout: Duration::from_millis(config.health_timeout_ms),
+            service_health_timeouts: config
+                .service_health_timeouts
+                .iter()
+                .map(|(service, ms)| (service.clone(), Duration::from_millis(*ms)))
+                .collect(),
+            max_rpc_response_size: config.max_rpc_response_size,
+            retention: config.retention,
+            assets_dir: config.assets_dir.clone(),
+            confirm_stop: config.confirm_stop,
+            protect: config.protect.clone(),
+            stop_undo: Duration::from_secs(config.stop_undo_seconds),
+            branding: config.branding.clone(),
+            update_index: config.update_index.clone(),
+            update_check_interval: config.update_check_interval,
+            update_alerts: config.update_alerts,
+            docker: config.docker,
+            systemd: config.systemd,
+            launchd: config.launchd,
+            kubernetes: config.kubernetes.clone(),
+            kubernetes_namespace: config.kubernetes_namespace.clone(),
+            widgets: config.widgets.clone(),
+            webhooks: config
+                .webhooks
+                .iter()
+                .map(WebhookConfig::subscription)
+                .collect(),
+            schedule: config.schedule.clone(),
+            s3: config.s3.clone(),
+            redact: Redactor::new(config.redact_keys.clone()),
+            namespaces: config.namespaces.clone(),
+            quotas: config.quotas,
+        }
+    }
+
+    /// Health probe timeout for a service
+    pub fn health_timeout(&self, service: &str) -> Duration {
+        self.service_health_timeouts
+            .get(service)
+            .copied()
+            .unwrap_or(self.health_timeout)
+    }
+
+    /// A namespace from the config file
+    pub fn namespace(&self, name: &str) -> Option<&Namespace> {
+        self.namespaces
+            .iter()
+            .find(|namespace| namespace.name == name)
+    }
+
+    /// Directory of an installed service, from the first services dir that
+    /// has it; `None` for names that aren't a single path component
+    pub fn service_dir(&self, service: &str) -> Option<PathBuf> {
+        if !is_service_name(service) {
+            return None;
+        }
+        self.services_dirs
+            .iter()
+            .map(|dir| dir.join(service))
+            .find(|dir| dir.is_dir())
+    }
+
+    /// Path of a service's FGP socket, from the first directory that has it;
+    /// `None` for names that aren't a single path component
+    pub fn socket_path(&self, service: &str) -> Option<PathBuf> {
+        if !is_service_name(service) {
+            return None;
+        }
+        let services_dir = self
+            .services_dirs
+            .iter()
+            .find(|dir| dir.join(service).is_dir())
+            .or_else(|| self.services_dirs.first())
+            .cloned()
+            .unwrap_or_else(fgp_daemon::fgp_services_dir);
+        socket_path(&services_dir, service)
+    }
+}
+
+/// Whether `service` names an entry of a services dir rather than a path
+/// that could lead out of it
+pub fn is_service_name(service: &str) -> bool {
+    !service.is_empty() && service != "." && service != ".." && !service.contains(['/', '\\', '\0'])
+}
+
+/// Path of a service's FGP socket inside a services directory, or its named
+/// pipe on Windows; `None` for names that aren't a single path component
+pub fn socket_path(services_dir: &Path, service: &str) -> Option<PathBuf> {
+    is_service_name(service).then(|| transport::endpoint_path(services_dir, service))
+}
+
+/// State shared across all handlers
+#[derive(Clone)]
+pub struct AppState {
+    settings: Arc<RwLock<Arc<Settings>>>,
+    /// Flags and environment the dashboard was started with, for reloads
+    args: Arc<Args>,
+    /// Address the server is bound to
+    listen: SocketAddr,
+    /// Whether the server speaks HTTPS
+    pub tls: bool,
+    /// Concurrency and body limits the router was built with
+    limits: [(&'static str, usize); 5],
+    /// Directory for the dashboard's own state
+    pub data_dir: Arc<PathBuf>,
+    /// HTTP client used to reach peers and webhooks
+    pub http: reqwest::Client,
+    /// Service events
+    pub events: Arc<EventBus>,
+    /// Registered webhooks
+    pub webhooks: Arc<WebhookRegistry>,
+    /// Startup progress reported by `/readyz`
+    pub readiness: Arc<Readiness>,
+    /// Recent daemon health results
+    pub health_cache: Arc<HealthCache>,
+    /// Latest service state, kept current by the poller
+    pub store: Arc<StateStore>,
+    /// Persistent state
+    pub db: Arc<Database>,
+    /// Manifest hashes of running services
+    pub drift: Arc<DriftTracker>,
+    /// Advertised methods of running services
+    pub capabilities: Arc<CapabilityTracker>,
+    /// Services being upgraded
+    pub upgrades: Arc<UpgradeLocks>,
+    /// One-time links and the sessions they started
+    pub grants: Arc<Grants>,
+    /// Usage counted against quotas
+    pub quotas: Arc<QuotaTracker>,
+    /// Latest released versions of services
+    pub updates: Arc<UpdateChecker>,
+    /// Next and running scheduled tasks
+    pub scheduler: Arc<Scheduler>,
+    /// The running chaos experiment
+    pub chaos: Arc<Chaos>,
+    /// The synthetic fleet served instead of real services, with `--demo`
+    pub demo: Option<Arc<Fleet>>,
+}
+
+/// Limits applied when the router is built, by config key
+fn limits(config: &Config) -> [(&'static str, usize); 5] {
+    [
+        ("max_concurrent_reads", config.max_concurrent_reads),
+        ("max_concurrent_control", config.max_concurrent_control),
+        ("max_concurrent_calls", config.max_concurrent_calls),
+        ("max_request_body", config.max_request_body),
+        ("max_rpc_upload_size", config.max_rpc_upload_size),
+    ]
+}
+
+impl AppState {
+    pub fn new(args: Args, config: &Config, webhooks: WebhookRegistry, db: Database) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        let history = db.recent_events(events::RECENT_EVENTS).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load stored events: {:#}", e);
+            Vec::new()
+        });
+
+        Self {
+            settings: Arc::new(RwLock::new(Arc::new(Settings::from_config(config)))),
+            args: Arc::new(args),
+            listen: SocketAddr::new(config.bind, config.port),
+            tls: config.tls.is_some(),
+            limits: limits(config),
+            data_dir: Arc::new(config.data_dir.clone()),
+            http,
+            events: Arc::new(EventBus::with_history(history)),
+            webhooks: Arc::new(webhooks),
+            readiness: Arc::new(Readiness::default()),
+            health_cache: Arc::new(HealthCache::default()),
+            store: Arc::new(StateStore::default()),
+            db: Arc::new(db),
+            drift: Arc::new(DriftTracker::default()),
+            capabilities: Arc::new(CapabilityTracker::default()),
+            upgrades: Arc::new(UpgradeLocks::default()),
+            grants: Arc::new(Grants::default()),
+            quotas: Arc::new(QuotaTracker::default()),
+            updates: Arc::new(UpdateChecker::default()),
+            scheduler: Arc::new(Scheduler::default()),
+            chaos: Arc::new(Chaos::default()),
+            demo: config.demo.then(|| Arc::new(Fleet::default())),
+        }
+    }
+
+    /// Locations of the files a backup covers
+    pub fn backup_paths(&self) -> backup::Paths {
+        backup::Paths {
+            data_dir: self.data_dir.as_ref().clone(),
+            config_file: self.args.config_path(),
+        }
+    }
+
+    /// Current reloadable settings
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Re-read the config file and apply settings that don't need a restart
+    ///
+    /// Returns the names of changed settings that only take effect after a
+    /// restart.
+    pub fn reload(&self) -> Result<Vec<&'static str>> {
+        // Rotated secrets are fetched again
+        secrets::clear_cache();
+        let config = self.args.as_ref().clone().resolve()?;
+        exposure::check(&config)?;
+        exposure::warn(&config);
+
+        let mut restart_required = Vec::new();
+        if SocketAddr::new(config.bind, config.port) != self.listen {
+            restart_required.push("bind/port");
+        }
+        if config.tls.is_some() != self.tls {
+            restart_required.push("tls");
+        }
+        if config.data_dir != *self.data_dir {
+            restart_required.push("data_dir");
+        }
+        for ((name, value), (_, current)) in limits(&config).into_iter().zip(self.limits) {
+            if value != current {
+                restart_required.push(name);
+            }
+        }
+
+        *self.settings.write().unwrap() = Arc::new(Settings::from_config(&config));
+
+        Ok(restart_required)
+    }
+
+    /// All hosts known to this dashboard
+    pub fn hosts(&self) -> Vec<HostInfo> {
+        vec![self.settings().host.clone()]
+    }
+
+    /// Scan local services into the store and health history, publishing
+    /// events for any status changes
+    ///
+    /// Cached health results are reused unless `options` asks for fresh
+    /// ones.
+    pub async fn scan(&self, options: ProbeOptions) -> Arc<Snapshot> {
+        let settings = self.settings();
+        let services = match &self.demo {
+            Some(fleet) => fleet.scan(&settings),
+            None => {
+                let mut services = api::scan_services(&settings, &self.health_cache, options).await;
+                self.drift.check(&mut services, &self.events).await;
+                self.capabilities.check(&mut services, &settings).await;
+                wasm::checks().check(&mut services).await;
+                self.updates
+                    .check(&mut services, &self.events, settings.update_alerts);
+                services
+            }
+        };
+        self.events.observe(&services);
+
+        let db = self.db.clone();
+        let samples = services.clone();
+        let timestamp = events::now();
+        if let Err(e) = calls::blocking(move || db.record_samples(timestamp, &samples)).await {
+            tracing::warn!("Failed to store health samples: {:#}", e);
+        }
+
+        let snapshot = self.store.update(services);
+        self.readiness.mark_scanned();
+        snapshot
+    }
+
+    /// Latest service state, scanning first if nothing has been stored yet
+    pub async fn services(&self) -> Arc<Snapshot> {
+        match self.store.snapshot() {
+            Some(snapshot) => snapshot,
+            None => self.scan(ProbeOptions::default()).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_names_are_single_path_components() {
+        for name in ["gateway", "payments-api", "v1.2", "..hidden"] {
+            assert!(is_service_name(name), "{}", name);
+        }
+        for name in [
+            "",
+            ".",
+            "..",
+            "payments-api/../billing",
+            "/etc",
+            "..\\billing",
+            "a\0b",
+        ] {
+            assert!(!is_service_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn socket_paths_refuse_other_names() {
+        let dir = Path::new("/srv/fgp");
+        assert!(socket_path(dir, "gateway").is_some());
+        assert!(socket_path(dir, "payments-api/../billing").is_none());
+        assert!(socket_path(dir, "..").is_none());
+    }
+}
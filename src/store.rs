@@ -0,0 +1,107 @@
+//! Central store of service state, kept current by a background poller.
+//!
+//! Handlers read the latest snapshot instead of scanning the services
+//! directories and probing sockets on every request, so API latency doesn't
+//! depend on how quickly daemons answer. The poller rescans every
+//! `--poll-interval` seconds (2 by default) and right after a service is
+//! started or stopped, or when the services directories change (see
+//! [`watcher`](crate::watcher)). `GET /api/services?fresh=true` still scans
+//! on the spot.
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::api::ServiceInfo;
+use crate::health_cache::ProbeOptions;
+use crate::state::AppState;
+use crate::watcher::ServicesWatcher;
+
+/// Poll interval used when none is configured
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 2;
+
+/// Delay between a refresh request and the rescan it triggers
+const REFRESH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The result of one scan
+pub struct Snapshot {
+    pub services: Vec<ServiceInfo>,
+    /// When the scan finished
+    pub taken_at: Instant,
+}
+
+impl Snapshot {
+    /// How old the oldest health result in the snapshot is
+    pub fn age(&self) -> Duration {
+        let probed = self
+            .services
+            .iter()
+            .filter_map(|service| service.cache_age)
+            .max()
+            .unwrap_or_default();
+        self.taken_at.elapsed() + probed
+    }
+}
+
+/// Latest service state
+#[derive(Default)]
+pub struct StateStore {
+    current: RwLock<Option<Arc<Snapshot>>>,
+    refresh: Notify,
+}
+
+impl StateStore {
+    /// The latest snapshot, if a scan has completed
+    pub fn snapshot(&self) -> Option<Arc<Snapshot>> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Replace the snapshot with the result of a scan
+    pub fn update(&self, services: Vec<ServiceInfo>) -> Arc<Snapshot> {
+        let snapshot = Arc::new(Snapshot {
+            services,
+            taken_at: Instant::now(),
+        });
+        *self.current.write().unwrap() = Some(snapshot.clone());
+        snapshot
+    }
+
+    /// Ask the poller to rescan now rather than at its next tick
+    pub fn request_refresh(&self) {
+        self.refresh.notify_one();
+    }
+}
+
+/// Keep the store current until the process exits
+pub fn spawn_poller(state: AppState) {
+    // The demo fleet has no directories to watch
+    let mut watcher = match state.demo {
+        Some(_) => None,
+        None => match ServicesWatcher::new(state.store.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                tracing::warn!("Cannot watch services directories, polling only: {}", e);
+                None
+            }
+        },
+    };
+
+    tokio::spawn(async move {
+        loop {
+            let settings = state.settings();
+            if let Some(watcher) = &mut watcher {
+                watcher.sync(&settings.services_dirs);
+            }
+
+            state.scan(ProbeOptions::default()).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(settings.poll_interval) => {}
+                _ = state.store.refresh.notified() => {
+                    // Let a burst of filesystem events settle
+                    tokio::time::sleep(REFRESH_DEBOUNCE).await;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,198 @@
+//! Services that systemd also supervises.
+//!
+//! With `--systemd`, every scan asks systemd about the unit of each service
+//! found in a services dir: the manifest's `systemd_unit`, or
+//! `fgp-<name>.service` when it names none. The user manager is asked first,
+//! then the system one; units neither knows are left out. A service's
+//! `supervisor` then carries the unit's active state, sub-state and whether
+//! it is enabled, next to the health its daemon reports over FGP.
+//!
+//! Starting, stopping and restarting such a service goes through
+//! `systemctl`, so systemd doesn't bring back a daemon stopped behind its
+//! back, and its log tab can read the unit's journal. The `systemctl` and
+//! `journalctl` CLIs are used rather than D-Bus.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::api::{self, ServiceInfo};
+use crate::docker::Lifecycle;
+use crate::drift;
+
+/// `manager` of units supervised by systemd
+pub const MANAGER: &str = "systemd";
+
+/// Manifest field naming a service's unit
+const MANIFEST_FIELD: &str = "systemd_unit";
+
+/// Unit properties read on every scan
+const PROPERTIES: &str = "Id,LoadState,ActiveState,SubState,UnitFileState,MainPID";
+
+/// What supervises a service's process, and what it thinks of it
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct Supervision {
+    /// The supervisor: `systemd`, or `launchd` for launch agents
+    pub manager: String,
+    pub unit: String,
+    /// Whether the unit belongs to the user's manager rather than the system's
+    pub user: bool,
+    /// `active`, `inactive`, `failed`, ...
+    pub state: String,
+    /// Finer state such as `running` or `exited`
+    pub sub_state: Option<String>,
+    /// `enabled`, `disabled`, `static`, ...
+    pub enabled: Option<String>,
+    /// PID of the unit's main process while it runs
+    pub pid: Option<u32>,
+}
+
+/// The unit expected to run a service
+fn unit_name(service: &ServiceInfo) -> String {
+    let dir = Path::new(&service.source).join(&service.name);
+    drift::manifest_field(&dir, MANIFEST_FIELD)
+        .filter(|unit| !unit.trim().is_empty())
+        .unwrap_or_else(|| format!("fgp-{}.service", service.name))
+}
+
+fn scope_args(user: bool) -> &'static [&'static str] {
+    if user {
+        &["--user"]
+    } else {
+        &[]
+    }
+}
+
+/// Run a systemd CLI, returning its standard output
+fn run(program: &str, user: bool, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(scope_args(user))
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The state of `units` known to one manager, by unit name
+fn show(units: &[String], user: bool) -> Result<HashMap<String, Supervision>, String> {
+    let mut args = vec!["show", "--property", PROPERTIES, "--"];
+    args.extend(units.iter().map(String::as_str));
+    let output = run("systemctl", user, &args)?;
+
+    // One block of `Key=Value` lines per unit, in the order asked for
+    let blocks = output
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty());
+    Ok(units
+        .iter()
+        .zip(blocks)
+        .filter_map(|(unit, block)| {
+            let properties: HashMap<&str, &str> = block
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .collect();
+            let get = |key: &str| {
+                properties
+                    .get(key)
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+            };
+            if matches!(get("LoadState"), None | Some("not-found")) {
+                return None;
+            }
+            let supervision = Supervision {
+                manager: MANAGER.to_string(),
+                unit: get("Id").unwrap_or(unit).to_string(),
+                user,
+                state: get("ActiveState").unwrap_or("unknown").to_string(),
+                sub_state: get("SubState").map(str::to_string),
+                enabled: get("UnitFileState").map(str::to_string),
+                pid: get("MainPID")
+                    .and_then(|pid| pid.parse().ok())
+                    .filter(|pid| *pid != 0),
+            };
+            Some((unit.clone(), supervision))
+        })
+        .collect())
+}
+
+/// Set `supervisor` on services systemd has a unit for
+///
+/// Blocks, so call it off the async runtime.
+pub fn annotate(services: &mut [ServiceInfo]) {
+    let units: Vec<String> = services
+        .iter()
+        .filter(|service| !api::is_adapter_source(&service.source))
+        .map(unit_name)
+        .collect();
+    if units.is_empty() {
+        return;
+    }
+
+    let mut known = HashMap::new();
+    for user in [false, true] {
+        match show(&units, user) {
+            // The user manager wins for units both know
+            Ok(found) => known.extend(found),
+            Err(e) => tracing::debug!("Failed to query systemd units: {}", e),
+        }
+    }
+
+    let supervised = services
+        .iter_mut()
+        .filter(|service| !api::is_adapter_source(&service.source));
+    for (service, unit) in supervised.zip(&units) {
+        service.supervisor = known.get(unit).cloned();
+    }
+}
+
+/// Start, stop or restart a service's unit
+///
+/// Blocks, so call it off the async runtime.
+pub fn control(supervision: &Supervision, lifecycle: Lifecycle) -> Result<(), String> {
+    run(
+        "systemctl",
+        supervision.user,
+        &[lifecycle.verb(), "--", &supervision.unit],
+    )
+    .map(|_| ())
+}
+
+/// The last `lines` lines of a unit's journal
+///
+/// Blocks, so call it off the async runtime.
+pub fn journal(supervision: &Supervision, lines: usize) -> Result<Vec<String>, String> {
+    let lines = lines.to_string();
+    let output = run(
+        "journalctl",
+        supervision.user,
+        &[
+            "--unit",
+            &supervision.unit,
+            "--lines",
+            &lines,
+            "--output",
+            "short-iso",
+            "--no-pager",
+            "--quiet",
+        ],
+    )?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+/// Whether `systemctl` can be run
+pub fn available() -> Result<String, String> {
+    let version = run("systemctl", false, &["--version"])?;
+    Ok(version.lines().next().unwrap_or_default().to_string())
+}
@@ -0,0 +1,61 @@
+//! Global request timeout.
+//!
+//! Requests that take longer than `--request-timeout` (10s by default, 0 to
+//! disable) get a 504 naming the backend call that was still in flight, so a
+//! hung daemon socket or peer can't hold a request open forever. The
+//! profiling endpoints under `/debug/` are exempt, as are backups and
+//! restores, which can take long and mustn't stop halfway.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::state::AppState;
+
+/// Timeout used when none is configured
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// Paths that may take as long as they need
+const EXEMPT: &[&str] = &["/api/admin/backup", "/api/admin/restore"];
+
+fn exempt(path: &str) -> bool {
+    path.starts_with("/debug/") || EXEMPT.contains(&path)
+}
+
+/// Fail requests that exceed the configured timeout with a 504
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let limit = state.settings().request_timeout;
+    if limit.is_zero() || exempt(req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    // Keep the handler alive until the in-flight call has been read; dropping
+    // it would clear the call
+    let response = next.run(req);
+    tokio::pin!(response);
+
+    tokio::select! {
+        response = &mut response => response,
+        _ = tokio::time::sleep(limit) => {
+            let message = match calls::in_flight() {
+                Some(call) => format!(
+                    "Request timed out after {}s waiting for {}",
+                    limit.as_secs_f64(),
+                    call
+                ),
+                None => format!("Request timed out after {}s", limit.as_secs_f64()),
+            };
+            tracing::warn!("{}", message);
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                ApiResponse::<()>::error(&message),
+            )
+                .into_response()
+        }
+    }
+}
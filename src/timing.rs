@@ -0,0 +1,59 @@
+//! Where the time in a daemon call goes.
+//!
+//! Socket calls are timed in two parts: getting a connection (connecting,
+//! or checking a pooled one out, including any retries) and the call
+//! itself. A socket that is slow to accept shows up in the first, a daemon
+//! that is slow to answer in the second. `fgp_daemon`'s client sends the
+//! request and waits for the response in one step, so writing and waiting
+//! are reported together; a write only stalls when the daemon isn't
+//! reading, which is the daemon being slow all the same.
+//!
+//! The passthrough and `/api/health/{service}` report the breakdown in a
+//! `Server-Timing` header, which browser dev tools display, and services
+//! carry their last probe's as `probe_timing`.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+/// Header carrying the breakdown of the daemon call behind a response
+pub const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// Breakdown of one daemon call
+#[derive(Clone, Copy, Default, Serialize, Deserialize, TS)]
+pub struct CallTiming {
+    /// Getting a connection, across all attempts
+    pub connect_ms: f64,
+    /// Sending the request and waiting for the response
+    pub call_ms: f64,
+    /// Connection attempts made
+    pub attempts: u32,
+    /// The connection came from the pool rather than a fresh connect
+    pub reused: bool,
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl CallTiming {
+    /// Add the time since `started` to the connect time
+    pub fn connected(&mut self, started: Instant) {
+        self.connect_ms += ms(started.elapsed());
+    }
+
+    /// Add the time since `started` to the call time
+    pub fn answered(&mut self, started: Instant) {
+        self.call_ms += ms(started.elapsed());
+    }
+
+    /// The breakdown as a `Server-Timing` value
+    pub fn header_value(&self) -> String {
+        format!(
+            "connect;dur={:.2};desc=\"{}\", call;dur={:.2}",
+            self.connect_ms,
+            if self.reused { "pooled" } else { "new" },
+            self.call_ms
+        )
+    }
+}
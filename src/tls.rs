@@ -0,0 +1,105 @@
+//! Serving the dashboard over HTTPS.
+//!
+//! `--tls-cert` and `--tls-key` name PEM files with the certificate chain
+//! and its private key. They are read at startup, so replacing them needs a
+//! restart.
+
+use anyhow::{Context, Result};
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// Longest a client may take to complete the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate and key files
+#[derive(Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Server settings presenting `identity`
+pub fn server_config(identity: &Identity) -> Result<Arc<rustls::ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(&identity.cert)
+        .with_context(|| format!("Failed to read {}", identity.cert.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate in {}", identity.cert.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("{} holds no certificate", identity.cert.display());
+    }
+    let key = PrivateKeyDer::from_pem_file(&identity.key).with_context(|| {
+        format!(
+            "Failed to read a private key from {}",
+            identity.key.display()
+        )
+    })?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| {
+            format!(
+                "{} doesn't match {}",
+                identity.key.display(),
+                identity.cert.display()
+            )
+        })?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Serve `app` over TLS, one task per connection
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: Arc<rustls::ServerConfig>,
+) -> Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually out of file descriptors; give connections time to close
+                tracing::warn!("Failed to accept a connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", remote, e);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {} timed out", remote);
+                        return;
+                    }
+                };
+            let service =
+                TowerToHyperService::new(app.layer(Extension(ConnectInfo::<SocketAddr>(remote))));
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Connection from {} failed: {}", remote, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,483 @@
+//! How the dashboard reaches daemons.
+//!
+//! On Unix each service listens on `daemon.sock` in its directory and calls
+//! go through `fgp_daemon`'s client. Windows has no Unix sockets for our
+//! purposes, so daemons there listen on the named pipe
+//! `\\.\pipe\fgp-<service>` and the dashboard speaks the same
+//! newline-delimited JSON frames over it. A service's "socket path" is
+//! whichever of the two applies, so the rest of the dashboard handles both
+//! alike: [`endpoint_path`] names it, [`endpoint_exists`] tells whether the
+//! daemon opened it and [`Client`] calls it.
+//!
+//! Named pipes are global rather than per directory, so on Windows a
+//! service's pipe is the same whichever services dir it is installed in.
+//!
+//! A daemon that can't share a socket, such as one in a container, can
+//! listen on TCP instead by declaring an `address` in its manifest:
+//! `host:port` or `tcp://host:port` for plain TCP, `tls://host:port` for
+//! TLS. TLS servers are verified against the usual public roots, or against
+//! the PEM certificates in the manifest's `tls_ca` (relative to the service
+//! directory) if given, with `tls_server_name` overriding the name checked.
+//! Such a service's socket path is its address, carrying the TLS options as
+//! a query (`tls://10.0.0.5:7443?ca=/srv/fgp/gateway/ca.pem`). TCP endpoints
+//! are always probed, and a refused connection counts as the daemon being
+//! stopped.
+//!
+//! Sockets inside containers that no bind mount exposes are reached
+//! through a `docker exec` proxy, with `docker://` socket paths, and those
+//! in pods through `kubectl exec`, with `k8s://` ones; see
+//! [`docker`](crate::docker) and [`kubernetes`](crate::kubernetes).
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::docker;
+use crate::drift;
+use crate::kubernetes;
+
+/// Name of the socket file inside each service directory
+pub const SOCKET_FILE: &str = "daemon.sock";
+
+/// Prefix of the named pipe each service listens on, on Windows
+pub const PIPE_PREFIX: &str = r"\\.\pipe\fgp-";
+
+/// Manifest field declaring a TCP address
+const ADDRESS_FIELD: &str = "address";
+
+/// Manifest field naming a CA bundle for TLS addresses
+const TLS_CA_FIELD: &str = "tls_ca";
+
+/// Manifest field overriding the name a TLS certificate is checked against
+const TLS_SERVER_NAME_FIELD: &str = "tls_server_name";
+
+/// Path of a service's FGP endpoint: its manifest's TCP address if it
+/// declares one, its socket or named pipe otherwise
+pub fn endpoint_path(services_dir: &Path, service: &str) -> PathBuf {
+    let dir = services_dir.join(service);
+    match drift::manifest_field(&dir, ADDRESS_FIELD) {
+        Some(address) => PathBuf::from(tcp_endpoint(&dir, &address)),
+        None => local_endpoint(services_dir, service),
+    }
+}
+
+#[cfg(not(windows))]
+fn local_endpoint(services_dir: &Path, service: &str) -> PathBuf {
+    services_dir.join(service).join(SOCKET_FILE)
+}
+
+#[cfg(windows)]
+fn local_endpoint(_services_dir: &Path, service: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", PIPE_PREFIX, service))
+}
+
+/// The endpoint for a manifest's `address`, with its TLS options
+fn tcp_endpoint(dir: &Path, address: &str) -> String {
+    let address = if address.contains("://") {
+        address.to_string()
+    } else {
+        format!("tcp://{}", address)
+    };
+    if !address.starts_with("tls://") {
+        return address;
+    }
+
+    let mut options = Vec::new();
+    if let Some(ca) = drift::manifest_field(dir, TLS_CA_FIELD) {
+        options.push(format!("ca={}", dir.join(ca).display()));
+    }
+    if let Some(name) = drift::manifest_field(dir, TLS_SERVER_NAME_FIELD) {
+        options.push(format!("server_name={}", name));
+    }
+    if options.is_empty() {
+        address
+    } else {
+        format!("{}?{}", address, options.join("&"))
+    }
+}
+
+/// A TCP endpoint, parsed from its socket path
+pub struct TcpEndpoint<'a> {
+    tls: bool,
+    /// `host:port`
+    address: &'a str,
+    ca: Option<&'a str>,
+    server_name: Option<&'a str>,
+}
+
+impl<'a> TcpEndpoint<'a> {
+    fn parse(endpoint: &'a Path) -> Option<Self> {
+        let endpoint = endpoint.to_str()?;
+        let (tls, rest) = if let Some(rest) = endpoint.strip_prefix("tcp://") {
+            (false, rest)
+        } else {
+            (true, endpoint.strip_prefix("tls://")?)
+        };
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let option = |key: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+        };
+        Some(Self {
+            tls,
+            address,
+            ca: option("ca"),
+            server_name: option("server_name"),
+        })
+    }
+
+    /// The host part of the address, without brackets around IPv6
+    fn host(&self) -> &'a str {
+        let host = self
+            .address
+            .rsplit_once(':')
+            .map_or(self.address, |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+/// Whether a service is reached over TCP
+pub fn is_tcp(endpoint: &Path) -> bool {
+    TcpEndpoint::parse(endpoint).is_some()
+}
+
+/// Whether a service is reached through a `docker exec` or `kubectl exec`
+/// proxy
+pub fn is_proxied(endpoint: &Path) -> bool {
+    endpoint.to_str().is_some_and(|endpoint| {
+        endpoint.starts_with(docker::SCHEME) || endpoint.starts_with(kubernetes::SCHEME)
+    })
+}
+
+/// Whether the daemon behind an endpoint has opened it
+///
+/// TCP and proxied endpoints can't be checked without connecting, so they
+/// always count as open.
+pub fn endpoint_exists(endpoint: &Path) -> bool {
+    is_tcp(endpoint) || is_proxied(endpoint) || local_endpoint_exists(endpoint)
+}
+
+#[cfg(not(windows))]
+fn local_endpoint_exists(endpoint: &Path) -> bool {
+    endpoint.exists()
+}
+
+/// Looked up in the pipe namespace, since opening a pipe to check for it
+/// would take up one of its instances
+#[cfg(windows)]
+fn local_endpoint_exists(endpoint: &Path) -> bool {
+    let Some(name) = endpoint
+        .to_str()
+        .and_then(|path| path.strip_prefix(r"\\.\pipe\"))
+    else {
+        return endpoint.exists();
+    };
+    std::fs::read_dir(r"\\.\pipe\").is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| entry.file_name().eq_ignore_ascii_case(name))
+    })
+}
+
+/// When an endpoint was created, which changes when its daemon restarts
+///
+/// Named pipes, TCP addresses and proxies have no such time, so this is
+/// `None` for them.
+pub fn endpoint_stamp(endpoint: &Path) -> Option<SystemTime> {
+    if cfg!(windows) || is_tcp(endpoint) || is_proxied(endpoint) {
+        return None;
+    }
+    std::fs::metadata(endpoint)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Whether an error means the endpoint is busy with other clients
+///
+/// All instances of a named pipe being in use is Windows' equivalent of a
+/// full listen backlog.
+pub fn is_busy(error: &io::Error) -> bool {
+    /// `ERROR_PIPE_BUSY`
+    const PIPE_BUSY: i32 = 231;
+    cfg!(windows) && error.raw_os_error() == Some(PIPE_BUSY)
+}
+
+/// Whether a failed connect to a TCP endpoint means nothing listens there
+pub fn is_refused(endpoint: &Path, error: &anyhow::Error) -> bool {
+    is_tcp(endpoint)
+        && error.chain().any(|cause| {
+            cause
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::ConnectionRefused)
+        })
+}
+
+/// A daemon's answer to a call
+pub struct Reply {
+    pub ok: bool,
+    pub result: Option<serde_json::Value>,
+    /// Error message, when `ok` is false
+    pub error: Option<String>,
+}
+
+/// A connection to one daemon
+pub enum Client {
+    #[cfg(not(windows))]
+    Socket(fgp_daemon::FgpClient),
+    /// Named pipes, TCP and proxies, which the dashboard frames itself
+    Stream(frames::StreamClient),
+}
+
+impl Client {
+    pub fn connect(endpoint: &Path) -> anyhow::Result<Self> {
+        if let Some(tcp) = TcpEndpoint::parse(endpoint) {
+            return Ok(Self::Stream(frames::StreamClient::tcp(&tcp)?));
+        }
+        let proxy = endpoint.to_str().and_then(|endpoint| {
+            docker::proxy_command(endpoint).or_else(|| kubernetes::proxy_command(endpoint))
+        });
+        if let Some(command) = proxy {
+            return Ok(Self::Stream(frames::StreamClient::child(command)?));
+        }
+        connect_local(endpoint)
+    }
+
+    pub fn call(&self, method: &str, params: serde_json::Value) -> anyhow::Result<Reply> {
+        match self {
+            #[cfg(not(windows))]
+            Self::Socket(client) => {
+                let response = client.call(method, params).map_err(anyhow::Error::from)?;
+                Ok(Reply {
+                    ok: response.ok,
+                    result: response.result,
+                    error: response.error.map(|e| e.message),
+                })
+            }
+            Self::Stream(client) => client.call(method, params),
+        }
+    }
+
+    pub fn health(&self) -> anyhow::Result<Reply> {
+        match self {
+            #[cfg(not(windows))]
+            Self::Socket(client) => {
+                let response = client.health().map_err(anyhow::Error::from)?;
+                Ok(Reply {
+                    ok: response.ok,
+                    result: response.result,
+                    error: response.error.map(|e| e.message),
+                })
+            }
+            Self::Stream(client) => client.call("health", serde_json::json!({})),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn connect_local(endpoint: &Path) -> anyhow::Result<Client> {
+    let client = fgp_daemon::FgpClient::new(endpoint).map_err(anyhow::Error::from)?;
+    Ok(Client::Socket(client))
+}
+
+#[cfg(windows)]
+fn connect_local(endpoint: &Path) -> anyhow::Result<Client> {
+    Ok(Client::Stream(frames::StreamClient::pipe(endpoint)?))
+}
+
+mod frames {
+    use anyhow::{bail, Context};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::time::Duration;
+
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, ServerName};
+
+    use super::{Reply, TcpEndpoint};
+
+    /// FGP protocol version sent with each request
+    const PROTOCOL_VERSION: u64 = 1;
+
+    /// Longest a TCP connect may take
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Longest a TCP daemon may take to answer; probes and requests have
+    /// their own, usually shorter, limits on top
+    const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+    trait Stream: Read + Write + Send {}
+
+    impl<T: Read + Write + Send> Stream for T {}
+
+    /// A proxy process, talked to over its stdin and stdout
+    struct ChildStream {
+        child: Child,
+        stdin: ChildStdin,
+        stdout: ChildStdout,
+    }
+
+    impl Read for ChildStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.stdout.read(buf)
+        }
+    }
+
+    impl Write for ChildStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.stdin.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.stdin.flush()
+        }
+    }
+
+    impl Drop for ChildStream {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// A connection speaking newline-delimited JSON frames
+    pub struct StreamClient {
+        stream: Mutex<BufReader<Box<dyn Stream>>>,
+        next_id: AtomicU64,
+    }
+
+    impl StreamClient {
+        fn new(stream: Box<dyn Stream>) -> Self {
+            Self {
+                stream: Mutex::new(BufReader::new(stream)),
+                next_id: AtomicU64::new(1),
+            }
+        }
+
+        #[cfg(windows)]
+        pub fn pipe(endpoint: &std::path::Path) -> anyhow::Result<Self> {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(endpoint)?;
+            Ok(Self::new(Box::new(file)))
+        }
+
+        pub fn tcp(endpoint: &TcpEndpoint) -> anyhow::Result<Self> {
+            let addr = endpoint
+                .address
+                .to_socket_addrs()
+                .with_context(|| format!("cannot resolve {}", endpoint.address))?
+                .next()
+                .with_context(|| format!("{} has no addresses", endpoint.address))?;
+            let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+            stream.set_read_timeout(Some(IO_TIMEOUT))?;
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            if !endpoint.tls {
+                return Ok(Self::new(Box::new(stream)));
+            }
+
+            let server_name =
+                ServerName::try_from(endpoint.server_name.unwrap_or(endpoint.host()).to_string())
+                    .context("invalid TLS server name")?;
+            let config = match endpoint.ca {
+                Some(ca) => tls_config(Some(ca))?,
+                None => default_tls_config()?,
+            };
+            let connection = rustls::ClientConnection::new(config, server_name)?;
+            Ok(Self::new(Box::new(rustls::StreamOwned::new(
+                connection, stream,
+            ))))
+        }
+
+        pub fn child(mut command: Command) -> anyhow::Result<Self> {
+            let mut child = command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .context("cannot start the socket proxy")?;
+            let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                let _ = child.kill();
+                bail!("the socket proxy has no stdin or stdout");
+            };
+            Ok(Self::new(Box::new(ChildStream {
+                child,
+                stdin,
+                stdout,
+            })))
+        }
+
+        /// Send one request and read its response
+        pub fn call(&self, method: &str, params: serde_json::Value) -> anyhow::Result<Reply> {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+            let mut request = serde_json::to_string(&serde_json::json!({
+                "id": id,
+                "v": PROTOCOL_VERSION,
+                "method": method,
+                "params": params,
+            }))?;
+            request.push('\n');
+
+            let mut stream = self.stream.lock().unwrap();
+            stream.get_mut().write_all(request.as_bytes())?;
+            stream.get_mut().flush()?;
+            let mut line = String::new();
+            if stream.read_line(&mut line)? == 0 {
+                bail!("the daemon closed the connection");
+            }
+
+            let response: serde_json::Value =
+                serde_json::from_str(&line).context("invalid response from the daemon")?;
+            let error = response.get("error").and_then(|error| {
+                error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .or_else(|| error.as_str())
+                    .map(str::to_string)
+            });
+            Ok(Reply {
+                ok: response["ok"].as_bool().unwrap_or(false),
+                result: response.get("result").filter(|r| !r.is_null()).cloned(),
+                error,
+            })
+        }
+    }
+
+    /// TLS settings trusting the public roots, built once
+    fn default_tls_config() -> anyhow::Result<Arc<rustls::ClientConfig>> {
+        static CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
+        if let Some(config) = CONFIG.get() {
+            return Ok(config.clone());
+        }
+        let config = tls_config(None)?;
+        Ok(CONFIG.get_or_init(|| config).clone())
+    }
+
+    /// TLS settings trusting the certificates in `ca`, or the public roots
+    fn tls_config(ca: Option<&str>) -> anyhow::Result<Arc<rustls::ClientConfig>> {
+        let mut roots = rustls::RootCertStore::empty();
+        match ca {
+            Some(ca) => {
+                for cert in CertificateDer::pem_file_iter(ca)
+                    .with_context(|| format!("cannot read {}", ca))?
+                {
+                    roots
+                        .add(cert.with_context(|| format!("invalid certificate in {}", ca))?)
+                        .with_context(|| format!("invalid certificate in {}", ca))?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Arc::new(config))
+    }
+}
@@ -0,0 +1,96 @@
+//! TypeScript definitions for the REST API types.
+//!
+//! `GET /api/types.d.ts` is generated from the Rust structs with ts-rs, so
+//! frontends consuming the API can't drift out of sync with it.
+
+use axum::{http::header, response::IntoResponse};
+use ts_rs::TS;
+
+use crate::annotations::Annotation;
+use crate::api::{ApiResponse, ServiceInfo};
+use crate::bench::{BenchReport, Latency};
+use crate::capabilities::Capabilities;
+use crate::chaos::{ChaosAction, ChaosStatus, ChaosStrike};
+use crate::events::Event;
+use crate::history::HistoryPoint;
+use crate::hosts::HostInfo;
+use crate::namespaces::{NamespaceInfo, NamespaceList};
+use crate::plugins::{PluginAction, PluginFailure, PluginInfo, PluginList};
+use crate::quotas::{QuotaReport, QuotaUsage, Quotas};
+use crate::scaffold::Scaffolded;
+use crate::scheduler::{TaskInfo, TaskRun};
+use crate::scripts::{ScriptFailure, ScriptInfo, ScriptList};
+use crate::service_config::ServiceConfig;
+use crate::systemd::Supervision;
+use crate::timing::CallTiming;
+use crate::ui::{BrandingInfo, ConfirmStop, FooterLink, ProtectRule, UiSettings};
+use crate::version::BuildInfo;
+use crate::wasm::CheckInfo;
+use crate::webhooks::{Delivery, SubscriptionView};
+use crate::widgets::{Widget, WidgetKind};
+
+/// Render all exported declarations as a single `.d.ts` file
+pub fn definitions() -> String {
+    let declarations = [
+        ApiResponse::<()>::decl(),
+        ServiceInfo::decl(),
+        Capabilities::decl(),
+        Supervision::decl(),
+        CallTiming::decl(),
+        Latency::decl(),
+        BenchReport::decl(),
+        Annotation::decl(),
+        HostInfo::decl(),
+        Event::decl(),
+        HistoryPoint::decl(),
+        SubscriptionView::decl(),
+        NamespaceInfo::decl(),
+        NamespaceList::decl(),
+        Quotas::decl(),
+        QuotaUsage::decl(),
+        QuotaReport::decl(),
+        Delivery::decl(),
+        Scaffolded::decl(),
+        PluginAction::decl(),
+        PluginInfo::decl(),
+        PluginFailure::decl(),
+        PluginList::decl(),
+        CheckInfo::decl(),
+        ScriptInfo::decl(),
+        ScriptFailure::decl(),
+        ScriptList::decl(),
+        ServiceConfig::decl(),
+        TaskRun::decl(),
+        TaskInfo::decl(),
+        ChaosAction::decl(),
+        ChaosStrike::decl(),
+        ChaosStatus::decl(),
+        WidgetKind::decl(),
+        Widget::decl(),
+        BuildInfo::decl(),
+        ConfirmStop::decl(),
+        ProtectRule::decl(),
+        FooterLink::decl(),
+        BrandingInfo::decl(),
+        UiSettings::decl(),
+    ];
+
+    let mut out = String::from("// Generated by fgp-dashboard. Do not edit.\n\n");
+    for declaration in declarations {
+        out.push_str("export ");
+        out.push_str(&declaration);
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Serve the TypeScript definitions
+pub async fn type_definitions() -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/typescript; charset=utf-8",
+        )],
+        definitions(),
+    )
+}
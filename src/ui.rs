@@ -0,0 +1,215 @@
+//! Server-side settings for the web UI.
+//!
+//! `GET /api/ui` returns the settings below, which belong to the deployment
+//! rather than to a browser, so they live in the config file instead of the
+//! preferences store.
+//!
+//! Stopping a service asks for confirmation according to `--confirm-stop`:
+//! `always` (the default), `protected` or `never`. Services matching a
+//! `--protect KEY=VALUE` rule, by label or annotation, always need their name
+//! typed in, whatever the mode. Confirmed stops are held in the browser for
+//! `--stop-undo-seconds` (5 by default, 0 disables) so they can be undone
+//! before they are sent.
+//!
+//! The recommended refresh interval is the poller's (`--poll-interval`):
+//! the service list can't change faster than that, so the UI never refreshes
+//! more often, whatever the `refresh_interval` preference says.
+//!
+//! The `[branding]` section of the config file white-labels the UI: a
+//! title replacing "FGP Dashboard", a logo file served at `/api/ui/logo`,
+//! accent colors (with an optional light-theme variant) and footer links.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::etag;
+use crate::state::AppState;
+
+/// Undo window used when none is configured
+pub const DEFAULT_STOP_UNDO_SECS: u64 = 5;
+
+/// When stopping a service asks for confirmation
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, TS, Clone, Copy, PartialEq, Eq, Debug, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmStop {
+    /// Every stop
+    #[default]
+    Always,
+    /// Only stops of protected services
+    Protected,
+    /// Never, except for protected services
+    Never,
+}
+
+/// A label or annotation that marks a service as protected
+#[derive(Serialize, TS, Clone)]
+pub struct ProtectRule {
+    pub key: String,
+    pub value: String,
+}
+
+/// A link in the UI footer
+#[derive(Deserialize, Serialize, TS, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FooterLink {
+    pub label: String,
+    pub url: String,
+}
+
+/// The `[branding]` section of the config file
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Branding {
+    pub title: Option<String>,
+    /// Image file shown next to the title and used as the favicon
+    pub logo: Option<PathBuf>,
+    /// CSS color, e.g. `#7c3aed`
+    pub accent_color: Option<String>,
+    /// Accent for the light theme, if it should differ
+    pub accent_color_light: Option<String>,
+    pub footer_links: Vec<FooterLink>,
+}
+
+/// Whether `color` is a hex color or a named one, so it can't smuggle
+/// other CSS into the page
+fn is_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => {
+            matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    }
+}
+
+impl Branding {
+    /// Reject colors and links the UI shouldn't apply
+    pub fn validate(&self) -> Result<(), String> {
+        for color in [&self.accent_color, &self.accent_color_light]
+            .into_iter()
+            .flatten()
+        {
+            if !is_color(color) {
+                return Err(format!(
+                    "invalid branding color '{}', expected #rrggbb or a color name",
+                    color
+                ));
+            }
+        }
+        for link in &self.footer_links {
+            let url = link.url.to_ascii_lowercase();
+            if !(url.starts_with("https://") || url.starts_with("http://") || url.starts_with('/'))
+            {
+                return Err(format!(
+                    "invalid footer link '{}', expected an http(s) URL or a path",
+                    link.url
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Branding as the UI sees it
+#[derive(Serialize, TS)]
+pub struct BrandingInfo {
+    pub title: Option<String>,
+    /// Set when a logo is configured
+    pub logo_url: Option<String>,
+    pub accent_color: Option<String>,
+    pub accent_color_light: Option<String>,
+    pub footer_links: Vec<FooterLink>,
+}
+
+/// What `GET /api/ui` reports
+#[derive(Serialize, TS)]
+pub struct UiSettings {
+    pub confirm_stop: ConfirmStop,
+    pub protect: Vec<ProtectRule>,
+    /// Seconds a confirmed stop waits before it is sent
+    #[ts(type = "number")]
+    pub stop_undo_seconds: u64,
+    /// Shortest useful refresh interval, in seconds
+    #[ts(type = "number")]
+    pub refresh_interval_seconds: u64,
+    pub branding: BrandingInfo,
+}
+
+/// Settings for the web UI
+pub async fn ui_settings(State(state): State<AppState>) -> impl IntoResponse {
+    let settings = state.settings();
+    ApiResponse::success(UiSettings {
+        confirm_stop: settings.confirm_stop,
+        protect: settings
+            .protect
+            .iter()
+            .map(|(key, value)| ProtectRule {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect(),
+        stop_undo_seconds: settings.stop_undo.as_secs(),
+        refresh_interval_seconds: settings.poll_interval.as_secs().max(1),
+        branding: BrandingInfo {
+            title: settings.branding.title.clone(),
+            logo_url: settings
+                .branding
+                .logo
+                .as_ref()
+                .map(|_| "/api/ui/logo".to_string()),
+            accent_color: settings.branding.accent_color.clone(),
+            accent_color_light: settings.branding.accent_color_light.clone(),
+            footer_links: settings.branding.footer_links.clone(),
+        },
+    })
+}
+
+/// The configured logo
+pub async fn logo(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(path) = state.settings().branding.logo.clone() else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<()>::error("No logo configured"),
+        )
+            .into_response();
+    };
+    let body = match tokio::fs::read(&path).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("Failed to read logo {}: {}", path.display(), e);
+            return (
+                StatusCode::NOT_FOUND,
+                ApiResponse::<()>::error(&format!("Failed to read logo: {}", e)),
+            )
+                .into_response();
+        }
+    };
+
+    let etag = etag::etag_for(&body);
+    let etag_value = HeaderValue::from_str(&etag).expect("ETag is valid ASCII");
+    if etag::if_none_match(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response();
+    }
+    let content_type = mime_guess::from_path(&path).first_or_octet_stream();
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(content_type.as_ref())
+                    .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+            ),
+            (header::ETAG, etag_value),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        body,
+    )
+        .into_response()
+}
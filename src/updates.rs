@@ -0,0 +1,218 @@
+//! Noticing services that have a newer release out.
+//!
+//! Every `--update-check-interval` (6h by default) the latest version of
+//! each installed service is looked up in two places:
+//!
+//! - the `--update-index`, an http(s) URL or local file holding a JSON
+//!   object that maps service names to versions, or to objects with a
+//!   `version` or `latest` field, optionally nested under `services`
+//! - a `latest_version_url` in the service's own manifest, answering with
+//!   the same kind of object or with the bare version as text; this wins
+//!   over the index
+//!
+//! Scans then set `latest_version` on each service found and `outdated` on
+//! those whose daemon reports an older version. With `--update-alerts`, an
+//! `update_available` event is published the first time a service is seen
+//! behind a given release, which webhooks and the UI's notifications pick up
+//! as a low-priority notice.
+//!
+//! Versions compare as dotted numbers; a leading `v` and anything after `-`
+//! or `+` are ignored. A lookup that fails keeps the previous answer.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::api::ServiceInfo;
+use crate::calls;
+use crate::drift;
+use crate::events::{self, EventBus};
+use crate::state::AppState;
+
+/// How often latest versions are looked up when not configured
+pub const DEFAULT_CHECK_INTERVAL_SECS: u64 = 6 * 3600;
+
+/// Manifest field naming where a service publishes its latest version
+const MANIFEST_FIELD: &str = "latest_version_url";
+
+/// Latest known versions of installed services
+#[derive(Default)]
+pub struct UpdateChecker {
+    latest: Mutex<HashMap<String, String>>,
+    /// Release each service was last alerted about
+    alerted: Mutex<HashMap<String, String>>,
+}
+
+impl UpdateChecker {
+    /// Set `latest_version` and `outdated` on scanned services, publishing
+    /// an event for newly outdated ones if `alert` is set
+    pub fn check(&self, services: &mut [ServiceInfo], events: &EventBus, alert: bool) {
+        let latest = self.latest.lock().unwrap();
+        let mut alerted = self.alerted.lock().unwrap();
+
+        for service in services.iter_mut() {
+            let Some(newest) = latest.get(&service.name) else {
+                continue;
+            };
+            service.latest_version = Some(newest.clone());
+            service.outdated = service
+                .version
+                .as_deref()
+                .is_some_and(|running| is_newer(newest, running));
+
+            if service.outdated && alert && alerted.get(&service.name) != Some(newest) {
+                events.publish(
+                    &service.name,
+                    events::UPDATE_AVAILABLE,
+                    format!(
+                        "version {} is available (running {})",
+                        newest,
+                        service.version.as_deref().unwrap_or("unknown")
+                    ),
+                );
+                alerted.insert(service.name.clone(), newest.clone());
+            }
+        }
+    }
+
+    /// Look up the latest version of every installed service
+    pub async fn refresh(&self, state: &AppState) {
+        let settings = state.settings();
+        let previous = self.latest.lock().unwrap().clone();
+
+        let mut latest = match &settings.update_index {
+            Some(index) => match fetch(&state.http, index)
+                .await
+                .and_then(|text| parse_index(&text))
+            {
+                Ok(latest) => latest,
+                Err(e) => {
+                    tracing::warn!("Failed to read update index {}: {}", index, e);
+                    previous.clone()
+                }
+            },
+            None => HashMap::new(),
+        };
+
+        let snapshot = state.services().await;
+        for service in &snapshot.services {
+            let dir = Path::new(&service.source).join(&service.name);
+            let Some(url) =
+                calls::blocking(move || drift::manifest_field(&dir, MANIFEST_FIELD)).await
+            else {
+                continue;
+            };
+            let version = fetch(&state.http, &url).await.and_then(|text| {
+                parse_version(&text).ok_or_else(|| "no version in the response".to_string())
+            });
+            match version {
+                Ok(version) => {
+                    latest.insert(service.name.clone(), version);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to check {} for updates: {}", service.name, e);
+                    match previous.get(&service.name) {
+                        Some(version) => latest.insert(service.name.clone(), version.clone()),
+                        None => latest.remove(&service.name),
+                    };
+                }
+            }
+        }
+
+        *self.latest.lock().unwrap() = latest;
+    }
+}
+
+/// Look up latest versions, rescanning afterwards so services are flagged
+/// without waiting for the next poll; the scheduler runs this every
+/// `--update-check-interval`
+pub async fn check_now(state: &AppState) -> Result<String, String> {
+    state.updates.refresh(state).await;
+    state.store.request_refresh();
+    Ok("Looked up latest versions".to_string())
+}
+
+/// Read a URL or local file
+async fn fetch(http: &reqwest::Client, source: &str) -> Result<String, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        http.get(source)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    } else {
+        tokio::fs::read_to_string(source)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Service versions from an update index
+fn parse_index(text: &str) -> Result<HashMap<String, String>, String> {
+    let index: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+    let entries = index
+        .get("services")
+        .unwrap_or(&index)
+        .as_object()
+        .ok_or("expected an object of service versions")?;
+    Ok(entries
+        .iter()
+        .filter_map(|(name, entry)| Some((name.clone(), version_of(entry)?)))
+        .collect())
+}
+
+/// A version given as a string or as an object's `version` or `latest`
+fn version_of(entry: &serde_json::Value) -> Option<String> {
+    let version = match entry {
+        serde_json::Value::String(version) => version.as_str(),
+        _ => entry
+            .get("version")
+            .or_else(|| entry.get("latest"))?
+            .as_str()?,
+    };
+    let version = version.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// The version in a `latest_version_url` response
+fn parse_version(text: &str) -> Option<String> {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => version_of(&value),
+        Err(_) => {
+            let version = text.lines().next()?.trim();
+            (!version.is_empty()).then(|| version.to_string())
+        }
+    }
+}
+
+/// Numeric parts of a version, without a leading `v` or any suffix
+fn parts(version: &str) -> Vec<u64> {
+    let version = version.trim();
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or(0))
+        .collect()
+}
+
+/// Whether `candidate` is a later version than `current`
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    let (candidate, current) = (parts(candidate), parts(current));
+    for i in 0..candidate.len().max(current.len()) {
+        let (a, b) = (
+            candidate.get(i).copied().unwrap_or(0),
+            current.get(i).copied().unwrap_or(0),
+        );
+        if a != b {
+            return a > b;
+        }
+    }
+    false
+}
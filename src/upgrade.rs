@@ -0,0 +1,528 @@
+//! Coordinated daemon upgrades.
+//!
+//! `POST /api/upgrade/{service}` replaces a service's files with a new
+//! release and restarts it, putting the old files back if the new daemon
+//! doesn't come up:
+//!
+//! ```text
+//! {"source": "/opt/releases/gateway-1.5.tar.gz", "sha256": "9f2c…", "version": "1.5.0"}
+//! ```
+//!
+//! `source` is a directory or a `.tar.gz` holding the release's files (at
+//! its root or in a single top-level directory), which must include a
+//! manifest. It is checked against `sha256` if given and staged before the
+//! request returns, so a bad release is refused with 400 and nothing is
+//! touched. The rest runs in the background with 202 Accepted: stop the
+//! daemon, move the release's files into the service directory (keeping
+//! the ones they replace), start it and wait up to
+//! `health_timeout_seconds` (30 by default) for it to report healthy, and
+//! the requested `version` if one was given. Any failure after the stop
+//! rolls back: the new files are removed, the old ones restored and the
+//! old daemon started again if it was running. Files the release doesn't
+//! contain, such as logs and data, are left alone.
+//!
+//! Every step is published as an `upgrade_progress` event, and the outcome
+//! as `upgraded` or `upgrade_rolled_back`, so the events stream shows the
+//! upgrade as it happens. Daemons speaking an incompatible protocol can be
+//! upgraded, since that is usually the fix.
+
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::{self, ApiResponse, HealthOutcome};
+use crate::calls;
+use crate::drift::MANIFEST_FILES;
+use crate::events::{self, EventBus};
+use crate::protocol;
+use crate::sockets;
+use crate::state::AppState;
+use crate::transport;
+
+/// How long a new daemon has to report healthy when the request doesn't say
+const DEFAULT_HEALTH_TIMEOUT_SECS: u64 = 30;
+
+/// Delay between health checks of a new daemon
+const HEALTH_POLL: Duration = Duration::from_secs(1);
+
+/// Directory inside a services dir holding staged releases and the files
+/// they replace; hidden, so it isn't taken for a service
+const UPGRADES_DIR: &str = ".fgp-upgrades";
+
+/// Runtime files that belong to the running daemon, never to a release
+const RUNTIME_FILES: &[&str] = &[transport::SOCKET_FILE, "daemon.pid"];
+
+#[derive(Deserialize)]
+pub struct UpgradeRequest {
+    /// Directory or `.tar.gz` holding the new release's files
+    pub source: PathBuf,
+    /// Expected SHA-256 of `source`, when it is an archive
+    pub sha256: Option<String>,
+    /// Version the upgraded daemon must report
+    pub version: Option<String>,
+    pub health_timeout_seconds: Option<u64>,
+}
+
+/// Services with an upgrade in progress
+#[derive(Default)]
+pub struct UpgradeLocks {
+    running: Mutex<HashSet<String>>,
+}
+
+impl UpgradeLocks {
+    /// Mark a service as upgrading, unless it already is
+    fn acquire(self: &Arc<Self>, service: &str) -> Option<UpgradeLock> {
+        self.running
+            .lock()
+            .unwrap()
+            .insert(service.to_string())
+            .then(|| UpgradeLock {
+                locks: self.clone(),
+                service: service.to_string(),
+            })
+    }
+}
+
+/// An upgrade in progress, ended when dropped
+struct UpgradeLock {
+    locks: Arc<UpgradeLocks>,
+    service: String,
+}
+
+impl Drop for UpgradeLock {
+    fn drop(&mut self) {
+        self.locks.running.lock().unwrap().remove(&self.service);
+    }
+}
+
+/// Where one upgrade keeps its files, removed when dropped
+struct Workspace {
+    root: PathBuf,
+    /// The staged release
+    release: PathBuf,
+    /// Files the release replaced
+    previous: PathBuf,
+    /// Left in place for recovery by hand
+    keep: bool,
+}
+
+impl Workspace {
+    fn new(services_dir: &FsPath, service: &str) -> Self {
+        let root =
+            services_dir
+                .join(UPGRADES_DIR)
+                .join(format!("{}-{}", service, uuid::Uuid::new_v4()));
+        Self {
+            release: root.join("release"),
+            previous: root.join("previous"),
+            root,
+            keep: false,
+        }
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn sha256_file(path: &FsPath) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn copy_dir(from: &FsPath, to: &FsPath) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a release and copy it into the workspace
+///
+/// Blocks, so call it off the async runtime.
+fn stage(request: &UpgradeRequest, workspace: &Workspace) -> Result<()> {
+    let source = &request.source;
+    let metadata =
+        fs::metadata(source).with_context(|| format!("Cannot read {}", source.display()))?;
+    let unpacked = workspace.root.join("unpacked");
+
+    if metadata.is_dir() {
+        if request.sha256.is_some() {
+            bail!("sha256 can only be checked for an archive");
+        }
+        copy_dir(source, &unpacked)?;
+    } else {
+        if let Some(expected) = &request.sha256 {
+            let actual = sha256_file(source)?;
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                bail!("Checksum mismatch: expected {}, got {}", expected, actual);
+            }
+        }
+        fs::create_dir_all(&unpacked)?;
+        let file = fs::File::open(source)?;
+        tar::Archive::new(GzDecoder::new(file))
+            .unpack(&unpacked)
+            .with_context(|| format!("{} is not a .tar.gz archive", source.display()))?;
+    }
+
+    // Archives often wrap the files in one top-level directory
+    let has_manifest = |dir: &FsPath| MANIFEST_FILES.iter().any(|name| dir.join(name).exists());
+    let mut root = unpacked.clone();
+    if !has_manifest(&root) {
+        let entries: Vec<_> = fs::read_dir(&root)?.flatten().collect();
+        if let [only] = entries.as_slice() {
+            if only.file_type()?.is_dir() {
+                root = only.path();
+            }
+        }
+    }
+    if !has_manifest(&root) {
+        bail!(
+            "Not a service release: none of {} found",
+            MANIFEST_FILES.join(", ")
+        );
+    }
+    for name in RUNTIME_FILES {
+        let _ = fs::remove_file(root.join(name));
+    }
+
+    fs::rename(&root, &workspace.release)
+        .with_context(|| format!("Failed to stage {}", workspace.release.display()))?;
+    let _ = fs::remove_dir_all(&unpacked);
+    fs::create_dir_all(&workspace.previous)?;
+    Ok(())
+}
+
+/// Move the release's files into the service directory, keeping the ones
+/// they replace
+///
+/// Adds each name to `swapped` once the file it replaces is set aside, so
+/// a failure part way can be rolled back too. Blocks.
+fn swap(workspace: &Workspace, service_dir: &FsPath, swapped: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(&workspace.release)? {
+        let name = entry?.file_name();
+        let current = service_dir.join(&name);
+        if current.exists() {
+            fs::rename(&current, workspace.previous.join(&name))
+                .with_context(|| format!("Failed to set aside {}", current.display()))?;
+        }
+        swapped.push(name.to_string_lossy().into_owned());
+        fs::rename(workspace.release.join(&name), &current)
+            .with_context(|| format!("Failed to install {}", current.display()))?;
+    }
+    Ok(())
+}
+
+/// Undo [`swap`] for the names it moved in. Blocks.
+fn restore(workspace: &Workspace, service_dir: &FsPath, swapped: &[String]) -> Result<()> {
+    for name in swapped {
+        let current = service_dir.join(name);
+        if current.is_dir() {
+            fs::remove_dir_all(&current)?;
+        } else if current.exists() {
+            fs::remove_file(&current)?;
+        }
+        let previous = workspace.previous.join(name);
+        if previous.exists() {
+            fs::rename(&previous, &current)
+                .with_context(|| format!("Failed to restore {}", current.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Wait for the daemon to report healthy, and `version` if given
+async fn wait_healthy(
+    state: &AppState,
+    service: &str,
+    version: Option<&str>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let settings = state.settings();
+    let socket_path = settings
+        .socket_path(service)
+        .ok_or_else(|| format!("Service '{}' not found", service))?;
+    let started = Instant::now();
+    let mut last_error = "the daemon never opened its socket".to_string();
+
+    while started.elapsed() < timeout {
+        tokio::time::sleep(HEALTH_POLL).await;
+        if !transport::endpoint_exists(&socket_path) {
+            continue;
+        }
+        let (outcome, _) = api::check_health(
+            socket_path.clone(),
+            settings.socket_retries,
+            settings.health_timeout(service),
+            false,
+        )
+        .await;
+        match outcome {
+            HealthOutcome::Healthy(health) => {
+                let status = health["status"].as_str().unwrap_or("running");
+                let reported = health["version"].as_str();
+                if let Some(mismatch) = protocol::reported_version(&health)
+                    .as_deref()
+                    .and_then(protocol::mismatch)
+                {
+                    return Err(mismatch);
+                }
+                if !matches!(status, "running" | "healthy") {
+                    last_error = format!("the daemon reports '{}'", status);
+                } else if version.is_some_and(|version| reported != Some(version)) {
+                    return Err(format!(
+                        "the daemon reports version {}, expected {}",
+                        reported.unwrap_or("-"),
+                        version.unwrap_or_default()
+                    ));
+                } else {
+                    return Ok(());
+                }
+            }
+            HealthOutcome::Failed { error, .. } => last_error = error,
+        }
+    }
+    Err(format!(
+        "not healthy after {}s: {}",
+        timeout.as_secs(),
+        last_error
+    ))
+}
+
+fn progress(events: &EventBus, service: &str, message: String) {
+    tracing::info!(service, "{}", message);
+    events.publish(service, events::UPGRADE_PROGRESS, message);
+}
+
+async fn start_daemon(service: &str) -> Result<(), String> {
+    let name = service.to_string();
+    calls::blocking(move || fgp_daemon::start_service(&name).map_err(|e| e.to_string())).await
+}
+
+async fn stop_daemon(service: &str) -> Result<(), String> {
+    let name = service.to_string();
+    calls::blocking(move || fgp_daemon::stop_service(&name).map_err(|e| e.to_string())).await
+}
+
+/// Stop, swap, start and check, rolling back on failure
+async fn run(
+    state: AppState,
+    service: String,
+    request: UpgradeRequest,
+    workspace: Workspace,
+    service_dir: PathBuf,
+) {
+    let events = &state.events;
+    let was_running = state.services().await.services.iter().any(|info| {
+        info.name == service && !matches!(info.status.as_str(), "stopped" | sockets::STALE)
+    });
+
+    if was_running {
+        progress(events, &service, format!("Stopping '{}'", service));
+        if let Err(e) = stop_daemon(&service).await {
+            let message = format!("Upgrade of '{}' aborted: stop failed: {}", service, e);
+            events.publish(&service, events::UPGRADE_ROLLED_BACK, message);
+            return;
+        }
+    }
+
+    progress(
+        events,
+        &service,
+        format!("Installing the new release of '{}'", service),
+    );
+    let (workspace, swapped, installed) = {
+        let service_dir = service_dir.clone();
+        calls::blocking(move || {
+            let mut swapped = Vec::new();
+            let installed = swap(&workspace, &service_dir, &mut swapped);
+            (workspace, swapped, installed)
+        })
+        .await
+    };
+
+    let failure = match installed {
+        Err(e) => Some(format!("install failed: {:#}", e)),
+        Ok(_) => {
+            progress(events, &service, format!("Starting '{}'", service));
+            match start_daemon(&service).await {
+                Err(e) => Some(format!("start failed: {}", e)),
+                Ok(()) => {
+                    progress(
+                        events,
+                        &service,
+                        format!("Waiting for '{}' to report healthy", service),
+                    );
+                    let timeout = Duration::from_secs(
+                        request
+                            .health_timeout_seconds
+                            .unwrap_or(DEFAULT_HEALTH_TIMEOUT_SECS),
+                    );
+                    wait_healthy(&state, &service, request.version.as_deref(), timeout)
+                        .await
+                        .err()
+                }
+            }
+        }
+    };
+
+    let Some(failure) = failure else {
+        let message = match &request.version {
+            Some(version) => format!("Service '{}' upgraded to {}", service, version),
+            None => format!("Service '{}' upgraded", service),
+        };
+        events.publish(&service, events::UPGRADED, message);
+        state.store.request_refresh();
+        return;
+    };
+
+    progress(
+        events,
+        &service,
+        format!("Rolling back '{}': {}", service, failure),
+    );
+    let _ = stop_daemon(&service).await;
+    let previous_dir = workspace.previous.clone();
+    let restored = calls::blocking(move || {
+        let mut workspace = workspace;
+        let restored = restore(&workspace, &service_dir, &swapped);
+        workspace.keep = restored.is_err();
+        restored
+    })
+    .await;
+    let message = match restored {
+        Err(e) => format!(
+            "Upgrade of '{}' failed ({}) and rolling back failed too: {:#}; the old files are in {}",
+            service,
+            failure,
+            e,
+            previous_dir.display()
+        ),
+        Ok(_) if was_running => match start_daemon(&service).await {
+            Ok(()) => format!("Upgrade of '{}' rolled back: {}", service, failure),
+            Err(e) => format!(
+                "Upgrade of '{}' rolled back ({}), but the old version failed to start: {}",
+                service, failure, e
+            ),
+        },
+        Ok(_) => format!("Upgrade of '{}' rolled back: {}", service, failure),
+    };
+    tracing::warn!(service = %service, "{}", message);
+    events.publish(&service, events::UPGRADE_ROLLED_BACK, message);
+    state.store.request_refresh();
+}
+
+/// Upgrade a service to a new release
+pub async fn upgrade_service(
+    State(state): State<AppState>,
+    Path(service): Path<String>,
+    Json(request): Json<UpgradeRequest>,
+) -> impl IntoResponse {
+    let settings = state.settings();
+    let Some(service_dir) = settings.service_dir(&service) else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "Service '{}' is not installed",
+                service
+            )),
+        );
+    };
+    let services_dir = service_dir.parent().unwrap_or(&service_dir).to_path_buf();
+
+    let Some(lock) = state.upgrades.acquire(&service) else {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "An upgrade of '{}' is already in progress",
+                service
+            )),
+        );
+    };
+
+    // The lock goes with the staging, so a request timing out mid-stage
+    // only frees the service once staging is over
+    let workspace = Workspace::new(&services_dir, &service);
+    let (lock, workspace, request, staged) = calls::blocking(move || {
+        let staged = stage(&request, &workspace);
+        (lock, workspace, request, staged)
+    })
+    .await;
+    if let Err(e) = staged {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<serde_json::Value>::error(&format!("Invalid release: {:#}", e)),
+        );
+    }
+
+    progress(
+        &state.events,
+        &service,
+        format!("Upgrading '{}' from {}", service, request.source.display()),
+    );
+    let message = format!("Upgrade of '{}' started", service);
+    tokio::spawn({
+        let state = state.clone();
+        let service = service.clone();
+        async move {
+            run(
+                state.clone(),
+                service.clone(),
+                request,
+                workspace,
+                service_dir,
+            )
+            .await;
+            drop(lock);
+        }
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        ApiResponse::success(serde_json::json!({ "message": message })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrade_locks_are_released_on_drop() {
+        let locks = Arc::new(UpgradeLocks::default());
+        let lock = locks.acquire("gmail").unwrap();
+        assert!(locks.acquire("gmail").is_none());
+        assert!(locks.acquire("slack").is_some());
+
+        drop(lock);
+        assert!(locks.acquire("gmail").is_some());
+    }
+}
@@ -0,0 +1,47 @@
+//! Build identity of the running dashboard.
+//!
+//! The values are embedded by `build.rs`.
+
+use axum::response::IntoResponse;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+
+/// What `GET /api/version` reports
+#[derive(Clone, Serialize, Deserialize, TS)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Full git commit hash, or `unknown` when built outside a checkout
+    pub git_commit: String,
+    /// Unix time of the build
+    #[ts(type = "number")]
+    pub build_timestamp: u64,
+    /// Enabled cargo features
+    pub features: Vec<String>,
+    /// Version of the linked `fgp_daemon` crate
+    pub fgp_daemon_version: String,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("FGP_DASHBOARD_GIT_COMMIT").to_string(),
+            build_timestamp: env!("FGP_DASHBOARD_BUILD_TIMESTAMP")
+                .parse()
+                .unwrap_or_default(),
+            features: env!("FGP_DASHBOARD_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(|feature| feature.to_string())
+                .collect(),
+            fgp_daemon_version: env!("FGP_DASHBOARD_FGP_DAEMON_VERSION").to_string(),
+        }
+    }
+}
+
+/// Report the dashboard's build identity
+pub async fn version() -> impl IntoResponse {
+    ApiResponse::success(BuildInfo::current())
+}
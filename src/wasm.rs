@@ -0,0 +1,335 @@
+//! Sandboxed WebAssembly checks over service state.
+//!
+//! Every `.wasm` module in the plugin dir is loaded at startup and runs on
+//! each scan, once per service, in the order of its file name. A check sees
+//! the service as `/api/services` lists it together with the daemon's last
+//! health payload, and may override its status, explain why and add labels,
+//! so teams can grade health by their own rules or derive values from what
+//! the daemon reports. Later checks see what earlier ones changed.
+//!
+//! Host API, version [`ABI_VERSION`]. A module exports:
+//!
+//! - `memory`
+//! - `fgp_abi() -> i32`, returning the version
+//! - `fgp_alloc(len: i32) -> i32`, returning where `len` bytes may be written
+//! - `fgp_check(ptr: i32, len: i32) -> i64`, reading the JSON input
+//!   `{"service": ..., "health": ...}` at `ptr` and returning
+//!   `(answer_ptr << 32) | answer_len` of a JSON answer, or 0 for no opinion
+//!
+//! and may import `fgp.log(level: i32, ptr: i32, len: i32)` to log a UTF-8
+//! message of up to 4 KiB at level 0 (debug) to 3 (error). The answer is an
+//! object with optional `status`, `reason` and `labels` (an object of
+//! strings).
+//!
+//! Each call may use `--wasm-fuel` units of fuel (roughly instructions) and
+//! each instance `--wasm-memory-mb` of memory; a check that runs out, traps
+//! or answers nonsense leaves the service as it was, and its error shows in
+//! `/api/plugins`. Modules can't touch files, sockets or the clock.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use ts_rs::TS;
+use wasmtime::{Caller, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::api::ServiceInfo;
+use crate::calls;
+use crate::config::Config;
+
+/// Version of the host API this dashboard offers
+pub const ABI_VERSION: i32 = 1;
+
+/// Fuel a call may use when not configured
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Memory an instance may use when not configured, in MiB
+pub const DEFAULT_MEMORY_MB: usize = 16;
+
+/// Longest log message a check may write; the rest is cut off
+const MAX_LOG_BYTES: usize = 4096;
+
+/// What a check may use
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub fuel: u64,
+    pub memory_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: DEFAULT_FUEL,
+            memory_bytes: DEFAULT_MEMORY_MB * 1024 * 1024,
+        }
+    }
+}
+
+/// A loaded module
+#[derive(Clone)]
+struct Check {
+    name: String,
+    path: PathBuf,
+    module: Module,
+}
+
+/// A check as `/api/plugins` lists it
+#[derive(Serialize, Deserialize, TS)]
+pub struct CheckInfo {
+    pub name: String,
+    pub path: String,
+    /// Why the check last failed, if it did on the last scan
+    pub error: Option<String>,
+}
+
+/// What a check answers
+#[derive(Deserialize)]
+struct Verdict {
+    status: Option<String>,
+    reason: Option<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+/// State of a store running one check
+struct Host {
+    limits: StoreLimits,
+    check: String,
+}
+
+/// The checks loaded at startup
+pub struct Checks {
+    engine: Engine,
+    checks: Vec<Check>,
+    limits: Limits,
+    /// Last error of each check, by name
+    errors: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Checks {
+    /// Compile the modules in `dir`, skipping those that don't load
+    pub fn load(dir: &Path, limits: Limits) -> Self {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).unwrap_or_else(|e| {
+            tracing::warn!("Failed to set up the WASM engine: {:#}", e);
+            Engine::default()
+        });
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let mut checks = Vec::new();
+        let errors = Arc::new(Mutex::new(HashMap::new()));
+        for path in paths {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let check = Module::from_file(&engine, &path)
+                .map(|module| Check {
+                    name: name.clone(),
+                    path: path.clone(),
+                    module,
+                })
+                .and_then(|check| {
+                    let (mut store, instance) = instantiate(&engine, &check, limits)?;
+                    let abi = instance
+                        .get_typed_func::<(), i32>(&mut store, "fgp_abi")?
+                        .call(&mut store, ())?;
+                    if abi != ABI_VERSION {
+                        bail!(
+                            "built for host API {}, this dashboard offers {}",
+                            abi,
+                            ABI_VERSION
+                        );
+                    }
+                    Ok(check)
+                });
+            match check {
+                Ok(check) => {
+                    tracing::info!("Loaded WASM check {}", check.name);
+                    checks.push(check);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to load WASM check {}: {:#}", path.display(), e);
+                    errors.lock().unwrap().insert(name, format!("{:#}", e));
+                }
+            }
+        }
+
+        Self {
+            engine,
+            checks,
+            limits,
+            errors,
+        }
+    }
+
+    /// Loaded checks and their last errors
+    pub fn list(&self) -> Vec<CheckInfo> {
+        let errors = self.errors.lock().unwrap();
+        self.checks
+            .iter()
+            .map(|check| CheckInfo {
+                name: check.name.clone(),
+                path: check.path.to_string_lossy().to_string(),
+                error: errors.get(&check.name).cloned(),
+            })
+            .collect()
+    }
+
+    /// Run every check over scanned services
+    pub async fn check(&self, services: &mut Vec<ServiceInfo>) {
+        if self.checks.is_empty() {
+            return;
+        }
+        let engine = self.engine.clone();
+        let checks = self.checks.clone();
+        let limits = self.limits;
+        let errors = self.errors.clone();
+        let mut scanned = std::mem::take(services);
+
+        *services = calls::blocking(move || {
+            for check in &checks {
+                let result = run(&engine, check, limits, &mut scanned);
+                let mut errors = errors.lock().unwrap();
+                match result {
+                    Ok(()) => errors.remove(&check.name),
+                    Err(e) => {
+                        tracing::debug!("WASM check {} failed: {:#}", check.name, e);
+                        errors.insert(check.name.clone(), format!("{:#}", e))
+                    }
+                };
+            }
+            scanned
+        })
+        .await;
+    }
+}
+
+static CHECKS: OnceLock<Checks> = OnceLock::new();
+
+/// Load the checks once, before anything scans for services
+pub fn init(config: &Config) {
+    CHECKS.get_or_init(|| Checks::load(&config.plugin_dir, config.wasm_limits));
+}
+
+/// The loaded checks; none before [`init`]
+pub fn checks() -> &'static Checks {
+    CHECKS.get_or_init(|| Checks::load(Path::new(""), Limits::default()))
+}
+
+fn instantiate(engine: &Engine, check: &Check, limits: Limits) -> Result<(Store<Host>, Instance)> {
+    let host = Host {
+        limits: StoreLimitsBuilder::new()
+            .memory_size(limits.memory_bytes)
+            .instances(1)
+            .build(),
+        check: check.name.clone(),
+    };
+    let mut store = Store::new(engine, host);
+    store.limiter(|host| &mut host.limits);
+    store.set_fuel(limits.fuel)?;
+
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        "fgp",
+        "log",
+        |mut caller: Caller<'_, Host>, level: i32, ptr: i32, len: i32| {
+            let Some(memory) = caller
+                .get_export("memory")
+                .and_then(|export| export.into_memory())
+            else {
+                return;
+            };
+            let len = (len.max(0) as usize).min(MAX_LOG_BYTES);
+            let Some(bytes) = guest_bytes(memory.data(&caller), ptr as u32 as usize, len) else {
+                return;
+            };
+            let message = String::from_utf8_lossy(bytes).into_owned();
+            let check = &caller.data().check;
+            match level {
+                3 => tracing::error!("WASM check {}: {}", check, message),
+                2 => tracing::warn!("WASM check {}: {}", check, message),
+                1 => tracing::info!("WASM check {}: {}", check, message),
+                _ => tracing::debug!("WASM check {}: {}", check, message),
+            }
+        },
+    )?;
+    let instance = linker.instantiate(&mut store, &check.module)?;
+    Ok((store, instance))
+}
+
+/// The `len` bytes at `ptr` of a module's memory, if they are all inside it
+fn guest_bytes(data: &[u8], ptr: usize, len: usize) -> Option<&[u8]> {
+    data.get(ptr..ptr.checked_add(len)?)
+}
+
+/// Run one check over every service, in a fresh instance
+fn run(engine: &Engine, check: &Check, limits: Limits, services: &mut [ServiceInfo]) -> Result<()> {
+    let (mut store, instance) = instantiate(engine, check, limits)?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| anyhow!("the module exports no memory"))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "fgp_alloc")?;
+    let evaluate = instance.get_typed_func::<(i32, i32), i64>(&mut store, "fgp_check")?;
+
+    for service in services.iter_mut() {
+        store.set_fuel(limits.fuel)?;
+        let input = serde_json::to_vec(&serde_json::json!({
+            "service": &*service,
+            "health": service.health,
+        }))?;
+        let len = i32::try_from(input.len()).context("input too large")?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as usize, &input)?;
+
+        let answer = evaluate
+            .call(&mut store, (ptr, len))
+            .with_context(|| format!("checking {}", service.name))?;
+        if answer == 0 {
+            continue;
+        }
+        let (answer_ptr, answer_len) = ((answer >> 32) as u32 as usize, answer as u32 as usize);
+        let bytes = guest_bytes(memory.data(&store), answer_ptr, answer_len)
+            .ok_or_else(|| anyhow!("answer for {} is outside memory", service.name))?;
+        let verdict: Verdict = serde_json::from_slice(bytes)
+            .with_context(|| format!("invalid answer for {}", service.name))?;
+
+        if let Some(status) = verdict.status.filter(|status| !status.is_empty()) {
+            if status != service.status {
+                service.probe_error = verdict.reason.or(service.probe_error.take());
+            }
+            service.status = status;
+        }
+        service.labels.extend(verdict.labels);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guest_bytes_stay_inside_memory() {
+        let memory = [1, 2, 3, 4];
+        assert_eq!(guest_bytes(&memory, 1, 2), Some(&[2, 3][..]));
+        assert_eq!(guest_bytes(&memory, 0, 4), Some(&memory[..]));
+        assert_eq!(guest_bytes(&memory, 4, 0), Some(&[][..]));
+        assert_eq!(guest_bytes(&memory, 3, 2), None);
+        assert_eq!(guest_bytes(&memory, 5, 0), None);
+        assert_eq!(guest_bytes(&memory, usize::MAX, 2), None);
+    }
+}
@@ -0,0 +1,70 @@
+//! Filesystem watching of the services directories.
+//!
+//! Services being installed or removed and sockets appearing or
+//! disappearing wake the poller immediately instead of waiting for its next
+//! tick. Directories that don't exist yet, or that were added by a config
+//! reload, are picked up on the poller's next pass.
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::store::StateStore;
+
+/// Watches the services directories and asks the store to refresh on
+/// changes
+pub struct ServicesWatcher {
+    watcher: RecommendedWatcher,
+    watched: Vec<PathBuf>,
+}
+
+/// Whether an event can change which services or sockets exist
+fn is_relevant(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    )
+}
+
+impl ServicesWatcher {
+    pub fn new(store: Arc<StateStore>) -> notify::Result<Self> {
+        let watcher =
+            notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+                Ok(event) if is_relevant(&event) => {
+                    tracing::debug!(paths = ?event.paths, "Services directory changed");
+                    store.request_refresh();
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Services directory watch failed: {}", e),
+            })?;
+
+        Ok(Self {
+            watcher,
+            watched: Vec::new(),
+        })
+    }
+
+    /// Watch exactly these directories
+    ///
+    /// Directories that were deleted are watched again once they reappear.
+    pub fn sync(&mut self, dirs: &[PathBuf]) {
+        for dir in &self.watched {
+            if !dirs.contains(dir) || !dir.is_dir() {
+                let _ = self.watcher.unwatch(dir);
+            }
+        }
+        self.watched
+            .retain(|dir| dirs.contains(dir) && dir.is_dir());
+
+        for dir in dirs {
+            if self.watched.contains(dir) || !dir.is_dir() {
+                continue;
+            }
+            match self.watcher.watch(dir, RecursiveMode::Recursive) {
+                Ok(()) => self.watched.push(dir.clone()),
+                Err(e) => tracing::warn!("Cannot watch {}: {}", dir.display(), e),
+            }
+        }
+    }
+}
@@ -0,0 +1,614 @@
+//! Webhook subscriptions.
+//!
+//! External systems register a callback URL plus an optional filter on
+//! services and event kinds via `/api/subscriptions`. Matching events are
+//! POSTed as JSON, signed with the subscription's secret in the
+//! `X-FGP-Signature` header (`sha256=<hex HMAC of the body>`), and retried
+//! with exponential backoff. Subscriptions are persisted in the data dir;
+//! delivery results are kept in memory.
+//!
+//! Webhooks can also be defined in the config file, where the secret may be
+//! a reference such as `${vault:secret/data/hooks#ops}` (see
+//! [`secrets`](crate::secrets)). They are listed as `config:<name>` and
+//! change only with the config:
+//!
+//! ```toml
+//! [[webhooks]]
+//! name = "ops"
+//! url = "https://hooks.example.com/fgp"
+//! events = ["stopped", "degraded"]
+//! secret = "${vault:secret/data/hooks#ops}"
+//! ```
+//!
+//! Secrets of subscriptions created through the API are used as given, and
+//! references are refused, so callers can't make the dashboard sign with its
+//! own credentials. Subscriptions created under
+//! `/api/ns/{ns}/subscriptions` belong to that namespace: they only get its
+//! services' events and are only listed there (see
+//! [`namespaces`](crate::namespaces)).
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::events::{self, Event};
+use crate::namespaces::{Scope, View};
+use crate::secrets;
+use crate::state::AppState;
+
+/// Delivery attempts before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry, doubled for each further attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Delivery results kept per subscription
+const RECENT_DELIVERIES: usize = 100;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A registered webhook
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    /// Only deliver events for these services (all if empty)
+    #[serde(default)]
+    pub services: Vec<String>,
+    /// Only deliver these event kinds (all if empty)
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Secret used to sign deliveries
+    pub secret: String,
+    pub created_at: u64,
+    /// Namespace the subscription was created in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Defined in the config file rather than through the API
+    #[serde(skip)]
+    pub from_config: bool,
+}
+
+impl Subscription {
+    fn matches(&self, event: &Event) -> bool {
+        (self.services.is_empty() || self.services.contains(&event.service))
+            && (self.events.is_empty() || self.events.contains(&event.kind))
+    }
+}
+
+/// A subscription as listed by the API, without its secret
+#[derive(Serialize, TS)]
+pub struct SubscriptionView {
+    pub id: String,
+    pub url: String,
+    pub services: Vec<String>,
+    pub events: Vec<String>,
+    #[ts(type = "number")]
+    pub created_at: u64,
+    pub namespace: Option<String>,
+    pub from_config: bool,
+}
+
+impl From<&Subscription> for SubscriptionView {
+    fn from(subscription: &Subscription) -> Self {
+        Self {
+            id: subscription.id.clone(),
+            url: subscription.url.clone(),
+            services: subscription.services.clone(),
+            events: subscription.events.clone(),
+            created_at: subscription.created_at,
+            namespace: subscription.namespace.clone(),
+            from_config: subscription.from_config,
+        }
+    }
+}
+
+/// A webhook from `[[webhooks]]` in the config file
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Secret used to sign deliveries, resolved when the config is loaded
+    pub secret: String,
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("a webhook has an empty name".to_string());
+        }
+        if !is_http(&self.url) {
+            return Err(format!(
+                "webhook '{}' needs an http:// or https:// url",
+                self.name
+            ));
+        }
+        if self.secret.is_empty() {
+            return Err(format!("webhook '{}' has an empty secret", self.name));
+        }
+        Ok(())
+    }
+
+    pub fn subscription(&self) -> Subscription {
+        Subscription {
+            id: format!("config:{}", self.name),
+            url: self.url.clone(),
+            services: self.services.clone(),
+            events: self.events.clone(),
+            secret: self.secret.clone(),
+            created_at: 0,
+            namespace: None,
+            from_config: true,
+        }
+    }
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Request body for registering a webhook
+#[derive(Deserialize)]
+pub struct NewSubscription {
+    pub url: String,
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Signing secret; generated if omitted
+    pub secret: Option<String>,
+}
+
+/// Outcome of delivering one event to one subscription
+#[derive(Clone, Serialize, TS)]
+pub struct Delivery {
+    #[ts(type = "number")]
+    pub event_id: u64,
+    #[ts(type = "number")]
+    pub timestamp: u64,
+    pub attempts: u32,
+    pub delivered: bool,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Subscriptions file inside the data dir
+pub const SUBSCRIPTIONS_FILE: &str = "subscriptions.json";
+
+/// Persistent set of subscriptions plus recent delivery results
+pub struct WebhookRegistry {
+    /// Where subscriptions are saved; `None` keeps them in memory only
+    path: Option<PathBuf>,
+    subscriptions: RwLock<Vec<Subscription>>,
+    deliveries: Mutex<HashMap<String, VecDeque<Delivery>>>,
+}
+
+impl WebhookRegistry {
+    /// Load subscriptions from `path`, starting empty if the file doesn't exist
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let subscriptions = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            subscriptions: RwLock::new(subscriptions),
+            deliveries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Start empty and never save, for `--demo`
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            subscriptions: RwLock::new(Vec::new()),
+            deliveries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Re-read subscriptions from disk, e.g. after a restore
+    pub fn reload(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let reloaded = Self::load(path.clone())?;
+        *self.subscriptions.write().unwrap() = reloaded.subscriptions.into_inner().unwrap();
+        self.deliveries.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn save(&self, subscriptions: &[Subscription]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(subscriptions)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Subscription> {
+        self.subscriptions.read().unwrap().clone()
+    }
+
+    pub fn add(&self, subscription: Subscription) -> Result<()> {
+        let mut subscriptions = self.subscriptions.write().unwrap();
+        subscriptions.push(subscription);
+        self.save(&subscriptions)
+    }
+
+    /// Remove a subscription, returning whether it existed
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let mut subscriptions = self.subscriptions.write().unwrap();
+        let before = subscriptions.len();
+        subscriptions.retain(|s| s.id != id);
+        if subscriptions.len() == before {
+            return Ok(false);
+        }
+        self.deliveries.lock().unwrap().remove(id);
+        self.save(&subscriptions)?;
+        Ok(true)
+    }
+
+    fn matching(&self, event: &Event) -> Vec<Subscription> {
+        self.subscriptions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| s.matches(event))
+            .cloned()
+            .collect()
+    }
+
+    fn record(&self, id: &str, delivery: Delivery) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let recent = deliveries.entry(id.to_string()).or_default();
+        if recent.len() == RECENT_DELIVERIES {
+            recent.pop_front();
+        }
+        recent.push_back(delivery);
+    }
+
+    pub fn deliveries(&self, id: &str) -> Vec<Delivery> {
+        self.deliveries
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Sign a delivery body with the subscription secret
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Deliver an event, retrying with exponential backoff
+async fn deliver(state: AppState, subscription: Subscription, event: Event) {
+    let body = serde_json::to_vec(&event).unwrap_or_default();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut delivery = Delivery {
+        event_id: event.id,
+        timestamp: events::now(),
+        attempts: 0,
+        delivered: false,
+        response_status: None,
+        error: None,
+    };
+    let signature = sign(&subscription.secret, &body);
+
+    while delivery.attempts < MAX_ATTEMPTS {
+        if delivery.attempts > 0 {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        delivery.attempts += 1;
+
+        let result = state
+            .http
+            .post(&subscription.url)
+            .header("content-type", "application/json")
+            .header("x-fgp-event", &event.kind)
+            .header("x-fgp-delivery", event.id.to_string())
+            .header("x-fgp-signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        match result {
+            Ok(response) => {
+                delivery.response_status = Some(response.status().as_u16());
+                if response.status().is_success() {
+                    delivery.delivered = true;
+                    delivery.error = None;
+                    break;
+                }
+                delivery.error = Some(format!("HTTP {}", response.status()));
+            }
+            Err(e) => delivery.error = Some(e.to_string()),
+        }
+    }
+
+    if !delivery.delivered {
+        tracing::warn!(
+            "Webhook {} failed for event {}: {}",
+            subscription.id,
+            event.id,
+            delivery.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    state.webhooks.record(&subscription.id, delivery);
+}
+
+/// Forward events to matching webhooks in the background
+pub fn spawn_dispatcher(state: AppState) {
+    tokio::spawn(async move {
+        let mut events = state.events.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let settings = state.settings();
+                    let from_config = settings
+                        .webhooks
+                        .iter()
+                        .filter(|subscription| subscription.matches(&event))
+                        .cloned();
+                    for subscription in state
+                        .webhooks
+                        .matching(&event)
+                        .into_iter()
+                        .chain(from_config)
+                    {
+                        let in_namespace = subscription.namespace.as_deref().is_none_or(|name| {
+                            settings
+                                .namespace(name)
+                                .is_some_and(|namespace| namespace.contains(&event.service))
+                        });
+                        if !in_namespace {
+                            continue;
+                        }
+                        tokio::spawn(deliver(state.clone(), subscription, event.clone()));
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Webhook dispatcher skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Whether a subscription shows in `view`: all do outside namespaces, and
+/// only its own within one
+fn visible(view: &View, subscription: &Subscription) -> bool {
+    view.namespace()
+        .is_none_or(|name| subscription.namespace.as_deref() == Some(name))
+}
+
+/// Webhooks from the config file, then those registered through the API
+fn all(state: &AppState) -> Vec<Subscription> {
+    let mut subscriptions = state.settings().webhooks.clone();
+    subscriptions.extend(state.webhooks.list());
+    subscriptions
+}
+
+/// List registered webhooks
+pub async fn list_subscriptions(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+) -> impl IntoResponse {
+    let view = View::of(&state.settings(), scope);
+    let subscriptions: Vec<SubscriptionView> = all(&state)
+        .iter()
+        .filter(|subscription| visible(&view, subscription))
+        .map(SubscriptionView::from)
+        .collect();
+    ApiResponse::success(subscriptions)
+}
+
+/// Register a webhook
+///
+/// The response includes the signing secret; it is not shown again.
+pub async fn create_subscription(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+    Json(request): Json<NewSubscription>,
+) -> impl IntoResponse {
+    let view = View::of(&state.settings(), scope);
+    if let Some(service) = request.services.iter().find(|service| !view.shows(service)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Subscription>::error(&format!("Service '{}' not found", service)),
+        );
+    }
+    if !is_http(&request.url) {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Subscription>::error("Callback URL must be http:// or https://"),
+        );
+    }
+    if request.secret.as_deref().is_some_and(secrets::is_reference) {
+        return (
+            StatusCode::BAD_REQUEST,
+            ApiResponse::<Subscription>::error(
+                "A secret set through the API can't be a ${...} reference; \
+                 define the webhook under [[webhooks]] in the config file instead",
+            ),
+        );
+    }
+
+    let subscription = Subscription {
+        id: uuid::Uuid::new_v4().to_string(),
+        url: request.url,
+        services: request.services,
+        events: request.events,
+        secret: request
+            .secret
+            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()),
+        created_at: events::now(),
+        namespace: view.namespace().map(str::to_string),
+        from_config: false,
+    };
+
+    match state.webhooks.add(subscription.clone()) {
+        Ok(()) => (StatusCode::CREATED, ApiResponse::success(subscription)),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<Subscription>::error(&e.to_string()),
+        ),
+    }
+}
+
+/// Remove a webhook
+pub async fn delete_subscription(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let view = View::of(&state.settings(), scope);
+    let Some(subscription) = all(&state)
+        .into_iter()
+        .find(|subscription| subscription.id == id && visible(&view, subscription))
+    else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Subscription '{}' not found", id)),
+        );
+    };
+    if subscription.from_config {
+        return (
+            StatusCode::CONFLICT,
+            ApiResponse::<serde_json::Value>::error(&format!(
+                "Subscription '{}' is defined in the config file",
+                id
+            )),
+        );
+    }
+    match state.webhooks.remove(&id) {
+        Ok(true) => (
+            StatusCode::OK,
+            ApiResponse::success(serde_json::json!({
+                "message": format!("Subscription '{}' removed", id)
+            })),
+        ),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error(&format!("Subscription '{}' not found", id)),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ApiResponse::<serde_json::Value>::error(&e.to_string()),
+        ),
+    }
+}
+
+/// Recent delivery results for a webhook
+pub async fn subscription_deliveries(
+    State(state): State<AppState>,
+    scope: Option<Extension<Scope>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let view = View::of(&state.settings(), scope);
+    if !all(&state).iter().any(|s| s.id == id && visible(&view, s)) {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<Vec<Delivery>>::error(&format!("Subscription '{}' not found", id)),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        ApiResponse::success(state.webhooks.deliveries(&id)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook() -> WebhookConfig {
+        WebhookConfig {
+            name: "ops".to_string(),
+            url: "https://hooks.example.com/fgp".to_string(),
+            services: Vec::new(),
+            events: vec!["stopped".to_string()],
+            secret: "s3cret".to_string(),
+        }
+    }
+
+    #[test]
+    fn config_webhooks_become_subscriptions() {
+        let subscription = webhook().subscription();
+        assert_eq!(subscription.id, "config:ops");
+        assert!(subscription.from_config);
+        assert_eq!(subscription.secret, "s3cret");
+    }
+
+    #[test]
+    fn config_webhooks_are_validated() {
+        assert!(webhook().validate().is_ok());
+        for broken in [
+            WebhookConfig {
+                name: " ".to_string(),
+                ..webhook()
+            },
+            WebhookConfig {
+                url: "ftp://hooks.example.com".to_string(),
+                ..webhook()
+            },
+            WebhookConfig {
+                secret: String::new(),
+                ..webhook()
+            },
+        ] {
+            assert!(broken.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn references_are_recognised() {
+        assert!(secrets::is_reference("${env:FGP_DASHBOARD_AUTH_TOKENS}"));
+        assert!(secrets::is_reference("${vault:secret/data/hooks#ops}"));
+        assert!(!secrets::is_reference("plain-secret"));
+        assert!(!secrets::is_reference("${unterminated"));
+    }
+
+    #[test]
+    fn signatures_are_hex_hmacs() {
+        let signature = sign("key", b"body");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign("other", b"body"));
+    }
+}
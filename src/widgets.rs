@@ -0,0 +1,228 @@
+//! Custom panels on the main page.
+//!
+//! Widgets come from `[[widgets]]` sections of the config file and from
+//! plugins' `widgets`. Each names where its data comes from, either
+//!
+//! - a daemon `method` on a `service`, called with `params`, or
+//! - a `path` under a `plugin`'s routes, fetched with a GET
+//!
+//! and how the UI renders it:
+//!
+//! - `stat`: a number or string, followed by the `unit`
+//! - `list`: an array of `{"label": ..., "value": ...}`, or an object whose
+//!   entries are listed
+//! - `table`: an array of objects, one column per key of the first
+//!
+//! A `pointer` (a JSON pointer such as `/stats/orders_per_sec`) picks the
+//! data out of a bigger answer. `GET /api/widgets` lists the widgets, and
+//! `GET /api/widgets/{id}/data` fetches one's data; widgets from plugins
+//! have IDs of the form `<plugin>.<id>`. Daemon methods are subject to the
+//! RPC passthrough's `--allow-method` / `--deny-method` rules.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::api::ApiResponse;
+use crate::calls;
+use crate::plugins::{self, PluginRequest};
+use crate::rpc;
+use crate::state::AppState;
+use crate::transport;
+
+/// How often the UI refreshes a widget that doesn't say
+pub const DEFAULT_REFRESH_SECONDS: u64 = 10;
+
+/// How a widget's data is drawn
+#[derive(Clone, Copy, Default, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum WidgetKind {
+    #[default]
+    Stat,
+    List,
+    Table,
+}
+
+/// A panel on the main page
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(deny_unknown_fields)]
+pub struct Widget {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub kind: WidgetKind,
+    /// Shown after a stat's value
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Daemon answering `method`
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    #[ts(type = "unknown")]
+    pub params: Option<serde_json::Value>,
+    /// Plugin answering requests to `path`; set by the dashboard for
+    /// widgets plugins define
+    #[serde(default)]
+    pub plugin: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    /// JSON pointer to the data within the answer
+    #[serde(default)]
+    pub pointer: Option<String>,
+    #[serde(default)]
+    #[ts(type = "number | null")]
+    pub refresh_seconds: Option<u64>,
+}
+
+impl Widget {
+    /// Reject widgets whose data can't be fetched
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_id = !self.id.is_empty()
+            && self
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_id {
+            return Err(format!(
+                "invalid widget id '{}', expected letters, digits, '-', '_' or '.'",
+                self.id
+            ));
+        }
+        match (&self.service, &self.method, &self.plugin, &self.path) {
+            (Some(_), Some(_), None, None) | (None, None, Some(_), Some(_)) => {}
+            _ => {
+                return Err(format!(
+                    "widget '{}' needs either a service and method or a plugin and path",
+                    self.id
+                ))
+            }
+        }
+        if let Some(pointer) = &self.pointer {
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                return Err(format!(
+                    "invalid pointer '{}' of widget '{}', expected e.g. /stats/total",
+                    pointer, self.id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Widgets from the config file and the plugins
+///
+/// Blocks, so call it off the async runtime.
+fn all(configured: Vec<Widget>) -> Vec<Widget> {
+    let mut widgets = configured;
+    for plugin in plugins::registry().iter() {
+        for widget in plugin.widgets() {
+            let widget = Widget {
+                id: format!("{}.{}", plugin.name(), widget.id),
+                plugin: widget.service.is_none().then(|| plugin.name().to_string()),
+                ..widget
+            };
+            match widget.validate() {
+                Ok(()) => widgets.push(widget),
+                Err(e) => tracing::warn!("Ignoring a widget of plugin {}: {}", plugin.name(), e),
+            }
+        }
+    }
+    widgets
+}
+
+/// Fetch a widget's data
+async fn fetch(state: &AppState, widget: Widget) -> Result<serde_json::Value, String> {
+    let settings = state.settings();
+    let answer = match (widget.service, widget.method, widget.plugin, widget.path) {
+        (Some(service), Some(method), _, _) => {
+            if !settings.methods.permits(&service, &method) {
+                return Err(format!(
+                    "Method '{}' is not allowed on '{}'",
+                    method, service
+                ));
+            }
+            let socket_path = settings
+                .socket_path(&service)
+                .ok_or_else(|| format!("Service '{}' not found", service))?;
+            if !transport::endpoint_exists(&socket_path) {
+                return Err(format!("Service '{}' is not running", service));
+            }
+            let params = widget.params.unwrap_or_else(|| serde_json::json!({}));
+            let retries = settings.socket_retries;
+            let mut answer =
+                calls::blocking(move || rpc::call(&socket_path, &method, params, retries)).await?;
+            settings.redact.json(&mut answer);
+            answer
+        }
+        (_, _, Some(plugin), Some(path)) => {
+            let response = calls::blocking(move || {
+                let plugin = plugins::registry()
+                    .get(&plugin)
+                    .ok_or_else(|| format!("No plugin named '{}'", plugin))?;
+                Ok::<_, String>(plugin.request(&PluginRequest {
+                    method: "GET".to_string(),
+                    path: path.trim_start_matches('/').to_string(),
+                    query: None,
+                    body: serde_json::Value::Null,
+                }))
+            })
+            .await?;
+            if !(200..300).contains(&response.status) {
+                return Err(format!("The plugin answered {}", response.status));
+            }
+            response.body
+        }
+        _ => return Err("The widget names no data source".to_string()),
+    };
+
+    match widget.pointer.as_deref() {
+        Some(pointer) if !pointer.is_empty() => answer
+            .pointer(pointer)
+            .cloned()
+            .ok_or_else(|| format!("The answer has nothing at {}", pointer)),
+        _ => Ok(answer),
+    }
+}
+
+/// List the widgets
+pub async fn list_widgets(State(state): State<AppState>) -> impl IntoResponse {
+    let configured = state.settings().widgets.clone();
+    let widgets: Vec<Widget> = calls::blocking(move || all(configured))
+        .await
+        .into_iter()
+        .map(|widget| Widget {
+            refresh_seconds: Some(widget.refresh_seconds.unwrap_or(DEFAULT_REFRESH_SECONDS)),
+            ..widget
+        })
+        .collect();
+    (StatusCode::OK, ApiResponse::success(widgets))
+}
+
+/// Fetch the data of one widget
+pub async fn widget_data(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let configured = state.settings().widgets.clone();
+    let widget = calls::blocking(move || all(configured).into_iter().find(|w| w.id == id)).await;
+    let Some(widget) = widget else {
+        return (
+            StatusCode::NOT_FOUND,
+            ApiResponse::<serde_json::Value>::error("No such widget"),
+        );
+    };
+
+    match fetch(&state, widget).await {
+        Ok(data) => (StatusCode::OK, ApiResponse::success(data)),
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            ApiResponse::<serde_json::Value>::error(&e),
+        ),
+    }
+}